@@ -17,7 +17,7 @@ use std::fmt;
 use std::marker::Unpin;
 use thiserror::Error;
 use tiny_keccak::keccak256;
-use web3::types::{Address, Log, H256};
+use web3::types::{Address, Bytes, Log, H256, U256};
 
 use graph::prelude::*;
 use graph::{
@@ -947,6 +947,35 @@ pub trait EthereumAdapter: Send + Sync + 'static {
         call: EthereumContractCall,
         cache: Arc<dyn EthereumCallCache>,
     ) -> Box<dyn Future<Item = Vec<Token>, Error = EthereumContractCallError> + Send>;
+
+    /// Get the balance of the account at `address` as of `block_ptr`.
+    async fn get_balance(
+        &self,
+        logger: &Logger,
+        address: Address,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<U256, EthereumContractCallError>;
+
+    /// Get the code of the account at `address` as of `block_ptr`. The code is empty if the
+    /// account is not a contract.
+    async fn get_code(
+        &self,
+        logger: &Logger,
+        address: Address,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<Bytes, EthereumContractCallError>;
+
+    /// Get the value of the storage `slot` of the contract at `address` as of `block_ptr`.
+    async fn get_storage_at(
+        &self,
+        logger: &Logger,
+        address: Address,
+        slot: H256,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<H256, EthereumContractCallError>;
 }
 
 #[cfg(test)]
@@ -875,7 +875,21 @@ pub struct Mapping {
 
 impl Mapping {
     pub fn requires_archive(&self) -> anyhow::Result<bool> {
-        calls_host_fn(&self.runtime, "ethereum.call")
+        // All of these read the state of the chain as of the block that is being processed,
+        // which usually is no longer available from nodes that are not archive nodes
+        const STATE_READING_HOST_FNS: [&str; 5] = [
+            "ethereum.call",
+            "ethereum.getBalance",
+            "ethereum.hasCode",
+            "ethereum.getCode",
+            "ethereum.getStorageAt",
+        ];
+        for host_fn in STATE_READING_HOST_FNS {
+            if calls_host_fn(&self.runtime, host_fn)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     pub fn has_call_handler(&self) -> bool {
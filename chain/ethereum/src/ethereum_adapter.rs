@@ -21,8 +21,9 @@ use graph::{
             self,
             types::{
                 Address, BlockId, BlockNumber as Web3BlockNumber, Bytes, CallRequest, Filter,
-                FilterBuilder, Log, Transaction, TransactionReceipt, H256,
+                FilterBuilder, Log, Transaction, TransactionReceipt, H256, U256,
             },
+            Transport as _,
         },
         BlockNumber, ChainStore, CheapClone, DynTryFuture, Error, EthereumCallCache, Logger,
         TimeoutError, TryFutureExt,
@@ -76,6 +77,62 @@ pub struct EthereumAdapter {
 // See also f0af4ab0-6b7c-4b68-9141-5b79346a5f61.
 const ETH_CALL_GAS: u32 = 50_000_000;
 
+/// A piece of account state that mappings can read
+#[derive(Clone, Copy, Debug)]
+enum AccountState {
+    Balance,
+    Code,
+    Storage(H256),
+}
+
+impl AccountState {
+    /// The JSON-RPC method that reads this state
+    fn method(&self) -> &'static str {
+        match self {
+            AccountState::Balance => "eth_getBalance",
+            AccountState::Code => "eth_getCode",
+            AccountState::Storage(_) => "eth_getStorageAt",
+        }
+    }
+
+    /// The parameters for `method`, except for the block
+    fn params(&self, address: Address) -> Vec<json::Value> {
+        let address = json::Value::String(format!("{:#x}", address));
+        match self {
+            AccountState::Balance | AccountState::Code => vec![address],
+            AccountState::Storage(slot) => {
+                vec![address, json::Value::String(format!("{:#x}", slot))]
+            }
+        }
+    }
+
+    /// The key under which this state is stored in the call cache. The calldata of a contract
+    /// call is a 4 byte function selector followed by 32 byte words, so these keys, whose
+    /// lengths are never 4 more than a multiple of 32, can't clash with a contract call.
+    fn cache_key(&self) -> Vec<u8> {
+        match self {
+            AccountState::Balance | AccountState::Code => self.method().as_bytes().to_vec(),
+            AccountState::Storage(slot) => [self.method().as_bytes(), slot.as_bytes()].concat(),
+        }
+    }
+
+    /// Turn the JSON the Ethereum node returns into the bytes we cache
+    fn to_bytes(self, value: json::Value) -> Result<Vec<u8>, json::Error> {
+        match self {
+            AccountState::Balance => {
+                let balance: U256 = json::from_value(value)?;
+                let mut bytes = vec![0; 32];
+                balance.to_big_endian(&mut bytes);
+                Ok(bytes)
+            }
+            AccountState::Code => json::from_value::<Bytes>(value).map(|code| code.0),
+            AccountState::Storage(_) => {
+                json::from_value::<H256>(value).map(|value| value.as_bytes().to_vec())
+            }
+        }
+    }
+}
+
 impl CheapClone for EthereumAdapter {
     fn cheap_clone(&self) -> Self {
         Self {
@@ -420,14 +477,7 @@ impl EthereumAdapter {
         block_ptr: BlockPtr,
     ) -> impl Future<Item = Bytes, Error = EthereumContractCallError> + Send {
         let web3 = self.web3.clone();
-
-        // Ganache does not support calls by block hash.
-        // See https://github.com/trufflesuite/ganache-cli/issues/973
-        let block_id = if !self.supports_eip_1898 {
-            BlockId::Number(block_ptr.number.into())
-        } else {
-            BlockId::Hash(block_ptr.hash_as_h256())
-        };
+        let block_id = self.block_id(&block_ptr);
         let retry_log_message = format!("eth_call RPC call for block {}", block_ptr);
         retry(retry_log_message, &logger)
             .when(|result| match result {
@@ -567,6 +617,71 @@ impl EthereumAdapter {
             .compat()
     }
 
+    /// The id with which we ask the Ethereum node for the state as of `block_ptr`
+    fn block_id(&self, block_ptr: &BlockPtr) -> BlockId {
+        // Ganache does not support calls by block hash.
+        // See https://github.com/trufflesuite/ganache-cli/issues/973
+        if !self.supports_eip_1898 {
+            BlockId::Number(block_ptr.number.into())
+        } else {
+            BlockId::Hash(block_ptr.hash_as_h256())
+        }
+    }
+
+    /// Read the `state` of the account at `address` as of `block_ptr`, using the call cache to
+    /// avoid asking the Ethereum node for the same state again.
+    async fn account_state(
+        &self,
+        logger: &Logger,
+        address: Address,
+        state: AccountState,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<Vec<u8>, EthereumContractCallError> {
+        let cache_key = state.cache_key();
+        match cache.get_call(address, &cache_key, block_ptr.clone()) {
+            Ok(Some(result)) => return Ok(result),
+            Ok(None) => {}
+            Err(e) => error!(logger, "call cache get error"; "error" => e.to_string()),
+        }
+
+        trace!(logger, "{}", state.method(); "address" => hex::encode(&address));
+
+        let web3 = self.web3.clone();
+        let mut params = state.params(address);
+        params.push(json::to_value(self.block_id(&block_ptr)).unwrap());
+        let retry_log_message = format!("{} RPC call for block {}", state.method(), block_ptr);
+        let result = retry(retry_log_message, logger)
+            .limit(ENV_VARS.request_retries)
+            .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
+            .run(move || {
+                let web3 = web3.cheap_clone();
+                let params = params.clone();
+                async move {
+                    let value = web3.transport().execute(state.method(), params).await?;
+                    state
+                        .to_bytes(value)
+                        .map_err(|e| web3::Error::Decoder(e.to_string()))
+                }
+            })
+            .await
+            .map_err(|e| {
+                e.into_inner()
+                    .map(EthereumContractCallError::Web3Error)
+                    .unwrap_or(EthereumContractCallError::Timeout)
+            })?;
+
+        // Don't block handler execution on writing to the cache.
+        let for_cache = result.clone();
+        let logger = logger.clone();
+        let _ = graph::spawn_blocking_allow_panic(move || {
+            cache
+                .set_call(address, &cache_key, block_ptr, &for_cache)
+                .map_err(|e| error!(logger, "call cache set error"; "error" => e.to_string()))
+        });
+        Ok(result)
+    }
+
     /// Request blocks by hash through JSON-RPC.
     fn load_blocks_rpc(
         &self,
@@ -1239,6 +1354,52 @@ impl EthereumAdapterTrait for EthereumAdapter {
         )
     }
 
+    async fn get_balance(
+        &self,
+        logger: &Logger,
+        address: Address,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<U256, EthereumContractCallError> {
+        let balance = self
+            .account_state(logger, address, AccountState::Balance, block_ptr, cache)
+            .await?;
+        Ok(U256::from_big_endian(&balance))
+    }
+
+    async fn get_code(
+        &self,
+        logger: &Logger,
+        address: Address,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<Bytes, EthereumContractCallError> {
+        let code = self
+            .account_state(logger, address, AccountState::Code, block_ptr, cache)
+            .await?;
+        Ok(Bytes(code))
+    }
+
+    async fn get_storage_at(
+        &self,
+        logger: &Logger,
+        address: Address,
+        slot: H256,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<H256, EthereumContractCallError> {
+        let value = self
+            .account_state(
+                logger,
+                address,
+                AccountState::Storage(slot),
+                block_ptr,
+                cache,
+            )
+            .await?;
+        Ok(H256::from_slice(&value))
+    }
+
     /// Load Ethereum blocks in bulk, returning results as they come back as a Stream.
     fn load_blocks(
         &self,
@@ -1993,9 +2154,13 @@ mod tests {
 
     use crate::trigger::{EthereumBlockTriggerType, EthereumTrigger};
 
-    use super::{parse_block_triggers, EthereumBlock, EthereumBlockFilter, EthereumBlockWithCalls};
+    use super::{
+        parse_block_triggers, AccountState, EthereumBlock, EthereumBlockFilter,
+        EthereumBlockWithCalls,
+    };
     use graph::blockchain::BlockPtr;
-    use graph::prelude::ethabi::ethereum_types::U64;
+    use graph::prelude::ethabi::ethereum_types::{U256, U64};
+    use graph::prelude::serde_json::json;
     use graph::prelude::web3::types::{Address, Block, Bytes, H256};
     use graph::prelude::EthereumCall;
     use std::collections::HashSet;
@@ -2101,6 +2266,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn account_state_requests_and_cache_keys() {
+        let slot = hash(7);
+        assert_eq!(
+            vec![json!("0x0000000000000000000000000000000000000004")],
+            AccountState::Balance.params(address(4))
+        );
+        assert_eq!(
+            vec![
+                json!("0x0000000000000000000000000000000000000004"),
+                json!(format!("0x{}", "07".repeat(32)))
+            ],
+            AccountState::Storage(slot).params(address(4))
+        );
+
+        // Cache keys must not look like the calldata of a contract call
+        for state in [
+            AccountState::Balance,
+            AccountState::Code,
+            AccountState::Storage(slot),
+        ] {
+            assert_ne!(4, state.cache_key().len() % 32, "{:?}", state);
+        }
+        assert_ne!(
+            AccountState::Storage(slot).cache_key(),
+            AccountState::Storage(hash(8)).cache_key()
+        );
+
+        let balance = AccountState::Balance.to_bytes(json!("0x2a")).unwrap();
+        assert_eq!(U256::from(42), U256::from_big_endian(&balance));
+        let code = AccountState::Code.to_bytes(json!("0x")).unwrap();
+        assert!(code.is_empty());
+        let value = AccountState::Storage(slot)
+            .to_bytes(json!(format!("0x{}", "01".repeat(32))))
+            .unwrap();
+        assert_eq!(vec![1; 32], value);
+    }
+
     fn address(id: u64) -> Address {
         Address::from_low_u64_be(id)
     }
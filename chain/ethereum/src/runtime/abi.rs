@@ -1,4 +1,4 @@
-use super::runtime_adapter::UnresolvedContractCall;
+use super::runtime_adapter::{StorageRequest, UnresolvedContractCall};
 use crate::trigger::{
     EthereumBlockData, EthereumCallData, EthereumEventData, EthereumTransactionData,
 };
@@ -149,6 +149,30 @@ impl FromAscObj<AscUnresolvedContractCall_0_0_4> for UnresolvedContractCall {
     }
 }
 
+#[repr(C)]
+#[derive(AscType)]
+pub struct AscStorageRequest {
+    pub address: AscPtr<AscAddress>,
+    pub slot: AscPtr<AscH256>,
+}
+
+impl AscIndexId for AscStorageRequest {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::StorageRequest;
+}
+
+impl FromAscObj<AscStorageRequest> for StorageRequest {
+    fn from_asc_obj<H: AscHeap + ?Sized>(
+        asc_request: AscStorageRequest,
+        heap: &H,
+        gas: &GasCounter,
+    ) -> Result<Self, DeterministicHostError> {
+        Ok(StorageRequest {
+            address: asc_get(heap, asc_request.address, gas)?,
+            slot: asc_get(heap, asc_request.slot, gas)?,
+        })
+    }
+}
+
 #[repr(C)]
 #[derive(AscType)]
 pub struct AscUnresolvedContractCall {
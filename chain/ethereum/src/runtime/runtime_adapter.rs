@@ -14,15 +14,18 @@ use graph::{
     cheap_clone::CheapClone,
     prelude::{
         ethabi::{self, Address, Token},
-        EthereumCallCache, Future01CompatExt,
+        web3::types::H256,
+        BigInt, EthereumCallCache, Future01CompatExt,
     },
     runtime::{asc_get, asc_new, AscPtr, HostExportError},
     semver::Version,
     slog::{info, trace, Logger},
 };
-use graph_runtime_wasm::asc_abi::class::{AscEnumArray, EthereumValueKind};
+use graph_runtime_wasm::asc_abi::class::{
+    AscAddress, AscBigInt, AscEnumArray, AscWrapped, EthereumValueKind, Uint8Array,
+};
 
-use super::abi::{AscUnresolvedContractCall, AscUnresolvedContractCall_0_0_4};
+use super::abi::{AscStorageRequest, AscUnresolvedContractCall, AscUnresolvedContractCall_0_0_4};
 
 // When making an ethereum call, the maximum ethereum gas is ETH_CALL_GAS which is 50 million. One
 // unit of Ethereum gas is at least 100ns according to these benchmarks [1], so 1000 of our gas. In
@@ -34,6 +37,10 @@ use super::abi::{AscUnresolvedContractCall, AscUnresolvedContractCall_0_0_4};
 // [1] - https://www.sciencedirect.com/science/article/abs/pii/S0166531620300900
 pub const ETHEREUM_CALL: Gas = Gas::new(5_000_000_000);
 
+// Reading the balance, code or storage of an account does not run any EVM code, so it is much
+// cheaper than an Ethereum call; the cost covers the round trip to the Ethereum node.
+pub const ETHEREUM_STATE_READ: Gas = Gas::new(500_000_000);
+
 pub struct RuntimeAdapter {
     pub eth_adapters: Arc<EthereumNetworkAdapters>,
     pub call_cache: Arc<dyn EthereumCallCache>,
@@ -53,13 +60,183 @@ impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
 
         let ethereum_call = HostFn {
             name: "ethereum.call",
+            func: Arc::new({
+                let eth_adapter = eth_adapter.cheap_clone();
+                let call_cache = call_cache.cheap_clone();
+                move |ctx, wasm_ptr| {
+                    ethereum_call(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr, &abis)
+                        .map(|ptr| ptr.wasm_ptr())
+                }
+            }),
+        };
+
+        let ethereum_get_balance = HostFn {
+            name: "ethereum.getBalance",
+            func: Arc::new({
+                let eth_adapter = eth_adapter.cheap_clone();
+                let call_cache = call_cache.cheap_clone();
+                move |ctx, wasm_ptr| {
+                    ethereum_get_balance(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr)
+                        .map(|ptr| ptr.wasm_ptr())
+                }
+            }),
+        };
+
+        let ethereum_has_code = HostFn {
+            name: "ethereum.hasCode",
+            func: Arc::new({
+                let eth_adapter = eth_adapter.cheap_clone();
+                let call_cache = call_cache.cheap_clone();
+                move |ctx, wasm_ptr| {
+                    ethereum_has_code(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr)
+                        .map(|ptr| ptr.wasm_ptr())
+                }
+            }),
+        };
+
+        let ethereum_get_code = HostFn {
+            name: "ethereum.getCode",
+            func: Arc::new({
+                let eth_adapter = eth_adapter.cheap_clone();
+                let call_cache = call_cache.cheap_clone();
+                move |ctx, wasm_ptr| {
+                    ethereum_get_code(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr)
+                        .map(|ptr| ptr.wasm_ptr())
+                }
+            }),
+        };
+
+        let ethereum_get_storage_at = HostFn {
+            name: "ethereum.getStorageAt",
             func: Arc::new(move |ctx, wasm_ptr| {
-                ethereum_call(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr, &abis)
+                ethereum_get_storage_at(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr)
                     .map(|ptr| ptr.wasm_ptr())
             }),
         };
 
-        Ok(vec![ethereum_call])
+        Ok(vec![
+            ethereum_call,
+            ethereum_get_balance,
+            ethereum_has_code,
+            ethereum_get_code,
+            ethereum_get_storage_at,
+        ])
+    }
+}
+
+/// function ethereum.getBalance(address: Address): BigInt
+fn ethereum_get_balance(
+    eth_adapter: &EthereumAdapter,
+    call_cache: Arc<dyn EthereumCallCache>,
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<AscBigInt>, HostExportError> {
+    ctx.gas.consume_host_fn(ETHEREUM_STATE_READ)?;
+
+    let address: Address = asc_get::<_, AscAddress, _>(ctx.heap, wasm_ptr.into(), &ctx.gas)?;
+    let balance = graph::block_on(eth_adapter.get_balance(
+        &ctx.logger,
+        address,
+        ctx.block_ptr.cheap_clone(),
+        call_cache,
+    ))
+    .map_err(|e| state_read_error("balance", address, e))?;
+
+    Ok(asc_new(
+        ctx.heap,
+        &BigInt::from_unsigned_u256(&balance),
+        &ctx.gas,
+    )?)
+}
+
+/// function ethereum.hasCode(address: Address): Wrapped<bool>
+fn ethereum_has_code(
+    eth_adapter: &EthereumAdapter,
+    call_cache: Arc<dyn EthereumCallCache>,
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<AscWrapped<bool>>, HostExportError> {
+    ctx.gas.consume_host_fn(ETHEREUM_STATE_READ)?;
+
+    let address: Address = asc_get::<_, AscAddress, _>(ctx.heap, wasm_ptr.into(), &ctx.gas)?;
+    let code = graph::block_on(eth_adapter.get_code(
+        &ctx.logger,
+        address,
+        ctx.block_ptr.cheap_clone(),
+        call_cache,
+    ))
+    .map_err(|e| state_read_error("code", address, e))?;
+
+    let inner = !code.0.is_empty();
+    Ok(asc_new(ctx.heap, &AscWrapped { inner }, &ctx.gas)?)
+}
+
+/// function ethereum.getCode(address: Address): Bytes
+fn ethereum_get_code(
+    eth_adapter: &EthereumAdapter,
+    call_cache: Arc<dyn EthereumCallCache>,
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<Uint8Array>, HostExportError> {
+    ctx.gas.consume_host_fn(ETHEREUM_STATE_READ)?;
+
+    let address: Address = asc_get::<_, AscAddress, _>(ctx.heap, wasm_ptr.into(), &ctx.gas)?;
+    let code = graph::block_on(eth_adapter.get_code(
+        &ctx.logger,
+        address,
+        ctx.block_ptr.cheap_clone(),
+        call_cache,
+    ))
+    .map_err(|e| state_read_error("code", address, e))?;
+
+    Ok(asc_new(ctx.heap, code.0.as_slice(), &ctx.gas)?)
+}
+
+/// function ethereum.getStorageAt(request: StorageRequest): Bytes
+fn ethereum_get_storage_at(
+    eth_adapter: &EthereumAdapter,
+    call_cache: Arc<dyn EthereumCallCache>,
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<Uint8Array>, HostExportError> {
+    ctx.gas.consume_host_fn(ETHEREUM_STATE_READ)?;
+
+    let request: StorageRequest =
+        asc_get::<_, AscStorageRequest, _>(ctx.heap, wasm_ptr.into(), &ctx.gas)?;
+    let value = graph::block_on(eth_adapter.get_storage_at(
+        &ctx.logger,
+        request.address,
+        request.slot,
+        ctx.block_ptr.cheap_clone(),
+        call_cache,
+    ))
+    .map_err(|e| state_read_error("storage", request.address, e))?;
+
+    Ok(asc_new(ctx.heap, &value, &ctx.gas)?)
+}
+
+/// Turn an error reading the `what` of the account at `address` into an error for the mapping
+fn state_read_error(what: &str, address: Address, e: EthereumContractCallError) -> HostExportError {
+    match e {
+        // Like for Ethereum calls, any error reported by the Ethereum node or a timeout could be
+        // due to the block no longer being on the main chain.
+        EthereumContractCallError::Web3Error(e) => HostExportError::PossibleReorg(anyhow::anyhow!(
+            "Ethereum node returned an error when reading the {} of account {:?}: {}",
+            what,
+            address,
+            e
+        )),
+        EthereumContractCallError::Timeout => HostExportError::PossibleReorg(anyhow::anyhow!(
+            "Ethereum node did not respond when reading the {} of account {:?}",
+            what,
+            address
+        )),
+        e => HostExportError::Unknown(anyhow::anyhow!(
+            "Failed to read the {} of account {:?}: {}",
+            what,
+            address,
+            e
+        )),
     }
 }
 
@@ -212,6 +389,13 @@ fn eth_call(
     result
 }
 
+/// The storage slot of a contract that `ethereum.getStorageAt` reads
+#[derive(Clone, Debug)]
+pub struct StorageRequest {
+    pub address: Address,
+    pub slot: H256,
+}
+
 #[derive(Clone, Debug)]
 pub struct UnresolvedContractCall {
     pub contract_name: String,
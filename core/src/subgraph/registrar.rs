@@ -167,13 +167,20 @@ where
                     match operation {
                         EntityChangeOperation::Set => {
                             store
-                                .assigned_node(&deployment)
+                                .assignment_status(&deployment)
                                 .map_err(|e| {
                                     anyhow!("Failed to get subgraph assignment entity: {}", e)
                                 })
                                 .map(|assigned| -> Box<dyn Stream<Item = _, Error = _> + Send> {
-                                    if let Some(assigned) = assigned {
-                                        if assigned == node_id {
+                                    if let Some((assigned, paused)) = assigned {
+                                        if paused {
+                                            // Paused deployments must not be indexed on any node
+                                            debug!(logger, "Deployment is paused, broadcasting remove event"; "assigned_to" => assigned, "node_id" => &node_id);
+                                            Box::new(stream::once(Ok(AssignmentEvent::Remove {
+                                                deployment,
+                                                node_id: node_id.clone(),
+                                            })))
+                                        } else if assigned == node_id {
                                             // Start subgraph on this node
                                             debug!(logger, "Deployment assignee is this node, broadcasting add event"; "assigned_to" => assigned, "node_id" => &node_id);
                                             Box::new(stream::once(Ok(AssignmentEvent::Add {
@@ -210,6 +217,26 @@ where
             .flatten()
     }
 
+    /// Find the unique deployment with the given hash
+    fn locate_deployment(
+        &self,
+        hash: &DeploymentHash,
+    ) -> Result<DeploymentLocator, SubgraphRegistrarError> {
+        let locations = self.store.locators(hash)?;
+        match locations.len() {
+            0 => Err(SubgraphRegistrarError::DeploymentNotFound(hash.to_string())),
+            1 => Ok(locations[0].clone()),
+            _ => Err(SubgraphRegistrarError::StoreError(
+                anyhow!(
+                    "there are {} different deployments with id {}",
+                    locations.len(),
+                    hash.as_str()
+                )
+                .into(),
+            )),
+        }
+    }
+
     fn start_assigned_subgraphs(&self) -> impl Future<Item = (), Error = Error> {
         let provider = self.provider.clone();
         let logger = self.logger.clone();
@@ -375,25 +402,31 @@ where
         hash: &DeploymentHash,
         node_id: &NodeId,
     ) -> Result<(), SubgraphRegistrarError> {
-        let locations = self.store.locators(hash)?;
-        let deployment = match locations.len() {
-            0 => return Err(SubgraphRegistrarError::DeploymentNotFound(hash.to_string())),
-            1 => locations[0].clone(),
-            _ => {
-                return Err(SubgraphRegistrarError::StoreError(
-                    anyhow!(
-                        "there are {} different deployments with id {}",
-                        locations.len(),
-                        hash.as_str()
-                    )
-                    .into(),
-                ))
-            }
-        };
+        let deployment = self.locate_deployment(hash)?;
         self.store.reassign_subgraph(&deployment, node_id)?;
 
         Ok(())
     }
+
+    /// Pause indexing a subgraph deployment while keeping its assignment.
+    async fn pause_subgraph(&self, hash: &DeploymentHash) -> Result<(), SubgraphRegistrarError> {
+        let deployment = self.locate_deployment(hash)?;
+        self.store.pause_subgraph(&deployment)?;
+
+        debug!(self.logger, "Paused subgraph"; "subgraph_hash" => hash.to_string());
+
+        Ok(())
+    }
+
+    /// Resume indexing a paused subgraph deployment.
+    async fn resume_subgraph(&self, hash: &DeploymentHash) -> Result<(), SubgraphRegistrarError> {
+        let deployment = self.locate_deployment(hash)?;
+        self.store.resume_subgraph(&deployment)?;
+
+        debug!(self.logger, "Resumed subgraph"; "subgraph_hash" => hash.to_string());
+
+        Ok(())
+    }
 }
 
 async fn handle_assignment_event(
@@ -536,6 +569,10 @@ async fn create_subgraph_version<C: Blockchain, S: SubgraphStore>(
         .await
         .map_err(SubgraphRegistrarError::ManifestValidationError)?;
 
+    for warning in manifest.schema.deprecation_warnings() {
+        warn!(logger, "{}", warning; "subgraph" => name.to_string());
+    }
+
     let network_name = manifest.network_name();
 
     let chain = chains
@@ -3,7 +3,7 @@ use futures::prelude::*;
 use crate::data::query::{CacheStatus, Query, QueryTarget};
 use crate::data::subscription::{Subscription, SubscriptionError, SubscriptionResult};
 use crate::data::{graphql::effort::LoadManager, query::QueryResults};
-use crate::prelude::DeploymentHash;
+use crate::prelude::{DeploymentHash, QueryExecutionError, SubgraphName};
 
 use async_trait::async_trait;
 use std::sync::Arc;
@@ -35,6 +35,22 @@ pub trait GraphQlRunner: Send + Sync + 'static {
         max_skip: Option<u32>,
     ) -> QueryResults;
 
+    /// Runs a batch of GraphQL queries. All parts of the queries that do
+    /// not ask for a specific block are run against the same block.
+    async fn run_query_batch(
+        self: Arc<Self>,
+        queries: Vec<Query>,
+        target: QueryTarget,
+    ) -> Vec<QueryResults>;
+
+    /// Runs a composite query against the deployments for several targets
+    /// at once. The schema of each target is mounted under its namespace
+    async fn run_composite_query(
+        self: Arc<Self>,
+        query: Query,
+        namespaces: Vec<(String, QueryTarget)>,
+    ) -> QueryResults;
+
     /// Runs a GraphQL subscription and returns a stream of results.
     async fn run_subscription(
         self: Arc<Self>,
@@ -42,6 +58,15 @@ pub trait GraphQlRunner: Send + Sync + 'static {
         target: QueryTarget,
     ) -> Result<SubscriptionResult, SubscriptionError>;
 
+    /// Find the target for `subgraph`, which is either the id of a
+    /// deployment or the name of a subgraph. An existing deployment takes
+    /// precedence over a subgraph with the same name. Returns `None` if
+    /// `subgraph` is neither a deployment id nor a subgraph name
+    async fn query_target(
+        self: Arc<Self>,
+        subgraph: String,
+    ) -> Result<Option<QueryTarget>, QueryExecutionError>;
+
     fn load_manager(&self) -> Arc<LoadManager>;
 }
 
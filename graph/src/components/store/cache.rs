@@ -5,7 +5,7 @@ use std::sync::Arc;
 
 use crate::blockchain::BlockPtr;
 use crate::components::store::{
-    self as s, Entity, EntityKey, EntityOp, EntityOperation, EntityType,
+    self as s, DerivedEntityQuery, Entity, EntityKey, EntityOp, EntityOperation, EntityType,
 };
 use crate::prelude::ENV_VARS;
 use crate::util::lfu_cache::LfuCache;
@@ -113,6 +113,85 @@ impl EntityCache {
         Ok(entity)
     }
 
+    /// Get the entity for `key` if it was created or changed in the current
+    /// block, and `None` otherwise. This never queries the store: if the
+    /// entity was only partially updated in this block, the result only
+    /// contains the attributes that were set in this block, regardless of
+    /// what the store or the cache hold for the entity
+    pub fn get_in_block(&self, key: &EntityKey) -> Option<Entity> {
+        let update = self.updates.get(key).cloned();
+        let handler_update = self.handler_updates.get(key).cloned();
+        if update.is_none() && handler_update.is_none() {
+            return None;
+        }
+
+        let mut entity = None;
+        if let Some(op) = update {
+            entity = op.apply_to(entity)
+        }
+        if let Some(op) = handler_update {
+            entity = op.apply_to(entity)
+        }
+        entity
+    }
+
+    /// Load the entities that the `@derivedFrom` field `field` of the
+    /// entity with `key` lists. The entities reflect all changes made so
+    /// far in this block, including the ones that have not been written to
+    /// the store yet, and are sorted by their key
+    pub fn load_related(
+        &mut self,
+        key: &EntityKey,
+        field: &str,
+    ) -> Result<Vec<Entity>, anyhow::Error> {
+        let (entity_types, entity_field) = self
+            .store
+            .input_schema()
+            .derived_from(&key.entity_type, field)?;
+
+        let mut related = BTreeMap::new();
+        for entity_type in entity_types {
+            let query = DerivedEntityQuery {
+                entity_type,
+                entity_field: entity_field.clone(),
+                value: key.entity_id.clone(),
+            };
+
+            for (key, mut entity) in self.store.get_derived(&query)? {
+                // `__typename` is for queries not for mappings.
+                entity.remove("__typename");
+                if self.updates.contains_key(&key) || self.handler_updates.contains_key(&key) {
+                    // Remember what the store has so that looking at the
+                    // changes below does not have to load it again
+                    if !self.current.contains_key(&key) {
+                        self.current.insert(key, Some(entity));
+                    }
+                } else {
+                    related.insert(key, entity);
+                }
+            }
+
+            // Entities that were changed might now refer to `key` or have
+            // stopped referring to it
+            let changed: Vec<_> = self
+                .updates
+                .keys()
+                .chain(self.handler_updates.keys())
+                .filter(|key| key.entity_type == query.entity_type)
+                .cloned()
+                .collect();
+            for key in changed {
+                match self.get(&key)? {
+                    Some(entity) if query.matches(&entity) => {
+                        related.insert(key, entity);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(related.into_values().collect())
+    }
+
     pub fn remove(&mut self, key: EntityKey) {
         self.entity_op(key, EntityOp::Remove);
     }
@@ -21,6 +21,7 @@ use std::time::Duration;
 
 use crate::blockchain::DataSource;
 use crate::blockchain::{Block, Blockchain};
+use crate::data::query::QueryExplain;
 use crate::data::{store::*, subgraph::Source};
 use crate::prelude::*;
 
@@ -116,6 +117,40 @@ impl EntityKey {
     }
 }
 
+/// A query for the entities of `entity_type` whose attribute
+/// `entity_field` refers to the entity with id `value`; this is how the
+/// entities for a `@derivedFrom` field are found. If the attribute is a
+/// list, it must contain `value`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DerivedEntityQuery {
+    /// Name of the entity type to search
+    pub entity_type: EntityType,
+
+    /// The attribute that refers to the entity with id `value`
+    pub entity_field: String,
+
+    /// The id of the entity that is referred to
+    pub value: String,
+}
+
+impl DerivedEntityQuery {
+    /// Return `true` if `entity` matches this query
+    pub fn matches(&self, entity: &Entity) -> bool {
+        fn refers_to(value: &Value, id: &str) -> bool {
+            match value {
+                Value::String(s) => s == id,
+                Value::Bytes(b) => b.to_string() == id,
+                Value::List(values) => values.iter().any(|value| refers_to(value, id)),
+                _ => false,
+            }
+        }
+
+        entity
+            .get(&self.entity_field)
+            .map_or(false, |value| refers_to(value, &self.value))
+    }
+}
+
 #[test]
 fn key_stable_hash() {
     use stable_hash_legacy::crypto::SetHasher;
@@ -148,10 +183,17 @@ pub enum EntityFilter {
     LessOrEqual(Attribute, Value),
     In(Attribute, Vec<Value>),
     NotIn(Attribute, Vec<Value>),
+    InNoCase(Attribute, Vec<Value>),
+    NotInNoCase(Attribute, Vec<Value>),
     Contains(Attribute, Value),
     ContainsNoCase(Attribute, Value),
     NotContains(Attribute, Value),
     NotContainsNoCase(Attribute, Value),
+    /// The list attribute has at least one element in common with the
+    /// given list
+    ContainsAny(Attribute, Value),
+    /// The list attribute contains every element of the given list
+    ContainsAll(Attribute, Value),
     StartsWith(Attribute, Value),
     StartsWithNoCase(Attribute, Value),
     NotStartsWith(Attribute, Value),
@@ -160,7 +202,23 @@ pub enum EntityFilter {
     EndsWithNoCase(Attribute, Value),
     NotEndsWith(Attribute, Value),
     NotEndsWithNoCase(Attribute, Value),
+    IsNull(Attribute),
+    IsNotNull(Attribute),
     ChangeBlockGte(BlockNumber),
+    Child(Child),
+}
+
+/// A filter on the attributes of the entities that an attribute refers
+/// to. For a reference stored with the parent, `attr` is the attribute of
+/// the parent; for a `@derivedFrom` field, `derived` is `true` and `attr`
+/// is the attribute of the child that references the parent. The filter
+/// matches a parent if any of its children match `filter`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Child {
+    pub attr: Attribute,
+    pub entity_type: EntityType,
+    pub filter: Box<EntityFilter>,
+    pub derived: bool,
 }
 
 // Define some convenience methods
@@ -212,6 +270,12 @@ pub enum EntityOrder {
     Ascending(String, ValueType),
     /// Order descending by the given attribute. Use `id` as a tie-breaker
     Descending(String, ValueType),
+    /// Order ascending by an attribute of the entity that the given
+    /// attribute refers to. Use `id` as a tie-breaker
+    ChildAscending(EntityOrderByChild),
+    /// Order descending by an attribute of the entity that the given
+    /// attribute refers to. Use `id` as a tie-breaker
+    ChildDescending(EntityOrderByChild),
     /// Order by the `id` of the entities
     Default,
     /// Do not order at all. This speeds up queries where we know that
@@ -219,6 +283,16 @@ pub enum EntityOrder {
     Unordered,
 }
 
+/// Sort by the attribute `child_attr` of the entity of type `child_type`
+/// that the parent's attribute `attr` refers to
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityOrderByChild {
+    pub attr: Attribute,
+    pub child_type: EntityType,
+    pub child_attr: Attribute,
+    pub value_type: ValueType,
+}
+
 /// How many entities to return, how many to skip etc.
 #[derive(Clone, Debug, PartialEq)]
 pub struct EntityRange {
@@ -227,6 +301,12 @@ pub struct EntityRange {
 
     /// How many entities to skip.
     pub skip: u32,
+
+    /// Only return entities that come after this position in the sort order
+    pub after: Option<EntityCursor>,
+
+    /// Only return entities that come before this position in the sort order
+    pub before: Option<EntityCursor>,
 }
 
 impl EntityRange {
@@ -235,10 +315,38 @@ impl EntityRange {
         Self {
             first: Some(n),
             skip: 0,
+            after: None,
+            before: None,
         }
     }
 }
 
+/// A position in the sort order of a query, given by the value of the
+/// attribute the query is sorted by and the `id` of an entity, which
+/// breaks ties between entities with the same value. Since a cursor does
+/// not depend on how many entities precede it, paging with cursors is not
+/// affected by entities being added or removed between requests
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityCursor {
+    pub value: Value,
+    pub id: String,
+}
+
+/// An aggregate that can be computed over the entities matching a query
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntityAggregate {
+    /// The number of entities
+    Count,
+    /// The sum of the values of the attribute
+    Sum(Attribute),
+    /// The average of the values of the attribute
+    Avg(Attribute),
+    /// The smallest value of the attribute
+    Min(Attribute),
+    /// The largest value of the attribute
+    Max(Attribute),
+}
+
 /// The attribute we want to window by in an `EntityWindow`. We have to
 /// distinguish between scalar and list attributes since we need to use
 /// different queries for them, and the JSONB storage scheme can not
@@ -394,6 +502,10 @@ pub struct EntityQuery {
 
     pub query_id: Option<String>,
 
+    /// Where to record the SQL for this query and its query plan, if the
+    /// query should be explained
+    pub explain: Option<QueryExplain>,
+
     _force_use_of_new: (),
 }
 
@@ -412,6 +524,7 @@ impl EntityQuery {
             range: EntityRange::first(100),
             logger: None,
             query_id: None,
+            explain: None,
             _force_use_of_new: (),
         }
     }
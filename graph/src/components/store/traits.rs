@@ -30,6 +30,18 @@ pub trait EnsLookup: Send + Sync + 'static {
     fn find_name(&self, hash: &str) -> Result<Option<String>, StoreError>;
 }
 
+/// Storage for the text of automatic persisted queries, keyed by the
+/// hex-encoded sha256 hash of the query text
+pub trait PersistedQueryStore: Send + Sync + 'static {
+    /// Find the text of the query with the given `hash`
+    fn find_query(&self, hash: &str) -> Result<Option<String>, StoreError>;
+
+    /// Remember `query` under `hash`. The caller must ensure that `hash`
+    /// is the hash of `query`. Queries are not removed here; a periodic
+    /// job removes the oldest ones when there are too many
+    fn insert_query(&self, hash: &str, query: &str) -> Result<(), StoreError>;
+}
+
 /// An entry point for all operations that require access to the node's storage
 /// layer. It provides access to a [`BlockStore`] and a [`SubgraphStore`].
 pub trait Store: Clone + StatusStore + Send + Sync + 'static {
@@ -49,6 +61,8 @@ pub trait Store: Clone + StatusStore + Send + Sync + 'static {
 pub trait SubgraphStore: Send + Sync + 'static {
     fn ens_lookup(&self) -> Arc<dyn EnsLookup>;
 
+    fn persisted_queries(&self) -> Arc<dyn PersistedQueryStore>;
+
     /// Check if the store is accepting queries for the specified subgraph.
     /// May return true even if the specified subgraph is not currently assigned to an indexing
     /// node, as the store will still accept queries.
@@ -86,8 +100,22 @@ pub trait SubgraphStore: Send + Sync + 'static {
         node_id: &NodeId,
     ) -> Result<(), StoreError>;
 
+    /// Pause indexing the deployment without changing the node it is
+    /// assigned to. Queries against the deployment keep working
+    fn pause_subgraph(&self, deployment: &DeploymentLocator) -> Result<(), StoreError>;
+
+    /// Resume indexing a paused deployment on the node it is assigned to
+    fn resume_subgraph(&self, deployment: &DeploymentLocator) -> Result<(), StoreError>;
+
     fn assigned_node(&self, deployment: &DeploymentLocator) -> Result<Option<NodeId>, StoreError>;
 
+    /// Return the node the deployment is assigned to, and whether the
+    /// deployment is paused, or `None` if the deployment is not assigned
+    fn assignment_status(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<(NodeId, bool)>, StoreError>;
+
     fn assignments(&self, node: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError>;
 
     /// Return `true` if a subgraph `name` exists, regardless of whether the
@@ -104,6 +132,32 @@ pub trait SubgraphStore: Send + Sync + 'static {
         block_number: BlockNumber,
     ) -> Result<Vec<EntityOperation>, StoreError>;
 
+    /// Like `entity_changes_in_block`, but for all blocks from `start` to
+    /// `end`, both inclusive. Blocks without changes are not in the result
+    fn entity_changes_in_block_range(
+        &self,
+        subgraph_id: &DeploymentHash,
+        start: BlockNumber,
+        end: BlockNumber,
+    ) -> Result<BTreeMap<BlockNumber, Vec<EntityOperation>>, StoreError>;
+
+    /// Return the block to which `subgraph_id` was reverted for each revert
+    /// since its `reorg_count` was `reorg_count`. Reverts that happened
+    /// before graph-node started recording them are missing, so that the
+    /// result can have fewer entries than the number of reverts
+    fn reverts_since(
+        &self,
+        subgraph_id: &DeploymentHash,
+        reorg_count: u32,
+    ) -> Result<Vec<BlockNumber>, StoreError>;
+
+    /// Return the current head, reorg count and earliest block of the
+    /// deployment `subgraph_id`
+    async fn deployment_state(
+        &self,
+        subgraph_id: &DeploymentHash,
+    ) -> Result<DeploymentState, StoreError>;
+
     /// Return the GraphQL schema supplied by the user
     fn input_schema(&self, subgraph_id: &DeploymentHash) -> Result<Arc<Schema>, StoreError>;
 
@@ -212,6 +266,12 @@ pub trait WritableStore: Send + Sync + 'static {
         ids_for_type: BTreeMap<&EntityType, Vec<&str>>,
     ) -> Result<BTreeMap<EntityType, Vec<Entity>>, StoreError>;
 
+    /// Look up all entities matching `query` as of the latest block
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError>;
+
     /// The deployment `id` finished syncing, mark it as synced in the database
     /// and promote it to the current version in the subgraphs where it was the
     /// pending version so far
@@ -254,6 +314,9 @@ pub trait QueryStoreManager: Send + Sync + 'static {
         target: QueryTarget,
         for_subscription: bool,
     ) -> Result<Arc<dyn QueryStore + Send + Sync>, QueryExecutionError>;
+
+    /// Whether there is a deployment with id `id`
+    async fn deployment_exists(&self, id: &DeploymentHash) -> Result<bool, QueryExecutionError>;
 }
 
 pub trait BlockStore: Send + Sync + 'static {
@@ -353,6 +416,12 @@ pub trait ChainStore: Send + Sync + 'static {
     /// Find the block with `block_hash` and return the network name and number
     fn block_number(&self, block_hash: H256) -> Result<Option<(String, BlockNumber)>, StoreError>;
 
+    /// Find the latest block on the canonical chain whose timestamp, in
+    /// seconds since the Unix epoch, is at or before `timestamp`. Return
+    /// `None` if there is no such block, or if the block cache has a gap
+    /// between the chain head and that block
+    fn block_ptr_by_timestamp(&self, timestamp: u64) -> Result<Option<BlockPtr>, StoreError>;
+
     /// Tries to retrieve all transactions receipts for a given block.
     async fn transaction_receipts_in_block(
         &self,
@@ -392,12 +461,25 @@ pub trait QueryStore: Send + Sync {
         query: EntityQuery,
     ) -> Result<Vec<BTreeMap<String, r::Value>>, QueryExecutionError>;
 
+    /// Compute `aggregates` over the entities that match the collection
+    /// and filter of `query` at its block; the order and range of `query`
+    /// are ignored. Returns one value for each entry in `aggregates`
+    fn find_query_aggregates(
+        &self,
+        query: EntityQuery,
+        aggregates: &[EntityAggregate],
+    ) -> Result<Vec<Value>, QueryExecutionError>;
+
     async fn is_deployment_synced(&self) -> Result<bool, Error>;
 
     async fn block_ptr(&self) -> Result<Option<BlockPtr>, StoreError>;
 
     fn block_number(&self, block_hash: H256) -> Result<Option<BlockNumber>, StoreError>;
 
+    /// Find the latest block on the deployment's chain whose timestamp, in
+    /// seconds since the Unix epoch, is at or before `timestamp`
+    fn block_ptr_by_timestamp(&self, timestamp: u64) -> Result<Option<BlockPtr>, StoreError>;
+
     fn wait_stats(&self) -> PoolWaitStats;
 
     /// If `block` is `None`, assumes the latest block.
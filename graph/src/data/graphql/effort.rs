@@ -3,6 +3,7 @@
 use prometheus::core::GenericCounter;
 use rand::{prelude::Rng, thread_rng};
 use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
 use std::iter::FromIterator;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
@@ -15,54 +16,57 @@ use crate::prelude::q;
 use crate::prelude::{async_trait, debug, info, o, warn, Logger, QueryLoadManager, ENV_VARS};
 use crate::util::stats::MovingStats;
 
-struct QueryEffort {
-    inner: Arc<RwLock<QueryEffortInner>>,
+struct QueryEffort<K = u64> {
+    inner: Arc<RwLock<QueryEffortInner<K>>>,
 }
 
-/// Track the effort for queries (identified by their ShapeHash) over a
-/// time window.
-struct QueryEffortInner {
+/// Track the effort for queries over a time window. Queries are grouped by
+/// a key, usually their ShapeHash, though they can also be grouped by the
+/// API key with which they were sent
+struct QueryEffortInner<K> {
     window_size: Duration,
     bin_size: Duration,
-    effort: HashMap<u64, MovingStats>,
+    effort: HashMap<K, MovingStats>,
     total: MovingStats,
 }
 
 /// Create a `QueryEffort` that uses the window and bin sizes configured in
 /// the environment
-impl Default for QueryEffort {
+impl<K: Eq + Hash> Default for QueryEffort<K> {
     fn default() -> Self {
         Self::new(ENV_VARS.load_window_size, ENV_VARS.load_bin_size)
     }
 }
 
-impl QueryEffort {
+impl<K: Eq + Hash> QueryEffort<K> {
     pub fn new(window_size: Duration, bin_size: Duration) -> Self {
         Self {
             inner: Arc::new(RwLock::new(QueryEffortInner::new(window_size, bin_size))),
         }
     }
 
-    pub fn add(&self, shape_hash: u64, duration: Duration, gauge: &Gauge) {
+    /// Add `duration` to the effort for `key` and return the average
+    /// total effort
+    pub fn add(&self, key: K, duration: Duration) -> Duration {
         let mut inner = self.inner.write().unwrap();
-        inner.add(shape_hash, duration);
-        gauge.set(inner.total.average().unwrap_or(Duration::ZERO).as_millis() as f64);
+        inner.add(key, duration);
+        inner.total.average().unwrap_or(Duration::ZERO)
     }
 
     /// Return what we know right now about the effort for the query
-    /// `shape_hash`, and about the total effort. If we have no measurements
+    /// `key`, and about the total effort. If we have no measurements
     /// at all, return `ZERO_DURATION` as the total effort. If we have no
     /// data for the particular query, return `None` as the effort
     /// for the query
-    pub fn current_effort(&self, shape_hash: u64) -> (Option<Duration>, Duration) {
+    pub fn current_effort(&self, key: &K) -> (Option<Duration>, Duration) {
         let inner = self.inner.read().unwrap();
         let total_effort = inner.total.duration();
-        let query_effort = inner.effort.get(&shape_hash).map(|stats| stats.duration());
+        let query_effort = inner.effort.get(key).map(|stats| stats.duration());
         (query_effort, total_effort)
     }
 }
 
-impl QueryEffortInner {
+impl<K: Eq + Hash> QueryEffortInner<K> {
     fn new(window_size: Duration, bin_size: Duration) -> Self {
         Self {
             window_size,
@@ -72,12 +76,12 @@ impl QueryEffortInner {
         }
     }
 
-    fn add(&mut self, shape_hash: u64, duration: Duration) {
+    fn add(&mut self, key: K, duration: Duration) {
         let window_size = self.window_size;
         let bin_size = self.bin_size;
         let now = Instant::now();
         self.effort
-            .entry(shape_hash)
+            .entry(key)
             .or_insert_with(|| MovingStats::new(window_size, bin_size))
             .add_at(now, duration);
         self.total.add_at(now, duration);
@@ -197,6 +201,12 @@ pub struct LoadManager {
     /// there is no way for a query to get out of jail other than
     /// restarting the process
     jailed_queries: RwLock<HashSet<u64>>,
+    /// The effort caused by the queries sent with each API key
+    key_effort: QueryEffort<String>,
+    /// Names of API keys that have caused more than `JAIL_THRESHOLD`
+    /// proportion of the work while the system was overloaded. Unlike
+    /// queries, keys are released from jail once the overload is resolved
+    jailed_keys: RwLock<HashSet<String>>,
     kill_state: RwLock<KillState>,
     effort_gauge: Box<Gauge>,
     query_counters: HashMap<CacheStatus, Counter>,
@@ -257,6 +267,8 @@ impl LoadManager {
             effort: QueryEffort::default(),
             blocked_queries,
             jailed_queries: RwLock::new(HashSet::new()),
+            key_effort: QueryEffort::default(),
+            jailed_keys: RwLock::new(HashSet::new()),
             kill_state: RwLock::new(KillState::new()),
             effort_gauge,
             query_counters,
@@ -272,7 +284,16 @@ impl LoadManager {
             .get(&cache_status)
             .map(GenericCounter::inc);
         if !ENV_VARS.load_management_is_disabled() {
-            self.effort.add(shape_hash, duration, &self.effort_gauge);
+            let total = self.effort.add(shape_hash, duration);
+            self.effort_gauge.set(total.as_millis() as f64);
+        }
+    }
+
+    /// Record that we spent `duration` amount of work for a query sent
+    /// with the API key `api_key`
+    pub fn record_key_work(&self, api_key: &str, duration: Duration) {
+        if !ENV_VARS.load_management_is_disabled() {
+            self.key_effort.add(api_key.to_string(), duration);
         }
     }
 
@@ -311,7 +332,13 @@ impl LoadManager {
     /// 2) If a query, during an overload situation, causes more than
     ///    `JAIL_THRESHOLD` fraction of the total query effort, we will
     ///    refuse to run this query again for the lifetime of the process
-    /// 3) During an overload situation, we step a `kill_rate` from 0 to 1,
+    /// 3) If the query was sent with an API key, and that key, during an
+    ///    overload situation, causes more than `JAIL_THRESHOLD` fraction of
+    ///    the total query effort, we will refuse to run queries with that
+    ///    key until the overload is resolved. Keys are checked before
+    ///    query shapes, so that a key that is abusive gets jailed before
+    ///    a query shape that well-behaved keys also send
+    /// 4) During an overload situation, we step a `kill_rate` from 0 to 1,
     ///    roughly in steps of `KILL_RATE_STEP`, though with an eye towards
     ///    not hitting a `kill_rate` of 1 too soon. We will decline to run
     ///    queries randomly with a probability of
@@ -322,7 +349,13 @@ impl LoadManager {
     /// case, we also do not take any locks when asked to update statistics,
     /// or to check whether we are overloaded; these operations amount to
     /// noops.
-    pub fn decide(&self, wait_stats: &PoolWaitStats, shape_hash: u64, query: &str) -> Decision {
+    pub fn decide(
+        &self,
+        wait_stats: &PoolWaitStats,
+        shape_hash: u64,
+        api_key: Option<&str>,
+        query: &str,
+    ) -> Decision {
         use Decision::*;
 
         if self.blocked_queries.contains(&shape_hash) {
@@ -332,6 +365,16 @@ impl LoadManager {
             return Proceed;
         }
 
+        if let Some(api_key) = api_key {
+            if self.jailed_keys.read().unwrap().contains(api_key) {
+                return if ENV_VARS.load_simulate {
+                    Proceed
+                } else {
+                    TooExpensive
+                };
+            }
+        }
+
         if self.jailed_queries.read().unwrap().contains(&shape_hash) {
             return if ENV_VARS.load_simulate {
                 Proceed
@@ -346,7 +389,7 @@ impl LoadManager {
             return Proceed;
         }
 
-        let (query_effort, total_effort) = self.effort.current_effort(shape_hash);
+        let (query_effort, total_effort) = self.effort.current_effort(&shape_hash);
         // When `total_effort` is `Duratino::ZERO`, we haven't done any work. All are
         // welcome
         if total_effort.is_zero() {
@@ -363,6 +406,32 @@ impl LoadManager {
 
         // When this variable is not set, we never jail any queries.
         if let Some(jail_threshold) = ENV_VARS.load_jail_threshold {
+            let key_effort = api_key.and_then(|api_key| {
+                self.key_effort
+                    .current_effort(&api_key.to_string())
+                    .0
+                    .map(|effort| (api_key, effort.as_millis() as f64))
+            });
+            if let Some((api_key, key_effort)) = key_effort {
+                if key_effort / total_effort > jail_threshold {
+                    warn!(self.logger, "Jailing API key";
+                    "api_key" => api_key,
+                    "wait_ms" => wait_ms.as_millis(),
+                    "key_effort_ms" => key_effort,
+                    "total_effort_ms" => total_effort,
+                    "ratio" => format!("{:.4}", key_effort/total_effort));
+                    self.jailed_keys
+                        .write()
+                        .unwrap()
+                        .insert(api_key.to_string());
+                    return if ENV_VARS.load_simulate {
+                        Proceed
+                    } else {
+                        TooExpensive
+                    };
+                }
+            }
+
             if known_query && query_effort / total_effort > jail_threshold {
                 // Any single query that causes at least JAIL_THRESHOLD of the
                 // effort in an overload situation gets killed
@@ -470,6 +539,7 @@ impl LoadManager {
                         "duration_ms" => duration.as_millis(),
                         "wait_ms" => wait_ms.as_millis(),
                         "event" => "resolved");
+                    self.jailed_keys.write().unwrap().clear();
                 }
                 Ongoing(duration) => {
                     info!(self.logger, "Query overload still happening";
@@ -498,7 +568,8 @@ impl QueryLoadManager for LoadManager {
             .get(&cache_status)
             .map(|counter| counter.inc());
         if !ENV_VARS.load_management_is_disabled() {
-            self.effort.add(shape_hash, duration, &self.effort_gauge);
+            let total = self.effort.add(shape_hash, duration);
+            self.effort_gauge.set(total.as_millis() as f64);
         }
     }
 }
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::components::server::query::GraphQLServerError;
+use crate::data::query::{QueryExecutionError, QueryTarget};
+
+/// A limit on how quickly something can be used: on average `rate` units
+/// per second, with bursts of up to `burst` units
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: f64,
+}
+
+/// A token bucket that enforces a `RateLimit`
+#[derive(Debug)]
+struct TokenBucket {
+    limit: RateLimit,
+    /// The tokens that were available at the given time
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        TokenBucket {
+            limit,
+            state: Mutex::new((limit.burst, Instant::now())),
+        }
+    }
+
+    /// Take `amount` tokens from the bucket if there are enough. Amounts
+    /// that are bigger than the whole bucket can be taken when the bucket
+    /// is full; the bucket then has to refill for longer before it can be
+    /// used again
+    fn take_at(&self, now: Instant, amount: f64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, last) = *state;
+        let elapsed = now.saturating_duration_since(last).as_secs_f64();
+        let tokens = (tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        if tokens >= amount.min(self.limit.burst) {
+            *state = (tokens - amount, now);
+            true
+        } else {
+            *state = (tokens, now);
+            false
+        }
+    }
+
+    fn take(&self, amount: f64) -> bool {
+        self.take_at(Instant::now(), amount)
+    }
+}
+
+/// An API key that clients use to identify themselves when they send
+/// queries. Each key can be restricted to a list of subgraphs and can have
+/// its own limits on how many queries it can send and how complex these
+/// queries can be
+#[derive(Debug)]
+pub struct ApiKey {
+    name: String,
+    /// The subgraph names and deployment ids that this key can query. If
+    /// the list is empty, the key can query any subgraph
+    subgraphs: Vec<String>,
+    max_complexity: Option<u64>,
+    queries: Option<TokenBucket>,
+    complexity: Option<TokenBucket>,
+    /// Whether the key can be used for diagnostics that are not meant for
+    /// ordinary clients, like explaining how queries are executed
+    admin: bool,
+}
+
+impl ApiKey {
+    pub fn new(
+        name: String,
+        subgraphs: Vec<String>,
+        max_complexity: Option<u64>,
+        queries: Option<RateLimit>,
+        complexity: Option<RateLimit>,
+        admin: bool,
+    ) -> Self {
+        ApiKey {
+            name,
+            subgraphs,
+            max_complexity,
+            queries: queries.map(TokenBucket::new),
+            complexity: complexity.map(TokenBucket::new),
+            admin,
+        }
+    }
+
+    /// The name of the key. Unlike the key itself, the name is not secret
+    /// and can be logged
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The maximum complexity of each query sent with this key
+    pub fn max_complexity(&self) -> Option<u64> {
+        self.max_complexity
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    /// Whether this key can query `target`. Subgraphs are matched by the
+    /// name or deployment id that the client used to address them
+    pub fn allows(&self, target: &QueryTarget) -> bool {
+        if self.subgraphs.is_empty() {
+            return true;
+        }
+        let target = match target {
+            QueryTarget::Name(name) => name.as_str(),
+            QueryTarget::Deployment(id) => id.as_str(),
+        };
+        self.subgraphs.iter().any(|subgraph| subgraph == target)
+    }
+
+    /// Account for `count` queries sent with this key, and fail if the key
+    /// exceeded its rate limit
+    pub fn check_rate(&self, count: usize) -> Result<(), GraphQLServerError> {
+        match &self.queries {
+            Some(bucket) if !bucket.take(count as f64) => {
+                Err(GraphQLServerError::TooManyRequests(format!(
+                    "API key `{}` exceeded its limit of {} queries per second",
+                    self.name, bucket.limit.rate
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Account for running a query with the given `complexity`, and fail
+    /// if that exceeds the complexity budget of this key
+    pub fn charge_complexity(&self, complexity: u64) -> Result<(), QueryExecutionError> {
+        match &self.complexity {
+            Some(bucket) if !bucket.take(complexity as f64) => Err(
+                QueryExecutionError::ComplexityBudgetExceeded(self.name.clone()),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The API keys that a query server accepts
+#[derive(Debug, Default)]
+pub struct ApiKeys {
+    /// Whether queries must carry a valid API key
+    required: bool,
+    keys: HashMap<String, Arc<ApiKey>>,
+}
+
+impl ApiKeys {
+    /// Accept the keys in `keys`, which maps the secret key to its
+    /// settings. When `required` is `true`, queries without a valid key
+    /// are rejected; otherwise, keys only serve to give clients their own
+    /// limits
+    pub fn new(required: bool, keys: Vec<(String, ApiKey)>) -> Self {
+        let keys = keys
+            .into_iter()
+            .map(|(key, api_key)| (key, Arc::new(api_key)))
+            .collect();
+        ApiKeys { required, keys }
+    }
+
+    /// Find the API key for `token` and check that it can query `target`.
+    /// Returns `None` if the client did not send a key and keys are not
+    /// required
+    pub fn authenticate(
+        &self,
+        token: Option<&str>,
+        target: &QueryTarget,
+    ) -> Result<Option<Arc<ApiKey>>, GraphQLServerError> {
+        let token = match token {
+            Some(token) => token,
+            None if self.required => {
+                return Err(GraphQLServerError::Unauthorized(
+                    "an API key is required".to_string(),
+                ))
+            }
+            None => return Ok(None),
+        };
+        let key = self
+            .keys
+            .get(token)
+            .ok_or_else(|| GraphQLServerError::Unauthorized("invalid API key".to_string()))?;
+        if !key.allows(target) {
+            return Err(GraphQLServerError::Unauthorized(format!(
+                "API key `{}` can not query this subgraph",
+                key.name
+            )));
+        }
+        Ok(Some(key.clone()))
+    }
+
+    /// Extract the token from the value of an `Authorization: Bearer
+    /// <token>` header
+    pub fn bearer_token(authorization: &str) -> Option<&str> {
+        authorization.strip_prefix("Bearer ").map(str::trim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::prelude::{DeploymentHash, SubgraphName};
+
+    use super::*;
+
+    #[test]
+    fn token_bucket_refills() {
+        let bucket = TokenBucket::new(RateLimit {
+            rate: 2.0,
+            burst: 4.0,
+        });
+        let start = bucket.state.lock().unwrap().1;
+
+        for _ in 0..4 {
+            assert!(bucket.take_at(start, 1.0));
+        }
+        assert!(!bucket.take_at(start, 1.0));
+        assert!(bucket.take_at(start + Duration::from_millis(500), 1.0));
+        assert!(!bucket.take_at(start + Duration::from_millis(500), 1.0));
+
+        // A full bucket allows taking more than its size, but then has to
+        // refill for longer
+        assert!(bucket.take_at(start + Duration::from_secs(10), 6.0));
+        assert!(!bucket.take_at(start + Duration::from_secs(11), 1.0));
+        assert!(bucket.take_at(start + Duration::from_millis(11500), 1.0));
+    }
+
+    #[test]
+    fn authenticates_keys() {
+        let name = QueryTarget::Name(SubgraphName::new("org/subgraph").unwrap());
+        let other = QueryTarget::Deployment(DeploymentHash::new("QmOther").unwrap());
+        let scoped = ApiKey::new(
+            "scoped".to_string(),
+            vec!["org/subgraph".to_string()],
+            None,
+            None,
+            None,
+            false,
+        );
+        let open = ApiKey::new("open".to_string(), vec![], None, None, None, false);
+        let keys = vec![
+            ("secret1".to_string(), scoped),
+            ("secret2".to_string(), open),
+        ];
+
+        let optional = ApiKeys::new(false, keys);
+        assert!(optional.authenticate(None, &name).unwrap().is_none());
+        assert!(optional.authenticate(Some("nope"), &name).is_err());
+        assert_eq!(
+            "scoped",
+            optional
+                .authenticate(Some("secret1"), &name)
+                .unwrap()
+                .unwrap()
+                .name()
+        );
+        assert!(optional.authenticate(Some("secret1"), &other).is_err());
+        assert!(optional.authenticate(Some("secret2"), &other).is_ok());
+
+        let required = ApiKeys::new(true, vec![]);
+        assert!(required.authenticate(None, &name).is_err());
+    }
+}
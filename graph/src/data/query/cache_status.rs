@@ -1,8 +1,9 @@
 use std::fmt;
 use std::slice::Iter;
 
-/// Used for checking if a response hit the cache.
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+/// Used for checking if a response hit the cache. The statuses are ordered
+/// from the most to the least cached.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum CacheStatus {
     /// Hit is a hit in the generational cache.
     Hit,
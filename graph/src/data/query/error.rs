@@ -9,8 +9,9 @@ use std::string::FromUtf8Error;
 use std::sync::Arc;
 
 use crate::data::graphql::SerializableValue;
+use crate::data::store::scalar::TimestampError;
 use crate::data::subgraph::*;
-use crate::prelude::q;
+use crate::prelude::{q, BlockNumber};
 use crate::{components::store::StoreError, prelude::CacheWeight};
 
 #[derive(Debug)]
@@ -54,6 +55,7 @@ pub enum QueryExecutionError {
     SubgraphDeploymentIdError(String),
     RangeArgumentsError(&'static str, u32, i64),
     InvalidFilterError,
+    InvalidCursorError(String),
     EntityFieldError(String, String),
     ListTypesError(String, Vec<String>),
     ListFilterError(String),
@@ -83,6 +85,9 @@ pub enum QueryExecutionError {
     SubgraphManifestResolveError(Arc<SubgraphManifestResolveError>),
     InvalidSubgraphManifest,
     ResultTooBig(usize, usize),
+    BlockPruned(BlockNumber, BlockNumber), // (block, earliest_block)
+    PersistedQueryNotFound,
+    ComplexityBudgetExceeded(String), // name of the API key
 }
 
 impl QueryExecutionError {
@@ -108,6 +113,7 @@ impl QueryExecutionError {
             | MultipleSubscriptionFields
             | SubgraphDeploymentIdError(_)
             | InvalidFilterError
+            | InvalidCursorError(_)
             | EntityFieldError(_, _)
             | ListTypesError(_, _)
             | ListFilterError(_)
@@ -139,7 +145,10 @@ impl QueryExecutionError {
             | SubgraphManifestResolveError(_)
             | InvalidSubgraphManifest
             | ValidationError(_, _)
-            | ResultTooBig(_, _) => false,
+            | ResultTooBig(_, _)
+            | BlockPruned(_, _)
+            | PersistedQueryNotFound
+            | ComplexityBudgetExceeded(_) => false,
         }
     }
 }
@@ -221,6 +230,7 @@ impl fmt::Display for QueryExecutionError {
                 write!(f, "The `{}` argument must be between 0 and {}, but is {}", arg, max, actual)
             }
             InvalidFilterError => write!(f, "Filter must by an object"),
+            InvalidCursorError(cursor) => write!(f, "Invalid cursor `{}`", cursor),
             EntityFieldError(e, a) => {
                 write!(f, "Entity `{}` has no attribute `{}`", e, a)
             }
@@ -287,6 +297,11 @@ impl fmt::Display for QueryExecutionError {
             SubgraphManifestResolveError(e) => write!(f, "failed to resolve subgraph manifest: {}", e),
             InvalidSubgraphManifest => write!(f, "invalid subgraph manifest file"),
             ResultTooBig(actual, limit) => write!(f, "the result size of {} is larger than the allowed limit of {}", actual, limit),
+            BlockPruned(block, earliest) => write!(f, "the history for block {} has been pruned and is no longer available; the earliest block that can be queried is block {}", block, earliest),
+            // Clients that support automatic persisted queries look for
+            // exactly this message
+            PersistedQueryNotFound => write!(f, "PersistedQueryNotFound"),
+            ComplexityBudgetExceeded(key) => write!(f, "API key `{}` exceeded its complexity budget; please try again later", key),
         }
     }
 }
@@ -315,6 +330,12 @@ impl From<bigdecimal::ParseBigDecimalError> for QueryExecutionError {
     }
 }
 
+impl From<TimestampError> for QueryExecutionError {
+    fn from(e: TimestampError) -> Self {
+        QueryExecutionError::ValueParseError("Timestamp".to_string(), e.to_string())
+    }
+}
+
 impl From<StoreError> for QueryExecutionError {
     fn from(e: StoreError) -> Self {
         QueryExecutionError::StoreError(CloneableAnyhowError(Arc::new(e.into())))
@@ -463,6 +484,12 @@ impl Serialize for QueryError {
                 map.serialize_entry("prefetch", &SerializableValue(prefetch))?;
                 format!("{}", self)
             }
+            QueryError::ExecutionError(PersistedQueryNotFound) => {
+                let mut extensions = HashMap::new();
+                extensions.insert("code", "PERSISTED_QUERY_NOT_FOUND");
+                map.serialize_entry("extensions", &extensions)?;
+                format!("{}", self)
+            }
             _ => format!("{}", self),
         };
 
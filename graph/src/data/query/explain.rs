@@ -0,0 +1,89 @@
+use serde::ser::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::cheap_clone::CheapClone;
+
+/// A SQL query that was run to resolve a field of a GraphQL query, and how
+/// Postgres executed it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SqlExplain {
+    /// The field of the GraphQL query, in the form `<type>.<response key>`
+    pub field: String,
+    /// The text of the SQL query with placeholders for bind parameters
+    pub sql: String,
+    /// The values of the bind parameters
+    pub binds: String,
+    /// The output of `explain (analyze, buffers)` for the query, one entry
+    /// per line
+    pub plan: Vec<String>,
+    /// How long the query took to run, not counting running `explain`
+    pub time: Duration,
+    /// How many rows the query returned
+    pub entity_count: usize,
+}
+
+impl Serialize for SqlExplain {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SqlExplain", 6)?;
+        state.serialize_field("field", &self.field)?;
+        state.serialize_field("sql", &self.sql)?;
+        state.serialize_field("binds", &self.binds)?;
+        state.serialize_field("plan", &self.plan)?;
+        state.serialize_field("timeMs", &(self.time.as_micros() as f64 / 1000.0))?;
+        state.serialize_field("entityCount", &self.entity_count)?;
+        state.end()
+    }
+}
+
+/// Collects the SQL queries that are run for a GraphQL query together
+/// with their query plans. Passing a `QueryExplain` to the store makes it
+/// run every query a second time with `explain (analyze, buffers)`, which
+/// is expensive; it should therefore only be used to diagnose slow queries
+#[derive(Clone, Debug, Default)]
+pub struct QueryExplain {
+    field: String,
+    entries: Arc<Mutex<Vec<SqlExplain>>>,
+}
+
+impl CheapClone for QueryExplain {}
+
+impl QueryExplain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A `QueryExplain` that adds the queries for `field` to the same list
+    /// as `self`
+    pub fn for_field(&self, field: String) -> Self {
+        QueryExplain {
+            field,
+            entries: self.entries.cheap_clone(),
+        }
+    }
+
+    /// Record that the query `sql` was run with bind parameters `binds`
+    pub fn record(
+        &self,
+        sql: String,
+        binds: String,
+        plan: Vec<String>,
+        time: Duration,
+        entity_count: usize,
+    ) {
+        self.entries.lock().unwrap().push(SqlExplain {
+            field: self.field.clone(),
+            sql,
+            binds,
+            plan,
+            time,
+            entity_count,
+        })
+    }
+
+    /// Remove all recorded queries and return them in the order in which
+    /// they were run
+    pub fn take(&self) -> Vec<SqlExplain> {
+        std::mem::take(&mut *self.entries.lock().unwrap())
+    }
+}
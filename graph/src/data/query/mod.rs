@@ -1,9 +1,13 @@
+mod api_key;
 mod cache_status;
 mod error;
+mod explain;
 mod query;
 mod result;
 
+pub use self::api_key::{ApiKey, ApiKeys, RateLimit};
 pub use self::cache_status::CacheStatus;
 pub use self::error::{QueryError, QueryExecutionError};
+pub use self::explain::{QueryExplain, SqlExplain};
 pub use self::query::{Query, QueryTarget, QueryVariables};
-pub use self::result::{QueryResult, QueryResults};
+pub use self::result::{QueryCost, QueryResult, QueryResults};
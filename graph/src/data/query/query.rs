@@ -5,6 +5,7 @@ use std::convert::TryFrom;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 
+use super::ApiKey;
 use crate::{
     data::graphql::shape_hash::shape_hash,
     prelude::{q, r, DeploymentHash, SubgraphName, ENV_VARS},
@@ -136,6 +137,17 @@ pub struct Query {
     pub shape_hash: u64,
     pub query_text: Arc<String>,
     pub variables_text: Arc<String>,
+    /// The API key the client sent the query with, if any
+    pub api_key: Option<Arc<ApiKey>>,
+    /// Whether to report the SQL queries that are run for this query
+    /// together with their query plans
+    pub explain: bool,
+    /// Whether to report what it cost to execute this query in
+    /// `extensions.cost`
+    pub cost: bool,
+    /// The `operationName` the client sent, which picks the operation to
+    /// run from a document with several operations
+    pub operation_name: Option<String>,
     _force_use_of_new: (),
 }
 
@@ -160,6 +172,10 @@ impl Query {
             shape_hash,
             query_text: Arc::new(query_text),
             variables_text: Arc::new(variables_text),
+            api_key: None,
+            explain: false,
+            cost: false,
+            operation_name: None,
             _force_use_of_new: (),
         }
     }
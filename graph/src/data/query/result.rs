@@ -1,6 +1,8 @@
 use super::error::{QueryError, QueryExecutionError};
+use super::explain::SqlExplain;
+use super::CacheStatus;
 use crate::data::value::Object;
-use crate::prelude::{r, CacheWeight, DeploymentHash};
+use crate::prelude::{r, BlockNumber, CacheWeight, DeploymentHash};
 use http::header::{
     ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
     CONTENT_TYPE,
@@ -9,6 +11,7 @@ use serde::ser::*;
 use serde::Serialize;
 use std::convert::TryFrom;
 use std::sync::Arc;
+use std::time::Duration;
 
 fn serialize_data<S>(data: &Option<Data>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -41,22 +44,100 @@ where
 
 pub type Data = Object;
 
+/// What it cost to execute a query. It is sent to clients as
+/// `extensions.cost` in the response
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryCost {
+    /// The complexity of the query as computed when the query was validated
+    pub complexity: u64,
+    /// The block at which the query was executed. If parts of the query
+    /// were executed at different blocks, the highest of them
+    pub block: BlockNumber,
+    /// How the query used the query result cache. If parts of the query
+    /// were executed at different blocks, the least cached of them
+    pub cache_status: CacheStatus,
+    /// The time spent running SQL queries
+    pub sql_time: Duration,
+}
+
+impl QueryCost {
+    pub fn new(complexity: u64) -> Self {
+        QueryCost {
+            complexity,
+            block: 0,
+            cache_status: CacheStatus::Hit,
+            sql_time: Duration::from_secs(0),
+        }
+    }
+
+    /// Account for executing the part of the query for one block
+    pub fn add(&mut self, block: BlockNumber, cache_status: CacheStatus, sql_time: Duration) {
+        self.block = self.block.max(block);
+        self.cache_status = self.cache_status.max(cache_status);
+        self.sql_time += sql_time;
+    }
+}
+
+impl Serialize for QueryCost {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("QueryCost", 4)?;
+        state.serialize_field("complexity", &self.complexity)?;
+        state.serialize_field("block", &self.block)?;
+        state.serialize_field("cacheStatus", &self.cache_status.to_string())?;
+        state.serialize_field("sqlTimeMs", &(self.sql_time.as_micros() as f64 / 1000.0))?;
+        state.end()
+    }
+}
+
 #[derive(Debug)]
 /// A collection of query results that is serialized as a single result.
 pub struct QueryResults {
     results: Vec<Arc<QueryResult>>,
+    cost: Option<QueryCost>,
+    explain: Option<Vec<SqlExplain>>,
 }
 
 impl QueryResults {
     pub fn empty() -> Self {
         QueryResults {
             results: Vec::new(),
+            cost: None,
+            explain: None,
         }
     }
 
     pub fn first(&self) -> Option<&Arc<QueryResult>> {
         self.results.first()
     }
+
+    pub fn cost(&self) -> Option<&QueryCost> {
+        self.cost.as_ref()
+    }
+
+    pub fn set_cost(&mut self, cost: QueryCost) {
+        self.cost = Some(cost);
+    }
+
+    pub fn explain(&self) -> Option<&[SqlExplain]> {
+        self.explain.as_deref()
+    }
+
+    pub fn set_explain(&mut self, explain: Vec<SqlExplain>) {
+        self.explain = Some(explain);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.results.iter().any(|result| result.has_errors())
+    }
+
+    /// Whether the response for these results can be attested. Cost and
+    /// explain output contain timings that differ between indexers, and
+    /// responses that include them are therefore never attestable
+    fn is_attestable(&self) -> bool {
+        self.cost.is_none()
+            && self.explain.is_none()
+            && self.results.iter().all(|r| r.is_attestable())
+    }
 }
 
 impl Serialize for QueryResults {
@@ -70,6 +151,9 @@ impl Serialize for QueryResults {
         if has_errors {
             len += 1;
         }
+        if self.cost.is_some() || self.explain.is_some() {
+            len += 1;
+        }
 
         let mut state = serializer.serialize_struct("QueryResults", len)?;
 
@@ -106,6 +190,26 @@ impl Serialize for QueryResults {
             state.serialize_field("errors", &SerError(self))?;
         }
 
+        // Serialize extensions.
+        if self.cost.is_some() || self.explain.is_some() {
+            struct SerExtensions<'a>(&'a QueryResults);
+
+            impl Serialize for SerExtensions<'_> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    let mut map = serializer.serialize_map(None)?;
+                    if let Some(cost) = &self.0.cost {
+                        map.serialize_entry("cost", cost)?;
+                    }
+                    if let Some(explain) = &self.0.explain {
+                        map.serialize_entry("explain", explain)?;
+                    }
+                    map.end()
+                }
+            }
+
+            state.serialize_field("extensions", &SerExtensions(self))?;
+        }
+
         state.end()
     }
 }
@@ -114,6 +218,8 @@ impl From<Data> for QueryResults {
     fn from(x: Data) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            cost: None,
+            explain: None,
         }
     }
 }
@@ -122,13 +228,19 @@ impl From<QueryResult> for QueryResults {
     fn from(x: QueryResult) -> Self {
         QueryResults {
             results: vec![Arc::new(x)],
+            cost: None,
+            explain: None,
         }
     }
 }
 
 impl From<Arc<QueryResult>> for QueryResults {
     fn from(x: Arc<QueryResult>) -> Self {
-        QueryResults { results: vec![x] }
+        QueryResults {
+            results: vec![x],
+            cost: None,
+            explain: None,
+        }
     }
 }
 
@@ -136,6 +248,8 @@ impl From<QueryExecutionError> for QueryResults {
     fn from(x: QueryExecutionError) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            cost: None,
+            explain: None,
         }
     }
 }
@@ -144,6 +258,8 @@ impl From<Vec<QueryExecutionError>> for QueryResults {
     fn from(x: Vec<QueryExecutionError>) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            cost: None,
+            explain: None,
         }
     }
 }
@@ -153,22 +269,64 @@ impl QueryResults {
         self.results.push(other);
     }
 
+    /// Combine the data and errors of all results into one result, for
+    /// example to embed them in the result of a bigger query
+    pub fn into_result(self) -> QueryResult {
+        let mut combined = QueryResult {
+            data: None,
+            errors: Vec::new(),
+            deployment: None,
+        };
+        for result in self.results {
+            // Results can be shared with the query cache
+            let (data, errors) = match Arc::try_unwrap(result) {
+                Ok(result) => (result.data, result.errors),
+                Err(result) => (result.data.clone(), result.errors.clone()),
+            };
+            if let Some(data) = data {
+                combined.data.get_or_insert_with(Object::new).extend(data);
+            }
+            combined.errors.extend(errors);
+        }
+        combined
+    }
+
     pub fn as_http_response<T: From<String>>(&self) -> http::Response<T> {
-        let status_code = http::StatusCode::OK;
         let json =
             serde_json::to_string(self).expect("Failed to serialize GraphQL response to JSON");
+        self.http_response_builder().body(T::from(json)).unwrap()
+    }
+
+    /// The response to a batch of queries, which is a list of the
+    /// results of each query
+    pub fn batch_as_http_response<T: From<String>>(batch: &[QueryResults]) -> http::Response<T> {
+        let json =
+            serde_json::to_string(batch).expect("Failed to serialize GraphQL response to JSON");
+        Self::batch_http_response_builder(batch)
+            .body(T::from(json))
+            .unwrap()
+    }
+
+    /// The status and headers of the response for these results. This is
+    /// useful for callers that want to produce the body themselves
+    pub fn http_response_builder(&self) -> http::response::Builder {
+        Self::response_builder(self.is_attestable())
+    }
+
+    /// The status and headers of the response for a batch of queries
+    pub fn batch_http_response_builder(batch: &[QueryResults]) -> http::response::Builder {
+        Self::response_builder(batch.iter().all(|r| r.is_attestable()))
+    }
+
+    fn response_builder(attestable: bool) -> http::response::Builder {
+        let status_code = http::StatusCode::OK;
         http::Response::builder()
             .status(status_code)
             .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
             .header(ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, User-Agent")
             .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, OPTIONS, POST")
             .header(CONTENT_TYPE, "application/json")
-            .header(
-                "Graph-Attestable",
-                self.results.iter().all(|r| r.is_attestable()).to_string(),
-            )
-            .body(T::from(json))
-            .unwrap()
+            .header("Graph-Attestable", attestable.to_string())
     }
 }
 
@@ -326,3 +484,70 @@ fn multiple_data_items() {
     let actual = serde_json::to_string(&res).unwrap();
     assert_eq!(expected, actual)
 }
+
+#[test]
+fn cost_in_extensions() {
+    use serde_json::json;
+
+    let mut map = Object::new();
+    map.insert("key".to_owned(), r::Value::String("value".to_owned()));
+    let mut res = QueryResults::from(map);
+
+    let mut cost = QueryCost::new(17);
+    cost.add(3, CacheStatus::Hit, Duration::from_millis(2));
+    cost.add(5, CacheStatus::Insert, Duration::from_millis(3));
+    res.set_cost(cost);
+
+    let expected = serde_json::to_string(&json!({
+        "data": { "key": "value" },
+        "extensions": {
+            "cost": {
+                "complexity": 17,
+                "block": 5,
+                "cacheStatus": "insert",
+                "sqlTimeMs": 5.0
+            }
+        }
+    }))
+    .unwrap();
+    let actual = serde_json::to_string(&res).unwrap();
+    assert_eq!(expected, actual);
+
+    // Responses that report the cost are not attestable
+    let response = res.as_http_response::<String>();
+    assert_eq!("false", response.headers()["Graph-Attestable"]);
+}
+
+#[test]
+fn explain_in_extensions() {
+    use super::QueryExplain;
+    use serde_json::json;
+
+    let explain = QueryExplain::new();
+    explain.for_field("Query.users".to_owned()).record(
+        "select 1".to_owned(),
+        "[1]".to_owned(),
+        vec!["Result".to_owned()],
+        Duration::from_millis(2),
+        1,
+    );
+    let mut res = QueryResults::empty();
+    res.set_explain(explain.take());
+    assert!(explain.take().is_empty());
+
+    let expected = serde_json::to_string(&json!({
+        "extensions": {
+            "explain": [{
+                "field": "Query.users",
+                "sql": "select 1",
+                "binds": "[1]",
+                "plan": ["Result"],
+                "timeMs": 2.0,
+                "entityCount": 1
+            }]
+        }
+    }))
+    .unwrap();
+    let actual = serde_json::to_string(&res).unwrap();
+    assert_eq!(expected, actual)
+}
@@ -1,8 +1,10 @@
 use crate::cheap_clone::CheapClone;
 use crate::components::store::{EntityKey, EntityType, SubgraphStore};
-use crate::data::graphql::ext::{DirectiveExt, DirectiveFinder, DocumentExt, TypeExt, ValueExt};
+use crate::data::graphql::ext::{
+    DirectiveExt, DirectiveFinder, DocumentExt, TypeDefinitionExt, TypeExt, ValueExt,
+};
 use crate::data::graphql::ObjectTypeExt;
-use crate::data::store::{self, ValueType};
+use crate::data::store::{self, Entity, ValueType};
 use crate::data::subgraph::{DeploymentHash, SubgraphName};
 use crate::prelude::{
     anyhow, lazy_static,
@@ -33,6 +35,10 @@ pub const SCHEMA_TYPE_NAME: &str = "_Schema_";
 pub const META_FIELD_TYPE: &str = "_Meta_";
 pub const META_FIELD_NAME: &str = "_meta";
 
+pub const CURSOR_FIELD_NAME: &str = "_cursor";
+
+pub const RANK_FIELD_NAME: &str = "_rank";
+
 pub const BLOCK_FIELD_TYPE: &str = "_Block_";
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -112,6 +118,16 @@ pub enum SchemaValidationError {
     FulltextIncludedFieldMissingRequiredProperty,
     #[error("Fulltext entity field, {0}, not found or not a string")]
     FulltextIncludedFieldInvalid(String),
+    #[error("Fulltext weight is invalid: {0}. It must be one of: A, B, C, D")]
+    FulltextWeightInvalid(String),
+    #[error("Fulltext directive `{0}`: `{1}` is not an interface")]
+    FulltextInterfaceNotFound(String, String),
+    #[error("Fulltext directive `{0}`: entity type `{1}` does not implement interface `{2}`")]
+    FulltextInterfaceNotImplemented(String, String, String), // (name, entity, interface)
+    #[error("Fulltext directive `{0}` must include entity type `{1}` since it implements interface `{2}`")]
+    FulltextInterfaceImplementorMissing(String, String, String), // (name, entity, interface)
+    #[error("Aggregation `{0}` is invalid: {1}")]
+    InvalidAggregation(String, String), // (type, reason)
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -211,16 +227,62 @@ pub struct FulltextConfig {
     pub algorithm: FulltextAlgorithm,
 }
 
+/// The weight of a field in a fulltext index. Matches in fields with a
+/// higher weight contribute more to the rank of a search result; `A` is
+/// the highest and `D`, the default, the lowest weight
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FulltextWeight {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl TryFrom<&str> for FulltextWeight {
+    type Error = String;
+    fn try_from(weight: &str) -> Result<Self, Self::Error> {
+        match weight {
+            "A" => Ok(FulltextWeight::A),
+            "B" => Ok(FulltextWeight::B),
+            "C" => Ok(FulltextWeight::C),
+            "D" => Ok(FulltextWeight::D),
+            invalid => Err(format!(
+                "The provided fulltext weight {} is invalid. It must be one of: A, B, C, D",
+                invalid
+            )),
+        }
+    }
+}
+
+impl FulltextWeight {
+    /// Return the weight as Postgres' `setweight` expects it
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FulltextWeight::A => "A",
+            FulltextWeight::B => "B",
+            FulltextWeight::C => "C",
+            FulltextWeight::D => "D",
+        }
+    }
+}
+
 pub struct FulltextDefinition {
     pub config: FulltextConfig,
-    pub included_fields: HashSet<String>,
+    /// The fields of the entity type that go into the fulltext index,
+    /// together with their weight
+    pub included_fields: BTreeMap<String, FulltextWeight>,
     pub name: String,
 }
 
-impl From<&s::Directive> for FulltextDefinition {
+impl FulltextDefinition {
+    /// The definition of the fulltext field declared by `directive` for
+    /// the entity type `entity`, or `None` if the directive does not
+    /// include `entity`. Directives without an `interface` only ever
+    /// searched the first entity type they include, and we keep it that
+    /// way for them
     // Assumes the input is a Fulltext Directive that has already been validated because it makes
     // liberal use of unwrap() where specific types are expected
-    fn from(directive: &Directive) -> Self {
+    pub fn for_entity(directive: &Directive, entity: &str) -> Option<Self> {
         let name = directive.argument("name").unwrap().as_str().unwrap();
 
         let algorithm = FulltextAlgorithm::try_from(
@@ -232,34 +294,533 @@ impl From<&s::Directive> for FulltextDefinition {
             FulltextLanguage::try_from(directive.argument("language").unwrap().as_enum().unwrap())
                 .unwrap();
 
-        let included_entity_list = directive.argument("include").unwrap().as_list().unwrap();
-        // Currently fulltext query fields are limited to 1 entity, so we just take the first (and only) included Entity
-        let included_entity = included_entity_list.first().unwrap().as_object().unwrap();
+        let includes = directive.argument("include").unwrap().as_list().unwrap();
+        let searched = match directive.argument("interface") {
+            Some(_) => includes.len(),
+            None => 1,
+        };
+        let included_entity = includes
+            .iter()
+            .take(searched)
+            .map(|include| include.as_object().unwrap())
+            .find(|include| include.get("entity").unwrap().as_str() == Some(entity))?;
         let included_field_values = included_entity.get("fields").unwrap().as_list().unwrap();
-        let included_fields: HashSet<String> = included_field_values
+        let included_fields: BTreeMap<String, FulltextWeight> = included_field_values
             .iter()
             .map(|field| {
-                field
-                    .as_object()
-                    .unwrap()
-                    .get("name")
-                    .unwrap()
-                    .as_str()
-                    .unwrap()
-                    .into()
+                let field = field.as_object().unwrap();
+                let name = field.get("name").unwrap().as_str().unwrap().into();
+                let weight = field
+                    .get("weight")
+                    .and_then(|weight| weight.as_enum())
+                    .map(|weight| FulltextWeight::try_from(weight).unwrap())
+                    .unwrap_or(FulltextWeight::D);
+                (name, weight)
             })
             .collect();
 
-        FulltextDefinition {
+        Some(FulltextDefinition {
             config: FulltextConfig {
                 language,
                 algorithm,
             },
             included_fields,
             name: name.into(),
+        })
+    }
+}
+pub const AGGREGATION_DIRECTIVE: &str = "aggregation";
+pub const AGGREGATE_DIRECTIVE: &str = "aggregate";
+
+/// The time intervals over which an `@aggregation` can roll up its source
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AggregationInterval {
+    Hour,
+    Day,
+}
+
+impl TryFrom<&str> for AggregationInterval {
+    type Error = String;
+    fn try_from(interval: &str) -> Result<Self, Self::Error> {
+        match interval {
+            "hour" => Ok(AggregationInterval::Hour),
+            "day" => Ok(AggregationInterval::Day),
+            invalid => Err(format!(
+                "the interval `{}` is invalid. It must be one of: hour, day",
+                invalid
+            )),
         }
     }
 }
+
+impl AggregationInterval {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+
+    /// The length of the interval in seconds
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Self::Hour => 3600,
+            Self::Day => 86400,
+        }
+    }
+
+    /// The name of the entity type that holds the rollups of `aggregation`
+    /// for this interval, e.g., `SwapStatsHour`
+    fn type_name(&self, aggregation: &str) -> String {
+        format!("{}{}", aggregation, self.as_str().to_pascal_case())
+    }
+}
+
+/// The functions that an `@aggregate` directive can apply
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AggregateFn {
+    Sum,
+    Count,
+    Min,
+    Max,
+    First,
+    Last,
+}
+
+impl TryFrom<&str> for AggregateFn {
+    type Error = String;
+    fn try_from(func: &str) -> Result<Self, Self::Error> {
+        match func {
+            "sum" => Ok(AggregateFn::Sum),
+            "count" => Ok(AggregateFn::Count),
+            "min" => Ok(AggregateFn::Min),
+            "max" => Ok(AggregateFn::Max),
+            "first" => Ok(AggregateFn::First),
+            "last" => Ok(AggregateFn::Last),
+            invalid => Err(format!(
+                "the aggregation function `{}` is invalid. It must be one of: \
+                 sum, count, min, max, first, last",
+                invalid
+            )),
+        }
+    }
+}
+
+/// A field of an `@aggregation` type that is computed with
+/// `@aggregate(fn: .., arg: ..)` from a field of the source entity
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregate {
+    /// The name of the field in the aggregation
+    pub name: String,
+    pub func: AggregateFn,
+    /// The field of the source entity that is aggregated; `None` for `count`
+    pub arg: Option<String>,
+    pub value_type: ValueType,
+}
+
+impl Aggregate {
+    /// Combine the current value `acc` of this aggregate with the value of
+    /// the `arg` field of a new source entity
+    fn fold(&self, acc: Option<&store::Value>, source: &Entity) -> Result<store::Value, Error> {
+        use store::Value as V;
+
+        let value = self
+            .arg
+            .as_ref()
+            .and_then(|arg| source.get(arg))
+            .unwrap_or(&V::Null);
+
+        let acc = match acc {
+            Some(V::Null) | None => None,
+            Some(acc) => Some(acc),
+        };
+
+        match (self.func, acc, value) {
+            (AggregateFn::Count, None, _) => Ok(match self.value_type {
+                ValueType::Int => V::Int(1),
+                _ => V::Int8(1),
+            }),
+            (AggregateFn::Count, Some(V::Int(n)), _) => n
+                .checked_add(1)
+                .map(V::Int)
+                .ok_or_else(|| anyhow!("overflow in count for `{}`", self.name)),
+            (AggregateFn::Count, Some(V::Int8(n)), _) => n
+                .checked_add(1)
+                .map(V::Int8)
+                .ok_or_else(|| anyhow!("overflow in count for `{}`", self.name)),
+            (AggregateFn::Count, Some(acc), _) => Err(anyhow!(
+                "count `{}` has invalid type {}",
+                self.name,
+                acc.type_name()
+            )),
+            // Null values in the source entity do not change the aggregate
+            (_, acc, V::Null) => Ok(acc.cloned().unwrap_or(V::Null)),
+            (_, None, value) | (AggregateFn::Last, _, value) => Ok(value.clone()),
+            (AggregateFn::First, Some(acc), _) => Ok(acc.clone()),
+            (AggregateFn::Sum, Some(acc), value) => match (acc, value) {
+                (V::Int(a), V::Int(b)) => a
+                    .checked_add(*b)
+                    .map(V::Int)
+                    .ok_or_else(|| anyhow!("overflow in sum for `{}`", self.name)),
+                (V::Int8(a), V::Int8(b)) => a
+                    .checked_add(*b)
+                    .map(V::Int8)
+                    .ok_or_else(|| anyhow!("overflow in sum for `{}`", self.name)),
+                (V::BigInt(a), V::BigInt(b)) => Ok(V::BigInt(a.clone() + b.clone())),
+                (V::BigDecimal(a), V::BigDecimal(b)) => Ok(V::BigDecimal(a.clone() + b.clone())),
+                (acc, value) => Err(anyhow!(
+                    "can not add {} and {} for `{}`",
+                    acc.type_name(),
+                    value.type_name(),
+                    self.name
+                )),
+            },
+            (AggregateFn::Min, Some(acc), value) | (AggregateFn::Max, Some(acc), value) => {
+                let ord = match (acc, value) {
+                    (V::Int(a), V::Int(b)) => a.cmp(b),
+                    (V::Int8(a), V::Int8(b)) => a.cmp(b),
+                    (V::BigInt(a), V::BigInt(b)) => a.cmp(b),
+                    (V::BigDecimal(a), V::BigDecimal(b)) => a.cmp(b),
+                    (acc, value) => {
+                        return Err(anyhow!(
+                            "can not compare {} and {} for `{}`",
+                            acc.type_name(),
+                            value.type_name(),
+                            self.name
+                        ))
+                    }
+                };
+                let take_value = match self.func {
+                    AggregateFn::Min => ord == std::cmp::Ordering::Greater,
+                    _ => ord == std::cmp::Ordering::Less,
+                };
+                Ok(if take_value { value } else { acc }.clone())
+            }
+        }
+    }
+}
+
+/// One interval of an `@aggregation` type. For a type `SwapStats` with
+/// `@aggregation(intervals: ["hour", "day"], source: "Swap")`, there are
+/// two aggregations, one stored in the entity type `SwapStatsHour` and one
+/// in `SwapStatsDay`. The store maintains these entities whenever it writes
+/// new `Swap` entities
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregation {
+    /// The entity type that stores the rollups
+    pub entity_type: EntityType,
+    /// The immutable entity type whose entities are rolled up
+    pub source: EntityType,
+    pub interval: AggregationInterval,
+    /// The type of the `timestamp` field of the source entity type
+    source_timestamp: ValueType,
+    /// The type of the `timestamp` field of the rollups
+    timestamp: ValueType,
+    /// Fields that are copied from the source entity and by which
+    /// rollups are grouped in addition to the time interval
+    pub dimensions: Vec<String>,
+    pub aggregates: Vec<Aggregate>,
+}
+
+impl Aggregation {
+    /// Return the id of the rollup that `source` is part of together with
+    /// the value of the rollup's `timestamp` field, i.e., the start of the
+    /// interval. The id consists of the start of the interval followed by
+    /// `-` and the hex encoding of each dimension, or `-null` for
+    /// dimensions that are not set. Since neither contains a `-`, ids for
+    /// different combinations of dimensions can not collide
+    pub fn bucket(&self, source: &Entity) -> Result<(String, store::Value), Error> {
+        let secs = match (&self.source_timestamp, source.get("timestamp")) {
+            (ValueType::Int, Some(store::Value::Int(secs))) => *secs as i64,
+            (ValueType::Int8, Some(store::Value::Int8(secs))) => *secs,
+            (ValueType::Timestamp, Some(store::Value::Timestamp(ts))) => {
+                ts.as_microseconds_since_epoch().div_euclid(1_000_000)
+            }
+            (_, value) => {
+                return Err(anyhow!(
+                    "entity of type `{}` has invalid timestamp {:?}",
+                    self.source,
+                    value
+                ))
+            }
+        };
+        let start = secs - secs.rem_euclid(self.interval.seconds());
+
+        let mut id = start.to_string();
+        for dimension in &self.dimensions {
+            match source.get(dimension).unwrap_or(&store::Value::Null) {
+                store::Value::Null => id.push_str("-null"),
+                value => {
+                    id.push('-');
+                    id.push_str(&hex::encode(value.to_string()));
+                }
+            }
+        }
+
+        let timestamp = match self.timestamp {
+            ValueType::Timestamp => {
+                store::Value::Timestamp(scalar::Timestamp::since_epoch(start * 1_000_000)?)
+            }
+            _ => store::Value::Int8(start),
+        };
+        Ok((id, timestamp))
+    }
+
+    /// Fold the `source` entity into the rollup `acc`. If `acc` is `None`,
+    /// start a new rollup
+    pub fn fold(&self, acc: Option<Entity>, source: &Entity) -> Result<Entity, Error> {
+        let mut acc = match acc {
+            Some(acc) => acc,
+            None => {
+                let (id, timestamp) = self.bucket(source)?;
+                let mut acc = Entity::new();
+                acc.set("id", id);
+                acc.set("timestamp", timestamp);
+                for dimension in &self.dimensions {
+                    let value = source.get(dimension).cloned().unwrap_or(store::Value::Null);
+                    acc.set(dimension.as_str(), value);
+                }
+                acc
+            }
+        };
+        for aggregate in &self.aggregates {
+            let value = aggregate.fold(acc.get(&aggregate.name), source)?;
+            acc.set(aggregate.name.as_str(), value);
+        }
+        Ok(acc)
+    }
+
+    /// Check the `@aggregation` type `agg_type` and turn it into one
+    /// `Aggregation` and one entity type per interval
+    fn expand(
+        document: &s::Document,
+        agg_type: &ObjectType,
+    ) -> Result<Vec<(Aggregation, ObjectType)>, SchemaValidationError> {
+        let invalid = |reason: String| {
+            SchemaValidationError::InvalidAggregation(agg_type.name.clone(), reason)
+        };
+        let base_type = |field: &s::Field| -> Result<ValueType, SchemaValidationError> {
+            if field.field_type.is_list() {
+                return Err(invalid(format!(
+                    "field `{}` must not be a list",
+                    field.name
+                )));
+            }
+            let base = field.field_type.get_base_type();
+            if document.get_named_type(base).is_some() {
+                // References to entities and enums are stored as strings
+                return Ok(ValueType::String);
+            }
+            Ok(ValueType::from_str(base).unwrap_or(ValueType::String))
+        };
+
+        let directive = agg_type.find_directive(AGGREGATION_DIRECTIVE).unwrap();
+        let intervals = match directive.argument("intervals") {
+            Some(Value::List(intervals)) if !intervals.is_empty() => intervals
+                .iter()
+                .map(|interval| match interval {
+                    Value::String(interval) => AggregationInterval::try_from(interval.as_str()),
+                    _ => Err("intervals must be strings".to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(invalid)?,
+            _ => return Err(invalid("`intervals` must be a non-empty list".to_string())),
+        };
+        let source = match directive.argument("source") {
+            Some(Value::String(source)) => source,
+            _ => {
+                return Err(invalid(
+                    "`source` must be the name of an entity type".to_string(),
+                ))
+            }
+        };
+        let source_type = document
+            .get_object_type_definition(source)
+            .filter(|source_type| source_type.find_directive("entity").is_some())
+            .ok_or_else(|| invalid(format!("source type `{}` is not an entity type", source)))?;
+        if !source_type.is_immutable() {
+            return Err(invalid(format!(
+                "source type `{}` must be immutable",
+                source
+            )));
+        }
+        let source_timestamp = match source_type.field("timestamp").map(base_type).transpose()? {
+            Some(t @ (ValueType::Int | ValueType::Int8 | ValueType::Timestamp)) => t,
+            _ => {
+                return Err(invalid(format!(
+                    "source type `{}` must have a `timestamp` field of type Int, Int8 or Timestamp",
+                    source
+                )))
+            }
+        };
+
+        let mut timestamp = None;
+        let mut dimensions = vec![];
+        let mut aggregates = vec![];
+        for field in &agg_type.fields {
+            let value_type = base_type(field)?;
+            match field.name.as_str() {
+                "id" => {
+                    if value_type != ValueType::String {
+                        return Err(invalid("the `id` field must be of type ID".to_string()));
+                    }
+                    continue;
+                }
+                "timestamp" => {
+                    if !matches!(value_type, ValueType::Int8 | ValueType::Timestamp) {
+                        return Err(invalid(
+                            "the `timestamp` field must be of type Int8 or Timestamp".to_string(),
+                        ));
+                    }
+                    timestamp = Some(value_type);
+                    continue;
+                }
+                _ => {}
+            }
+
+            let aggregate = match field.find_directive(AGGREGATE_DIRECTIVE) {
+                Some(aggregate) => aggregate,
+                None => {
+                    // A dimension, which must be a field of the source type
+                    match source_type.field(&field.name) {
+                        Some(source_field) if source_field.field_type == field.field_type => {
+                            dimensions.push(field.name.clone());
+                            continue;
+                        }
+                        _ => {
+                            return Err(invalid(format!(
+                                "field `{}` must either have an @aggregate directive or \
+                                 have the same type as the field of the same name in `{}`",
+                                field.name, source
+                            )))
+                        }
+                    }
+                }
+            };
+
+            let func = match aggregate.argument("fn") {
+                Some(Value::String(func)) => {
+                    AggregateFn::try_from(func.as_str()).map_err(invalid)?
+                }
+                _ => {
+                    return Err(invalid(format!(
+                        "the @aggregate directive on `{}` must have a `fn` argument",
+                        field.name
+                    )))
+                }
+            };
+            let arg = match (func, aggregate.argument("arg")) {
+                (AggregateFn::Count, None) => None,
+                (_, Some(Value::String(arg))) => Some(arg.clone()),
+                _ => {
+                    return Err(invalid(format!(
+                        "the @aggregate directive on `{}` must have an `arg` argument",
+                        field.name
+                    )))
+                }
+            };
+            match &arg {
+                None => {
+                    if !matches!(value_type, ValueType::Int | ValueType::Int8) {
+                        return Err(invalid(format!(
+                            "the count `{}` must be of type Int or Int8",
+                            field.name
+                        )));
+                    }
+                }
+                Some(arg) => {
+                    let arg_field = source_type.field(arg).ok_or_else(|| {
+                        invalid(format!("`{}` is not a field of `{}`", arg, source))
+                    })?;
+                    let arg_type = base_type(arg_field)?;
+                    let arg_base = arg_field.field_type.get_base_type();
+                    if !ValueType::is_scalar(arg_base)
+                        || document.get_named_type(arg_base).is_some()
+                    {
+                        return Err(invalid(format!("the argument `{}` must be a scalar", arg)));
+                    }
+                    if arg_type != value_type {
+                        return Err(invalid(format!(
+                            "field `{}` must have the same type as `{}.{}`",
+                            field.name, source, arg
+                        )));
+                    }
+                    let numeric = matches!(
+                        arg_type,
+                        ValueType::Int
+                            | ValueType::Int8
+                            | ValueType::BigInt
+                            | ValueType::BigDecimal
+                    );
+                    if matches!(func, AggregateFn::Sum | AggregateFn::Min | AggregateFn::Max)
+                        && !numeric
+                    {
+                        return Err(invalid(format!(
+                            "field `{}` aggregates `{}` which is not numeric",
+                            field.name, arg
+                        )));
+                    }
+                }
+            }
+
+            aggregates.push(Aggregate {
+                name: field.name.clone(),
+                func,
+                arg,
+                value_type,
+            });
+        }
+        let timestamp = timestamp
+            .ok_or_else(|| invalid("the aggregation must have a `timestamp` field".to_string()))?;
+        if agg_type.field("id").is_none() {
+            return Err(invalid(
+                "the aggregation must have an `id` field".to_string(),
+            ));
+        }
+
+        // The entity types that hold the rollups are plain mutable entity
+        // types; the information from the `@aggregate` directives is only
+        // kept in the `Aggregation`
+        let fields: Vec<_> = agg_type
+            .fields
+            .iter()
+            .map(|field| {
+                let mut field = field.clone();
+                field
+                    .directives
+                    .retain(|directive| directive.name != AGGREGATE_DIRECTIVE);
+                field
+            })
+            .collect();
+        let entity_directive = s::Directive {
+            name: "entity".to_string(),
+            position: Pos::default(),
+            arguments: vec![],
+        };
+
+        Ok(intervals
+            .into_iter()
+            .map(|interval| {
+                let name = interval.type_name(&agg_type.name);
+                let aggregation = Aggregation {
+                    entity_type: EntityType::new(name.clone()),
+                    source: EntityType::new(source.clone()),
+                    interval,
+                    source_timestamp: source_timestamp.clone(),
+                    timestamp: timestamp.clone(),
+                    dimensions: dimensions.clone(),
+                    aggregates: aggregates.clone(),
+                };
+                let mut object_type = agg_type.clone();
+                object_type.name = name;
+                object_type.directives = vec![entity_directive.clone()];
+                object_type.fields = fields.clone();
+                (aggregation, object_type)
+            })
+            .collect())
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq, Clone)]
 pub enum SchemaImportError {
     #[error("Schema for imported subgraph `{0}` was not found")]
@@ -293,6 +854,15 @@ impl fmt::Display for ImportedType {
 }
 
 impl ImportedType {
+    /// The type `name` imported as `<namespace>_<name>`
+    fn namespaced(namespace: &str, name: &str) -> Self {
+        ImportedType {
+            name: name.to_string(),
+            alias: format!("{}_{}", namespace, name),
+            explicit: true,
+        }
+    }
+
     fn parse(type_import: &Value) -> Option<Self> {
         match type_import {
             Value::String(type_name) => Some(ImportedType {
@@ -505,6 +1075,229 @@ impl ApiSchema {
     }
 }
 
+/// The schema for composite queries that query several deployments at
+/// once. The API schema of each deployment is mounted under a namespace:
+/// the root `Query` type has one field for each namespace whose type is the
+/// `Query` type of that namespace's deployment. All other types of the
+/// deployment are imported under the name `<namespace>_<type>`, just as if
+/// they had been imported with `@import(types: [{ name: "<type>", as:
+/// "<namespace>_<type>" }], ..)`. Scalars and directives are the same for
+/// all deployments and are shared
+#[derive(Debug)]
+pub struct CompositeSchema {
+    schema: Arc<ApiSchema>,
+    /// For each namespace, the types of its deployment keyed by their name
+    /// in the composite schema
+    imports: BTreeMap<String, HashMap<String, ImportedType>>,
+}
+
+impl CompositeSchema {
+    pub fn new(parts: Vec<(String, Arc<ApiSchema>)>) -> Result<Self, Error> {
+        fn is_valid_namespace(namespace: &str) -> bool {
+            let mut chars = namespace.chars();
+            matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !namespace.starts_with("__")
+        }
+
+        fn rename_name(name: &mut String, types: &HashMap<&str, ImportedType>) {
+            if let Some(imported) = types.get(name.as_str()) {
+                *name = imported.alias.clone();
+            }
+        }
+
+        fn rename(typ: &mut s::Type, types: &HashMap<&str, ImportedType>) {
+            match typ {
+                s::Type::NamedType(name) => rename_name(name, types),
+                s::Type::ListType(inner) | s::Type::NonNullType(inner) => rename(inner, types),
+            }
+        }
+
+        fn rename_fields(fields: &mut Vec<s::Field>, types: &HashMap<&str, ImportedType>) {
+            // The introspection fields are only added to the root `Query`
+            fields.retain(|field| !field.name.starts_with("__"));
+            for field in fields {
+                rename(&mut field.field_type, types);
+                for arg in &mut field.arguments {
+                    rename(&mut arg.value_type, types);
+                }
+            }
+        }
+
+        let mut definitions = Vec::new();
+        let mut names = HashSet::new();
+        let mut root_fields = Vec::new();
+        let mut imports = BTreeMap::new();
+
+        for (namespace, schema) in parts {
+            if !is_valid_namespace(&namespace) {
+                return Err(anyhow!("`{}` is not a valid namespace", namespace));
+            }
+            if imports.contains_key(&namespace) {
+                return Err(anyhow!("namespace `{}` is used more than once", namespace));
+            }
+
+            // Introspection types are added to the composite schema once,
+            // and subscriptions are not supported
+            let types: HashMap<&str, ImportedType> = schema
+                .document()
+                .definitions
+                .iter()
+                .filter_map(|defn| match defn {
+                    Definition::TypeDefinition(TypeDefinition::Scalar(_)) => None,
+                    Definition::TypeDefinition(typedef) => Some(typedef.name()),
+                    _ => None,
+                })
+                .filter(|name| !name.starts_with("__") && *name != "Subscription")
+                .map(|name| (name, ImportedType::namespaced(&namespace, name)))
+                .collect();
+
+            for defn in &schema.document().definitions {
+                let mut typedef = match defn {
+                    Definition::TypeDefinition(TypeDefinition::Scalar(scalar)) => {
+                        if names.insert(scalar.name.clone()) {
+                            definitions.push(defn.clone());
+                        }
+                        continue;
+                    }
+                    Definition::DirectiveDefinition(directive) => {
+                        if names.insert(format!("@{}", directive.name)) {
+                            definitions.push(defn.clone());
+                        }
+                        continue;
+                    }
+                    Definition::TypeDefinition(typedef) if types.contains_key(typedef.name()) => {
+                        typedef.clone()
+                    }
+                    _ => continue,
+                };
+                match &mut typedef {
+                    TypeDefinition::Object(t) => {
+                        rename_name(&mut t.name, &types);
+                        for interface in &mut t.implements_interfaces {
+                            rename_name(interface, &types);
+                        }
+                        rename_fields(&mut t.fields, &types);
+                    }
+                    TypeDefinition::Interface(t) => {
+                        rename_name(&mut t.name, &types);
+                        rename_fields(&mut t.fields, &types);
+                    }
+                    TypeDefinition::Union(t) => {
+                        rename_name(&mut t.name, &types);
+                        for member in &mut t.types {
+                            rename_name(member, &types);
+                        }
+                    }
+                    TypeDefinition::Enum(t) => rename_name(&mut t.name, &types),
+                    TypeDefinition::InputObject(t) => {
+                        rename_name(&mut t.name, &types);
+                        for field in &mut t.fields {
+                            rename(&mut field.value_type, &types);
+                        }
+                    }
+                    TypeDefinition::Scalar(_) => unreachable!("scalars are shared"),
+                }
+                if !names.insert(typedef.name().to_string()) {
+                    return Err(anyhow!(
+                        "type `{}` is defined more than once",
+                        typedef.name()
+                    ));
+                }
+                definitions.push(Definition::TypeDefinition(typedef));
+            }
+
+            let query_type = types.get("Query").ok_or_else(|| {
+                anyhow!(
+                    "the schema for namespace `{}` has no `Query` type",
+                    namespace
+                )
+            })?;
+            root_fields.push(s::Field {
+                position: Pos::default(),
+                description: Some(format!("Query deployment `{}`", schema.id())),
+                name: namespace.clone(),
+                arguments: vec![],
+                field_type: s::Type::NonNullType(Box::new(s::Type::NamedType(
+                    query_type.alias.clone(),
+                ))),
+                directives: vec![],
+            });
+            let types = types
+                .into_values()
+                .map(|imported| (imported.alias.clone(), imported))
+                .collect();
+            imports.insert(namespace, types);
+        }
+
+        definitions.push(Definition::TypeDefinition(TypeDefinition::Object(
+            ObjectType {
+                position: Pos::default(),
+                description: None,
+                name: "Query".to_string(),
+                implements_interfaces: vec![],
+                directives: vec![],
+                fields: root_fields,
+            },
+        )));
+        let document = s::Document { definitions };
+
+        let mut types_for_interface =
+            BTreeMap::from_iter(document.definitions.iter().filter_map(|d| match d {
+                Definition::TypeDefinition(TypeDefinition::Interface(t)) => {
+                    Some((EntityType::from(t), vec![]))
+                }
+                _ => None,
+            }));
+        let mut interfaces_for_type = BTreeMap::<_, Vec<_>>::new();
+        for object_type in document.get_object_type_definitions() {
+            for name in &object_type.implements_interfaces {
+                if let Some(TypeDefinition::Interface(interface)) = document.get_named_type(name) {
+                    interfaces_for_type
+                        .entry(EntityType::from(object_type))
+                        .or_default()
+                        .push(interface.clone());
+                    types_for_interface
+                        .entry(EntityType::new(name.clone()))
+                        .or_default()
+                        .push(object_type.clone());
+                }
+            }
+        }
+
+        let schema = Schema {
+            id: DeploymentHash::new("composite").unwrap(),
+            document,
+            interfaces_for_type,
+            types_for_interface,
+            immutable_types: HashSet::new(),
+            aggregations: vec![],
+        };
+        let schema = Arc::new(ApiSchema::from_api_schema(schema)?);
+
+        Ok(CompositeSchema { schema, imports })
+    }
+
+    pub fn api_schema(&self) -> Arc<ApiSchema> {
+        self.schema.cheap_clone()
+    }
+
+    pub fn is_namespace(&self, name: &str) -> bool {
+        self.imports.contains_key(name)
+    }
+
+    /// The name that the type `name` from the composite schema has in the
+    /// schema of the deployment for `namespace`. Types that are shared
+    /// between all deployments have the same name everywhere
+    pub fn local_type_name<'a>(&'a self, namespace: &str, name: &'a str) -> &'a str {
+        self.imports
+            .get(namespace)
+            .and_then(|types| types.get(name))
+            .map(|imported| imported.name.as_str())
+            .unwrap_or(name)
+    }
+}
+
 lazy_static! {
     static ref INTROSPECTION_SCHEMA: Document = {
         let schema = include_str!("introspection.graphql");
@@ -581,6 +1374,9 @@ pub struct Schema {
     pub types_for_interface: BTreeMap<EntityType, Vec<ObjectType>>,
 
     immutable_types: HashSet<EntityType>,
+
+    // The rollups that the store maintains for `@aggregation` types
+    aggregations: Vec<Aggregation>,
 }
 
 impl Schema {
@@ -590,7 +1386,11 @@ impl Schema {
     // some is done here makes it incredibly murky whether a `Schema` is
     // fully validated. The code should be changed to make sure that a
     // `Schema` is always fully valid
-    pub fn new(id: DeploymentHash, document: s::Document) -> Result<Self, SchemaValidationError> {
+    pub fn new(
+        id: DeploymentHash,
+        mut document: s::Document,
+    ) -> Result<Self, SchemaValidationError> {
+        let aggregations = Self::expand_aggregations(&mut document)?;
         let (interfaces_for_type, types_for_interface) = Self::collect_interfaces(&document)?;
         let immutable_types = Self::collect_immutable_types(&document);
 
@@ -600,6 +1400,7 @@ impl Schema {
             interfaces_for_type,
             types_for_interface,
             immutable_types,
+            aggregations,
         };
 
         schema.add_subgraph_id_directives(id);
@@ -644,6 +1445,53 @@ impl Schema {
         self.immutable_types.contains(entity_type)
     }
 
+    /// Resolve the `@derivedFrom` field `field` of `entity_type`. Return
+    /// the entity types whose entities the field lists, and the attribute
+    /// of those types that refers back to `entity_type`. If the field has
+    /// an interface type, the entity types are all the types that
+    /// implement the interface
+    pub fn derived_from(
+        &self,
+        entity_type: &EntityType,
+        field: &str,
+    ) -> Result<(Vec<EntityType>, String), Error> {
+        let field_def = self
+            .document
+            .get_object_type_definition(entity_type.as_str())
+            .ok_or_else(|| anyhow!("unknown entity type `{}`", entity_type))?
+            .field(field)
+            .ok_or_else(|| anyhow!("entity type `{}` has no field `{}`", entity_type, field))?;
+        let attribute = match field_def
+            .find_directive("derivedFrom")
+            .and_then(|directive| directive.argument("field"))
+        {
+            Some(Value::String(attribute)) => attribute.clone(),
+            _ => {
+                return Err(anyhow!(
+                    "field `{}` of entity type `{}` is not derived",
+                    field,
+                    entity_type
+                ))
+            }
+        };
+
+        let target_type = EntityType::new(field_def.field_type.get_base_type().to_string());
+        let entity_types = match self.types_for_interface.get(&target_type) {
+            Some(object_types) => object_types
+                .iter()
+                .map(|object_type| EntityType::new(object_type.name.clone()))
+                .collect(),
+            None => vec![target_type],
+        };
+        Ok((entity_types, attribute))
+    }
+
+    /// The aggregations for all `@aggregation` types in the schema, one
+    /// for each interval of each type
+    pub fn aggregations(&self) -> &[Aggregation] {
+        &self.aggregations
+    }
+
     pub fn resolve_schema_references<S: SubgraphStore>(
         &self,
         store: Arc<S>,
@@ -741,6 +1589,40 @@ impl Schema {
         Ok((interfaces_for_type, types_for_interface))
     }
 
+    /// Replace each `@aggregation` type in `document` with one entity type
+    /// per interval, and return the corresponding aggregations
+    fn expand_aggregations(
+        document: &mut s::Document,
+    ) -> Result<Vec<Aggregation>, SchemaValidationError> {
+        let mut aggregations = vec![];
+        let mut definitions = Vec::with_capacity(document.definitions.len());
+        for definition in &document.definitions {
+            match definition {
+                Definition::TypeDefinition(TypeDefinition::Object(agg_type))
+                    if agg_type.find_directive(AGGREGATION_DIRECTIVE).is_some() =>
+                {
+                    for (aggregation, object_type) in Aggregation::expand(document, agg_type)? {
+                        if document.get_named_type(&object_type.name).is_some() {
+                            return Err(SchemaValidationError::InvalidAggregation(
+                                agg_type.name.clone(),
+                                format!("type `{}` already exists", object_type.name),
+                            ));
+                        }
+                        aggregations.push(aggregation);
+                        definitions.push(Definition::TypeDefinition(TypeDefinition::Object(
+                            object_type,
+                        )));
+                    }
+                }
+                definition => definitions.push(definition.clone()),
+            }
+        }
+        if !aggregations.is_empty() {
+            document.definitions = definitions;
+        }
+        Ok(aggregations)
+    }
+
     fn collect_immutable_types(document: &s::Document) -> HashSet<EntityType> {
         HashSet::from_iter(
             document
@@ -1022,6 +1904,10 @@ impl Schema {
                             self.validate_fulltext_directive_includes(fulltext)
                                 .into_iter(),
                         );
+                        errors.extend(
+                            self.validate_fulltext_directive_interface(fulltext)
+                                .into_iter(),
+                        );
                         errors
                     })
             })
@@ -1151,14 +2037,26 @@ impl Schema {
                     };
 
                     for field_value in fields {
-                        let field_name = match field_value {
+                        let (field_name, weight) = match field_value {
                             Value::Object(field_map) => match field_map.get("name") {
-                                Some(Value::String(name)) => name,
+                                Some(Value::String(name)) => (name, field_map.get("weight")),
                                 _ => return vec![SchemaValidationError::FulltextIncludedFieldMissingRequiredProperty],
                             },
                             _ => return vec![SchemaValidationError::FulltextIncludeEntityMissingOrIncorrectAttributes],
                         };
 
+                        // Validate the weight of the field, if there is one
+                        match weight {
+                            None => {}
+                            Some(Value::Enum(weight))
+                                if FulltextWeight::try_from(weight.as_str()).is_ok() => {}
+                            Some(weight) => {
+                                return vec![SchemaValidationError::FulltextWeightInvalid(
+                                    weight.to_string(),
+                                )]
+                            }
+                        }
+
                         // Validate the included field is a String field on the local entity types specified
                         if !&entity_type
                             .fields
@@ -1180,6 +2078,78 @@ impl Schema {
         return vec![];
     }
 
+    /// Validate the `interface` argument of a fulltext directive. A
+    /// directive that includes several entity types should name an
+    /// interface; its query field then returns that interface. The
+    /// directive must include exactly the types that implement the
+    /// interface since the query for the interface searches all of them.
+    /// Directives that include several entity types without naming an
+    /// interface are deprecated but still valid, see
+    /// `deprecation_warnings`
+    fn validate_fulltext_directive_interface(
+        &self,
+        fulltext: &Directive,
+    ) -> Vec<SchemaValidationError> {
+        let name = match fulltext.argument("name") {
+            Some(Value::String(name)) => name.as_str(),
+            _ => "",
+        };
+        let entities: Vec<&str> = match fulltext.argument("include") {
+            Some(Value::List(includes)) => includes
+                .iter()
+                .filter_map(|include| include.as_object())
+                .filter_map(|include| include.get("entity"))
+                .filter_map(|entity| entity.as_str())
+                .collect(),
+            _ => return vec![],
+        };
+
+        let interface = match fulltext.argument("interface") {
+            None => return vec![],
+            Some(Value::String(interface)) => interface,
+            Some(interface) => {
+                return vec![SchemaValidationError::FulltextInterfaceNotFound(
+                    name.to_string(),
+                    interface.to_string(),
+                )]
+            }
+        };
+        if self.document.find_interface(interface).is_none() {
+            return vec![SchemaValidationError::FulltextInterfaceNotFound(
+                name.to_string(),
+                interface.to_string(),
+            )];
+        }
+
+        let implementors: Vec<&str> = self
+            .document
+            .get_object_type_definitions()
+            .into_iter()
+            .filter(|typ| typ.implements_interfaces.contains(interface))
+            .map(|typ| typ.name.as_str())
+            .collect();
+        let mut errors = vec![];
+        for entity in &entities {
+            if !implementors.contains(entity) {
+                errors.push(SchemaValidationError::FulltextInterfaceNotImplemented(
+                    name.to_string(),
+                    entity.to_string(),
+                    interface.to_string(),
+                ));
+            }
+        }
+        for implementor in implementors {
+            if !entities.contains(&implementor) {
+                errors.push(SchemaValidationError::FulltextInterfaceImplementorMissing(
+                    name.to_string(),
+                    implementor.to_string(),
+                    interface.to_string(),
+                ));
+            }
+        }
+        errors
+    }
+
     fn validate_import_directives(&self) -> Vec<SchemaValidationError> {
         self.subgraph_schema_object_type()
             .map_or(vec![], |subgraph_schema_type| {
@@ -1307,6 +2277,17 @@ impl Schema {
             .map(|type_name| format!("{}_orderBy", type_name))
             .collect();
 
+        // TYPE_NAME_aggregate types for all object types, and the types of
+        // their `sum`, `avg`, `min` and `max` fields
+        let mut aggregate_types: Vec<_> = object_types
+            .iter()
+            .flat_map(|type_name| {
+                ["", "_sum", "_avg", "_fields"]
+                    .iter()
+                    .map(move |suffix| format!("{}_aggregate{}", type_name, suffix))
+            })
+            .collect();
+
         let mut reserved_types: Vec<String> = vec![
             // The built-in scalar types
             "Boolean".into(),
@@ -1316,6 +2297,10 @@ impl Schema {
             "String".into(),
             "Bytes".into(),
             "BigInt".into(),
+            // `Int8` and `Timestamp` are not reserved since subgraphs
+            // could define types with those names before they became
+            // builtin scalars. A type the subgraph defines itself takes
+            // precedence over the builtin scalar
             // Reserved Query and Subscription types
             "Query".into(),
             "Subscription".into(),
@@ -1323,6 +2308,7 @@ impl Schema {
 
         reserved_types.append(&mut filter_types);
         reserved_types.append(&mut order_by_types);
+        reserved_types.append(&mut aggregate_types);
 
         // `reserved_types` will now only contain
         // the reserved types that the given schema *is* using.
@@ -1550,6 +2536,33 @@ impl Schema {
             .find(|object_type| object_type.name.eq(SCHEMA_TYPE_NAME))
     }
 
+    /// Warnings about deprecated constructs in the schema that are still
+    /// accepted. Fulltext directives that include several entity types
+    /// without naming an interface only search the first one
+    pub fn deprecation_warnings(&self) -> Vec<String> {
+        let directives = match self.document.get_fulltext_directives() {
+            Ok(directives) => directives,
+            Err(_) => return vec![],
+        };
+        directives
+            .into_iter()
+            .filter(|fulltext| fulltext.argument("interface").is_none())
+            .filter_map(|fulltext| {
+                let name = fulltext.argument("name")?.as_str()?;
+                let includes = fulltext.argument("include")?.as_list()?;
+                let first = includes.first()?.as_object()?.get("entity")?.as_str()?;
+                (includes.len() > 1).then(|| {
+                    format!(
+                        "Fulltext directive `{}` includes several entity types without \
+                         naming an interface that they implement; only `{}` is searched. \
+                         This is deprecated, add an `interface` to search all of them",
+                        name, first
+                    )
+                })
+            })
+            .collect()
+    }
+
     pub fn entity_fulltext_definitions(
         entity: &str,
         document: &Document,
@@ -1557,21 +2570,7 @@ impl Schema {
         Ok(document
             .get_fulltext_directives()?
             .into_iter()
-            .filter(|directive| match directive.argument("include") {
-                Some(Value::List(includes)) if !includes.is_empty() => {
-                    includes.iter().any(|include| match include {
-                        Value::Object(include) => match include.get("entity") {
-                            Some(Value::String(fulltext_entity)) if fulltext_entity == entity => {
-                                true
-                            }
-                            _ => false,
-                        },
-                        _ => false,
-                    })
-                }
-                _ => false,
-            })
-            .map(FulltextDefinition::from)
+            .filter_map(|directive| FulltextDefinition::for_entity(directive, entity))
             .collect())
     }
 }
@@ -1884,6 +2883,30 @@ fn test_reserved_types_validation() {
     }
 }
 
+#[test]
+fn test_user_types_named_like_new_scalars() {
+    const SCHEMA: &str = r#"
+    type Timestamp @entity {
+        id: ID!
+        value: Int!
+    }
+
+    type Int8 @entity {
+        id: ID!
+    }
+
+    type Block @entity {
+        id: ID!
+        timestamp: Timestamp!
+        number: Int8!
+    }
+    "#;
+
+    let dummy_hash = DeploymentHash::new("dummy").unwrap();
+    let schema = Schema::parse(SCHEMA, dummy_hash).unwrap();
+    assert_eq!(Ok(()), schema.validate(&HashMap::new()));
+}
+
 #[test]
 fn test_reserved_filter_and_group_by_types_validation() {
     const SCHEMA: &str = r#"
@@ -1923,6 +2946,33 @@ fn test_reserved_filter_and_group_by_types_validation() {
     }
 }
 
+#[test]
+fn test_reserved_aggregate_types_validation() {
+    const SCHEMA: &str = r#"
+    type Pool @entity {
+        id: ID!
+    }
+    type Pool_aggregate @entity {
+        id: ID!
+    }
+    type Pool_aggregate_sum @entity {
+        id: ID!
+    }
+    "#;
+
+    let dummy_hash = DeploymentHash::new("dummy").unwrap();
+    let schema = Schema::parse(SCHEMA, dummy_hash).unwrap();
+    let errors = schema.validate(&HashMap::new()).unwrap_err();
+
+    assert_eq!(
+        vec![SchemaValidationError::UsageOfReservedTypes(Strings(vec![
+            "Pool_aggregate".into(),
+            "Pool_aggregate_sum".into()
+        ]))],
+        errors
+    );
+}
+
 #[test]
 fn test_fulltext_directive_validation() {
     const SCHEMA: &str = r#"
@@ -1952,3 +3002,337 @@ type Gravatar @entity {
 
     assert_eq!(schema.validate_fulltext_directives(), vec![]);
 }
+
+#[test]
+fn test_multi_entity_fulltext_directive_validation() {
+    fn validate(directive: &str) -> Vec<SchemaValidationError> {
+        let schema = format!(
+            r#"
+type _Schema_ {}
+interface Item {{ id: ID! title: String! }}
+type Book implements Item @entity {{ id: ID! title: String! blurb: String }}
+type Movie implements Item @entity {{ id: ID! title: String! plot: String }}
+type Author @entity {{ id: ID! name: String! }}"#,
+            directive
+        );
+        let document = graphql_parser::parse_schema(&schema).expect("Failed to parse schema");
+        let schema = Schema::new(DeploymentHash::new("id1").unwrap(), document).unwrap();
+        schema.validate_fulltext_directives()
+    }
+
+    const BOOKS: &str =
+        r#"{ entity: "Book", fields: [{ name: "title", weight: A }, { name: "blurb" }] }"#;
+    const MOVIES: &str = r#"{ entity: "Movie", fields: [{ name: "title", weight: A }, { name: "plot", weight: C }] }"#;
+    const AUTHORS: &str = r#"{ entity: "Author", fields: [{ name: "name" }] }"#;
+    let directive = |interface: &str, includes: &[&str]| {
+        format!(
+            "@fulltext(name: \"itemSearch\", language: en, algorithm: rank, {} include: [{}])",
+            interface,
+            includes.join(", ")
+        )
+    };
+
+    assert_eq!(
+        validate(&directive(r#"interface: "Item","#, &[BOOKS, MOVIES])),
+        vec![]
+    );
+    // Several entity types without an interface are deprecated, but
+    // still valid
+    assert_eq!(validate(&directive("", &[BOOKS, MOVIES])), vec![]);
+    assert_eq!(
+        validate(&directive(r#"interface: "Author","#, &[AUTHORS])),
+        vec![SchemaValidationError::FulltextInterfaceNotFound(
+            "itemSearch".to_string(),
+            "Author".to_string()
+        )]
+    );
+    assert_eq!(
+        validate(&directive(r#"interface: "Item","#, &[BOOKS, AUTHORS])),
+        vec![
+            SchemaValidationError::FulltextInterfaceNotImplemented(
+                "itemSearch".to_string(),
+                "Author".to_string(),
+                "Item".to_string()
+            ),
+            SchemaValidationError::FulltextInterfaceImplementorMissing(
+                "itemSearch".to_string(),
+                "Movie".to_string(),
+                "Item".to_string()
+            )
+        ]
+    );
+    assert_eq!(
+        validate(&directive(
+            "",
+            &[r#"{ entity: "Book", fields: [{ name: "title", weight: E }] }"#]
+        )),
+        vec![SchemaValidationError::FulltextWeightInvalid(
+            "E".to_string()
+        )]
+    );
+
+    // Without an interface, only the first entity type is searched
+    let document = graphql_parser::parse_schema(&format!(
+        "type _Schema_ {}\ntype Book @entity {{ id: ID! title: String! blurb: String }}\n\
+         type Movie @entity {{ id: ID! title: String! plot: String }}",
+        directive("", &[BOOKS, MOVIES])
+    ))
+    .unwrap();
+    let schema = Schema::new(DeploymentHash::new("id1").unwrap(), document).unwrap();
+    let legacy = &schema
+        .document
+        .get_object_type_definition(SCHEMA_TYPE_NAME)
+        .unwrap()
+        .directives[0];
+    assert!(FulltextDefinition::for_entity(legacy, "Book").is_some());
+    assert!(FulltextDefinition::for_entity(legacy, "Movie").is_none());
+    assert_eq!(1, schema.deprecation_warnings().len());
+
+    let document = graphql_parser::parse_schema(&format!(
+        "type _Schema_ {}",
+        directive(r#"interface: "Item","#, &[BOOKS, MOVIES])
+    ))
+    .unwrap();
+    let directive = &document
+        .get_object_type_definition(SCHEMA_TYPE_NAME)
+        .unwrap()
+        .directives[0];
+    let books = FulltextDefinition::for_entity(directive, "Book").unwrap();
+    assert_eq!(
+        books.included_fields,
+        BTreeMap::from_iter(vec![
+            ("blurb".to_string(), FulltextWeight::D),
+            ("title".to_string(), FulltextWeight::A)
+        ])
+    );
+    let movies = FulltextDefinition::for_entity(directive, "Movie").unwrap();
+    assert_eq!(
+        movies.included_fields,
+        BTreeMap::from_iter(vec![
+            ("plot".to_string(), FulltextWeight::C),
+            ("title".to_string(), FulltextWeight::A)
+        ])
+    );
+    assert!(FulltextDefinition::for_entity(directive, "Author").is_none());
+}
+
+#[test]
+fn test_aggregation_expansion() {
+    const SCHEMA: &str = r#"
+type Swap @entity(immutable: true) {
+  id: ID!
+  timestamp: Int8!
+  token: Bytes!
+  amount: BigDecimal!
+}
+
+type SwapStats @aggregation(intervals: ["hour", "day"], source: "Swap") {
+  id: ID!
+  timestamp: Int8!
+  token: Bytes!
+  totalAmount: BigDecimal! @aggregate(fn: "sum", arg: "amount")
+  maxAmount: BigDecimal! @aggregate(fn: "max", arg: "amount")
+  count: Int8! @aggregate(fn: "count")
+}"#;
+
+    let schema = Schema::parse(SCHEMA, DeploymentHash::new("id1").unwrap()).unwrap();
+    assert_eq!(schema.validate(&HashMap::new()), Ok(()));
+
+    assert!(schema
+        .document
+        .get_object_type_definition("SwapStats")
+        .is_none());
+    let hourly = schema
+        .document
+        .get_object_type_definition("SwapStatsHour")
+        .expect("the hourly rollup type exists");
+    assert!(hourly.find_directive("entity").is_some());
+    assert!(hourly
+        .fields
+        .iter()
+        .all(|field| field.find_directive(AGGREGATE_DIRECTIVE).is_none()));
+
+    let aggregations = schema.aggregations();
+    assert_eq!(2, aggregations.len());
+    let hour = &aggregations[0];
+    assert_eq!("SwapStatsHour", hour.entity_type.as_str());
+    assert_eq!(AggregationInterval::Hour, hour.interval);
+    assert_eq!(vec!["token".to_string()], hour.dimensions);
+
+    let swap = |timestamp: i64, amount: i32| {
+        let mut swap = Entity::new();
+        swap.set("id", format!("swap-{}", timestamp));
+        swap.set("timestamp", store::Value::Int8(timestamp));
+        swap.set("token", scalar::Bytes::from_str("0x01").unwrap());
+        swap.set("amount", scalar::BigDecimal::from(amount));
+        swap
+    };
+
+    let (id, timestamp) = hour.bucket(&swap(7230, 1)).unwrap();
+    assert_eq!("7200-30783031", id);
+    assert_eq!(store::Value::Int8(7200), timestamp);
+
+    let rollup = hour.fold(None, &swap(7230, 3)).unwrap();
+    let rollup = hour.fold(Some(rollup), &swap(7290, 5)).unwrap();
+    assert_eq!(
+        Some(&store::Value::BigDecimal(scalar::BigDecimal::from(8))),
+        rollup.get("totalAmount")
+    );
+    assert_eq!(
+        Some(&store::Value::BigDecimal(scalar::BigDecimal::from(5))),
+        rollup.get("maxAmount")
+    );
+    assert_eq!(Some(&store::Value::Int8(2)), rollup.get("count"));
+    assert_eq!(Some(&store::Value::Int8(7200)), rollup.get("timestamp"));
+}
+
+#[test]
+fn test_aggregation_buckets_and_overflow() {
+    const SCHEMA: &str = r#"
+type Trade @entity(immutable: true) {
+  id: ID!
+  timestamp: Int!
+  base: String
+  quote: String
+}
+
+type TradeStats @aggregation(intervals: ["hour"], source: "Trade") {
+  id: ID!
+  timestamp: Int8!
+  base: String
+  quote: String
+  count: Int! @aggregate(fn: "count")
+}"#;
+
+    let schema = Schema::parse(SCHEMA, DeploymentHash::new("id1").unwrap()).unwrap();
+    let hour = &schema.aggregations()[0];
+
+    let trade = |base: Option<&str>, quote: &str| {
+        let mut trade = Entity::new();
+        trade.set("id", "trade");
+        trade.set("timestamp", 7230);
+        trade.set("base", base.map(str::to_string));
+        trade.set("quote", quote);
+        trade
+    };
+
+    // Dimensions that contain the separator do not lead to collisions
+    let (id1, _) = hour.bucket(&trade(Some("a-b"), "c")).unwrap();
+    let (id2, _) = hour.bucket(&trade(Some("a"), "b-c")).unwrap();
+    assert_ne!(id1, id2);
+    // Neither does a dimension that is not set
+    let (id1, _) = hour.bucket(&trade(None, "c")).unwrap();
+    let (id2, _) = hour.bucket(&trade(Some("null"), "c")).unwrap();
+    assert_eq!("7200-null-63", id1);
+    assert_ne!(id1, id2);
+
+    let mut rollup = hour.fold(None, &trade(None, "c")).unwrap();
+    rollup.set("count", i32::MAX);
+    hour.fold(Some(rollup), &trade(None, "c"))
+        .expect_err("count overflows");
+}
+
+#[test]
+fn test_aggregation_validation() {
+    fn check(source: &str, aggregation: &str, reason: &str) {
+        let schema = format!(
+            "type Swap @entity{} {{ id: ID!, timestamp: Int8!, amount: BigInt!, note: String }}\n\
+             type Stats @aggregation(intervals: [\"hour\"], source: \"Swap\") {{\n\
+               id: ID!\n  timestamp: Int8!\n  {}\n}}",
+            source, aggregation
+        );
+        let document = graphql_parser::parse_schema(&schema).expect("Failed to parse schema");
+        let err = Schema::new(DeploymentHash::new("id1").unwrap(), document.into_static())
+            .expect_err("schema is invalid");
+        match err {
+            SchemaValidationError::InvalidAggregation(name, msg) => {
+                assert_eq!("Stats", name);
+                assert!(
+                    msg.contains(reason),
+                    "`{}` does not contain `{}`",
+                    msg,
+                    reason
+                );
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    check(
+        "",
+        "total: BigInt! @aggregate(fn: \"sum\", arg: \"amount\")",
+        "must be immutable",
+    );
+    check(
+        "(immutable: true)",
+        "total: BigInt! @aggregate(fn: \"avg\", arg: \"amount\")",
+        "`avg` is invalid",
+    );
+    check(
+        "(immutable: true)",
+        "total: BigInt! @aggregate(fn: \"sum\")",
+        "must have an `arg` argument",
+    );
+    check(
+        "(immutable: true)",
+        "total: BigDecimal! @aggregate(fn: \"sum\", arg: \"amount\")",
+        "must have the same type",
+    );
+    check(
+        "(immutable: true)",
+        "total: String @aggregate(fn: \"max\", arg: \"note\")",
+        "not numeric",
+    );
+    check("(immutable: true)", "other: Int!", "@aggregate directive");
+}
+
+#[test]
+fn test_composite_schema() {
+    fn api_schema(id: &str, sdl: &str) -> Arc<ApiSchema> {
+        let document = graphql_parser::parse_schema(sdl).expect("Failed to parse schema");
+        let schema = Schema::new(DeploymentHash::new(id).unwrap(), document).unwrap();
+        Arc::new(ApiSchema::from_api_schema(schema).unwrap())
+    }
+
+    const SCHEMA: &str = r#"
+interface Named { name: String! }
+type Thing implements Named { id: ID!, name: String! }
+type Query { things(first: Int): [Thing!]!, named: [Named!]! }
+"#;
+
+    let parts = vec![
+        ("a".to_string(), api_schema("QmA", SCHEMA)),
+        ("b".to_string(), api_schema("QmB", SCHEMA)),
+    ];
+    let composite = CompositeSchema::new(parts).expect("Schemas can be combined");
+    let schema = composite.api_schema();
+
+    let query = schema.document().get_root_query_type().unwrap();
+    let fields: Vec<_> = query
+        .fields
+        .iter()
+        .map(|field| field.name.as_str())
+        .collect();
+    assert!(fields.contains(&"a"));
+    assert!(fields.contains(&"b"));
+    assert!(schema.document().get_named_type("a_Thing").is_some());
+    assert!(schema.document().get_named_type("b_Named").is_some());
+    assert!(schema.document().get_named_type("Thing").is_none());
+    assert_eq!(
+        schema.types_for_interface()[&EntityType::new("a_Named".to_string())].len(),
+        1
+    );
+
+    assert!(composite.is_namespace("a"));
+    assert!(!composite.is_namespace("c"));
+    assert_eq!("Thing", composite.local_type_name("b", "b_Thing"));
+    assert_eq!("String", composite.local_type_name("b", "String"));
+
+    let duplicate = vec![
+        ("a".to_string(), api_schema("QmA", SCHEMA)),
+        ("a".to_string(), api_schema("QmB", SCHEMA)),
+    ];
+    assert!(CompositeSchema::new(duplicate).is_err());
+    let invalid = vec![("not-valid".to_string(), api_schema("QmA", SCHEMA))];
+    assert!(CompositeSchema::new(invalid).is_err());
+}
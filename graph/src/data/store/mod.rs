@@ -139,6 +139,8 @@ pub const ID: &str = "ID";
 pub const BYTES_SCALAR: &str = "Bytes";
 pub const BIG_INT_SCALAR: &str = "BigInt";
 pub const BIG_DECIMAL_SCALAR: &str = "BigDecimal";
+pub const INT8_SCALAR: &str = "Int8";
+pub const TIMESTAMP_SCALAR: &str = "Timestamp";
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ValueType {
@@ -147,7 +149,9 @@ pub enum ValueType {
     Bytes,
     BigDecimal,
     Int,
+    Int8,
     String,
+    Timestamp,
 }
 
 impl FromStr for ValueType {
@@ -160,7 +164,9 @@ impl FromStr for ValueType {
             "Bytes" => Ok(ValueType::Bytes),
             "BigDecimal" => Ok(ValueType::BigDecimal),
             "Int" => Ok(ValueType::Int),
+            "Int8" => Ok(ValueType::Int8),
             "String" | "ID" => Ok(ValueType::String),
+            "Timestamp" => Ok(ValueType::Timestamp),
             s => Err(anyhow!("Type not available in this context: {}", s)),
         }
     }
@@ -187,6 +193,8 @@ pub enum Value {
     Null,
     Bytes(scalar::Bytes),
     BigInt(scalar::BigInt),
+    Int8(i64),
+    Timestamp(scalar::Timestamp),
 }
 
 impl StableHash for Value {
@@ -210,6 +218,8 @@ impl StableHash for Value {
             List(inner) => inner.stable_hash(sequence_number, state),
             Bytes(inner) => inner.stable_hash(sequence_number, state),
             BigInt(inner) => inner.stable_hash(sequence_number, state),
+            Int8(inner) => inner.stable_hash(sequence_number, state),
+            Timestamp(inner) => inner.stable_hash(sequence_number, state),
         }
     }
 }
@@ -243,9 +253,17 @@ impl Value {
                     BYTES_SCALAR => Value::Bytes(scalar::Bytes::from_str(s)?),
                     BIG_INT_SCALAR => Value::BigInt(scalar::BigInt::from_str(s)?),
                     BIG_DECIMAL_SCALAR => Value::BigDecimal(scalar::BigDecimal::from_str(s)?),
+                    INT8_SCALAR => Value::Int8(s.parse::<i64>().map_err(|_| {
+                        QueryExecutionError::ValueParseError("Int8".to_string(), s.to_string())
+                    })?),
+                    TIMESTAMP_SCALAR => Value::Timestamp(scalar::Timestamp::from_str(s)?),
                     _ => Value::String(s.clone()),
                 }
             }
+            (r::Value::Int(i), NamedType(n)) if n == INT8_SCALAR => Value::Int8(*i),
+            (r::Value::Int(i), NamedType(n)) if n == TIMESTAMP_SCALAR => {
+                Value::Timestamp(scalar::Timestamp::since_epoch(*i)?)
+            }
             (r::Value::Int(i), _) => Value::Int(*i as i32),
             (r::Value::Boolean(b), _) => Value::Bool(b.to_owned()),
             (r::Value::Null, _) => Value::Null,
@@ -286,6 +304,22 @@ impl Value {
         }
     }
 
+    pub fn as_int8(&self) -> Option<i64> {
+        if let Value::Int8(i) = self {
+            Some(*i)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<scalar::Timestamp> {
+        if let Value::Timestamp(ts) = self {
+            Some(*ts)
+        } else {
+            None
+        }
+    }
+
     pub fn as_big_decimal(self) -> Option<scalar::BigDecimal> {
         if let Value::BigDecimal(d) = self {
             Some(d)
@@ -334,6 +368,7 @@ impl Value {
             Value::Bool(_) => "Boolean".to_owned(),
             Value::Bytes(_) => "Bytes".to_owned(),
             Value::Int(_) => "Int".to_owned(),
+            Value::Int8(_) => "Int8".to_owned(),
             Value::List(values) => {
                 if let Some(v) = values.first() {
                     format!("[{}]", v.type_name())
@@ -343,6 +378,7 @@ impl Value {
             }
             Value::Null => "Null".to_owned(),
             Value::String(_) => "String".to_owned(),
+            Value::Timestamp(_) => "Timestamp".to_owned(),
         }
     }
 
@@ -354,6 +390,8 @@ impl Value {
             | (Value::Bool(_), ValueType::Boolean)
             | (Value::Bytes(_), ValueType::Bytes)
             | (Value::Int(_), ValueType::Int)
+            | (Value::Int8(_), ValueType::Int8)
+            | (Value::Timestamp(_), ValueType::Timestamp)
             | (Value::Null, _) => true,
             (Value::List(values), _) if is_list => values
                 .iter()
@@ -378,6 +416,8 @@ impl fmt::Display for Value {
                     format!("[{}]", values.iter().map(ToString::to_string).join(", ")),
                 Value::Bytes(ref bytes) => bytes.to_string(),
                 Value::BigInt(ref number) => number.to_string(),
+                Value::Int8(i) => i.to_string(),
+                Value::Timestamp(ref ts) => ts.to_string(),
             }
         )
     }
@@ -394,6 +434,8 @@ impl fmt::Debug for Value {
             Self::Null => write!(f, "Null"),
             Self::Bytes(bytes) => bytes.fmt(f),
             Self::BigInt(number) => number.fmt(f),
+            Self::Int8(i) => f.debug_tuple("Int8").field(i).finish(),
+            Self::Timestamp(ts) => ts.fmt(f),
         }
     }
 }
@@ -411,6 +453,8 @@ impl From<Value> for q::Value {
             }
             Value::Bytes(bytes) => q::Value::String(bytes.to_string()),
             Value::BigInt(number) => q::Value::String(number.to_string()),
+            Value::Int8(i) => q::Value::String(i.to_string()),
+            Value::Timestamp(ts) => q::Value::String(ts.to_string()),
         }
     }
 }
@@ -428,6 +472,8 @@ impl From<Value> for r::Value {
             }
             Value::Bytes(bytes) => r::Value::String(bytes.to_string()),
             Value::BigInt(number) => r::Value::String(number.to_string()),
+            Value::Int8(i) => r::Value::String(i.to_string()),
+            Value::Timestamp(ts) => r::Value::String(ts.to_string()),
         }
     }
 }
@@ -468,6 +514,18 @@ impl From<i32> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Int8(value)
+    }
+}
+
+impl From<scalar::Timestamp> for Value {
+    fn from(value: scalar::Timestamp) -> Value {
+        Value::Timestamp(value)
+    }
+}
+
 impl From<scalar::BigDecimal> for Value {
     fn from(value: scalar::BigDecimal) -> Value {
         Value::BigDecimal(value)
@@ -628,7 +686,9 @@ impl Entity {
         fn scalar_value_type(schema: &Schema, field_type: &s::Type) -> ValueType {
             use s::TypeDefinition as t;
             match field_type {
-                s::Type::NamedType(name) => ValueType::from_str(name).unwrap_or_else(|_| {
+                s::Type::NamedType(name) => {
+                    // Types defined in the schema take precedence over
+                    // builtin scalars with the same name
                     match schema.document.get_named_type(name) {
                         Some(t::Object(obj_type)) => {
                             let id = obj_type.field("id").expect("all object types have an id");
@@ -661,9 +721,10 @@ impl Entity {
                         Some(t::Scalar(_)) => unreachable!("user-defined scalars are not used"),
                         Some(t::Union(_)) => unreachable!("unions are not used"),
                         Some(t::InputObject(_)) => unreachable!("inputObjects are not used"),
-                        None => unreachable!("names of field types have been validated"),
+                        None => ValueType::from_str(name)
+                            .expect("names of field types have been validated"),
                     }
-                }),
+                }
                 s::Type::NonNullType(inner) => scalar_value_type(schema, inner),
                 s::Type::ListType(inner) => scalar_value_type(schema, inner),
             }
@@ -838,6 +899,27 @@ fn value_bigint() {
     assert_eq!(r::Value::from(from_query), graphql_value);
 }
 
+#[test]
+fn value_int8_and_timestamp() {
+    let graphql_value = r::Value::String("9223372036854775807".to_owned());
+    let ty = q::Type::NamedType(INT8_SCALAR.to_owned());
+    let from_query = Value::from_query_value(&graphql_value, &ty).unwrap();
+    assert_eq!(from_query, Value::Int8(i64::MAX));
+    assert_eq!(r::Value::from(from_query), graphql_value);
+
+    let from_query = Value::from_query_value(&r::Value::Int(-17), &ty).unwrap();
+    assert_eq!(from_query, Value::Int8(-17));
+
+    let graphql_value = r::Value::String("1640995200123456".to_owned());
+    let ty = q::Type::NamedType(TIMESTAMP_SCALAR.to_owned());
+    let from_query = Value::from_query_value(&graphql_value, &ty).unwrap();
+    assert_eq!(
+        from_query,
+        Value::Timestamp(scalar::Timestamp::since_epoch(1640995200123456).unwrap())
+    );
+    assert_eq!(r::Value::from(from_query), graphql_value);
+}
+
 #[test]
 fn entity_validation() {
     fn make_thing(name: &str) -> Entity {
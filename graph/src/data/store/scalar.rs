@@ -1,8 +1,10 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
 use diesel::deserialize::FromSql;
 use diesel::serialize::ToSql;
 use diesel_derives::{AsExpression, FromSqlRow};
 use hex;
 use num_bigint;
+use num_traits::{One, Signed, Zero};
 use serde::{self, Deserialize, Serialize};
 use thiserror::Error;
 use web3::types::*;
@@ -11,10 +13,11 @@ use stable_hash_legacy::{
     prelude::*,
     utils::{AsBytes, AsInt},
 };
+use std::cmp::Ordering;
 use std::convert::{TryFrom, TryInto};
 use std::fmt::{self, Display, Formatter};
 use std::io::Write;
-use std::ops::{Add, BitAnd, BitOr, Deref, Div, Mul, Rem, Shl, Shr, Sub};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Deref, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub};
 use std::str::FromStr;
 
 pub use num_bigint::Sign as BigIntSign;
@@ -51,8 +54,6 @@ impl BigDecimal {
     }
 
     pub fn zero() -> BigDecimal {
-        use bigdecimal::Zero;
-
         BigDecimal(bigdecimal::BigDecimal::zero())
     }
 
@@ -84,6 +85,256 @@ impl BigDecimal {
 
         BigDecimal(bigdecimal::BigDecimal::new(int_val, scale))
     }
+
+    pub fn abs(&self) -> BigDecimal {
+        BigDecimal(self.0.abs())
+    }
+
+    /// Round to `places` digits after the decimal point; a negative number
+    /// of `places` rounds to a power of ten. Returns `None` if the result is
+    /// outside of the range `MIN_EXP..=MAX_EXP`
+    pub fn round(&self, places: i64, mode: RoundingMode) -> Option<BigDecimal> {
+        let (int, scale) = self.0.as_bigint_and_exponent();
+        if scale <= places {
+            return Some(self.clone()).filter(BigDecimal::in_range);
+        }
+
+        // Dividing by more than `10^(digits + 1)` leads to the same quotient
+        // and the same comparison of the remainder with half the divisor, and
+        // we avoid computing huge powers of ten
+        let shift = (scale - places).min(self.0.digits() as i64 + 1);
+        let divisor = ten_to_the(shift as u64);
+        let quotient = &int / &divisor;
+        let remainder = &int % &divisor;
+
+        let half = (remainder.abs() * num_bigint::BigInt::from(2)).cmp(&divisor);
+        let away_from_zero = match mode {
+            RoundingMode::Up => !remainder.is_zero(),
+            RoundingMode::Down => false,
+            RoundingMode::Ceiling => remainder.is_positive(),
+            RoundingMode::Floor => remainder.is_negative(),
+            RoundingMode::HalfUp => half != Ordering::Less,
+            RoundingMode::HalfDown => half == Ordering::Greater,
+            RoundingMode::HalfEven => {
+                half == Ordering::Greater
+                    || (half == Ordering::Equal
+                        && !(&quotient % num_bigint::BigInt::from(2)).is_zero())
+            }
+        };
+        let quotient = match (away_from_zero, remainder.is_negative()) {
+            (false, _) => quotient,
+            (true, false) => quotient + num_bigint::BigInt::one(),
+            (true, true) => quotient - num_bigint::BigInt::one(),
+        };
+
+        Some(Self::from(bigdecimal::BigDecimal::new(quotient, places))).filter(BigDecimal::in_range)
+    }
+
+    /// Round towards zero to `places` digits after the decimal point.
+    /// Returns `None` if the result is outside of the range
+    /// `MIN_EXP..=MAX_EXP`
+    pub fn truncate(&self, places: i64) -> Option<BigDecimal> {
+        self.round(places, RoundingMode::Down)
+    }
+
+    /// Raise to an integer power by repeated squaring. Returns `None` if
+    /// the result is undefined or outside of the range `MIN_EXP..=MAX_EXP`
+    pub fn pow(&self, exponent: i32) -> Option<BigDecimal> {
+        if exponent == 0 {
+            return Some(BigDecimal::from(1));
+        }
+        if self.0.is_zero() {
+            return if exponent > 0 {
+                Some(BigDecimal::zero())
+            } else {
+                None
+            };
+        }
+
+        let mut base = self.0.clone();
+        let mut result = bigdecimal::BigDecimal::one();
+        let mut n = exponent.unsigned_abs();
+        loop {
+            if n & 1 == 1 {
+                result = (&result * &base).with_prec(WORKING_PRECISION);
+            }
+            n >>= 1;
+            if n == 0 {
+                break;
+            }
+            // The result is at least as far out of range as any of the
+            // powers of `base` that we need for it, and stopping early
+            // keeps exponents from overflowing
+            if magnitude(&base).abs() > 2 * Self::MAX_EXP as i64 {
+                return None;
+            }
+            base = (&base * &base).with_prec(WORKING_PRECISION);
+        }
+        if exponent < 0 {
+            result = div_prec(&bigdecimal::BigDecimal::one(), &result, WORKING_PRECISION);
+        }
+
+        Some(Self::from(result)).filter(BigDecimal::in_range)
+    }
+
+    /// The square root, or `None` for negative numbers
+    pub fn sqrt(&self) -> Option<BigDecimal> {
+        if self.0.is_negative() {
+            return None;
+        }
+        Some(Self::from(sqrt_prec(&self.0, WORKING_PRECISION)))
+    }
+
+    /// The natural logarithm, or `None` if it is undefined or the number is
+    /// outside of the range `MIN_EXP..=MAX_EXP`
+    pub fn ln(&self) -> Option<BigDecimal> {
+        if !self.0.is_positive() || !self.in_range() {
+            return None;
+        }
+
+        // Take square roots until `x` is close to 1, using that
+        // ln(x) = 2^k ln(x^(1/2^k)), so that the series for ln(x) in terms
+        // of `z = (x - 1) / (x + 1)` converges quickly
+        let one = bigdecimal::BigDecimal::one();
+        let mut x = self.0.clone();
+        let mut halvings = 0;
+        let z = loop {
+            let z = div_prec(&(&x - &one), &(&x + &one), WORKING_PRECISION);
+            if z.is_zero() || magnitude(&z) < -2 {
+                break z;
+            }
+            x = sqrt_prec(&x, WORKING_PRECISION);
+            halvings += 1;
+        };
+
+        // ln(x) = 2 (z + z^3/3 + z^5/5 + ...)
+        let z_squared = (&z * &z).with_prec(WORKING_PRECISION);
+        let mut power = z.clone();
+        let mut sum = z;
+        for k in (3u64..).step_by(2) {
+            power = (&power * &z_squared).with_prec(WORKING_PRECISION);
+            let term = div_prec(&power, &bigdecimal::BigDecimal::from(k), WORKING_PRECISION);
+            if term.is_zero() || magnitude(&term) < magnitude(&sum) - WORKING_PRECISION as i64 {
+                break;
+            }
+            sum = (sum + term).with_prec(WORKING_PRECISION);
+        }
+
+        Some(Self::from(
+            sum * bigdecimal::BigDecimal::from(2u64.pow(halvings + 1)),
+        ))
+    }
+
+    /// The exponential function `e^x`, or `None` if the result is outside
+    /// of the range `MIN_EXP..=MAX_EXP`
+    pub fn exp(&self) -> Option<BigDecimal> {
+        // e^x is out of range for any `x` with more than 5 digits before
+        // the decimal point since e^100000 is about 10^43429
+        let digits = magnitude(&self.0) + 1;
+        if digits > 5 {
+            return None;
+        }
+
+        // Compute e^r for r = x / 2^k with |r| < 2^-10 from its series, and
+        // use that e^x = (e^r)^(2^k)
+        let squarings = 4 * digits.max(0) as u32 + 10;
+        let r = div_prec(
+            &self.0,
+            &bigdecimal::BigDecimal::from(2u64.pow(squarings)),
+            WORKING_PRECISION,
+        );
+        let mut term = bigdecimal::BigDecimal::one();
+        let mut sum = bigdecimal::BigDecimal::one();
+        for k in 1u64.. {
+            term = div_prec(
+                &(&term * &r),
+                &bigdecimal::BigDecimal::from(k),
+                WORKING_PRECISION,
+            );
+            if term.is_zero() || magnitude(&term) < magnitude(&sum) - WORKING_PRECISION as i64 {
+                break;
+            }
+            sum = (sum + &term).with_prec(WORKING_PRECISION);
+        }
+        for _ in 0..squarings {
+            sum = (&sum * &sum).with_prec(WORKING_PRECISION);
+        }
+
+        Some(Self::from(sum)).filter(BigDecimal::in_range)
+    }
+
+    fn in_range(&self) -> bool {
+        self.0.is_zero()
+            || (Self::MIN_EXP as i64..=Self::MAX_EXP as i64).contains(&magnitude(&self.0))
+    }
+}
+
+/// How `BigDecimal::round` treats the digits it drops
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Round away from zero
+    Up,
+    /// Round towards zero
+    Down,
+    /// Round towards positive infinity
+    Ceiling,
+    /// Round towards negative infinity
+    Floor,
+    /// Round to the nearest neighbor, or away from zero if both neighbors
+    /// are equally near
+    HalfUp,
+    /// Round to the nearest neighbor, or towards zero if both neighbors are
+    /// equally near
+    HalfDown,
+    /// Round to the nearest neighbor, or to the even one if both neighbors
+    /// are equally near
+    HalfEven,
+}
+
+/// The number of significant digits of intermediate results in `pow`,
+/// `sqrt`, `ln` and `exp`. The digits beyond `MAX_SIGNFICANT_DIGITS` absorb
+/// rounding errors so that the final result is accurate
+const WORKING_PRECISION: u64 = BigDecimal::MAX_SIGNFICANT_DIGITS as u64 + 16;
+
+fn ten_to_the(exp: u64) -> num_bigint::BigInt {
+    num_traits::pow::pow(num_bigint::BigInt::from(10), exp as usize)
+}
+
+/// The position of the most significant digit of `x`, i.e., the `m` with
+/// `10^m <= |x| < 10^(m+1)`
+fn magnitude(x: &bigdecimal::BigDecimal) -> i64 {
+    let (_, scale) = x.as_bigint_and_exponent();
+    x.digits() as i64 - scale - 1
+}
+
+/// Divide `x` by `y`, which must not be zero, with `prec` significant digits
+fn div_prec(
+    x: &bigdecimal::BigDecimal,
+    y: &bigdecimal::BigDecimal,
+    prec: u64,
+) -> bigdecimal::BigDecimal {
+    let (x_int, x_scale) = x.as_bigint_and_exponent();
+    let (y_int, y_scale) = y.as_bigint_and_exponent();
+
+    // Shift `x` so that the integer quotient has more than `prec` digits
+    let shift = (prec + y.digits() + 1).saturating_sub(x.digits());
+    let quotient = x_int * ten_to_the(shift) / y_int;
+    bigdecimal::BigDecimal::new(quotient, x_scale - y_scale + shift as i64).with_prec(prec)
+}
+
+/// The square root of `x`, which must not be negative, with `prec`
+/// significant digits
+fn sqrt_prec(x: &bigdecimal::BigDecimal, prec: u64) -> bigdecimal::BigDecimal {
+    let (int, scale) = x.as_bigint_and_exponent();
+
+    // Shift `x` so that its scale is even and the integer square root has
+    // more than `prec` digits
+    let mut shift = (2 * prec + 2).saturating_sub(x.digits());
+    if (scale + shift as i64) % 2 != 0 {
+        shift += 1;
+    }
+    let root = (int * ten_to_the(shift)).sqrt();
+    bigdecimal::BigDecimal::new(root, (scale + shift as i64) / 2).with_prec(prec)
 }
 
 impl Display for BigDecimal {
@@ -154,6 +405,14 @@ impl Mul for BigDecimal {
     }
 }
 
+impl Neg for BigDecimal {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        BigDecimal(self.0.neg())
+    }
+}
+
 impl Div for BigDecimal {
     type Output = Self;
 
@@ -337,6 +596,14 @@ impl BigInt {
     pub fn bits(&self) -> usize {
         self.0.bits()
     }
+
+    /// The square root rounded down, or `None` for negative numbers
+    pub fn sqrt(&self) -> Option<BigInt> {
+        if self.0.is_negative() {
+            return None;
+        }
+        Some(BigInt(self.0.sqrt()))
+    }
 }
 
 impl Display for BigInt {
@@ -476,6 +743,22 @@ impl BitAnd for BigInt {
     }
 }
 
+impl BitXor for BigInt {
+    type Output = Self;
+
+    fn bitxor(self, other: Self) -> Self {
+        Self::from(self.0.bitxor(other.0))
+    }
+}
+
+impl Not for BigInt {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self::from(self.0.not())
+    }
+}
+
 impl Shl<u8> for BigInt {
     type Output = Self;
 
@@ -574,9 +857,98 @@ impl<'de> Deserialize<'de> for Bytes {
     }
 }
 
+/// A point in time with microsecond precision. Timestamps are stored as
+/// `timestamptz` in the database, and represented in GraphQL as the number
+/// of microseconds since the Unix epoch
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub DateTime<Utc>);
+
+#[derive(Error, Debug)]
+pub enum TimestampError {
+    #[error("Invalid timestamp string: {0}")]
+    StringParseError(String),
+    #[error("Timestamp {0} is out of range")]
+    OutOfRange(i64),
+}
+
+impl Timestamp {
+    /// Construct a timestamp from the number of microseconds since the
+    /// Unix epoch
+    pub fn since_epoch(micros: i64) -> Result<Self, TimestampError> {
+        let secs = micros.div_euclid(1_000_000);
+        let nsecs = (micros.rem_euclid(1_000_000) * 1000) as u32;
+        NaiveDateTime::from_timestamp_opt(secs, nsecs)
+            .map(|dt| Timestamp(DateTime::from_utc(dt, Utc)))
+            .ok_or(TimestampError::OutOfRange(micros))
+    }
+
+    /// Parse a timestamp in RFC 3339 format, like the ones that Postgres
+    /// produces when formatting a `timestamptz` as JSON
+    pub fn parse_rfc3339(s: &str) -> Result<Self, TimestampError> {
+        DateTime::parse_from_rfc3339(s)
+            .map(|dt| Timestamp(dt.with_timezone(&Utc)))
+            .map_err(|_| TimestampError::StringParseError(s.to_string()))
+    }
+
+    pub fn as_microseconds_since_epoch(&self) -> i64 {
+        self.0.timestamp() * 1_000_000 + self.0.timestamp_subsec_micros() as i64
+    }
+
+    /// The timestamp in RFC 3339 format with microsecond precision,
+    /// suitable for passing to Postgres
+    pub fn to_rfc3339(&self) -> String {
+        self.0.to_rfc3339_opts(SecondsFormat::Micros, true)
+    }
+}
+
+impl StableHash for Timestamp {
+    fn stable_hash<H: StableHasher>(&self, sequence_number: H::Seq, state: &mut H) {
+        self.as_microseconds_since_epoch()
+            .stable_hash(sequence_number, state)
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = TimestampError;
+
+    /// Parse the number of microseconds since the Unix epoch
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let micros =
+            i64::from_str(s).map_err(|_| TimestampError::StringParseError(s.to_string()))?;
+        Timestamp::since_epoch(micros)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_microseconds_since_epoch())
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Timestamp({})", self.to_rfc3339())
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_microseconds_since_epoch().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let micros = i64::deserialize(deserializer)?;
+        Timestamp::since_epoch(micros).map_err(D::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{BigDecimal, BigInt, Bytes};
+    use super::{BigDecimal, BigInt, Bytes, RoundingMode, Timestamp};
     use stable_hash_legacy::crypto::SetHasher;
     use stable_hash_legacy::prelude::*;
     use stable_hash_legacy::utils::stable_hash;
@@ -677,6 +1049,95 @@ mod test {
         }
     }
 
+    #[test]
+    fn big_decimal_math() {
+        let dec = |s: &str| BigDecimal::from_str(s).unwrap();
+        let show = |x: Option<BigDecimal>| x.map(|x| x.to_string());
+
+        let cases = vec![
+            (dec("2").sqrt(), Some("1.414213562373095048801688724209698")),
+            (dec("0.25").sqrt(), Some("0.5")),
+            (dec("-1").sqrt(), None),
+            (dec("0").exp(), Some("1")),
+            (dec("1").exp(), Some("2.718281828459045235360287471352662")),
+            (
+                dec("-1").exp(),
+                Some("0.3678794411714423215955237701614609"),
+            ),
+            (dec("15000").exp(), None),
+            (dec("1").ln(), Some("0")),
+            (dec("10").ln(), Some("2.302585092994045684017991454684364")),
+            (
+                dec("1e6144").ln(),
+                Some("14147.08281135541668260653949758073"),
+            ),
+            (dec("0").ln(), None),
+            (dec("5").exp().unwrap().ln(), Some("5")),
+            (dec("2").pow(10), Some("1024")),
+            (dec("2").pow(-2), Some("0.25")),
+            (dec("-1.5").pow(3), Some("-3.375")),
+            (dec("0").pow(-1), None),
+            (dec("10").pow(7000), None),
+            (
+                dec("1.0001").pow(887272),
+                Some("340256786836388094050805785052946500000"),
+            ),
+        ];
+        for (actual, expected) in cases {
+            assert_eq!(expected.map(str::to_string), show(actual));
+        }
+    }
+
+    #[test]
+    fn big_decimal_round() {
+        use RoundingMode::*;
+
+        let cases = vec![
+            ("2.5", 0, HalfEven, "2"),
+            ("3.5", 0, HalfEven, "4"),
+            ("-0.5", 0, HalfEven, "0"),
+            ("2.5", 0, HalfUp, "3"),
+            ("-2.5", 0, HalfUp, "-3"),
+            ("2.5", 0, HalfDown, "2"),
+            ("-2.1", 0, Floor, "-3"),
+            ("-2.1", 0, Ceiling, "-2"),
+            ("-2.1", 0, Up, "-3"),
+            ("0.0000001", 2, Up, "0.01"),
+            ("-1.999", 0, Down, "-1"),
+            ("1234.5678", 2, HalfUp, "1234.57"),
+            ("1234.5678", -2, HalfUp, "1200"),
+            ("1.5", 5, HalfUp, "1.5"),
+        ];
+        for (x, places, mode, expected) in cases {
+            let x = BigDecimal::from_str(x).unwrap();
+            assert_eq!(expected, x.round(places, mode).unwrap().to_string());
+        }
+        assert_eq!(
+            "1.99",
+            BigDecimal::from_str("1.999")
+                .unwrap()
+                .truncate(2)
+                .unwrap()
+                .to_string()
+        );
+
+        // Rounding away from zero to a power of ten that is out of range
+        let x = BigDecimal::from_str("1.5").unwrap();
+        assert_eq!(None, x.round(-(BigDecimal::MAX_EXP as i64) - 1, Up));
+        assert_eq!(
+            Some(BigDecimal::from(0)),
+            x.truncate(-(BigDecimal::MAX_EXP as i64) - 1)
+        );
+    }
+
+    #[test]
+    fn big_int_bit_ops_and_sqrt() {
+        assert_eq!(BigInt::from(6), BigInt::from(5) ^ BigInt::from(3));
+        assert_eq!(BigInt::from(-6), !BigInt::from(5));
+        assert_eq!(Some(BigInt::from(9)), BigInt::from(99).sqrt());
+        assert_eq!(None, BigInt::from(-1).sqrt());
+    }
+
     #[test]
     fn fmt_debug() {
         let bi = BigInt::from(-17);
@@ -686,4 +1147,17 @@ mod test {
         assert_eq!("BigDecimal(-0.17)", format!("{:?}", bd));
         assert_eq!("Bytes(0xdeadbeef)", format!("{:?}", bytes));
     }
+
+    #[test]
+    fn timestamp_roundtrip() {
+        for micros in [0, 1, -1, 1_640_995_200_123_456, -86_400_000_001] {
+            let ts = Timestamp::since_epoch(micros).unwrap();
+            assert_eq!(micros, ts.as_microseconds_since_epoch());
+            assert_eq!(ts, Timestamp::from_str(&ts.to_string()).unwrap());
+            assert_eq!(ts, Timestamp::parse_rfc3339(&ts.to_rfc3339()).unwrap());
+        }
+
+        let ts = Timestamp::parse_rfc3339("2022-01-01T02:00:00.5+02:00").unwrap();
+        assert_eq!(1_640_995_200_500_000, ts.as_microseconds_since_epoch());
+    }
 }
@@ -762,6 +762,9 @@ pub struct DeploymentState {
     pub max_reorg_depth: u32,
     /// The number of the last block that the subgraph has processed
     pub latest_ethereum_block_number: BlockNumber,
+    /// The number of the earliest block for which the subgraph still has
+    /// data; history before that block has been pruned
+    pub earliest_block_number: BlockNumber,
 }
 
 impl DeploymentState {
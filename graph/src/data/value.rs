@@ -193,6 +193,8 @@ impl Value {
         match (using_type.name.as_str(), self) {
             (_, Value::Null) => Ok(Value::Null),
             ("Boolean", Value::Boolean(b)) => Ok(Value::Boolean(b)),
+            ("Float", Value::Float(f)) => Ok(Value::Float(f)),
+            ("Float", Value::Int(i)) => Ok(Value::Float(i as f64)),
             ("BigDecimal", Value::Float(f)) => Ok(Value::String(f.to_string())),
             ("BigDecimal", Value::Int(i)) => Ok(Value::String(i.to_string())),
             ("BigDecimal", Value::String(s)) => Ok(Value::String(s)),
@@ -209,6 +211,13 @@ impl Value {
             ("Bytes", Value::String(s)) => Ok(Value::String(s)),
             ("BigInt", Value::String(s)) => Ok(Value::String(s)),
             ("BigInt", Value::Int(n)) => Ok(Value::String(n.to_string())),
+            ("Int8", Value::Int(n)) => Ok(Value::Int(n)),
+            ("Int8", Value::String(s)) => match s.parse::<i64>() {
+                Ok(n) => Ok(Value::Int(n)),
+                Err(_) => Err(Value::String(s)),
+            },
+            ("Timestamp", Value::Int(n)) => Ok(Value::String(n.to_string())),
+            ("Timestamp", Value::String(s)) => Ok(Value::String(s)),
             ("JSONObject", Value::Object(obj)) => Ok(Value::Object(obj)),
             ("Date", Value::String(obj)) => Ok(Value::String(obj)),
             (_, v) => Err(v),
@@ -82,6 +82,57 @@ pub struct EnvVarsGraphQl {
     /// Set by the flag `GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION`. No
     /// default is provided.
     pub max_operations_per_connection: Option<usize>,
+    /// How many automatic persisted queries to keep in memory.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_PERSISTED_QUERY_CACHE_SIZE`. The default value is 1000.
+    pub persisted_query_cache_size: usize,
+    /// Whether automatic persisted queries should also be stored in the
+    /// primary database so that they survive restarts and are shared
+    /// between query nodes.
+    ///
+    /// Set by the flag `GRAPH_GRAPHQL_PERSIST_QUERIES`. Off by default.
+    pub persist_queries: bool,
+    /// Whether requests without an API key may store automatic persisted
+    /// queries in the primary database. Queries that such requests
+    /// register are otherwise only kept in memory.
+    ///
+    /// Set by the flag `GRAPH_GRAPHQL_PERSIST_ANONYMOUS_QUERIES`. Off by
+    /// default.
+    pub persist_anonymous_queries: bool,
+    /// The maximum size in bytes of the text of an automatic persisted
+    /// query; registering larger queries fails.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_MAX_PERSISTED_QUERY_SIZE`. The default value is 16384.
+    pub max_persisted_query_size: usize,
+    /// How many automatic persisted queries to keep in the primary
+    /// database; a periodic job removes the ones that were stored first
+    /// when there are more.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_MAX_PERSISTED_QUERIES`.
+    /// The default value is 10000.
+    pub max_persisted_queries: usize,
+    /// The `max-age` in seconds of the `Cache-Control` header for responses
+    /// to `GET` requests that send the hash of a persisted query.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_PERSISTED_QUERY_MAX_AGE`. The default value is 1.
+    pub persisted_query_max_age: u64,
+    /// The maximum number of queries in a batch of queries sent in one
+    /// HTTP request.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_MAX_BATCH_SIZE`. The
+    /// default value is 100.
+    pub max_batch_size: usize,
+    /// How long a client that uses the `graphql-transport-ws` protocol has
+    /// to send its `connection_init` message before the server closes the
+    /// connection.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_WS_CONNECTION_INIT_TIMEOUT` (expressed in seconds). The
+    /// default value is 3s.
+    pub ws_connection_init_timeout: Duration,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -125,6 +176,14 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
             warn_result_size: x.warn_result_size.0 .0,
             error_result_size: x.error_result_size.0 .0,
             max_operations_per_connection: x.max_operations_per_connection,
+            persisted_query_cache_size: x.persisted_query_cache_size,
+            persist_queries: x.persist_queries.0,
+            persist_anonymous_queries: x.persist_anonymous_queries.0,
+            max_persisted_query_size: x.max_persisted_query_size,
+            max_persisted_queries: x.max_persisted_queries,
+            persisted_query_max_age: x.persisted_query_max_age,
+            max_batch_size: x.max_batch_size,
+            ws_connection_init_timeout: Duration::from_secs(x.ws_connection_init_timeout_in_secs),
         }
     }
 }
@@ -168,4 +227,20 @@ pub struct InnerGraphQl {
     error_result_size: WithDefaultUsize<NoUnderscores<usize>, { usize::MAX }>,
     #[envconfig(from = "GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION")]
     max_operations_per_connection: Option<usize>,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERY_CACHE_SIZE", default = "1000")]
+    persisted_query_cache_size: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSIST_QUERIES", default = "false")]
+    persist_queries: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSIST_ANONYMOUS_QUERIES", default = "false")]
+    persist_anonymous_queries: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_MAX_PERSISTED_QUERY_SIZE", default = "16384")]
+    max_persisted_query_size: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_MAX_PERSISTED_QUERIES", default = "10000")]
+    max_persisted_queries: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERY_MAX_AGE", default = "1")]
+    persisted_query_max_age: u64,
+    #[envconfig(from = "GRAPH_GRAPHQL_MAX_BATCH_SIZE", default = "100")]
+    max_batch_size: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_WS_CONNECTION_INIT_TIMEOUT", default = "3")]
+    ws_connection_init_timeout_in_secs: u64,
 }
@@ -1,6 +1,7 @@
 use std::fmt;
 
 use super::*;
+use crate::components::store::BlockNumber;
 
 #[derive(Clone)]
 pub struct EnvVarsStore {
@@ -78,6 +79,13 @@ pub struct EnvVarsStore {
     /// Set by the environment variable `GRAPH_REMOVE_UNUSED_INTERVAL`
     /// (expressed in minutes). The default value is 360 minutes.
     pub remove_unused_interval: chrono::Duration,
+    /// The smallest number of blocks of history that a deployment can be
+    /// pruned to. This needs to be at least as large as the reorg
+    /// threshold of any chain so that reverts never need pruned data.
+    ///
+    /// Set by the environment variable `GRAPH_STORE_HISTORY_MIN_BLOCKS`. The
+    /// default value is 250 blocks.
+    pub history_min_blocks: BlockNumber,
 
     // These should really be set through the configuration file, especially for
     // `GRAPH_STORE_CONNECTION_MIN_IDLE` and
@@ -140,6 +148,7 @@ impl From<InnerStore> for EnvVarsStore {
             remove_unused_interval: chrono::Duration::minutes(
                 x.remove_unused_interval_in_minutes as i64,
             ),
+            history_min_blocks: x.history_min_blocks,
             connection_timeout: Duration::from_millis(x.connection_timeout_in_millis),
             connection_min_idle: x.connection_min_idle,
             connection_idle_timeout: Duration::from_secs(x.connection_idle_timeout_in_secs),
@@ -177,6 +186,8 @@ pub struct InnerStore {
     connection_try_always: EnvVarBoolean,
     #[envconfig(from = "GRAPH_REMOVE_UNUSED_INTERVAL", default = "360")]
     remove_unused_interval_in_minutes: u64,
+    #[envconfig(from = "GRAPH_STORE_HISTORY_MIN_BLOCKS", default = "250")]
+    history_min_blocks: BlockNumber,
 
     // These should really be set through the configuration file, especially for
     // `GRAPH_STORE_CONNECTION_MIN_IDLE` and
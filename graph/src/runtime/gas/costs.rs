@@ -50,6 +50,27 @@ pub const BIG_MATH_GAS_OP: GasOp = GasOp {
     size_mult: BIG_MATH_GAS_PER_BYTE,
 };
 
+/// `pow`, `sqrt`, `ln` and `exp` for `BigDecimal` iterate on numbers with a fixed number of
+/// digits, which takes up to about 100µs no matter how big the argument is.
+pub const BIG_DECIMAL_ITERATIVE_GAS_OP: GasOp = GasOp {
+    base_cost: GAS_PER_SECOND / 10_000,
+    size_mult: BIG_MATH_GAS_PER_BYTE,
+};
+
+/// Hash functions are much faster than the default assumption, so assume a bandwidth of
+/// 100 MB/s like for big math.
+pub const HASH_GAS_OP: GasOp = GasOp {
+    base_cost: DEFAULT_BASE_COST,
+    size_mult: GAS_PER_SECOND / 100_000_000,
+};
+
+/// Recovering a public key from a signature or verifying a signature takes about 100µs no matter
+/// how small the input is; after that, the message is hashed at the default bandwidth.
+pub const SIGNATURE_GAS_OP: GasOp = GasOp {
+    base_cost: GAS_PER_SECOND / 10_000,
+    size_mult: DEFAULT_GAS_PER_BYTE,
+};
+
 // Allow up to 100,000 data sources to be created
 pub const CREATE_DATA_SOURCE: Gas = Gas(CONST_MAX_GAS_PER_HANDLER / 100_000);
 
@@ -73,4 +94,11 @@ pub const STORE_GET: GasOp = GasOp {
     size_mult: CONST_MAX_GAS_PER_HANDLER / 10_000_000_000,
 };
 
+// Looking an entity up among the changes of the current block never goes
+// to the database, and is therefore much cheaper than a `STORE_GET`.
+pub const STORE_GET_IN_BLOCK: GasOp = GasOp {
+    base_cost: CONST_MAX_GAS_PER_HANDLER / 100_000_000,
+    size_mult: STORE_GET.size_mult,
+};
+
 pub const STORE_REMOVE: GasOp = STORE_SET;
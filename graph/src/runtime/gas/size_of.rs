@@ -19,6 +19,8 @@ impl GasSizeOf for Value {
             Value::Bytes(bytes) => bytes.gas_size_of(),
             Value::Bool(bool) => bool.gas_size_of(),
             Value::BigInt(big_int) => big_int.gas_size_of(),
+            Value::Int8(int) => int.gas_size_of(),
+            Value::Timestamp(ts) => ts.as_microseconds_since_epoch().gas_size_of(),
         };
         Gas(4) + inner
     }
@@ -258,10 +258,11 @@ pub enum IndexForAscTypeId {
     Log = 1001,
     ArrayH256 = 1002,
     ArrayLog = 1003,
+    StorageRequest = 1004,
     // Continue to add more Ethereum type IDs here.
     // e.g.:
-    // NextEthereumType = 1004,
-    // AnotherEthereumType = 1005,
+    // NextEthereumType = 1005,
+    // AnotherEthereumType = 1006,
     // ...
     // LastEthereumType = 1499,
 
@@ -333,6 +334,11 @@ pub enum IndexForAscTypeId {
     //    name and implementation before running this script.
     // 2. Replace `2500` part with the first number of that blockchain's reserved discriminant space.
     // 3. Insert the output right before the end of this block.
+
+    // Reserved discriminant space for more type IDs that are not specific
+    // to a blockchain: [3,500, 4,499]
+    ArrayTypedMapStringStoreValue = 3500,
+    // Continue to add more type IDs here.
 }
 
 impl ToAscObj<u32> for IndexForAscTypeId {
@@ -86,7 +86,9 @@ impl CacheWeight for Value {
             Value::List(values) => values.indirect_weight(),
             Value::Bytes(bytes) => bytes.indirect_weight(),
             Value::BigInt(n) => n.indirect_weight(),
-            Value::Int(_) | Value::Bool(_) | Value::Null => 0,
+            Value::Int(_) | Value::Int8(_) | Value::Timestamp(_) | Value::Bool(_) | Value::Null => {
+                0
+            }
         }
     }
 }
@@ -0,0 +1,86 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// A map that holds at most `capacity` entries; when it is full, inserting
+/// an entry evicts the entry that was used least recently. Looking an entry
+/// up with `get` counts as using it
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// The value and the tick when it was last accessed
+    entries: HashMap<K, (V, u64)>,
+    /// Maps the tick of the last access to the key of the entry
+    order: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (value, last) = self.entries.get_mut(key)?;
+        self.order.remove(last);
+        self.order.insert(tick, key.clone());
+        *last = tick;
+        Some(value.clone())
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, last)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.order.remove(&last);
+        }
+        self.order.insert(self.tick, key);
+        while self.entries.len() > self.capacity {
+            let oldest = *self.order.keys().next().unwrap();
+            let key = self.order.remove(&oldest).unwrap();
+            self.entries.remove(&key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[test]
+fn evicts_least_recently_used() {
+    let mut cache = LruCache::new(2);
+    cache.insert("one", 1);
+    cache.insert("two", 2);
+    assert_eq!(Some(1), cache.get(&"one"));
+
+    // `two` is now the least recently used entry
+    cache.insert("three", 3);
+    assert_eq!(2, cache.len());
+    assert_eq!(None, cache.get(&"two"));
+    assert_eq!(Some(1), cache.get(&"one"));
+    assert_eq!(Some(3), cache.get(&"three"));
+
+    // Replacing an entry does not grow the cache
+    cache.insert("three", 4);
+    assert_eq!(2, cache.len());
+    assert_eq!(Some(4), cache.get(&"three"));
+
+    let mut cache = LruCache::new(0);
+    cache.insert("one", 1);
+    assert!(cache.is_empty());
+}
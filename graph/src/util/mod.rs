@@ -6,6 +6,8 @@ pub mod security;
 
 pub mod lfu_cache;
 
+pub mod lru_cache;
+
 pub mod timed_cache;
 
 pub mod error;
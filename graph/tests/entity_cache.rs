@@ -7,7 +7,9 @@ use slog::Logger;
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use graph::components::store::{EntityType, StoredDynamicDataSource, WritableStore};
+use graph::components::store::{
+    DerivedEntityQuery, EntityType, StoredDynamicDataSource, WritableStore,
+};
 use graph::{
     components::store::{DeploymentId, DeploymentLocator},
     prelude::{anyhow, DeploymentHash, Entity, EntityCache, EntityKey, EntityModification, Value},
@@ -26,6 +28,18 @@ lazy_static! {
                 founded: Int
                 label: String
             }
+
+            type Account @entity {
+                id: ID!
+                name: String!
+                wallets: [Wallet!]! @derivedFrom(field: "account")
+            }
+
+            type Wallet @entity {
+                id: ID!
+                balance: Int!
+                account: Account!
+            }
             ",
             SUBGRAPH_ID.clone(),
         )
@@ -122,6 +136,27 @@ impl WritableStore for MockStore {
         Ok(self.get_many_res.clone())
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        Ok(self
+            .get_many_res
+            .get(&query.entity_type)
+            .into_iter()
+            .flatten()
+            .filter(|entity| query.matches(entity))
+            .map(|entity| {
+                let key = EntityKey::data(
+                    SUBGRAPH_ID.clone(),
+                    query.entity_type.to_string(),
+                    entity.id().unwrap(),
+                );
+                (key, entity.clone())
+            })
+            .collect())
+    }
+
     async fn is_deployment_synced(&self) -> Result<bool, StoreError> {
         unimplemented!()
     }
@@ -239,6 +274,15 @@ fn overwrite_modifications() {
                 vec![("id", "sigurros".into()), ("name", "Sigur Ros".into())],
             )
             .1,
+            make_band(
+                "tortoise",
+                vec![
+                    ("id", "tortoise".into()),
+                    ("name", "Tortoise".into()),
+                    ("founded", 1990.into()),
+                ],
+            )
+            .1,
         ];
         MockStore::new(entity_version_map("Band", entities))
     };
@@ -340,3 +384,164 @@ fn consecutive_modifications() {
         },])
     );
 }
+
+fn make_wallet(id: &'static str, account: &str, balance: i32) -> (EntityKey, Entity) {
+    (
+        EntityKey::data(SUBGRAPH_ID.clone(), "Wallet".to_string(), id.into()),
+        Entity::from(vec![
+            ("id", id.into()),
+            ("account", account.into()),
+            ("balance", balance.into()),
+        ]),
+    )
+}
+
+#[test]
+fn load_related_entities() {
+    let store = {
+        let wallets = vec![
+            make_wallet("w1", "a1", 10).1,
+            make_wallet("w2", "a1", 20).1,
+            make_wallet("w3", "a2", 30).1,
+        ];
+        MockStore::new(entity_version_map("Wallet", wallets))
+    };
+
+    let store = Arc::new(store);
+    let mut cache = EntityCache::new(store.clone());
+
+    let a1 = EntityKey::data(SUBGRAPH_ID.clone(), "Account".to_string(), "a1".into());
+    let a2 = EntityKey::data(SUBGRAPH_ID.clone(), "Account".to_string(), "a2".into());
+
+    // A new wallet for `a1`, a wallet that moves from `a1` to `a2`, and a
+    // change to a wallet of `a2` that does not change its account
+    let (w4_key, w4) = make_wallet("w4", "a1", 40);
+    cache.set(w4_key, w4.clone()).unwrap();
+    let w2_key = make_wallet("w2", "a2", 20).0;
+    cache
+        .set(w2_key, Entity::from(vec![("account", "a2".into())]))
+        .unwrap();
+    let w3_key = make_wallet("w3", "a2", 30).0;
+    cache
+        .set(w3_key, Entity::from(vec![("balance", 35.into())]))
+        .unwrap();
+
+    assert_eq!(
+        vec![make_wallet("w1", "a1", 10).1, w4],
+        cache.load_related(&a1, "wallets").unwrap()
+    );
+    assert_eq!(
+        vec![make_wallet("w2", "a2", 20).1, make_wallet("w3", "a2", 35).1],
+        cache.load_related(&a2, "wallets").unwrap()
+    );
+
+    // Removing an entity removes it from the related entities
+    cache.remove(make_wallet("w1", "a1", 10).0);
+    assert_eq!(
+        vec![make_wallet("w4", "a1", 40).1],
+        cache.load_related(&a1, "wallets").unwrap()
+    );
+
+    // Only `@derivedFrom` fields can be loaded
+    assert!(cache.load_related(&a1, "name").is_err());
+}
+
+#[test]
+fn get_in_block_only_sees_changes() {
+    let store = {
+        let entities = vec![
+            make_band(
+                "mogwai",
+                vec![("id", "mogwai".into()), ("name", "Mogwai".into())],
+            )
+            .1,
+            make_band(
+                "sigurros",
+                vec![("id", "sigurros".into()), ("name", "Sigur Ros".into())],
+            )
+            .1,
+            make_band(
+                "tortoise",
+                vec![
+                    ("id", "tortoise".into()),
+                    ("name", "Tortoise".into()),
+                    ("founded", 1990.into()),
+                ],
+            )
+            .1,
+        ];
+        MockStore::new(entity_version_map("Band", entities))
+    };
+
+    let store = Arc::new(store);
+    let mut cache = EntityCache::new(store.clone());
+
+    // Entities that are only in the store are not visible
+    let (mogwai_key, _) = make_band("mogwai", vec![]);
+    let (sigurros_key, _) = make_band("sigurros", vec![]);
+    assert_eq!(None, cache.get_in_block(&mogwai_key));
+    assert_eq!(None, cache.get_in_block(&sigurros_key));
+
+    // New entities are visible
+    let (bob_key, bob_data) = make_band("bob", vec![("id", "bob".into()), ("name", "Bob".into())]);
+    cache.set(bob_key.clone(), bob_data.clone()).unwrap();
+    assert_eq!(Some(bob_data), cache.get_in_block(&bob_key));
+
+    // Partial updates only contain the attributes set in this block, even
+    // though `set` had to load the entity from the store to validate it
+    cache
+        .set(
+            mogwai_key.clone(),
+            Entity::from(vec![("founded", 1995.into())]),
+        )
+        .unwrap();
+    assert_eq!(
+        Some(Entity::from(vec![
+            ("id", "mogwai".into()),
+            ("founded", 1995.into()),
+        ])),
+        cache.get_in_block(&mogwai_key)
+    );
+
+    // Updates that are valid by themselves are never merged with the
+    // version from the store
+    let (tortoise_key, _) = make_band("tortoise", vec![]);
+    cache
+        .set(
+            tortoise_key.clone(),
+            Entity::from(vec![
+                ("name", "Tortoise!".into()),
+                ("label", "Thrill".into()),
+            ]),
+        )
+        .unwrap();
+    assert_eq!(
+        Some(Entity::from(vec![
+            ("id", "tortoise".into()),
+            ("name", "Tortoise!".into()),
+            ("label", "Thrill".into()),
+        ])),
+        cache.get_in_block(&tortoise_key)
+    );
+
+    // Removed entities are not visible
+    cache.remove(mogwai_key.clone());
+    assert_eq!(None, cache.get_in_block(&mogwai_key));
+
+    // Entities that are removed and then set again do not include
+    // attributes from the store
+    cache.remove(tortoise_key.clone());
+    cache
+        .set(
+            tortoise_key.clone(),
+            Entity::from(vec![("name", "Tortoise".into())]),
+        )
+        .unwrap();
+    assert_eq!(
+        Some(Entity::from(vec![
+            ("id", "tortoise".into()),
+            ("name", "Tortoise".into()),
+        ])),
+        cache.get_in_block(&tortoise_key)
+    );
+}
@@ -0,0 +1,517 @@
+//! Composite queries query several deployments at once through a
+//! `CompositeSchema`. They are executed by splitting them into one query
+//! for each namespace field at the root of the query; each of these
+//! queries is then run against the deployment for the namespace like any
+//! other query
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use graph::data::schema::CompositeSchema;
+use graph::prelude::{q, r, Query, QueryExecutionError};
+use graphql_parser::Pos;
+
+use crate::introspection::is_introspection_field;
+use crate::query::ast::get_operation;
+
+/// A part of a composite query that can be run on its own
+pub(crate) enum Part {
+    /// Introspection fields at the root of the composite query. They are
+    /// run against the composite schema, and their data goes directly
+    /// into the result
+    Introspection(Query),
+    /// The selection of a namespace field. It is run against the
+    /// deployment for `namespace`, and its data goes into the result
+    /// under `response_key`
+    Namespace {
+        response_key: String,
+        namespace: String,
+        query: Query,
+    },
+}
+
+/// A composite query and the composite schema it is run against
+pub(crate) struct CompositeQuery<'a> {
+    schema: &'a CompositeSchema,
+    query: &'a Query,
+    name: Option<String>,
+    variable_definitions: &'a [q::VariableDefinition],
+    selection_set: &'a q::SelectionSet,
+    fragments: HashMap<&'a str, &'a q::FragmentDefinition>,
+}
+
+impl<'a> CompositeQuery<'a> {
+    /// Find the operation of `query`, using its `operation_name` if the
+    /// document has several operations. Composite queries can only be
+    /// queries, not subscriptions or mutations
+    pub fn new(schema: &'a CompositeSchema, query: &'a Query) -> Result<Self, QueryExecutionError> {
+        let fragments = query
+            .document
+            .definitions
+            .iter()
+            .filter_map(|defn| match defn {
+                q::Definition::Fragment(frag) => Some((frag.name.as_str(), frag)),
+                q::Definition::Operation(_) => None,
+            })
+            .collect();
+
+        let operation = get_operation(&query.document, query.operation_name.as_deref())?;
+        let (name, variable_definitions, selection_set) = match operation {
+            q::OperationDefinition::SelectionSet(selection_set) => (None, &[][..], selection_set),
+            q::OperationDefinition::Query(query) => (
+                query.name.clone(),
+                query.variable_definitions.as_slice(),
+                &query.selection_set,
+            ),
+            q::OperationDefinition::Subscription(_) | q::OperationDefinition::Mutation(_) => {
+                return Err(QueryExecutionError::NotSupported(
+                    "Composite queries only support queries".to_string(),
+                ))
+            }
+        };
+
+        Ok(CompositeQuery {
+            schema,
+            query,
+            name,
+            variable_definitions,
+            selection_set,
+            fragments,
+        })
+    }
+
+    /// Split the query into parts. The query must have been validated
+    /// against the composite schema
+    pub fn parts(&self) -> Result<Vec<Part>, QueryExecutionError> {
+        let mut root_fields = Vec::new();
+        self.root_fields(self.selection_set, &mut root_fields)?;
+
+        // Group the namespace fields by response key; fields with the same
+        // response key are merged into one
+        let mut introspection = Vec::new();
+        let mut namespaces: Vec<(String, &str, Vec<q::Selection>)> = Vec::new();
+        for field in root_fields {
+            if field.name == "__typename" || is_introspection_field(&field.name) {
+                introspection.push(q::Selection::Field(field.clone()));
+                continue;
+            }
+            let response_key = field.alias.as_ref().unwrap_or(&field.name);
+            let items = field.selection_set.items.iter().cloned();
+            match namespaces
+                .iter_mut()
+                .find(|(key, _, _)| key == response_key)
+            {
+                Some((_, _, selections)) => selections.extend(items),
+                None => namespaces.push((response_key.clone(), &field.name, items.collect())),
+            }
+        }
+
+        let mut parts = Vec::new();
+        if !introspection.is_empty() {
+            let query = self.subquery(introspection, &|name| name.to_string())?;
+            parts.push(Part::Introspection(query));
+        }
+        for (response_key, namespace, selections) in namespaces {
+            let query = self.subquery(selections, &|name| {
+                self.schema.local_type_name(namespace, name).to_string()
+            })?;
+            parts.push(Part::Namespace {
+                response_key,
+                namespace: namespace.to_string(),
+                query,
+            });
+        }
+        Ok(parts)
+    }
+
+    fn fragment(&self, name: &str) -> Result<&'a q::FragmentDefinition, QueryExecutionError> {
+        self.fragments
+            .get(name)
+            .copied()
+            .ok_or_else(|| QueryExecutionError::UndefinedFragment(name.to_string()))
+    }
+
+    /// Collect the fields at the root of the query that are not excluded
+    /// with `@skip` or `@include`, looking through fragments
+    fn root_fields(
+        &self,
+        selection_set: &'a q::SelectionSet,
+        fields: &mut Vec<&'a q::Field>,
+    ) -> Result<(), QueryExecutionError> {
+        for selection in &selection_set.items {
+            match selection {
+                q::Selection::Field(field) => {
+                    if self.is_included(&field.directives) {
+                        fields.push(field);
+                    }
+                }
+                q::Selection::FragmentSpread(spread) => {
+                    if self.is_included(&spread.directives) {
+                        let fragment = self.fragment(&spread.fragment_name)?;
+                        self.root_fields(&fragment.selection_set, fields)?;
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    if self.is_included(&fragment.directives) {
+                        self.root_fields(&fragment.selection_set, fields)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_included(&self, directives: &[q::Directive]) -> bool {
+        directives.iter().all(|directive| {
+            let condition = directive
+                .arguments
+                .iter()
+                .find(|(name, _)| name == "if")
+                .map(|(_, value)| self.is_true(value))
+                .unwrap_or(false);
+            match directive.name.as_str() {
+                "skip" => !condition,
+                "include" => condition,
+                _ => true,
+            }
+        })
+    }
+
+    fn is_true(&self, value: &q::Value) -> bool {
+        match value {
+            q::Value::Boolean(value) => *value,
+            q::Value::Variable(name) => {
+                match self
+                    .query
+                    .variables
+                    .as_ref()
+                    .and_then(|vars| vars.get(name))
+                {
+                    Some(value) => matches!(value, r::Value::Boolean(true)),
+                    None => self
+                        .variable_definitions
+                        .iter()
+                        .find(|defn| &defn.name == name)
+                        .and_then(|defn| defn.default_value.as_ref())
+                        .map(|value| matches!(value, q::Value::Boolean(true)))
+                        .unwrap_or(false),
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Build a query whose root selection set consists of `selections`,
+    /// together with the fragments and variable definitions it uses. The
+    /// names of all types in the query are changed with `rename`
+    fn subquery(
+        &self,
+        selections: Vec<q::Selection>,
+        rename: &dyn Fn(&str) -> String,
+    ) -> Result<Query, QueryExecutionError> {
+        let mut selection_set = q::SelectionSet {
+            span: (Pos::default(), Pos::default()),
+            items: selections,
+        };
+
+        let mut fragment_names = BTreeSet::new();
+        self.used_fragments(&selection_set, &mut fragment_names)?;
+        let mut fragments = fragment_names
+            .into_iter()
+            .map(|name| self.fragment(name).map(|fragment| fragment.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut variables = HashSet::new();
+        selection_set_variables(&selection_set, &mut variables);
+        for fragment in &fragments {
+            directive_variables(&fragment.directives, &mut variables);
+            selection_set_variables(&fragment.selection_set, &mut variables);
+        }
+        let variable_definitions = self
+            .variable_definitions
+            .iter()
+            .filter(|defn| variables.contains(defn.name.as_str()))
+            .map(|defn| {
+                let mut defn = defn.clone();
+                rename_type(&mut defn.var_type, rename);
+                defn
+            })
+            .collect();
+
+        rename_selection_set(&mut selection_set, rename);
+        for fragment in &mut fragments {
+            let q::TypeCondition::On(type_name) = &mut fragment.type_condition;
+            *type_name = rename(type_name);
+            rename_selection_set(&mut fragment.selection_set, rename);
+        }
+
+        let operation = q::OperationDefinition::Query(q::Query {
+            position: Pos::default(),
+            name: self.name.clone(),
+            variable_definitions,
+            directives: vec![],
+            selection_set,
+        });
+        let mut definitions = vec![q::Definition::Operation(operation)];
+        definitions.extend(fragments.into_iter().map(q::Definition::Fragment));
+
+        let mut query = Query::new(q::Document { definitions }, self.query.variables.clone());
+        query.api_key = self.query.api_key.clone();
+        query.explain = self.query.explain;
+        query.cost = self.query.cost;
+        Ok(query)
+    }
+
+    /// Add the names of all fragments that `selection_set` uses, directly
+    /// or through other fragments, to `names`
+    fn used_fragments(
+        &self,
+        selection_set: &q::SelectionSet,
+        names: &mut BTreeSet<&'a str>,
+    ) -> Result<(), QueryExecutionError> {
+        for selection in &selection_set.items {
+            match selection {
+                q::Selection::Field(field) => self.used_fragments(&field.selection_set, names)?,
+                q::Selection::FragmentSpread(spread) => {
+                    let fragment = self.fragment(&spread.fragment_name)?;
+                    if names.insert(fragment.name.as_str()) {
+                        self.used_fragments(&fragment.selection_set, names)?;
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    self.used_fragments(&fragment.selection_set, names)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn value_variables<'v>(value: &'v q::Value, variables: &mut HashSet<&'v str>) {
+    match value {
+        q::Value::Variable(name) => {
+            variables.insert(name);
+        }
+        q::Value::List(values) => {
+            for value in values {
+                value_variables(value, variables);
+            }
+        }
+        q::Value::Object(values) => {
+            for value in values.values() {
+                value_variables(value, variables);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn directive_variables<'v>(directives: &'v [q::Directive], variables: &mut HashSet<&'v str>) {
+    for directive in directives {
+        for (_, value) in &directive.arguments {
+            value_variables(value, variables);
+        }
+    }
+}
+
+fn selection_set_variables<'v>(
+    selection_set: &'v q::SelectionSet,
+    variables: &mut HashSet<&'v str>,
+) {
+    for selection in &selection_set.items {
+        match selection {
+            q::Selection::Field(field) => {
+                for (_, value) in &field.arguments {
+                    value_variables(value, variables);
+                }
+                directive_variables(&field.directives, variables);
+                selection_set_variables(&field.selection_set, variables);
+            }
+            q::Selection::FragmentSpread(spread) => {
+                directive_variables(&spread.directives, variables)
+            }
+            q::Selection::InlineFragment(fragment) => {
+                directive_variables(&fragment.directives, variables);
+                selection_set_variables(&fragment.selection_set, variables);
+            }
+        }
+    }
+}
+
+fn rename_type(typ: &mut q::Type, rename: &dyn Fn(&str) -> String) {
+    match typ {
+        q::Type::NamedType(name) => *name = rename(name),
+        q::Type::ListType(inner) | q::Type::NonNullType(inner) => rename_type(inner, rename),
+    }
+}
+
+fn rename_selection_set(selection_set: &mut q::SelectionSet, rename: &dyn Fn(&str) -> String) {
+    for selection in &mut selection_set.items {
+        match selection {
+            q::Selection::Field(field) => rename_selection_set(&mut field.selection_set, rename),
+            q::Selection::FragmentSpread(_) => (),
+            q::Selection::InlineFragment(fragment) => {
+                if let Some(q::TypeCondition::On(type_name)) = &mut fragment.type_condition {
+                    *type_name = rename(type_name);
+                }
+                rename_selection_set(&mut fragment.selection_set, rename);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use graph::data::schema::{ApiSchema, CompositeSchema, Schema};
+    use graph::prelude::{DeploymentHash, Query, QueryExecutionError};
+
+    use super::{CompositeQuery, Part};
+
+    const SCHEMA: &str = r#"
+interface Named { name: String! }
+type Thing implements Named { id: ID!, name: String! }
+type Query { things(first: Int): [Thing!]!, named: [Named!]! }
+"#;
+
+    fn composite_schema() -> CompositeSchema {
+        let api_schema = |id: &str| {
+            let document = graphql_parser::parse_schema(SCHEMA).unwrap().into_static();
+            let schema = Schema::new(DeploymentHash::new(id).unwrap(), document).unwrap();
+            Arc::new(ApiSchema::from_api_schema(schema).unwrap())
+        };
+        let parts = vec![
+            ("a".to_string(), api_schema("QmA")),
+            ("b".to_string(), api_schema("QmB")),
+        ];
+        CompositeSchema::new(parts).unwrap()
+    }
+
+    fn query(text: &str, operation_name: Option<&str>) -> Query {
+        let document = graphql_parser::parse_query(text).unwrap().into_static();
+        let mut query = Query::new(document, None);
+        query.operation_name = operation_name.map(str::to_string);
+        query
+    }
+
+    /// The text of `query` with all whitespace collapsed
+    fn text(query: &Query) -> String {
+        query
+            .document
+            .to_string()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Split `query` into its parts and return the response key, the
+    /// namespace and the query text for each of them. Introspection parts
+    /// have an empty response key and namespace
+    fn split(query: &Query) -> Result<Vec<(String, String, String)>, QueryExecutionError> {
+        let schema = composite_schema();
+        let composite = CompositeQuery::new(&schema, query)?;
+        let parts = composite
+            .parts()?
+            .into_iter()
+            .map(|part| match part {
+                Part::Introspection(query) => (String::new(), String::new(), text(&query)),
+                Part::Namespace {
+                    response_key,
+                    namespace,
+                    query,
+                } => (response_key, namespace, text(&query)),
+            })
+            .collect();
+        Ok(parts)
+    }
+
+    fn part(response_key: &str, namespace: &str, text: &str) -> (String, String, String) {
+        (
+            response_key.to_string(),
+            namespace.to_string(),
+            text.to_string(),
+        )
+    }
+
+    #[test]
+    fn splits_by_namespace() {
+        let parts = split(&query(
+            r#"query Things($n: Int, $skip: Boolean = true) {
+                 __typename
+                 a { things(first: $n) { ...thing } named { ... on a_Thing { id } } }
+                 b @skip(if: $skip) { named { name } }
+               }
+               fragment thing on a_Thing { id name }
+               fragment other on b_Thing { id }"#,
+            None,
+        ))
+        .unwrap();
+
+        // Types are renamed to their names in the deployment, and the
+        // parts only use the variables and fragments they need
+        assert_eq!(
+            vec![
+                part("", "", "query Things { __typename }"),
+                part(
+                    "a",
+                    "a",
+                    "query Things($n: Int) { things(first: $n) { ...thing } \
+                     named { ... on Thing { id } } } fragment thing on Thing { id name }"
+                ),
+            ],
+            parts
+        );
+    }
+
+    #[test]
+    fn merges_fields_with_the_same_response_key() {
+        let parts = split(&query(
+            r#"{
+                 x: a { things { id } }
+                 a { named { name } }
+                 ... on Query { x: a { things { name } } }
+                 ... @include(if: false) { b { named { name } } }
+                 ...root
+               }
+               fragment root on Query { y: b { things { id } } }"#,
+            None,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                part("x", "a", "query { things { id } things { name } }"),
+                part("a", "a", "query { named { name } }"),
+                part("y", "b", "query { things { id } }"),
+            ],
+            parts
+        );
+    }
+
+    #[test]
+    fn uses_operation_name() {
+        const QUERY: &str = r#"
+            query First { a { things { id } } }
+            query Second { b { things { name } } }"#;
+
+        assert_eq!(
+            vec![part("b", "b", "query Second { things { name } }")],
+            split(&query(QUERY, Some("Second"))).unwrap()
+        );
+        assert!(matches!(
+            split(&query(QUERY, None)),
+            Err(QueryExecutionError::OperationNameRequired)
+        ));
+        assert!(matches!(
+            split(&query(QUERY, Some("Third"))),
+            Err(QueryExecutionError::OperationNotFound(name)) if name == "Third"
+        ));
+    }
+
+    #[test]
+    fn rejects_subscriptions() {
+        assert!(matches!(
+            split(&query("subscription { a { things { id } } }", None)),
+            Err(QueryExecutionError::NotSupported(_))
+        ));
+    }
+}
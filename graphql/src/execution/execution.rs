@@ -239,6 +239,7 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
     let mut key: Option<QueryHash> = None;
 
     let should_check_cache = R::CACHEABLE
+        && !ctx.query.explain
         && match ENV_VARS.graphql.cached_subgraph_ids {
             CachedSubgraphIds::All => true,
             CachedSubgraphIds::Only(ref subgraph_ids) => {
@@ -119,6 +119,8 @@ pub struct Query {
     pub selection_set: Arc<a::SelectionSet>,
     /// The ShapeHash of the original query
     pub shape_hash: u64,
+    /// The complexity of the query
+    pub complexity: u64,
 
     pub network: Option<String>,
 
@@ -133,6 +135,11 @@ pub struct Query {
     pub query_text: Arc<String>,
     pub variables_text: Arc<String>,
     pub query_id: String,
+
+    /// Whether to report the SQL queries that are run for this query
+    /// together with their query plans. Such queries bypass the query
+    /// result cache
+    pub explain: bool,
 }
 
 impl Query {
@@ -220,10 +227,11 @@ impl Query {
         };
 
         // It's important to check complexity first, so `validate_fields`
-        // doesn't risk a stack overflow from invalid queries. We don't
-        // really care about the resulting complexity, only that all the
-        // checks that `check_complexity` performs pass successfully
-        let _ = raw_query.check_complexity(max_complexity, max_depth)?;
+        // doesn't risk a stack overflow from invalid queries. The
+        // complexity is only reported back to clients; what matters is
+        // that all the checks that `check_complexity` performs pass
+        // successfully
+        let complexity = raw_query.check_complexity(max_complexity, max_depth)?;
         raw_query.validate_fields()?;
         let selection_set = raw_query.convert()?;
 
@@ -231,6 +239,7 @@ impl Query {
             schema,
             selection_set: Arc::new(selection_set),
             shape_hash: query.shape_hash,
+            complexity,
             kind,
             network,
             logger,
@@ -238,6 +247,7 @@ impl Query {
             query_text: query.query_text.cheap_clone(),
             variables_text: query.variables_text.cheap_clone(),
             query_id,
+            explain: query.explain,
         };
 
         Ok(Arc::new(query))
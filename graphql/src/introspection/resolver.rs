@@ -354,12 +354,18 @@ impl IntrospectionResolver {
 /// A GraphQL resolver that can resolve entities, enum values, scalar types and interfaces/unions.
 #[async_trait]
 impl Resolver for IntrospectionResolver {
-    // `IntrospectionResolver` is not used as a "top level" resolver,
-    // see `fn as_introspection_context`, so this value is irrelevant.
+    // `IntrospectionResolver` is only used as a "top level" resolver for
+    // the introspection fields of composite queries, see `fn
+    // as_introspection_context`; their results are not worth caching
     const CACHEABLE: bool = false;
 
     async fn query_permit(&self) -> tokio::sync::OwnedSemaphorePermit {
-        unreachable!()
+        // Introspection does not use the database and therefore does not
+        // need to wait for a permit
+        Arc::new(tokio::sync::Semaphore::new(1))
+            .acquire_owned()
+            .await
+            .unwrap()
     }
 
     fn prefetch(
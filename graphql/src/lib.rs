@@ -24,6 +24,9 @@ mod store;
 /// The external interface for actually running queries
 mod runner;
 
+/// Utilities for splitting queries that span several deployments
+mod composite;
+
 /// Prelude that exports the most important traits and types.
 pub mod prelude {
     pub use super::execution::{ast as a, ExecutionContext, Query, Resolver};
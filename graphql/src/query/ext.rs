@@ -7,7 +7,7 @@ use std::collections::{BTreeMap, HashMap};
 
 use anyhow::anyhow;
 use graph::data::query::QueryExecutionError;
-use graph::prelude::{q, r, web3::types::H256, BlockNumber, Error};
+use graph::prelude::{q, r, web3::types::H256, BlockNumber, BlockPtr, Error};
 
 pub trait ValueExt: Sized {
     fn as_object(&self) -> &BTreeMap<String, q::Value>;
@@ -61,6 +61,12 @@ pub enum BlockConstraint {
     /// Execute the query on the latest block only if the the subgraph has progressed to or past the
     /// given block number.
     Min(BlockNumber),
+    /// Execute the query on the latest block whose timestamp, in seconds
+    /// since the Unix epoch, is at or before the given timestamp
+    Timestamp(u64),
+    /// Execute the query on exactly this block. Used to run all queries
+    /// in a batch against the same block
+    Ptr(BlockPtr),
     Latest,
 }
 
@@ -89,6 +95,8 @@ impl TryFromValue for BlockConstraint {
             Ok(BlockConstraint::Min(BlockNumber::try_from_value(
                 number_value,
             )?))
+        } else if let Some(timestamp) = map.get("timestamp_lte") {
+            Ok(BlockConstraint::Timestamp(u64::try_from_value(timestamp)?))
         } else {
             Err(anyhow!("invalid `BlockConstraint`"))
         }
@@ -1,3 +1,4 @@
+use graph::data::query::CacheStatus;
 use graph::prelude::{BlockPtr, CheapClone, QueryExecutionError, QueryResult};
 use std::sync::Arc;
 use std::time::Instant;
@@ -29,14 +30,15 @@ pub struct QueryExecutionOptions<R> {
     pub load_manager: Arc<LoadManager>,
 }
 
-/// Executes a query and returns a result.
+/// Executes a query and returns a result together with how the result
+/// used the query result cache.
 /// If the query is not cacheable, the `Arc` may be unwrapped.
 pub async fn execute_query<R>(
     query: Arc<Query>,
     selection_set: Option<a::SelectionSet>,
     block_ptr: Option<BlockPtr>,
     options: QueryExecutionOptions<R>,
-) -> Arc<QueryResult>
+) -> (Arc<QueryResult>, CacheStatus)
 where
     R: Resolver,
 {
@@ -52,8 +54,11 @@ where
     });
 
     if !query.is_query() {
-        return Arc::new(
-            QueryExecutionError::NotSupported("Only queries are supported".to_string()).into(),
+        return (
+            Arc::new(
+                QueryExecutionError::NotSupported("Only queries are supported".to_string()).into(),
+            ),
+            CacheStatus::Miss,
         );
     }
     let selection_set = selection_set
@@ -81,5 +86,5 @@ where
         start,
         cache_status.to_string(),
     );
-    result
+    (result, cache_status)
 }
@@ -1,22 +1,29 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use crate::prelude::{QueryExecutionOptions, StoreResolver, SubscriptionExecutionOptions};
+use crate::composite::{CompositeQuery, Part};
+use crate::prelude::{
+    IntrospectionResolver, QueryExecutionOptions, StoreResolver, SubscriptionExecutionOptions,
+};
 use crate::query::execute_query;
+use crate::query::ext::BlockConstraint;
 use crate::subscription::execute_prepared_subscription;
 use graph::prelude::MetricsRegistry;
 use graph::prometheus::{Gauge, Histogram};
+use graph::util::lru_cache::LruCache;
 use graph::{
     components::store::SubscriptionManager,
+    data::{schema::CompositeSchema, value::Object},
     prelude::{
-        async_trait, o, CheapClone, DeploymentState, GraphQlRunner as GraphQlRunnerTrait, Logger,
-        Query, QueryExecutionError, Subscription, SubscriptionError, SubscriptionResult, ENV_VARS,
+        async_trait, o, r, ApiSchema, BlockPtr, CheapClone, DeploymentHash, DeploymentState,
+        GraphQlRunner as GraphQlRunnerTrait, Logger, Query, QueryExecutionError, QueryResult,
+        SubgraphName, Subscription, SubscriptionError, SubscriptionResult, ENV_VARS,
     },
 };
 use graph::{data::graphql::effort::LoadManager, prelude::QueryStoreManager};
 use graph::{
-    data::query::{QueryResults, QueryTarget},
+    data::query::{ApiKey, QueryCost, QueryExplain, QueryResults, QueryTarget},
     prelude::QueryStore,
 };
 
@@ -66,6 +73,21 @@ impl ResultSizeMetrics {
     }
 }
 
+/// Lower `max_complexity` to the maximum complexity that `api_key` allows
+fn limit_complexity(max_complexity: Option<u64>, api_key: Option<&ApiKey>) -> Option<u64> {
+    match (max_complexity, api_key.and_then(|key| key.max_complexity())) {
+        (Some(max), Some(key_max)) => Some(max.min(key_max)),
+        (max, key_max) => max.or(key_max),
+    }
+}
+
+/// How many composite schemas to keep in memory
+const COMPOSITE_SCHEMA_CACHE_SIZE: usize = 100;
+
+/// The namespaces of a composite schema and the deployments mounted under
+/// them
+type CompositeSchemaKey = Vec<(String, DeploymentHash)>;
+
 /// GraphQL runner implementation for The Graph.
 pub struct GraphQlRunner<S, SM> {
     logger: Logger,
@@ -73,6 +95,7 @@ pub struct GraphQlRunner<S, SM> {
     subscription_manager: Arc<SM>,
     load_manager: Arc<LoadManager>,
     result_size: Arc<ResultSizeMetrics>,
+    composite_schemas: Mutex<LruCache<CompositeSchemaKey, Arc<CompositeSchema>>>,
 }
 
 #[cfg(debug_assertions)]
@@ -102,6 +125,7 @@ where
             subscription_manager,
             load_manager,
             result_size,
+            composite_schemas: Mutex::new(LruCache::new(COMPOSITE_SCHEMA_CACHE_SIZE)),
         }
     }
 
@@ -155,6 +179,33 @@ where
         // point, and everything needs to go through the `store` we are
         // setting up here
         let store = self.store.query_store(target, false).await?;
+        self.execute_on(
+            store,
+            None,
+            query,
+            max_complexity,
+            max_depth,
+            max_first,
+            max_skip,
+            result_size,
+        )
+        .await
+    }
+
+    /// Execute `query` against `store`. If `latest` is given, the parts
+    /// of the query that do not ask for a specific block are executed at
+    /// `latest` instead of at the current head of the deployment
+    async fn execute_on(
+        &self,
+        store: Arc<dyn QueryStore + Send + Sync>,
+        latest: Option<&BlockPtr>,
+        query: Query,
+        max_complexity: Option<u64>,
+        max_depth: Option<u8>,
+        max_first: Option<u32>,
+        max_skip: Option<u32>,
+        result_size: Arc<ResultSizeMetrics>,
+    ) -> Result<QueryResults, QueryResults> {
         let state = store.deployment_state().await?;
         let network = Some(store.network_name().to_string());
         let schema = store.api_schema()?;
@@ -168,28 +219,40 @@ where
             .unwrap_or(state);
 
         let max_depth = max_depth.unwrap_or(ENV_VARS.graphql.max_depth);
+        let api_key = query.api_key.clone();
         let query = crate::execution::Query::new(
             &self.logger,
             schema,
             network,
             query,
-            max_complexity,
+            limit_complexity(max_complexity, api_key.as_deref()),
             max_depth,
         )?;
         self.load_manager
             .decide(
                 &store.wait_stats(),
                 query.shape_hash,
+                api_key.as_ref().map(|key| key.name()),
                 query.query_text.as_ref(),
             )
             .to_result()?;
+        if let Some(api_key) = &api_key {
+            api_key.charge_complexity(query.complexity)?;
+        }
         let by_block_constraint = query.block_constraint()?;
         let mut max_block = 0;
         let mut result: QueryResults = QueryResults::empty();
+        let mut cost = QueryCost::new(query.complexity);
+        let explain = query.explain.then(QueryExplain::new);
+        let start = Instant::now();
 
         // Note: This will always iterate at least once.
         for (bc, (selection_set, error_policy)) in by_block_constraint {
-            let resolver = StoreResolver::at_block(
+            let bc = match (bc, latest) {
+                (BlockConstraint::Latest, Some(ptr)) => BlockConstraint::Ptr(ptr.clone()),
+                (bc, _) => bc,
+            };
+            let mut resolver = StoreResolver::at_block(
                 &self.logger,
                 store.cheap_clone(),
                 self.subscription_manager.cheap_clone(),
@@ -199,13 +262,21 @@ where
                 result_size.cheap_clone(),
             )
             .await?;
+            if resolver.block_number() < state.earliest_block_number {
+                return Err(QueryExecutionError::BlockPruned(
+                    resolver.block_number(),
+                    state.earliest_block_number,
+                )
+                .into());
+            }
             max_block = max_block.max(resolver.block_number());
-            let query_res = execute_query(
+            resolver.explain = explain.clone();
+            let (query_res, cache_status) = execute_query(
                 query.clone(),
                 Some(selection_set),
                 resolver.block_ptr.clone(),
                 QueryExecutionOptions {
-                    resolver,
+                    resolver: resolver.cheap_clone(),
                     deadline: ENV_VARS.graphql.query_timeout.map(|t| Instant::now() + t),
                     max_first: max_first.unwrap_or(ENV_VARS.graphql.max_first),
                     max_skip: max_skip.unwrap_or(ENV_VARS.graphql.max_skip),
@@ -213,8 +284,19 @@ where
                 },
             )
             .await;
+            cost.add(resolver.block_number(), cache_status, resolver.sql_time());
             result.append(query_res);
         }
+        if query.cost {
+            result.set_cost(cost);
+        }
+        if let Some(explain) = explain {
+            result.set_explain(explain.take());
+        }
+        if let Some(api_key) = &api_key {
+            self.load_manager
+                .record_key_work(api_key.name(), start.elapsed());
+        }
 
         query.log_execution(max_block);
         self.deployment_changed(store.as_ref(), state, max_block as u64)
@@ -222,6 +304,144 @@ where
             .map_err(QueryResults::from)
             .map(|()| result)
     }
+
+    /// Return the composite schema that mounts each schema in `parts`
+    /// under its namespace. Composite schemas are cached since building
+    /// them for each query would be expensive
+    fn composite_schema(
+        &self,
+        parts: Vec<(String, Arc<ApiSchema>)>,
+    ) -> Result<Arc<CompositeSchema>, QueryExecutionError> {
+        let key: CompositeSchemaKey = parts
+            .iter()
+            .map(|(namespace, schema)| (namespace.clone(), schema.id().clone()))
+            .collect();
+        if let Some(schema) = self.composite_schemas.lock().unwrap().get(&key) {
+            return Ok(schema.cheap_clone());
+        }
+
+        let schema = CompositeSchema::new(parts)
+            .map(Arc::new)
+            .map_err(|e| QueryExecutionError::ValidationError(None, e.to_string()))?;
+        self.composite_schemas
+            .lock()
+            .unwrap()
+            .insert(key, schema.cheap_clone());
+        Ok(schema)
+    }
+
+    async fn execute_composite(
+        &self,
+        query: Query,
+        namespaces: Vec<(String, QueryTarget)>,
+    ) -> Result<QueryResults, QueryResults> {
+        let mut stores = HashMap::new();
+        let mut parts = Vec::new();
+        for (namespace, target) in namespaces {
+            let store = self.store.query_store(target, false).await?;
+            parts.push((namespace.clone(), store.api_schema()?));
+            stores.insert(namespace, store);
+        }
+        let schema = self.composite_schema(parts)?;
+        let composite = CompositeQuery::new(&schema, &query)?;
+
+        // Validate the entire query before running any of its parts
+        let max_depth = ENV_VARS.graphql.max_depth;
+        let validated = crate::execution::Query::new(
+            &self.logger,
+            schema.api_schema(),
+            None,
+            query.clone(),
+            limit_complexity(ENV_VARS.graphql.max_complexity, query.api_key.as_deref()),
+            max_depth,
+        )?;
+
+        let mut data = Object::new();
+        let mut errors = Vec::new();
+        let mut cost = QueryCost::new(validated.complexity);
+        let mut explain = Vec::new();
+        for part in composite.parts()? {
+            match part {
+                Part::Introspection(query) => {
+                    let api_schema = schema.api_schema();
+                    let resolver = IntrospectionResolver::new(&self.logger, api_schema.schema());
+                    let query = crate::execution::Query::new(
+                        &self.logger,
+                        api_schema.cheap_clone(),
+                        None,
+                        query,
+                        None,
+                        max_depth,
+                    )?;
+                    let (result, _) = execute_query(
+                        query,
+                        None,
+                        None,
+                        QueryExecutionOptions {
+                            resolver,
+                            deadline: ENV_VARS.graphql.query_timeout.map(|t| Instant::now() + t),
+                            max_first: std::u32::MAX,
+                            max_skip: std::u32::MAX,
+                            load_manager: self.load_manager.cheap_clone(),
+                        },
+                    )
+                    .await;
+                    let mut result = QueryResults::from(result).into_result();
+                    if let Some(part) = result.take_data() {
+                        data.extend(part);
+                    }
+                    errors.append(result.errors_mut());
+                }
+                Part::Namespace {
+                    response_key,
+                    namespace,
+                    query,
+                } => {
+                    let results = self
+                        .execute_on(
+                            stores[&namespace].cheap_clone(),
+                            None,
+                            query,
+                            ENV_VARS.graphql.max_complexity,
+                            Some(max_depth),
+                            Some(ENV_VARS.graphql.max_first),
+                            Some(ENV_VARS.graphql.max_skip),
+                            self.result_size.cheap_clone(),
+                        )
+                        .await
+                        .unwrap_or_else(|e| e);
+                    if let Some(part) = results.cost() {
+                        cost.add(part.block, part.cache_status, part.sql_time);
+                    }
+                    // Name the fields the way the composite schema names
+                    // the types of the namespace
+                    for entry in results.explain().unwrap_or_default() {
+                        let mut entry = entry.clone();
+                        entry.field = format!("{}_{}", namespace, entry.field);
+                        explain.push(entry);
+                    }
+                    let mut result = results.into_result();
+                    let value = result
+                        .take_data()
+                        .map(r::Value::Object)
+                        .unwrap_or(r::Value::Null);
+                    data.insert(response_key, value);
+                    errors.append(result.errors_mut());
+                }
+            }
+        }
+
+        let mut result = QueryResult::new(data);
+        result.errors_mut().append(&mut errors);
+        let mut results = QueryResults::from(result);
+        if query.cost {
+            results.set_cost(cost);
+        }
+        if query.explain {
+            results.set_explain(explain);
+        }
+        Ok(results)
+    }
 }
 
 #[async_trait]
@@ -264,6 +484,75 @@ where
         .unwrap_or_else(|e| e)
     }
 
+    async fn run_query_batch(
+        self: Arc<Self>,
+        queries: Vec<Query>,
+        target: QueryTarget,
+    ) -> Vec<QueryResults> {
+        // Use the same `QueryStore` and the same block for all queries so
+        // that they all see the same state of the deployment
+        let store = match self.store.query_store(target, false).await {
+            Ok(store) => store,
+            Err(e) => {
+                return queries
+                    .iter()
+                    .map(|_| QueryResults::from(e.clone()))
+                    .collect()
+            }
+        };
+        let latest = match store.block_ptr().await {
+            Ok(latest) => latest,
+            Err(e) => {
+                let e = QueryExecutionError::from(e);
+                return queries
+                    .iter()
+                    .map(|_| QueryResults::from(e.clone()))
+                    .collect();
+            }
+        };
+
+        let mut results = Vec::with_capacity(queries.len());
+        for query in queries {
+            let result = self
+                .execute_on(
+                    store.cheap_clone(),
+                    latest.as_ref(),
+                    query,
+                    ENV_VARS.graphql.max_complexity,
+                    Some(ENV_VARS.graphql.max_depth),
+                    Some(ENV_VARS.graphql.max_first),
+                    Some(ENV_VARS.graphql.max_skip),
+                    self.result_size.cheap_clone(),
+                )
+                .await
+                .unwrap_or_else(|e| e);
+            results.push(result);
+        }
+        results
+    }
+
+    async fn run_composite_query(
+        self: Arc<Self>,
+        query: Query,
+        namespaces: Vec<(String, QueryTarget)>,
+    ) -> QueryResults {
+        self.execute_composite(query, namespaces)
+            .await
+            .unwrap_or_else(|e| e)
+    }
+
+    async fn query_target(
+        self: Arc<Self>,
+        subgraph: String,
+    ) -> Result<Option<QueryTarget>, QueryExecutionError> {
+        if let Ok(id) = DeploymentHash::new(subgraph.as_str()) {
+            if self.store.deployment_exists(&id).await? {
+                return Ok(Some(QueryTarget::Deployment(id)));
+            }
+        }
+        Ok(SubgraphName::new(subgraph).ok().map(QueryTarget::Name))
+    }
+
     async fn run_subscription(
         self: Arc<Self>,
         subscription: Subscription,
@@ -272,13 +561,15 @@ where
         let store = self.store.query_store(target, true).await?;
         let schema = store.api_schema()?;
         let network = store.network_name().to_string();
+        let api_key = subscription.query.api_key.clone();
+        let max_complexity = limit_complexity(ENV_VARS.graphql.max_complexity, api_key.as_deref());
 
         let query = crate::execution::Query::new(
             &self.logger,
             schema,
             Some(network),
             subscription.query,
-            ENV_VARS.graphql.max_complexity,
+            max_complexity,
             ENV_VARS.graphql.max_depth,
         )?;
 
@@ -287,9 +578,14 @@ where
             .decide(
                 &store.wait_stats(),
                 query.shape_hash,
+                api_key.as_ref().map(|key| key.name()),
                 query.query_text.as_ref(),
             )
             .to_result()
+            .and_then(|()| match &api_key {
+                Some(api_key) => api_key.charge_complexity(query.complexity),
+                None => Ok(()),
+            })
         {
             return Err(SubscriptionError::GraphQLError(vec![err]));
         }
@@ -301,7 +597,7 @@ where
                 store,
                 subscription_manager: self.subscription_manager.cheap_clone(),
                 timeout: ENV_VARS.graphql.query_timeout,
-                max_complexity: ENV_VARS.graphql.max_complexity,
+                max_complexity,
                 max_depth: ENV_VARS.graphql.max_depth,
                 max_first: ENV_VARS.graphql.max_first,
                 max_skip: ENV_VARS.graphql.max_skip,
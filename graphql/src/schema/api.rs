@@ -7,8 +7,14 @@ use lazy_static::lazy_static;
 use crate::schema::ast;
 
 use graph::data::{
-    graphql::ext::{DirectiveExt, DocumentExt, ValueExt},
-    schema::{META_FIELD_NAME, META_FIELD_TYPE, SCHEMA_TYPE_NAME},
+    graphql::{
+        ext::{DirectiveExt, DocumentExt, TypeDefinitionExt, ValueExt},
+        TypeExt,
+    },
+    schema::{
+        CURSOR_FIELD_NAME, META_FIELD_NAME, META_FIELD_TYPE, RANK_FIELD_NAME, SCHEMA_TYPE_NAME,
+    },
+    store::{BIG_DECIMAL_SCALAR, BIG_INT_SCALAR, INT8_SCALAR},
 };
 use graph::prelude::s::{Value, *};
 use graph::prelude::*;
@@ -22,8 +28,14 @@ pub enum APISchemaError {
     TypeNotFound(String),
     #[error("Fulltext search is not yet deterministic")]
     FulltextSearchNonDeterministic,
+    #[error("the filter `{1}_` on the entities that field `{1}` of type {0} refers to has the same name as the filter for field `{1}_`; rename field `{1}_`")]
+    ChildFilterConflict(String, String), // (type, field)
 }
 
+/// The directive that marks `<types>_aggregate` query fields; its `entity`
+/// argument names the entity type to aggregate over
+pub(crate) const AGGREGATE_DIRECTIVE: &str = "aggregate";
+
 // The followoing types are defined in meta.graphql
 const BLOCK_HEIGHT: &str = "Block_height";
 const CHANGE_BLOCK_FILTER_NAME: &str = "BlockChangedFilter";
@@ -87,6 +99,8 @@ pub fn api_schema(input_schema: &Document) -> Result<Document, APISchemaError> {
     add_types_for_object_types(&mut schema, &object_types)?;
     add_types_for_interface_types(&mut schema, &interface_types)?;
     add_field_arguments(&mut schema, input_schema)?;
+    add_cursor_fields(&mut schema, &object_types, &interface_types);
+    add_rank_fields(&mut schema)?;
     add_query_type(&mut schema, &object_types, &interface_types)?;
     add_subscription_type(&mut schema, &object_types, &interface_types)?;
 
@@ -103,7 +117,8 @@ pub fn api_schema(input_schema: &Document) -> Result<Document, APISchemaError> {
 }
 
 /// Adds a global `_Meta_` type to the schema. The `_meta` field
-/// accepts values of this type
+/// accepts values of this type. Builtin scalars like `Timestamp` are only
+/// added if the schema does not define a type with the same name itself
 fn add_meta_field_type(schema: &mut Document) {
     lazy_static! {
         static ref META_FIELD_SCHEMA: Document = {
@@ -112,9 +127,16 @@ fn add_meta_field_type(schema: &mut Document) {
         };
     }
 
-    schema
+    let defs: Vec<_> = META_FIELD_SCHEMA
         .definitions
-        .extend(META_FIELD_SCHEMA.definitions.iter().cloned());
+        .iter()
+        .filter(|def| match def {
+            Definition::TypeDefinition(def) => schema.get_named_type(def.name()).is_none(),
+            _ => true,
+        })
+        .cloned()
+        .collect();
+    schema.definitions.extend(defs);
 }
 
 fn add_types_for_object_types(
@@ -123,8 +145,202 @@ fn add_types_for_object_types(
 ) -> Result<(), APISchemaError> {
     for object_type in object_types {
         if !object_type.name.eq(SCHEMA_TYPE_NAME) {
-            add_order_by_type(schema, &object_type.name, &object_type.fields)?;
+            let child_values = child_order_by_values(schema, &object_type.fields);
+            add_order_by_type(schema, &object_type.name, &object_type.fields, child_values)?;
             add_filter_type(schema, &object_type.name, &object_type.fields)?;
+            add_aggregate_types(schema, &object_type.name, &object_type.fields)?;
+        }
+    }
+    Ok(())
+}
+
+/// The fields of an entity type that can be aggregated with `sum`, `avg`,
+/// `min` and `max`, together with the name of their scalar type
+fn aggregatable_fields(fields: &[Field]) -> Vec<(&str, &str)> {
+    fields
+        .iter()
+        .filter(|field| {
+            !ast::is_list_or_non_null_list_field(field)
+                && ast::get_derived_from_directive(field).is_none()
+        })
+        .filter_map(|field| {
+            let type_name = field.field_type.get_base_type();
+            match type_name {
+                "Int" | INT8_SCALAR | BIG_INT_SCALAR | BIG_DECIMAL_SCALAR => {
+                    Some((field.name.as_str(), type_name))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Adds the `<type_name>_aggregate` type that is the result of the
+/// `<types>_aggregate` query field to the schema. Its `sum`, `avg`, `min`
+/// and `max` fields are objects of type `<type_name>_aggregate_sum`,
+/// `<type_name>_aggregate_avg` and `<type_name>_aggregate_fields` with one
+/// field for each numeric field of the entity type; they are left out if
+/// there are no numeric fields
+fn add_aggregate_types(
+    schema: &mut Document,
+    type_name: &str,
+    fields: &[Field],
+) -> Result<(), APISchemaError> {
+    fn object_type(name: String, fields: Vec<Field>) -> Definition {
+        Definition::TypeDefinition(TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name,
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields,
+        }))
+    }
+
+    fn field(name: &str, field_type: Type) -> Field {
+        Field {
+            position: Pos::default(),
+            description: None,
+            name: name.to_owned(),
+            arguments: vec![],
+            field_type,
+            directives: vec![],
+        }
+    }
+
+    let aggregate_type_name = format!("{}_aggregate", type_name);
+    let non_null = |name: &str| Type::NonNullType(Box::new(Type::NamedType(name.to_owned())));
+
+    let mut aggregate_fields = vec![field("count", non_null(BIG_INT_SCALAR))];
+    let mut types = vec![];
+
+    let numeric_fields = aggregatable_fields(fields);
+    if !numeric_fields.is_empty() {
+        let sum_fields = numeric_fields
+            .iter()
+            .map(|(name, type_name)| {
+                let sum_type = match *type_name {
+                    BIG_DECIMAL_SCALAR => BIG_DECIMAL_SCALAR,
+                    _ => BIG_INT_SCALAR,
+                };
+                field(name, Type::NamedType(sum_type.to_owned()))
+            })
+            .collect();
+        let avg_fields = numeric_fields
+            .iter()
+            .map(|(name, _)| field(name, Type::NamedType(BIG_DECIMAL_SCALAR.to_owned())))
+            .collect();
+        let min_max_fields = numeric_fields
+            .iter()
+            .map(|(name, type_name)| field(name, Type::NamedType(type_name.to_string())))
+            .collect();
+
+        let sum_type = format!("{}_sum", aggregate_type_name);
+        let avg_type = format!("{}_avg", aggregate_type_name);
+        let fields_type = format!("{}_fields", aggregate_type_name);
+        aggregate_fields.extend(vec![
+            field("sum", non_null(&sum_type)),
+            field("avg", non_null(&avg_type)),
+            field("min", non_null(&fields_type)),
+            field("max", non_null(&fields_type)),
+        ]);
+        types.push((sum_type, sum_fields));
+        types.push((avg_type, avg_fields));
+        types.push((fields_type, min_max_fields));
+    }
+    types.push((aggregate_type_name, aggregate_fields));
+
+    for (name, fields) in types {
+        if schema.get_named_type(&name).is_some() {
+            return Err(APISchemaError::TypeExists(name));
+        }
+        schema.definitions.push(object_type(name, fields));
+    }
+    Ok(())
+}
+
+/// Adds a `_cursor` field to the given object and interface types. The
+/// cursor marks the position of an entity in the collection it was
+/// fetched from and can be passed to `after` or `before` to page through
+/// that collection
+fn add_cursor_fields(
+    schema: &mut Document,
+    object_types: &[&ObjectType],
+    interface_types: &[&InterfaceType],
+) {
+    fn add_cursor_field(fields: &mut Vec<Field>) {
+        if fields.iter().any(|field| field.name == CURSOR_FIELD_NAME) {
+            return;
+        }
+        fields.push(Field {
+            position: Pos::default(),
+            description: None,
+            name: CURSOR_FIELD_NAME.to_string(),
+            arguments: vec![],
+            field_type: Type::NonNullType(Box::new(Type::NamedType("String".to_string()))),
+            directives: vec![],
+        });
+    }
+
+    for object_type in object_types {
+        if !object_type.name.eq(SCHEMA_TYPE_NAME) {
+            let object_type = ast::get_object_type_mut(schema, &object_type.name)
+                .expect("object type from input schema is missing in API schema");
+            add_cursor_field(&mut object_type.fields);
+        }
+    }
+    for interface_type in interface_types {
+        let interface_type = ast::get_interface_type_mut(schema, &interface_type.name)
+            .expect("interface type from input schema is missing in API schema");
+        add_cursor_field(&mut interface_type.fields);
+    }
+}
+
+/// Adds a `_rank` field to the entity types and interfaces that fulltext
+/// query fields return. For entities returned by a fulltext query, the
+/// field contains the rank of the entity for the search; it is `null`
+/// for entities returned by any other query
+fn add_rank_fields(schema: &mut Document) -> Result<(), APISchemaError> {
+    fn add_rank_field(fields: &mut Vec<Field>) {
+        if fields.iter().any(|field| field.name == RANK_FIELD_NAME) {
+            return;
+        }
+        fields.push(Field {
+            position: Pos::default(),
+            description: None,
+            name: RANK_FIELD_NAME.to_string(),
+            arguments: vec![],
+            field_type: Type::NamedType("Float".to_string()),
+            directives: vec![],
+        });
+    }
+
+    let type_names: Vec<String> = schema
+        .get_fulltext_directives()
+        .map_err(|_| APISchemaError::FulltextSearchNonDeterministic)?
+        .into_iter()
+        .flat_map(|fulltext| {
+            // Without an interface, only the first entity type is searched
+            let includes = fulltext.argument("include").unwrap().as_list().unwrap();
+            let searched = match fulltext.argument("interface") {
+                Some(_) => includes.len(),
+                None => 1,
+            };
+            includes
+                .iter()
+                .take(searched)
+                .map(|include| include.as_object().unwrap().get("entity").unwrap())
+                .chain(fulltext.argument("interface"))
+                .map(|name| name.as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    for type_name in type_names {
+        if let Some(object_type) = ast::get_object_type_mut(schema, &type_name) {
+            add_rank_field(&mut object_type.fields);
+        } else if let Some(interface_type) = ast::get_interface_type_mut(schema, &type_name) {
+            add_rank_field(&mut interface_type.fields);
         }
     }
     Ok(())
@@ -136,17 +352,19 @@ fn add_types_for_interface_types(
     interface_types: &[&InterfaceType],
 ) -> Result<(), APISchemaError> {
     for interface_type in interface_types {
-        add_order_by_type(schema, &interface_type.name, &interface_type.fields)?;
+        add_order_by_type(schema, &interface_type.name, &interface_type.fields, vec![])?;
         add_filter_type(schema, &interface_type.name, &interface_type.fields)?;
     }
     Ok(())
 }
 
-/// Adds a `<type_name>_orderBy` enum type for the given fields to the schema.
+/// Adds a `<type_name>_orderBy` enum type for the given fields and any
+/// additional `child_values` to the schema.
 fn add_order_by_type(
     schema: &mut Document,
     type_name: &str,
     fields: &[Field],
+    child_values: Vec<String>,
 ) -> Result<(), APISchemaError> {
     let type_name = format!("{}_orderBy", type_name);
 
@@ -159,11 +377,12 @@ fn add_order_by_type(
                 directives: vec![],
                 values: fields
                     .iter()
-                    .map(|field| &field.name)
+                    .map(|field| field.name.to_owned())
+                    .chain(child_values)
                     .map(|name| EnumValue {
                         position: Pos::default(),
                         description: None,
-                        name: name.to_owned(),
+                        name,
                         directives: vec![],
                     })
                     .collect(),
@@ -176,6 +395,33 @@ fn add_order_by_type(
     Ok(())
 }
 
+/// Generates `<field>__<child_field>` values for a `*_orderBy` enum that sort
+/// by a scalar field of the entity a field refers to. This is only
+/// possible for fields that hold a single reference to an object type and
+/// that are not `@derivedFrom`
+fn child_order_by_values(schema: &Document, fields: &[Field]) -> Vec<String> {
+    let is_sortable = |field: &Field| {
+        !ast::is_list_or_non_null_list_field(field)
+            && ast::get_derived_from_directive(field).is_none()
+    };
+
+    let mut values = vec![];
+    for field in fields.iter().filter(|field| is_sortable(field)) {
+        let child_type = match ast::get_type_definition_from_type(schema, &field.field_type) {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => continue,
+        };
+        for child_field in child_type.fields.iter().filter(|field| is_sortable(field)) {
+            if let Some(TypeDefinition::Scalar(_)) =
+                ast::get_type_definition_from_type(schema, &child_field.field_type)
+            {
+                values.push(format!("{}__{}", field.name, child_field.name));
+            }
+        }
+    }
+    values
+}
+
 /// Adds a `<type_name>_filter` enum type for the given fields to the schema.
 fn add_filter_type(
     schema: &mut Document,
@@ -185,8 +431,9 @@ fn add_filter_type(
     let filter_type_name = format!("{}_filter", type_name);
     match schema.get_named_type(&filter_type_name) {
         None => {
-            let mut generated_filter_fields = field_input_values(schema, fields)?;
+            let mut generated_filter_fields = field_input_values(schema, type_name, fields)?;
             generated_filter_fields.push(block_changed_filter_argument());
+            generated_filter_fields.extend(logical_filter_arguments(&filter_type_name, fields));
 
             let typedef = TypeDefinition::InputObject(InputObjectType {
                 position: Pos::default(),
@@ -207,15 +454,55 @@ fn add_filter_type(
 /// Generates `*_filter` input values for the given set of fields.
 fn field_input_values(
     schema: &Document,
+    type_name: &str,
     fields: &[Field],
 ) -> Result<Vec<InputValue>, APISchemaError> {
     let mut input_values = vec![];
     for field in fields {
-        input_values.extend(field_filter_input_values(schema, field, &field.field_type)?);
+        let filter_input_values = field_filter_input_values(schema, field, &field.field_type)?;
+        let nullable = !matches!(field.field_type, Type::NonNullType(_));
+        let is_null = (nullable && !filter_input_values.is_empty()).then(|| {
+            input_value(
+                &field.name,
+                "is_null",
+                Type::NamedType("Boolean".to_owned()),
+            )
+        });
+        input_values.extend(filter_input_values);
+        input_values.extend(is_null);
+        if let Some(child_filter) = field_child_filter_input_value(schema, field) {
+            if fields.iter().any(|other| other.name == child_filter.name) {
+                return Err(APISchemaError::ChildFilterConflict(
+                    type_name.to_string(),
+                    field.name.clone(),
+                ));
+            }
+            input_values.push(child_filter);
+        }
     }
     Ok(input_values)
 }
 
+/// Generates a `<field>_` input value that filters on the attributes of the
+/// entities the given field refers to. This is only possible for fields
+/// whose type is an object or interface type, including `@derivedFrom`
+/// fields
+fn field_child_filter_input_value(schema: &Document, field: &Field) -> Option<InputValue> {
+    let type_name = match ast::get_type_definition_from_type(schema, &field.field_type)? {
+        TypeDefinition::Object(t) => &t.name,
+        TypeDefinition::Interface(t) => &t.name,
+        _ => return None,
+    };
+    Some(InputValue {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}_", field.name),
+        value_type: Type::NamedType(format!("{}_filter", type_name)),
+        default_value: None,
+        directives: vec![],
+    })
+}
+
 /// Generates `*_filter` input values for the given field.
 fn field_filter_input_values(
     schema: &Document,
@@ -270,6 +557,8 @@ fn field_scalar_filter_input_values(
         "BigDecimal" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "ID" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "Int" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "Int8" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "Timestamp" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "String" => vec![
             "",
             "not",
@@ -279,6 +568,8 @@ fn field_scalar_filter_input_values(
             "lte",
             "in",
             "not_in",
+            "in_nocase",
+            "not_in_nocase",
             "contains",
             "contains_nocase",
             "not_contains",
@@ -298,7 +589,9 @@ fn field_scalar_filter_input_values(
     .map(|filter_type| {
         let field_type = Type::NamedType(field_type.name.to_owned());
         let value_type = match filter_type {
-            "in" | "not_in" => Type::ListType(Box::new(Type::NonNullType(Box::new(field_type)))),
+            "in" | "not_in" | "in_nocase" | "not_in_nocase" => {
+                Type::ListType(Box::new(Type::NonNullType(Box::new(field_type))))
+            }
             _ => field_type,
         };
         input_value(&field.name, filter_type, value_type)
@@ -359,6 +652,8 @@ fn field_list_filter_input_values(
                 "contains_nocase",
                 "not_contains",
                 "not_contains_nocase",
+                "contains_any",
+                "contains_all",
             ]
             .into_iter()
             .map(|filter_type| {
@@ -417,6 +712,13 @@ fn add_query_type(
         .filter_map(|fulltext| query_field_for_fulltext(fulltext))
         .collect();
     fields.append(&mut fulltext_fields);
+    fields.extend(
+        object_types
+            .iter()
+            .map(|t| t.name.as_str())
+            .filter(|name| !name.eq(&SCHEMA_TYPE_NAME))
+            .map(aggregate_query_field),
+    );
     fields.push(meta_field());
 
     let typedef = TypeDefinition::Object(ObjectType {
@@ -435,11 +737,17 @@ fn add_query_type(
 fn query_field_for_fulltext(fulltext: &Directive) -> Option<Field> {
     let name = fulltext.argument("name").unwrap().as_str().unwrap().into();
 
-    let includes = fulltext.argument("include").unwrap().as_list().unwrap();
-    // Only one include is allowed per fulltext directive
-    let include = includes.iter().next().unwrap();
-    let included_entity = include.as_object().unwrap();
-    let entity_name = included_entity.get("entity").unwrap().as_str().unwrap();
+    // Directives that include several entity types name the interface
+    // that they all implement; the field returns that interface
+    let entity_name = match fulltext.argument("interface") {
+        Some(interface) => interface.as_str().unwrap(),
+        None => {
+            let includes = fulltext.argument("include").unwrap().as_list().unwrap();
+            let include = includes.iter().next().unwrap();
+            let included_entity = include.as_object().unwrap();
+            included_entity.get("entity").unwrap().as_str().unwrap()
+        }
+    };
 
     let mut arguments = vec![
         // text: String
@@ -482,7 +790,7 @@ fn query_field_for_fulltext(fulltext: &Directive) -> Option<Field> {
         arguments,
         field_type: Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
             Box::new(Type::NamedType(entity_name.into())),
-        ))))), // included entity type name
+        ))))), // included entity type or interface name
         directives: vec![fulltext.clone()],
     })
 }
@@ -528,9 +836,12 @@ fn block_argument() -> InputValue {
             "The block at which the query should be executed. \
              Can either be a `{ hash: Bytes }` value containing a block hash, \
              a `{ number: Int }` containing the block number, \
-             or a `{ number_gte: Int }` containing the minimum block number. \
+             a `{ number_gte: Int }` containing the minimum block number, \
+             or a `{ timestamp_lte: Int8 }` containing a timestamp in seconds since the Unix epoch. \
              In the case of `number_gte`, the query will be executed on the latest block only if \
              the subgraph has progressed to or past the minimum block number. \
+             In the case of `timestamp_lte`, the query will be executed on the latest block \
+             at or before that timestamp. \
              Defaults to the latest block when omitted."
                 .to_owned(),
         ),
@@ -552,6 +863,26 @@ fn block_changed_filter_argument() -> InputValue {
     }
 }
 
+/// Generates the `and` and `or` arguments that combine a list of nested
+/// `<type_name>_filter` objects. An operator is left out if the type has a
+/// field with the same name, since that field's equality filter takes the name
+fn logical_filter_arguments(filter_type_name: &str, fields: &[Field]) -> Vec<InputValue> {
+    ["and", "or"]
+        .iter()
+        .filter(|op| !fields.iter().any(|field| field.name == **op))
+        .map(|op| InputValue {
+            position: Pos::default(),
+            description: None,
+            name: op.to_string(),
+            value_type: Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+                filter_type_name.to_owned(),
+            ))))),
+            default_value: None,
+            directives: vec![],
+        })
+        .collect()
+}
+
 fn subgraph_error_argument() -> InputValue {
     InputValue {
         position: Pos::default(),
@@ -608,6 +939,36 @@ fn query_fields_for_type(type_name: &str) -> Vec<Field> {
     ]
 }
 
+/// Generates the `Query` field that computes aggregates over the entities
+/// of the given type (e.g. `users_aggregate`). The field is marked with an
+/// `@aggregate(entity: ..)` directive so that query execution can tell it
+/// apart from fields that return entities
+fn aggregate_query_field(type_name: &str) -> Field {
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}_aggregate", type_name.to_plural().to_camel_case()),
+        arguments: vec![
+            input_value(
+                &"where".to_string(),
+                "",
+                Type::NamedType(format!("{}_filter", type_name)),
+            ),
+            block_argument(),
+            subgraph_error_argument(),
+        ],
+        field_type: Type::NonNullType(Box::new(Type::NamedType(format!(
+            "{}_aggregate",
+            type_name
+        )))),
+        directives: vec![Directive {
+            position: Pos::default(),
+            name: AGGREGATE_DIRECTIVE.to_string(),
+            arguments: vec![("entity".to_string(), Value::String(type_name.to_string()))],
+        }],
+    }
+}
+
 fn meta_field() -> Field {
     lazy_static! {
         static ref META_FIELD: Field = Field {
@@ -661,6 +1022,16 @@ fn collection_arguments_for_named_type(type_name: &str) -> Vec<InputValue> {
             "",
             Type::NamedType(format!("{}_filter", type_name)),
         ),
+        input_value(
+            &"after".to_string(),
+            "",
+            Type::NamedType("String".to_string()),
+        ),
+        input_value(
+            &"before".to_string(),
+            "",
+            Type::NamedType("String".to_string()),
+        ),
     ];
 
     args
@@ -741,10 +1112,10 @@ fn add_field_arguments(
 
 #[cfg(test)]
 mod tests {
-    use graph::data::graphql::DocumentExt;
+    use graph::data::graphql::{ext::DirectiveFinder, DocumentExt};
     use graphql_parser::schema::*;
 
-    use super::api_schema;
+    use super::{api_schema, AGGREGATE_DIRECTIVE, RANK_FIELD_NAME};
     use crate::schema::ast;
 
     #[test]
@@ -770,6 +1141,35 @@ mod tests {
             .expect("String type is missing in API schema");
     }
 
+    #[test]
+    fn api_schema_prefers_user_types_over_new_scalars() {
+        let input_schema = parse_schema(
+            "type Timestamp @entity { id: ID!, value: Int! }
+             type Block @entity { id: ID!, timestamp: Timestamp!, number: Int8! }",
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        assert!(matches!(
+            schema.get_named_type("Timestamp"),
+            Some(TypeDefinition::Object(_))
+        ));
+        assert!(matches!(
+            schema.get_named_type("Int8"),
+            Some(TypeDefinition::Scalar(_))
+        ));
+        let timestamps = schema
+            .definitions
+            .iter()
+            .filter_map(|def| match def {
+                Definition::TypeDefinition(def) => Some(ast::get_type_name(def)),
+                _ => None,
+            })
+            .filter(|name| *name == "Timestamp")
+            .count();
+        assert_eq!(1, timestamps);
+    }
+
     #[test]
     fn api_schema_contains_order_direction_enum() {
         let input_schema = parse_schema("type User { id: ID!, name: String! }")
@@ -827,6 +1227,122 @@ mod tests {
         assert_eq!(values, ["id", "name"]);
     }
 
+    #[test]
+    fn api_schema_contains_child_field_order_by_values() {
+        let input_schema = parse_schema(
+            r#"
+              type Pool { id: ID!, totalValueLocked: BigInt!, tokens: [ID!]!, swaps: [Swap!]! @derivedFrom(field: "pool") }
+              type Swap { id: ID!, pool: Pool!, pools: [Pool!]! }
+            "#,
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let swap_order_by = schema
+            .get_named_type("Swap_orderBy")
+            .expect("Swap_orderBy type is missing in derived API schema");
+
+        let enum_type = match swap_order_by {
+            TypeDefinition::Enum(t) => Some(t),
+            _ => None,
+        }
+        .expect("Swap_orderBy type is not an enum");
+
+        let values: Vec<&str> = enum_type
+            .values
+            .iter()
+            .map(|value| value.name.as_str())
+            .collect();
+        assert_eq!(
+            values,
+            ["id", "pool", "pools", "pool__id", "pool__totalValueLocked"]
+        );
+    }
+
+    #[test]
+    fn api_schema_contains_child_filters() {
+        let input_schema = parse_schema(
+            r#"
+              interface Named { id: ID!, name: String! }
+              type Pool implements Named { id: ID!, name: String!, swaps: [Swap!]! @derivedFrom(field: "pool") }
+              type Swap { id: ID!, pool: Pool!, named: Named }
+            "#,
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let child_filter = |type_name: &str, field: &str| match schema.get_named_type(type_name) {
+            Some(TypeDefinition::InputObject(t)) => t
+                .fields
+                .iter()
+                .find(|value| value.name == field)
+                .map(|value| value.value_type.to_string()),
+            _ => panic!("{} type is missing in derived API schema", type_name),
+        };
+        assert_eq!(
+            Some("Pool_filter".to_string()),
+            child_filter("Swap_filter", "pool_")
+        );
+        assert_eq!(
+            Some("Named_filter".to_string()),
+            child_filter("Swap_filter", "named_")
+        );
+        assert_eq!(
+            Some("Swap_filter".to_string()),
+            child_filter("Pool_filter", "swaps_")
+        );
+
+        // A field whose name is that of a child filter is an error
+        let input_schema = parse_schema(
+            r#"
+              type Pool { id: ID! }
+              type Swap { id: ID!, pool: Pool!, pool_: String }
+            "#,
+        )
+        .expect("Failed to parse input schema");
+        match api_schema(&input_schema) {
+            Err(APISchemaError::ChildFilterConflict(type_name, field)) => {
+                assert_eq!("Swap", type_name);
+                assert_eq!("pool", field);
+            }
+            other => panic!(
+                "Expected a child filter conflict but got {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+
+    #[test]
+    fn api_schema_contains_cursor_fields() {
+        let input_schema = parse_schema(
+            "interface Named { id: ID!, name: String! }
+             type User implements Named { id: ID!, name: String! }",
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let user_type = match schema.get_named_type("User") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("User type is missing in derived API schema"),
+        };
+        let cursor_field =
+            ast::get_field(user_type, "_cursor").expect("User type is missing the `_cursor` field");
+        assert_eq!(
+            cursor_field.field_type,
+            Type::NonNullType(Box::new(Type::NamedType("String".to_string())))
+        );
+
+        let named_type = schema
+            .get_named_type("Named")
+            .expect("Named type is missing in derived API schema");
+        match named_type {
+            TypeDefinition::Interface(t) => {
+                ast::get_field(t, "_cursor").expect("Named type is missing the `_cursor` field");
+            }
+            _ => panic!("Named type is not an interface"),
+        }
+    }
+
     #[test]
     fn api_schema_contains_object_type_filter_enum() {
         let input_schema = parse_schema(
@@ -892,6 +1408,8 @@ mod tests {
                 "name_lte",
                 "name_in",
                 "name_not_in",
+                "name_in_nocase",
+                "name_not_in_nocase",
                 "name_contains",
                 "name_contains_nocase",
                 "name_not_contains",
@@ -910,12 +1428,18 @@ mod tests {
                 "favoritePetNames_contains_nocase",
                 "favoritePetNames_not_contains",
                 "favoritePetNames_not_contains_nocase",
+                "favoritePetNames_contains_any",
+                "favoritePetNames_contains_all",
+                "favoritePetNames_is_null",
                 "pets",
                 "pets_not",
                 "pets_contains",
                 "pets_contains_nocase",
                 "pets_not_contains",
                 "pets_not_contains_nocase",
+                "pets_contains_any",
+                "pets_contains_all",
+                "pets_",
                 "favoriteFurType",
                 "favoriteFurType_not",
                 "favoriteFurType_in",
@@ -928,6 +1452,8 @@ mod tests {
                 "favoritePet_lte",
                 "favoritePet_in",
                 "favoritePet_not_in",
+                "favoritePet_in_nocase",
+                "favoritePet_not_in_nocase",
                 "favoritePet_contains",
                 "favoritePet_contains_nocase",
                 "favoritePet_not_contains",
@@ -940,7 +1466,12 @@ mod tests {
                 "favoritePet_ends_with_nocase",
                 "favoritePet_not_ends_with",
                 "favoritePet_not_ends_with_nocase",
-                "_change_block"
+                "favoritePet_",
+                "leastFavoritePet_",
+                "mostFavoritePets_",
+                "_change_block",
+                "and",
+                "or"
             ]
             .iter()
             .map(ToString::to_string)
@@ -1027,6 +1558,8 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "after",
+                "before",
                 "block",
                 "subgraphError",
             ]
@@ -1060,6 +1593,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn api_schema_contains_aggregate_fields_on_query_type() {
+        let input_schema = parse_schema(
+            "type User { id: ID!, name: String!, age: Int, weight: BigDecimal, tags: [Int!] }
+             type Tag { id: ID!, name: String! }",
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let query_type = match schema.get_named_type("Query") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Query type is missing in derived API schema"),
+        };
+        let aggregate_field = ast::get_field(query_type, "users_aggregate")
+            .expect("\"users_aggregate\" field is missing on Query type");
+        assert_eq!(
+            aggregate_field.field_type,
+            Type::NonNullType(Box::new(Type::NamedType("User_aggregate".to_string())))
+        );
+        assert_eq!(
+            aggregate_field
+                .arguments
+                .iter()
+                .map(|input_value| input_value.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["where", "block", "subgraphError"]
+        );
+        assert!(aggregate_field
+            .find_directive(AGGREGATE_DIRECTIVE)
+            .is_some());
+
+        let field_names = |name: &str| match schema.get_named_type(name) {
+            Some(TypeDefinition::Object(t)) => t
+                .fields
+                .iter()
+                .map(|field| field.name.as_str())
+                .collect::<Vec<_>>(),
+            _ => panic!("{} type is missing in derived API schema", name),
+        };
+        assert_eq!(
+            field_names("User_aggregate"),
+            vec!["count", "sum", "avg", "min", "max"]
+        );
+        assert_eq!(field_names("User_aggregate_sum"), vec!["age", "weight"]);
+        assert_eq!(field_names("User_aggregate_avg"), vec!["age", "weight"]);
+        assert_eq!(field_names("User_aggregate_fields"), vec!["age", "weight"]);
+
+        // Counts can exceed the range of `Int`
+        let count_type = match schema.get_named_type("User_aggregate") {
+            Some(TypeDefinition::Object(t)) => ast::get_field(t, "count").map(|f| &f.field_type),
+            _ => None,
+        };
+        assert_eq!(
+            count_type,
+            Some(&Type::NonNullType(Box::new(Type::NamedType(
+                "BigInt".to_string()
+            ))))
+        );
+
+        // Types without numeric fields can only be counted
+        assert_eq!(field_names("Tag_aggregate"), vec!["count"]);
+        assert!(schema.get_named_type("Tag_aggregate_sum").is_none());
+    }
+
     #[test]
     fn api_schema_contains_interface_fields_on_query_type() {
         let input_schema = parse_schema(
@@ -1124,6 +1721,8 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "after",
+                "before",
                 "block",
                 "subgraphError"
             ]
@@ -1170,4 +1769,57 @@ type Gravatar @entity {
         }
         .expect("\"metadata\" field is missing on Query type");
     }
+
+    #[test]
+    fn api_schema_contains_fulltext_query_field_for_interface() {
+        const SCHEMA: &str = r#"
+type _Schema_ @fulltext(
+  name: "itemSearch"
+  language: en
+  algorithm: rank
+  interface: "Item"
+  include: [
+    { entity: "Book", fields: [{ name: "title", weight: A }, { name: "blurb" }] },
+    { entity: "Movie", fields: [{ name: "title", weight: A }] }
+  ]
+)
+interface Item {
+  id: ID!
+  title: String!
+}
+type Book implements Item @entity {
+  id: ID!
+  title: String!
+  blurb: String
+}
+type Movie implements Item @entity {
+  id: ID!
+  title: String!
+}
+type Author @entity {
+  id: ID!
+  name: String!
+}
+"#;
+        let input_schema = parse_schema(SCHEMA).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let query_type = match schema.get_named_type("Query") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Query type is missing in derived API schema"),
+        };
+        let search = ast::get_field(query_type, "itemSearch")
+            .expect("\"itemSearch\" field is missing on Query type");
+        assert_eq!("[Item!]!", search.field_type.to_string());
+
+        for name in &["Item", "Book", "Movie"] {
+            let typ = schema.object_or_interface(name).unwrap();
+            let rank = typ
+                .field(RANK_FIELD_NAME)
+                .expect("searchable types have a rank field");
+            assert_eq!("Float", rank.field_type.to_string());
+        }
+        let author = schema.object_or_interface("Author").unwrap();
+        assert!(author.field(RANK_FIELD_NAME).is_none());
+    }
 }
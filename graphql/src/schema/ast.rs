@@ -20,8 +20,12 @@ pub(crate) enum FilterOp {
     LessOrEqual,
     In,
     NotIn,
+    InNoCase,
+    NotInNoCase,
     Contains,
     ContainsNoCase,
+    ContainsAny,
+    ContainsAll,
     NotContains,
     NotContainsNoCase,
     StartsWith,
@@ -32,11 +36,15 @@ pub(crate) enum FilterOp {
     EndsWithNoCase,
     NotEndsWith,
     NotEndsWithNoCase,
+    IsNull,
     Equal,
+    Child,
 }
 
 /// Split a "name_eq" style name into an attribute ("name") and a filter op (`Equal`).
-pub(crate) fn parse_field_as_filter(key: &str) -> (String, FilterOp) {
+/// A name ending in `_` is only a child filter if `entity` has a field with
+/// the name without the `_`, since the name of a field can end in `_`, too
+pub(crate) fn parse_field_as_filter(entity: ObjectOrInterface, key: &str) -> (String, FilterOp) {
     let (suffix, op) = match key {
         k if k.ends_with("_not") => ("_not", FilterOp::Not),
         k if k.ends_with("_gt") => ("_gt", FilterOp::GreaterThan),
@@ -45,12 +53,16 @@ pub(crate) fn parse_field_as_filter(key: &str) -> (String, FilterOp) {
         k if k.ends_with("_lte") => ("_lte", FilterOp::LessOrEqual),
         k if k.ends_with("_not_in") => ("_not_in", FilterOp::NotIn),
         k if k.ends_with("_in") => ("_in", FilterOp::In),
+        k if k.ends_with("_not_in_nocase") => ("_not_in_nocase", FilterOp::NotInNoCase),
+        k if k.ends_with("_in_nocase") => ("_in_nocase", FilterOp::InNoCase),
         k if k.ends_with("_not_contains") => ("_not_contains", FilterOp::NotContains),
         k if k.ends_with("_not_contains_nocase") => {
             ("_not_contains_nocase", FilterOp::NotContainsNoCase)
         }
         k if k.ends_with("_contains") => ("_contains", FilterOp::Contains),
         k if k.ends_with("_contains_nocase") => ("_contains_nocase", FilterOp::ContainsNoCase),
+        k if k.ends_with("_contains_any") => ("_contains_any", FilterOp::ContainsAny),
+        k if k.ends_with("_contains_all") => ("_contains_all", FilterOp::ContainsAll),
         k if k.ends_with("_not_starts_with") => ("_not_starts_with", FilterOp::NotStartsWith),
         k if k.ends_with("_not_starts_with_nocase") => {
             ("_not_starts_with_nocase", FilterOp::NotStartsWithNoCase)
@@ -65,11 +77,18 @@ pub(crate) fn parse_field_as_filter(key: &str) -> (String, FilterOp) {
         }
         k if k.ends_with("_ends_with") => ("_ends_with", FilterOp::EndsWith),
         k if k.ends_with("_ends_with_nocase") => ("_ends_with_nocase", FilterOp::EndsWithNoCase),
+        k if k.ends_with("_is_null") => ("_is_null", FilterOp::IsNull),
+        k if k
+            .strip_suffix('_')
+            .map_or(false, |name| get_field(entity, name).is_some()) =>
+        {
+            ("_", FilterOp::Child)
+        }
         _ => ("", FilterOp::Equal),
     };
 
     // Strip the operator suffix to get the attribute.
-    (key.trim_end_matches(suffix).to_owned(), op)
+    (key.strip_suffix(suffix).unwrap_or(key).to_owned(), op)
 }
 
 /// An `ObjectType` with `Hash` and `Eq` derived from the name.
@@ -510,3 +529,24 @@ fn entity_validation() {
         "Entity Thing[t8]: field `cruft` is derived and can not be set",
     );
 }
+
+#[test]
+fn child_filter_needs_known_field() {
+    let document = graphql_parser::parse_schema::<String>(
+        "type Thing @entity { id: ID!, owner: Thing, name_: String }",
+    )
+    .unwrap();
+    let thing = document.get_object_type_definition("Thing").unwrap();
+
+    let (field, op) = parse_field_as_filter(thing.into(), "owner_");
+    assert_eq!("owner", field);
+    assert!(matches!(op, FilterOp::Child));
+
+    let (field, op) = parse_field_as_filter(thing.into(), "name_");
+    assert_eq!("name_", field);
+    assert!(matches!(op, FilterOp::Equal));
+
+    let (field, op) = parse_field_as_filter(thing.into(), "name__in");
+    assert_eq!("name_", field);
+    assert!(matches!(op, FilterOp::In));
+}
@@ -3,6 +3,7 @@
 
 use anyhow::{anyhow, Error};
 use graph::constraint_violation;
+use graph::data::query::QueryExplain;
 use graph::data::value::Object;
 use graph::prelude::{r, CacheWeight};
 use graph::slog::warn;
@@ -12,7 +13,11 @@ use std::collections::BTreeMap;
 use std::rc::Rc;
 use std::time::Instant;
 
-use graph::{components::store::EntityType, data::graphql::*};
+use graph::{
+    components::store::{EntityAggregate, EntityType},
+    data::graphql::*,
+    data::schema::{CURSOR_FIELD_NAME, RANK_FIELD_NAME},
+};
 use graph::{
     data::graphql::ext::DirectiveFinder,
     prelude::{
@@ -25,8 +30,9 @@ use graph::{
 
 use crate::execution::{ast as a, ExecutionContext, Resolver};
 use crate::runner::ResultSizeMetrics;
+use crate::schema::api::AGGREGATE_DIRECTIVE;
 use crate::schema::ast as sast;
-use crate::store::query::build_query;
+use crate::store::query::{build_query, encode_cursor};
 use crate::store::StoreResolver;
 
 lazy_static! {
@@ -547,6 +553,17 @@ fn execute_selection_set<'a>(
             let field_type = object_type
                 .field(&field.name)
                 .expect("field names are valid");
+
+            let explain = resolver.explain_field(&object_type.name, field);
+
+            if let Some(directive) = field_type.find_directive(AGGREGATE_DIRECTIVE) {
+                match execute_aggregate(resolver, ctx, field, directive, explain) {
+                    Ok(node) => Join::perform(&mut parents, vec![node], field.response_key()),
+                    Err(e) => errors.push(e),
+                }
+                continue;
+            }
+
             let child_type = schema
                 .object_or_interface(field_type.field_type.get_base_type())
                 .expect("we only collect fields that are objects or interfaces");
@@ -576,6 +593,7 @@ fn execute_selection_set<'a>(
                 field,
                 field_type,
                 collected_columns,
+                explain,
             ) {
                 Ok(children) => {
                     match execute_selection_set(resolver, ctx, children, &field.selection_set) {
@@ -602,6 +620,118 @@ fn execute_selection_set<'a>(
     }
 }
 
+/// Executes a `<types>_aggregate` field by computing all the aggregates
+/// that the field's selection set asks for with one query. The result is a
+/// single node with the `count` and a child node for each of `sum`, `avg`,
+/// `min`, and `max`
+fn execute_aggregate(
+    resolver: &StoreResolver,
+    ctx: &ExecutionContext<impl Resolver>,
+    field: &a::Field,
+    directive: &s::Directive,
+    explain: Option<QueryExplain>,
+) -> Result<Node, QueryExecutionError> {
+    let schema = &ctx.query.schema;
+    let entity_name = directive
+        .argument("entity")
+        .and_then(|entity| match entity {
+            s::Value::String(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            constraint_violation!("the @aggregate directive must name an entity type")
+        })?;
+    let entity = schema
+        .object_or_interface(entity_name)
+        .ok_or_else(|| constraint_violation!("unknown entity type `{}`", entity_name))?;
+
+    let mut query = build_query(
+        entity,
+        resolver.block_number(),
+        field,
+        ctx.query.schema.types_for_interface(),
+        ctx.max_first,
+        ctx.max_skip,
+        SelectedAttributes::default(),
+        schema,
+    )?;
+    query.query_id = Some(ctx.query.query_id.clone());
+    query.explain = explain;
+
+    // Collect the aggregates the selection set asks for, and remember for
+    // each `sum`, `avg`, `min` and `max` field which attributes it needs
+    let mut aggregates: Vec<EntityAggregate> = Vec::new();
+    let mut selections: Vec<(&a::Field, Vec<(String, usize)>)> = Vec::new();
+    let mut add = |aggregate: EntityAggregate| match aggregates.iter().position(|a| a == &aggregate)
+    {
+        Some(pos) => pos,
+        None => {
+            aggregates.push(aggregate);
+            aggregates.len() - 1
+        }
+    };
+    let mut count = None;
+    for (_, fields) in field.selection_set.fields() {
+        for field in fields {
+            if field.name == "count" {
+                count = Some(add(EntityAggregate::Count));
+                continue;
+            }
+            let make: fn(String) -> EntityAggregate = match field.name.as_str() {
+                "sum" => EntityAggregate::Sum,
+                "avg" => EntityAggregate::Avg,
+                "min" => EntityAggregate::Min,
+                "max" => EntityAggregate::Max,
+                _ => continue,
+            };
+            let attrs = field
+                .selection_set
+                .fields()
+                .flat_map(|(_, fields)| fields)
+                .filter(|field| !field.name.starts_with("__"))
+                .map(|field| (field.name.clone(), add(make(field.name.clone()))))
+                .collect();
+            selections.push((field, attrs));
+        }
+    }
+
+    let start = Instant::now();
+    let values = resolver.store.find_query_aggregates(query, &aggregates);
+    resolver.record_sql_time(start);
+    let values = values?;
+
+    let mut entity = BTreeMap::new();
+    entity.insert(
+        "__typename".to_string(),
+        r::Value::String(format!("{}_aggregate", entity_name)),
+    );
+    if let Some(pos) = count {
+        let count = match &values[pos] {
+            StoreValue::Int8(n) => r::Value::String(n.to_string()),
+            StoreValue::Int(n) => r::Value::String(n.to_string()),
+            v => return Err(constraint_violation!("count must be an integer but is {}", v).into()),
+        };
+        entity.insert("count".to_string(), count);
+    }
+    let mut node = Node::from(entity);
+    for (field, attrs) in selections {
+        let typename = match field.name.as_str() {
+            "sum" | "avg" => format!("{}_aggregate_{}", entity_name, field.name),
+            _ => format!("{}_aggregate_fields", entity_name),
+        };
+        let mut child = BTreeMap::new();
+        child.insert("__typename".to_string(), r::Value::String(typename));
+        for (attr, pos) in attrs {
+            child.insert(attr, r::Value::from(values[pos].clone()));
+        }
+        node.set_children(
+            field.response_key().to_string(),
+            vec![Rc::new(Node::from(child))],
+        );
+    }
+    Ok(node)
+}
+
 /// Executes a field.
 fn execute_field(
     resolver: &StoreResolver,
@@ -611,6 +741,7 @@ fn execute_field(
     field: &a::Field,
     field_definition: &s::Field,
     selected_attrs: SelectedAttributes,
+    explain: Option<QueryExplain>,
 ) -> Result<Vec<Node>, Vec<QueryExecutionError>> {
     let multiplicity = if sast::is_list_or_non_null_list_field(field_definition) {
         ChildMultiplicity::Many
@@ -618,7 +749,8 @@ fn execute_field(
         ChildMultiplicity::Single
     };
 
-    fetch(
+    let start = Instant::now();
+    let nodes = fetch(
         ctx.logger.clone(),
         resolver.store.as_ref(),
         parents,
@@ -631,8 +763,11 @@ fn execute_field(
         ctx.max_skip,
         ctx.query.query_id.clone(),
         selected_attrs,
-    )
-    .map_err(|e| vec![e])
+        &ctx.query.schema,
+        explain,
+    );
+    resolver.record_sql_time(start);
+    nodes.map_err(|e| vec![e])
 }
 
 /// Query child entities for `parents` from the store. The `join` indicates
@@ -651,6 +786,8 @@ fn fetch(
     max_skip: u32,
     query_id: String,
     selected_attrs: SelectedAttributes,
+    schema: &ApiSchema,
+    explain: Option<QueryExplain>,
 ) -> Result<Vec<Node>, QueryExecutionError> {
     let mut query = build_query(
         join.child_type,
@@ -660,8 +797,10 @@ fn fetch(
         max_first,
         max_skip,
         selected_attrs,
+        schema,
     )?;
     query.query_id = Some(query_id);
+    query.explain = explain;
 
     if multiplicity == ChildMultiplicity::Single {
         // Suppress 'order by' in lookups of scalar values since
@@ -686,9 +825,48 @@ fn fetch(
         }
         query.collection = EntityCollection::Window(windows);
     }
-    store
-        .find_query_values(query)
-        .map(|entities| entities.into_iter().map(|entity| entity.into()).collect())
+
+    let cursor_attr = cursor_attribute(field, join.child_type)?;
+    store.find_query_values(query).map(|entities| {
+        entities
+            .into_iter()
+            .map(|mut entity| {
+                // Fulltext queries return the rank of each entity
+                if let Some(rank) = entity.remove("g$rank") {
+                    entity.insert(RANK_FIELD_NAME.to_string(), rank);
+                }
+                if let Some(attr) = cursor_attr {
+                    let cursor = encode_cursor(attr, &entity);
+                    entity.insert(CURSOR_FIELD_NAME.to_string(), r::Value::String(cursor));
+                }
+                entity.into()
+            })
+            .collect()
+    })
+}
+
+/// The attribute by which the cursors for the entities returned for
+/// `field` are ordered, or `None` if the query does not ask for cursors
+fn cursor_attribute<'a>(
+    field: &'a a::Field,
+    child_type: ObjectOrInterface,
+) -> Result<Option<&'a str>, QueryExecutionError> {
+    let wants_cursor = field
+        .selection_set
+        .fields()
+        .any(|(_, mut fields)| fields.any(|field| field.name == CURSOR_FIELD_NAME));
+    if !wants_cursor {
+        return Ok(None);
+    }
+    match field.argument_value("orderBy") {
+        Some(r::Value::Enum(order_by)) if child_type.field(order_by).is_some() => {
+            Ok(Some(order_by.as_str()))
+        }
+        Some(r::Value::Enum(_)) => Err(QueryExecutionError::NotSupported(
+            "cursors can not be used when ordering by fields of referenced entities".to_string(),
+        )),
+        _ => Ok(Some("id")),
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -698,6 +876,21 @@ impl SelectedAttributes {
     /// Extract the attributes we should select from `selection_set`. In
     /// particular, disregard derived fields since they are not stored
     fn for_field(field: &a::Field) -> Result<SelectedAttributes, Vec<QueryExecutionError>> {
+        // We need to also select the `orderBy` field if there is one.
+        // Because of how the API Schema is set up, `orderBy` can only have
+        // an enum value
+        let order_by = match field.argument_value("orderBy") {
+            None => None,
+            Some(r::Value::Enum(e)) => Some(e),
+            Some(v) => {
+                return Err(vec![constraint_violation!(
+                    "'orderBy' attribute must be an enum but is {:?}",
+                    v
+                )
+                .into()]);
+            }
+        };
+
         let mut map = BTreeMap::new();
         for (object_type, fields) in field.selection_set.fields() {
             let column_names = fields
@@ -709,35 +902,27 @@ impl SelectedAttributes {
                         .unwrap_or(false)
                 })
                 .filter_map(|field| {
-                    if field.name.starts_with("__") {
+                    if field.name.starts_with("__")
+                        || field.name == CURSOR_FIELD_NAME
+                        || field.name == RANK_FIELD_NAME
+                    {
                         None
                     } else {
                         Some(field.name.clone())
                     }
                 })
                 .collect();
-            map.insert(
-                object_type.name().to_string(),
-                AttributeNames::Select(column_names),
-            );
-        }
-        // We need to also select the `orderBy` field if there is one.
-        // Because of how the API Schema is set up, `orderBy` can only have
-        // an enum value
-        match field.argument_value("orderBy") {
-            None => { /* nothing to do */ }
-            Some(r::Value::Enum(e)) => {
-                for columns in map.values_mut() {
-                    columns.add_str(e);
-                }
-            }
-            Some(v) => {
-                return Err(vec![constraint_violation!(
-                    "'orderBy' attribute must be an enum but is {:?}",
-                    v
-                )
-                .into()]);
+            let mut column_names = AttributeNames::Select(column_names);
+            if let Some(order_by) = order_by {
+                // When we sort by a field of a child entity, we need the
+                // field that refers to the child
+                let column = match order_by.split_once("__") {
+                    Some((parent, _)) if sast::get_field(object_type, order_by).is_none() => parent,
+                    _ => order_by.as_str(),
+                };
+                column_names.add_str(column);
             }
+            map.insert(object_type.name().to_string(), column_names);
         }
         Ok(SelectedAttributes(map))
     }
@@ -3,7 +3,10 @@ use std::mem::discriminant;
 
 use graph::data::value::Object;
 use graph::prelude::*;
-use graph::{components::store::EntityType, data::graphql::ObjectOrInterface};
+use graph::{
+    components::store::{Child, EntityCursor, EntityOrderByChild, EntityType},
+    data::graphql::{ObjectOrInterface, TypeExt},
+};
 
 use crate::execution::ast as a;
 use crate::schema::ast as sast;
@@ -16,6 +19,14 @@ enum OrderDirection {
     Descending,
 }
 
+/// The attribute to order by, either of the entity itself or of an entity
+/// it refers to
+#[derive(Debug)]
+enum OrderByValue {
+    Direct(String, ValueType),
+    Child(EntityOrderByChild),
+}
+
 /// Builds a EntityQuery from GraphQL arguments.
 ///
 /// Panics if `entity` is not present in `schema`.
@@ -27,6 +38,7 @@ pub(crate) fn build_query<'a>(
     max_first: u32,
     max_skip: u32,
     mut column_names: SelectedAttributes,
+    schema: &ApiSchema,
 ) -> Result<EntityQuery, QueryExecutionError> {
     let entity = entity.into();
     let entity_types = EntityCollection::All(match &entity {
@@ -45,21 +57,29 @@ pub(crate) fn build_query<'a>(
     });
     let mut query = EntityQuery::new(parse_subgraph_id(entity)?, block, entity_types)
         .range(build_range(field, max_first, max_skip)?);
-    if let Some(filter) = build_filter(entity, field)? {
+    if let Some(filter) = build_filter(entity, field, schema)? {
         query = query.filter(filter);
     }
     let order = match (
-        build_order_by(entity, field)?,
+        build_order_by(entity, field, schema)?,
         build_order_direction(field)?,
     ) {
-        (Some((attr, value_type)), OrderDirection::Ascending) => {
+        (Some(OrderByValue::Direct(attr, value_type)), OrderDirection::Ascending) => {
             EntityOrder::Ascending(attr, value_type)
         }
-        (Some((attr, value_type)), OrderDirection::Descending) => {
+        (Some(OrderByValue::Direct(attr, value_type)), OrderDirection::Descending) => {
             EntityOrder::Descending(attr, value_type)
         }
+        (Some(OrderByValue::Child(child)), OrderDirection::Ascending) => {
+            EntityOrder::ChildAscending(child)
+        }
+        (Some(OrderByValue::Child(child)), OrderDirection::Descending) => {
+            EntityOrder::ChildDescending(child)
+        }
         (None, _) => EntityOrder::Default,
     };
+    query.range.after = build_cursor(entity, field, "after", &order)?;
+    query.range.before = build_cursor(entity, field, "before", &order)?;
     query = query.order(order);
     Ok(query)
 }
@@ -103,16 +123,80 @@ fn build_range(
     Ok(EntityRange {
         first: Some(first),
         skip,
+        after: None,
+        before: None,
     })
 }
 
+/// Encodes the position of `entity` in a collection ordered by the
+/// attribute `order_by` as an opaque cursor that can be passed to the
+/// `after` and `before` arguments
+pub(crate) fn encode_cursor(order_by: &str, entity: &BTreeMap<String, r::Value>) -> String {
+    let value = entity.get(order_by).unwrap_or(&r::Value::Null);
+    let id = entity.get("id").unwrap_or(&r::Value::Null);
+    hex::encode(serde_json::json!([order_by, value, id]).to_string())
+}
+
+/// Decodes a cursor created by `encode_cursor` into the attribute it was
+/// ordered by, the value of that attribute and the id of the entity
+fn decode_cursor(cursor: &str) -> Result<(String, r::Value, String), QueryExecutionError> {
+    let invalid = || QueryExecutionError::InvalidCursorError(cursor.to_string());
+
+    let bytes = hex::decode(cursor).map_err(|_| invalid())?;
+    let json: serde_json::Value = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+    match json.as_array().map(|parts| parts.as_slice()) {
+        Some([serde_json::Value::String(order_by), value, serde_json::Value::String(id)]) => {
+            Ok((order_by.clone(), r::Value::from(value.clone()), id.clone()))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses the cursor in the argument `arg` into an `EntityCursor`. The
+/// cursor must have been created for a collection with the same `order`
+fn build_cursor(
+    entity: ObjectOrInterface,
+    field: &a::Field,
+    arg: &str,
+    order: &EntityOrder,
+) -> Result<Option<EntityCursor>, QueryExecutionError> {
+    let cursor = match field.argument_value(arg) {
+        Some(r::Value::String(cursor)) => cursor,
+        Some(r::Value::Null) | None => return Ok(None),
+        _ => unreachable!("cursors are Strings"),
+    };
+
+    let attr = match order {
+        EntityOrder::Ascending(attr, _) | EntityOrder::Descending(attr, _) => attr.as_str(),
+        EntityOrder::Default | EntityOrder::Unordered => "id",
+        EntityOrder::ChildAscending(_) | EntityOrder::ChildDescending(_) => {
+            return Err(QueryExecutionError::NotSupported(
+                "cursors can not be used when ordering by fields of referenced entities"
+                    .to_string(),
+            ))
+        }
+    };
+
+    let (order_by, value, id) = decode_cursor(cursor)?;
+    if order_by != attr {
+        return Err(QueryExecutionError::InvalidCursorError(cursor.clone()));
+    }
+    let value = match entity.field(attr) {
+        Some(field) => Value::from_query_value(&value, &field.field_type)
+            .map_err(|_| QueryExecutionError::InvalidCursorError(cursor.clone()))?,
+        None => return Err(QueryExecutionError::InvalidCursorError(cursor.clone())),
+    };
+    Ok(Some(EntityCursor { value, id }))
+}
+
 /// Parses GraphQL arguments into an EntityFilter, if present.
 fn build_filter(
     entity: ObjectOrInterface,
     field: &a::Field,
+    schema: &ApiSchema,
 ) -> Result<Option<EntityFilter>, QueryExecutionError> {
     match field.argument_value("where") {
-        Some(r::Value::Object(object)) => build_filter_from_object(entity, object),
+        Some(r::Value::Object(object)) => build_filter_from_object(entity, object, schema),
         Some(r::Value::Null) => Ok(None),
         None => match field.argument_value("text") {
             Some(r::Value::Object(filter)) => build_fulltext_filter_from_object(filter),
@@ -157,6 +241,7 @@ fn parse_change_block_filter(value: &r::Value) -> Result<BlockNumber, QueryExecu
 fn build_filter_from_object(
     entity: ObjectOrInterface,
     object: &Object,
+    schema: &ApiSchema,
 ) -> Result<Option<EntityFilter>, QueryExecutionError> {
     Ok(Some(EntityFilter::And({
         object
@@ -171,9 +256,23 @@ fn build_filter_from_object(
                     };
                 }
 
+                // `and` and `or` combine nested filters, unless the entity
+                // has a field with that name
+                if (key == "and" || key == "or") && sast::get_field(entity, key).is_none() {
+                    if value == &r::Value::Null {
+                        return Ok(EntityFilter::And(vec![]));
+                    }
+                    let filters = build_filter_list(entity, value, schema)?;
+                    return Ok(if key == "and" {
+                        EntityFilter::And(filters)
+                    } else {
+                        EntityFilter::Or(filters)
+                    });
+                }
+
                 use self::sast::FilterOp::*;
 
-                let (field_name, op) = sast::parse_field_as_filter(key);
+                let (field_name, op) = sast::parse_field_as_filter(entity, key);
 
                 let field = sast::get_field(entity, &field_name).ok_or_else(|| {
                     QueryExecutionError::EntityFieldError(
@@ -182,6 +281,21 @@ fn build_filter_from_object(
                     )
                 })?;
 
+                if let Child = op {
+                    return build_child_filter_from_object(field_name, field, value, schema);
+                }
+
+                // `_is_null` takes a `Boolean` regardless of the type of
+                // the field it applies to
+                if let IsNull = op {
+                    return match value {
+                        r::Value::Boolean(true) => Ok(EntityFilter::IsNull(field_name)),
+                        r::Value::Boolean(false) => Ok(EntityFilter::IsNotNull(field_name)),
+                        r::Value::Null => Ok(EntityFilter::And(vec![])),
+                        _ => Err(QueryExecutionError::InvalidFilterError),
+                    };
+                }
+
                 let ty = &field.field_type;
                 let store_value = Value::from_query_value(value, ty)?;
 
@@ -193,8 +307,17 @@ fn build_filter_from_object(
                     LessOrEqual => EntityFilter::LessOrEqual(field_name, store_value),
                     In => EntityFilter::In(field_name, list_values(store_value, "_in")?),
                     NotIn => EntityFilter::NotIn(field_name, list_values(store_value, "_not_in")?),
+                    InNoCase => {
+                        EntityFilter::InNoCase(field_name, list_values(store_value, "_in_nocase")?)
+                    }
+                    NotInNoCase => EntityFilter::NotInNoCase(
+                        field_name,
+                        list_values(store_value, "_not_in_nocase")?,
+                    ),
                     Contains => EntityFilter::Contains(field_name, store_value),
                     ContainsNoCase => EntityFilter::ContainsNoCase(field_name, store_value),
+                    ContainsAny => EntityFilter::ContainsAny(field_name, store_value),
+                    ContainsAll => EntityFilter::ContainsAll(field_name, store_value),
                     NotContains => EntityFilter::NotContains(field_name, store_value),
                     NotContainsNoCase => EntityFilter::NotContainsNoCase(field_name, store_value),
                     StartsWith => EntityFilter::StartsWith(field_name, store_value),
@@ -208,12 +331,82 @@ fn build_filter_from_object(
                     NotEndsWith => EntityFilter::NotEndsWith(field_name, store_value),
                     NotEndsWithNoCase => EntityFilter::NotEndsWithNoCase(field_name, store_value),
                     Equal => EntityFilter::Equal(field_name, store_value),
+                    Child => unreachable!("child filters are handled above"),
+                    IsNull => unreachable!("null filters are handled above"),
                 })
             })
             .collect::<Result<Vec<EntityFilter>, QueryExecutionError>>()?
     })))
 }
 
+/// Parses the list of nested filter objects passed to `and` or `or`.
+fn build_filter_list(
+    entity: ObjectOrInterface,
+    value: &r::Value,
+    schema: &ApiSchema,
+) -> Result<Vec<EntityFilter>, QueryExecutionError> {
+    match value {
+        r::Value::List(list) => list
+            .iter()
+            .map(|item| match item {
+                r::Value::Object(object) => Ok(build_filter_from_object(entity, object, schema)?
+                    .unwrap_or(EntityFilter::And(vec![]))),
+                _ => Err(QueryExecutionError::InvalidFilterError),
+            })
+            .collect(),
+        _ => Err(QueryExecutionError::InvalidFilterError),
+    }
+}
+
+/// Parses the filter `<field>_: { .. }` on the attributes of the entities
+/// that `field` refers to. If `field` refers to an interface, the filter
+/// matches if the entities of any type that implements the interface
+/// match.
+fn build_child_filter_from_object(
+    field_name: String,
+    field: &s::Field,
+    value: &r::Value,
+    schema: &ApiSchema,
+) -> Result<EntityFilter, QueryExecutionError> {
+    let object = match value {
+        r::Value::Object(object) => object,
+        _ => return Err(QueryExecutionError::InvalidFilterError),
+    };
+    let child_entity = schema
+        .object_or_interface(field.field_type.get_base_type())
+        .ok_or(QueryExecutionError::InvalidFilterError)?;
+    let filter = build_filter_from_object(child_entity, object, schema)?
+        .unwrap_or_else(|| EntityFilter::And(vec![]));
+    let (attr, derived) = match sast::get_derived_from_field(child_entity, field) {
+        Some(child_field) => (child_field.name.clone(), true),
+        None => (field_name, false),
+    };
+
+    let child_types = match child_entity {
+        ObjectOrInterface::Object(_) => vec![EntityType::new(child_entity.name().to_owned())],
+        ObjectOrInterface::Interface(_) => schema
+            .types_for_interface()
+            .get(&EntityType::new(child_entity.name().to_owned()))
+            .map(|types| types.iter().map(EntityType::from).collect())
+            .unwrap_or_default(),
+    };
+    let mut filters: Vec<_> = child_types
+        .into_iter()
+        .map(|entity_type| {
+            EntityFilter::Child(Child {
+                attr: attr.clone(),
+                entity_type,
+                filter: Box::new(filter.clone()),
+                derived,
+            })
+        })
+        .collect();
+    Ok(match filters.len() {
+        1 => filters.pop().unwrap(),
+        _ => EntityFilter::Or(filters),
+    })
+}
+
 /// Parses a list of GraphQL values into a vector of entity field values.
 fn list_values(value: Value, filter_type: &str) -> Result<Vec<Value>, QueryExecutionError> {
     match value {
@@ -246,14 +439,16 @@ fn list_values(value: Value, filter_type: &str) -> Result<Vec<Value>, QueryExecu
 fn build_order_by(
     entity: ObjectOrInterface,
     field: &a::Field,
-) -> Result<Option<(String, ValueType)>, QueryExecutionError> {
+    schema: &ApiSchema,
+) -> Result<Option<OrderByValue>, QueryExecutionError> {
     match field.argument_value("orderBy") {
         Some(r::Value::Enum(name)) => {
-            let field = sast::get_field(entity, name).ok_or_else(|| {
-                QueryExecutionError::EntityFieldError(entity.name().to_owned(), name.clone())
-            })?;
+            let field = match sast::get_field(entity, name) {
+                Some(field) => field,
+                None => return build_child_order_by(entity, name, schema).map(Some),
+            };
             sast::get_field_value_type(&field.field_type)
-                .map(|value_type| Some((name.to_owned(), value_type)))
+                .map(|value_type| Some(OrderByValue::Direct(name.to_owned(), value_type)))
                 .map_err(|_| {
                     QueryExecutionError::OrderByNotSupportedError(
                         entity.name().to_owned(),
@@ -262,13 +457,52 @@ fn build_order_by(
                 })
         }
         _ => match field.argument_value("text") {
-            Some(r::Value::Object(filter)) => build_fulltext_order_by_from_object(filter),
+            Some(r::Value::Object(filter)) => {
+                build_fulltext_order_by_from_object(filter).map(|order| {
+                    order.map(|(attr, value_type)| OrderByValue::Direct(attr, value_type))
+                })
+            }
             None => Ok(None),
             _ => Err(QueryExecutionError::InvalidFilterError),
         },
     }
 }
 
+/// Parses an `orderBy` value of the form `<field>__<child_field>` that sorts
+/// by `child_field` of the entity that `field` refers to.
+fn build_child_order_by(
+    entity: ObjectOrInterface,
+    name: &str,
+    schema: &ApiSchema,
+) -> Result<OrderByValue, QueryExecutionError> {
+    let field_error =
+        || QueryExecutionError::EntityFieldError(entity.name().to_owned(), name.to_owned());
+    let not_supported =
+        || QueryExecutionError::OrderByNotSupportedError(entity.name().to_owned(), name.to_owned());
+
+    let (parent_name, child_name) = name.split_once("__").ok_or_else(field_error)?;
+    let parent_field = sast::get_field(entity, parent_name).ok_or_else(field_error)?;
+    if sast::is_list_or_non_null_list_field(parent_field)
+        || sast::get_derived_from_directive(parent_field).is_some()
+    {
+        return Err(not_supported());
+    }
+    let child_entity = match schema.object_or_interface(parent_field.field_type.get_base_type()) {
+        Some(ObjectOrInterface::Object(child_entity)) => child_entity,
+        _ => return Err(not_supported()),
+    };
+    let child_field = sast::get_field(child_entity, child_name).ok_or_else(field_error)?;
+    let value_type =
+        sast::get_field_value_type(&child_field.field_type).map_err(|_| not_supported())?;
+
+    Ok(OrderByValue::Child(EntityOrderByChild {
+        attr: parent_name.to_owned(),
+        child_type: EntityType::from(child_entity),
+        child_attr: child_name.to_owned(),
+        value_type,
+    }))
+}
+
 fn build_fulltext_order_by_from_object(
     object: &Object,
 ) -> Result<Option<(String, ValueType)>, QueryExecutionError> {
@@ -368,11 +602,11 @@ pub(crate) fn collect_entities_from_query_field(
 #[cfg(test)]
 mod tests {
     use graph::{
-        components::store::EntityType,
-        data::value::Object,
+        components::store::{Child, EntityCursor, EntityOrderByChild, EntityType},
+        data::{store::scalar::BigInt, value::Object},
         prelude::{
-            r, AttributeNames, EntityCollection, EntityFilter, EntityRange, Value, ValueType,
-            BLOCK_NUMBER_MAX,
+            r, ApiSchema, AttributeNames, DeploymentHash, EntityCollection, EntityFilter,
+            EntityRange, QueryExecutionError, Schema, Value, ValueType, BLOCK_NUMBER_MAX,
         },
         prelude::{
             s::{self, Directive, Field, InputValue, ObjectType, Type, Value as SchemaValue},
@@ -382,7 +616,20 @@ mod tests {
     use graphql_parser::Pos;
     use std::{collections::BTreeMap, iter::FromIterator, sync::Arc};
 
-    use super::{a, build_query};
+    use crate::schema::api::api_schema;
+
+    use super::{a, build_query, encode_cursor};
+
+    fn api_schema_from(raw: &str) -> ApiSchema {
+        let input_schema = graphql_parser::parse_schema(raw).unwrap().into_static();
+        let document = api_schema(&input_schema).unwrap();
+        let schema = Schema::new(DeploymentHash::new("id").unwrap(), document).unwrap();
+        ApiSchema::from_api_schema(schema).unwrap()
+    }
+
+    fn default_schema() -> ApiSchema {
+        api_schema_from("type Thing @entity { id: ID! }")
+    }
 
     fn default_object() -> ObjectType {
         let subgraph_id_argument = (
@@ -487,7 +734,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .collection,
@@ -501,7 +749,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .collection,
@@ -519,7 +768,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -538,7 +788,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -554,7 +805,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -573,7 +825,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -589,7 +842,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -611,7 +865,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -630,7 +885,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -652,7 +908,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -672,7 +929,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .order,
@@ -690,7 +948,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .range,
@@ -711,13 +970,16 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .range,
             EntityRange {
                 first: Some(100),
                 skip: 50,
+                after: None,
+                before: None,
             },
         );
     }
@@ -742,7 +1004,8 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .filter,
@@ -753,6 +1016,44 @@ mod tests {
         )
     }
 
+    #[test]
+    fn build_query_yields_is_null_and_in_nocase_filters() {
+        let query_field = default_field_with(
+            "where",
+            r::Value::Object(Object::from_iter(vec![
+                ("name_is_null".to_string(), r::Value::Boolean(false)),
+                (
+                    "name_in_nocase".to_string(),
+                    r::Value::List(vec![r::Value::String("Hello".to_string())]),
+                ),
+            ])),
+        );
+        assert_eq!(
+            build_query(
+                &ObjectType {
+                    fields: vec![field("name", Type::NamedType("string".to_owned()))],
+                    ..default_object()
+                },
+                BLOCK_NUMBER_MAX,
+                &query_field,
+                &BTreeMap::new(),
+                std::u32::MAX,
+                std::u32::MAX,
+                Default::default(),
+                &default_schema()
+            )
+            .unwrap()
+            .filter,
+            Some(EntityFilter::And(vec![
+                EntityFilter::IsNotNull("name".to_string()),
+                EntityFilter::InNoCase(
+                    "name".to_string(),
+                    vec![Value::String("Hello".to_string())]
+                ),
+            ]))
+        )
+    }
+
     #[test]
     fn build_query_yields_block_change_gte_filter() {
         let query_field = default_field_with(
@@ -776,11 +1077,235 @@ mod tests {
                 &BTreeMap::new(),
                 std::u32::MAX,
                 std::u32::MAX,
-                Default::default()
+                Default::default(),
+                &default_schema()
             )
             .unwrap()
             .filter,
             Some(EntityFilter::And(vec![EntityFilter::ChangeBlockGte(10)]))
         )
     }
+
+    #[test]
+    fn build_query_yields_nested_or_filter() {
+        let name_filter = |name: &str| {
+            r::Value::Object(Object::from_iter(vec![(
+                "name".to_string(),
+                r::Value::String(name.to_string()),
+            )]))
+        };
+        let query_field = default_field_with(
+            "where",
+            r::Value::Object(Object::from_iter(vec![(
+                "or".to_string(),
+                r::Value::List(vec![name_filter("Bob"), name_filter("Carol")]),
+            )])),
+        );
+        assert_eq!(
+            build_query(
+                &ObjectType {
+                    fields: vec![field("name", Type::NamedType("string".to_owned()))],
+                    ..default_object()
+                },
+                BLOCK_NUMBER_MAX,
+                &query_field,
+                &BTreeMap::new(),
+                std::u32::MAX,
+                std::u32::MAX,
+                Default::default(),
+                &default_schema()
+            )
+            .unwrap()
+            .filter,
+            Some(EntityFilter::And(vec![EntityFilter::Or(vec![
+                EntityFilter::And(vec![EntityFilter::Equal(
+                    "name".to_string(),
+                    Value::String("Bob".to_string()),
+                )]),
+                EntityFilter::And(vec![EntityFilter::Equal(
+                    "name".to_string(),
+                    Value::String("Carol".to_string()),
+                )]),
+            ])]))
+        )
+    }
+
+    #[test]
+    fn build_query_yields_child_filters() {
+        let schema = api_schema_from(
+            "interface Holder { id: ID!, balance: Int! }
+             type Owner implements Holder @entity { id: ID!, balance: Int!, tokens: [Token!]! @derivedFrom(field: \"owner\") }
+             type Vault implements Holder @entity { id: ID!, balance: Int! }
+             type Token @entity { id: ID!, owner: Owner!, holder: Holder }",
+        );
+        let filter_for = |entity: &str, key: &str, child_filter: (&str, r::Value)| {
+            let query_field = default_field_with(
+                "where",
+                r::Value::Object(Object::from_iter(vec![(
+                    key.to_string(),
+                    r::Value::Object(Object::from_iter(vec![(
+                        child_filter.0.to_string(),
+                        child_filter.1,
+                    )])),
+                )])),
+            );
+            build_query(
+                schema.object_or_interface(entity).unwrap(),
+                BLOCK_NUMBER_MAX,
+                &query_field,
+                schema.types_for_interface(),
+                std::u32::MAX,
+                std::u32::MAX,
+                Default::default(),
+                &schema,
+            )
+            .unwrap()
+            .filter
+        };
+
+        assert_eq!(
+            filter_for("Token", "owner_", ("balance_gt", r::Value::Int(100))),
+            Some(EntityFilter::And(vec![EntityFilter::Child(Child {
+                attr: "owner".to_string(),
+                entity_type: EntityType::from("Owner"),
+                filter: Box::new(EntityFilter::And(vec![EntityFilter::GreaterThan(
+                    "balance".to_string(),
+                    Value::Int(100),
+                )])),
+                derived: false,
+            })]))
+        );
+        assert_eq!(
+            filter_for(
+                "Owner",
+                "tokens_",
+                ("id", r::Value::String("token1".to_string()))
+            ),
+            Some(EntityFilter::And(vec![EntityFilter::Child(Child {
+                attr: "owner".to_string(),
+                entity_type: EntityType::from("Token"),
+                filter: Box::new(EntityFilter::And(vec![EntityFilter::Equal(
+                    "id".to_string(),
+                    Value::String("token1".to_string()),
+                )])),
+                derived: true,
+            })]))
+        );
+
+        // Child filters on interfaces match entities of any type that
+        // implements the interface
+        let child = |entity_type: &str| {
+            EntityFilter::Child(Child {
+                attr: "holder".to_string(),
+                entity_type: EntityType::from(entity_type),
+                filter: Box::new(EntityFilter::And(vec![EntityFilter::GreaterThan(
+                    "balance".to_string(),
+                    Value::Int(100),
+                )])),
+                derived: false,
+            })
+        };
+        assert_eq!(
+            filter_for("Token", "holder_", ("balance_gt", r::Value::Int(100))),
+            Some(EntityFilter::And(vec![EntityFilter::Or(vec![
+                child("Owner"),
+                child("Vault")
+            ])]))
+        );
+    }
+
+    #[test]
+    fn build_query_parses_child_order_by() {
+        let schema = api_schema_from(
+            "type Pool @entity { id: ID!, totalValueLocked: BigInt! }
+             type Swap @entity { id: ID!, pool: Pool! }",
+        );
+        let query_field = default_field_with_vec(vec![
+            (
+                "orderBy",
+                r::Value::Enum("pool__totalValueLocked".to_string()),
+            ),
+            ("orderDirection", r::Value::Enum("desc".to_string())),
+        ]);
+
+        assert_eq!(
+            build_query(
+                schema.object_or_interface("Swap").unwrap(),
+                BLOCK_NUMBER_MAX,
+                &query_field,
+                schema.types_for_interface(),
+                std::u32::MAX,
+                std::u32::MAX,
+                Default::default(),
+                &schema,
+            )
+            .unwrap()
+            .order,
+            EntityOrder::ChildDescending(EntityOrderByChild {
+                attr: "pool".to_string(),
+                child_type: EntityType::from("Pool"),
+                child_attr: "totalValueLocked".to_string(),
+                value_type: ValueType::BigInt,
+            })
+        );
+    }
+
+    #[test]
+    fn build_query_parses_cursors() {
+        let schema = api_schema_from("type Pool @entity { id: ID!, totalValueLocked: BigInt! }");
+        let entity = BTreeMap::from_iter(vec![
+            ("id".to_string(), r::Value::String("pool1".to_string())),
+            (
+                "totalValueLocked".to_string(),
+                r::Value::String("1000".to_string()),
+            ),
+        ]);
+        let cursor = encode_cursor("totalValueLocked", &entity);
+
+        let build = |query_field: a::Field| {
+            build_query(
+                schema.object_or_interface("Pool").unwrap(),
+                BLOCK_NUMBER_MAX,
+                &query_field,
+                schema.types_for_interface(),
+                std::u32::MAX,
+                std::u32::MAX,
+                Default::default(),
+                &schema,
+            )
+        };
+
+        let query = build(default_field_with_vec(vec![
+            ("orderBy", r::Value::Enum("totalValueLocked".to_string())),
+            ("after", r::Value::String(cursor.clone())),
+        ]))
+        .unwrap();
+        assert_eq!(
+            query.range.after,
+            Some(EntityCursor {
+                value: Value::BigInt(BigInt::from(1000)),
+                id: "pool1".to_string(),
+            })
+        );
+        assert_eq!(query.range.before, None);
+
+        // The cursor was made for a different `orderBy`
+        let res = build(default_field_with_vec(vec![
+            ("orderBy", r::Value::Enum("id".to_string())),
+            ("before", r::Value::String(cursor)),
+        ]));
+        assert!(matches!(
+            res,
+            Err(QueryExecutionError::InvalidCursorError(_))
+        ));
+
+        let res = build(default_field_with(
+            "after",
+            r::Value::String("not a cursor".to_string()),
+        ));
+        assert!(matches!(
+            res,
+            Err(QueryExecutionError::InvalidCursorError(_))
+        ));
+    }
 }
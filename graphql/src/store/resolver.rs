@@ -1,7 +1,10 @@
 use std::collections::BTreeMap;
 use std::result;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
+use graph::data::query::QueryExplain;
 use graph::data::value::Object;
 use graph::data::{
     graphql::{object, ObjectOrInterface},
@@ -30,6 +33,12 @@ pub struct StoreResolver {
     has_non_fatal_errors: bool,
     error_policy: ErrorPolicy,
     result_size: Arc<ResultSizeMetrics>,
+    /// The time spent running SQL queries, in nanoseconds. Shared between
+    /// all clones of this resolver
+    sql_time: Arc<AtomicU64>,
+    /// Where to record the SQL queries this resolver runs if the query
+    /// should be explained
+    pub(crate) explain: Option<QueryExplain>,
 }
 
 impl CheapClone for StoreResolver {}
@@ -57,6 +66,8 @@ impl StoreResolver {
             has_non_fatal_errors: false,
             error_policy: ErrorPolicy::Deny,
             result_size,
+            sql_time: Arc::new(AtomicU64::new(0)),
+            explain: None,
         }
     }
 
@@ -91,6 +102,8 @@ impl StoreResolver {
             has_non_fatal_errors,
             error_policy,
             result_size,
+            sql_time: Arc::new(AtomicU64::new(0)),
+            explain: None,
         };
         Ok(resolver)
     }
@@ -102,6 +115,30 @@ impl StoreResolver {
             .unwrap_or(BLOCK_NUMBER_MAX)
     }
 
+    /// Add the time since `start` to the time spent running SQL queries
+    pub(crate) fn record_sql_time(&self, start: Instant) {
+        self.sql_time
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// The total time spent running SQL queries through this resolver
+    /// and its clones
+    pub fn sql_time(&self) -> Duration {
+        Duration::from_nanos(self.sql_time.load(Ordering::Relaxed))
+    }
+
+    /// Where to record the SQL queries for `field` of `object_type` if
+    /// the query should be explained
+    pub(crate) fn explain_field(
+        &self,
+        object_type: &str,
+        field: &a::Field,
+    ) -> Option<QueryExplain> {
+        self.explain
+            .as_ref()
+            .map(|explain| explain.for_field(format!("{}.{}", object_type, field.response_key())))
+    }
+
     async fn locate_block(
         store: &dyn QueryStore,
         bc: BlockConstraint,
@@ -158,6 +195,21 @@ impl StoreResolver {
                 .await
                 .map_err(Into::into)
                 .and_then(|ptr| check_ptr(subgraph, ptr, number)),
+            BlockConstraint::Timestamp(timestamp) => {
+                let ptr = store.block_ptr_by_timestamp(timestamp)?.ok_or_else(|| {
+                    QueryExecutionError::ValueParseError(
+                        "block.timestamp_lte".to_owned(),
+                        format!(
+                            "no block with a timestamp at or before {} found in the block cache",
+                            timestamp
+                        ),
+                    )
+                })?;
+                let head = store.block_ptr().await?;
+                check_ptr(subgraph, head, ptr.number)?;
+                Ok(ptr)
+            }
+            BlockConstraint::Ptr(ptr) => Ok(ptr),
             BlockConstraint::Latest => {
                 store.block_ptr().await.map_err(Into::into).map(|ptr| {
                     ptr.expect("we should have already checked that the subgraph exists")
@@ -52,6 +52,15 @@ impl MaybeCoercible<ScalarType> for q::Value {
             ("BigInt", q::Value::Int(n)) => Ok(r::Value::String(
                 n.as_i64().ok_or(q::Value::Int(n))?.to_string(),
             )),
+            ("Int8", q::Value::Int(n)) => Ok(r::Value::Int(n.as_i64().ok_or(q::Value::Int(n))?)),
+            ("Int8", q::Value::String(s)) => match s.parse::<i64>() {
+                Ok(n) => Ok(r::Value::Int(n)),
+                Err(_) => Err(q::Value::String(s)),
+            },
+            ("Timestamp", q::Value::Int(n)) => Ok(r::Value::String(
+                n.as_i64().ok_or(q::Value::Int(n))?.to_string(),
+            )),
+            ("Timestamp", q::Value::String(s)) => Ok(r::Value::String(s)),
             (_, v) => Err(v),
         }
     }
@@ -421,4 +430,26 @@ mod tests {
             Ok(Value::Int((-13289123 as i32).into()))
         );
     }
+
+    #[test]
+    fn coerce_int8_scalar() {
+        let int8_type = TypeDefinition::Scalar(ScalarType::new("Int8".to_string()));
+        let resolver = |_: &str| Some(&int8_type);
+
+        assert_eq!(
+            coerce_to_definition(Value::Int(5_000_000_000), "", &resolver),
+            Ok(Value::Int(5_000_000_000))
+        );
+        assert_eq!(
+            coerce_to_definition(
+                Value::String("-9223372036854775808".to_string()),
+                "",
+                &resolver
+            ),
+            Ok(Value::Int(i64::MIN))
+        );
+        assert!(
+            coerce_to_definition(Value::String("not a number".to_string()), "", &resolver).is_err()
+        );
+    }
 }
@@ -10,7 +10,7 @@ use structopt::StructOpt;
 
 use graph::{
     log::logger,
-    prelude::{info, o, slog, tokio, Logger, NodeId, ENV_VARS},
+    prelude::{info, o, slog, tokio, BlockNumber, Logger, NodeId, ENV_VARS},
     url::Url,
 };
 use graph_node::{
@@ -143,6 +143,19 @@ pub enum Command {
         /// The deployment (see `help info`)
         deployment: DeploymentSearch,
     },
+    /// Pause a deployment
+    ///
+    /// The deployment stays assigned to its node, but stops indexing until
+    /// it is resumed. Queries against the deployment keep working
+    Pause {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+    },
+    /// Resume a paused deployment
+    Resume {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+    },
     /// Rewind a subgraph to a specific block
     Rewind {
         /// Force rewinding even if the block hash is not found in the local
@@ -164,6 +177,27 @@ pub enum Command {
         /// The deployments to rewind (see `help info`)
         deployments: Vec<DeploymentSearch>,
     },
+    /// Prune the history of a deployment
+    ///
+    /// Remove all entity versions that are only needed to answer queries
+    /// for blocks more than `--history` blocks behind the deployment head.
+    /// Unless `--once` is given, the number of blocks is also remembered
+    /// and the deployment is pruned periodically from then on
+    Prune {
+        /// The deployment (see `help info`)
+        deployment: DeploymentSearch,
+        /// How many blocks of history to keep. Defaults to the number of
+        /// blocks remembered from a previous `prune`
+        #[structopt(long)]
+        history: Option<BlockNumber>,
+        /// Prune only once and do not prune automatically afterwards
+        #[structopt(long, conflicts_with = "clear")]
+        once: bool,
+        /// Stop pruning the deployment automatically and keep its entire
+        /// history from now on
+        #[structopt(long, conflicts_with = "history")]
+        clear: bool,
+    },
     /// Deploy and run an arbitrary subgraph up to a certain block, although it can surpass it by a few blocks, it's not exact (use for dev and testing purposes) -- WARNING: WILL RUN MIGRATIONS ON THE DB, DO NOT USE IN PRODUCTION
     ///
     /// Also worth noting that the deployed subgraph will be removed at the end.
@@ -199,6 +233,9 @@ pub enum Command {
         query: String,
         /// The variables in the form `key=value`
         vars: Vec<String>,
+        /// Print the SQL queries that the query runs and their query plans
+        #[structopt(long)]
+        explain: bool,
     },
     /// Get information about chains and manipulate them
     Chain(ChainCommand),
@@ -346,6 +383,14 @@ pub enum ChainCommand {
     /// There must be no deployments using that chain. If there are, the
     /// subgraphs and/or deployments using the chain must first be removed
     Remove { name: String },
+    /// Index the timestamps of the blocks of a chain
+    ///
+    /// Looking up blocks by timestamp, which queries with a `block: {
+    /// timestamp }` constraint do, needs this index for chains whose block
+    /// cache was created before such queries were possible. The index is
+    /// built without blocking writes to the block cache, but that can take
+    /// a long time for large block caches
+    IndexTimestamps { name: String },
 }
 
 #[derive(Clone, Debug, StructOpt)]
@@ -415,6 +460,9 @@ pub enum IndexCommand {
         #[structopt(min_values = 1, required = true)]
         fields: Vec<String>,
         /// The index method. Defaults to `btree`.
+        ///
+        /// Use `gin` for list and fulltext fields; such indexes speed up
+        /// the `contains`, `contains_any` and `contains_all` filters.
         #[structopt(
             short, long, default_value = "btree",
             possible_values = &["btree", "hash", "gist", "spgist", "gin", "brin"]
@@ -757,6 +805,14 @@ async fn main() {
             let sender = ctx.notification_sender();
             commands::assign::unassign(ctx.primary_pool(), &sender, &deployment).await
         }
+        Pause { deployment } => {
+            let sender = ctx.notification_sender();
+            commands::assign::pause_or_resume(ctx.primary_pool(), &sender, &deployment, true)
+        }
+        Resume { deployment } => {
+            let sender = ctx.notification_sender();
+            commands::assign::pause_or_resume(ctx.primary_pool(), &sender, &deployment, false)
+        }
         Reassign { deployment, node } => {
             let sender = ctx.notification_sender();
             commands::assign::reassign(ctx.primary_pool(), &sender, &deployment, node)
@@ -779,6 +835,22 @@ async fn main() {
                 sleep,
             )
         }
+        Prune {
+            deployment,
+            history,
+            once,
+            clear,
+        } => {
+            let (store, primary_pool) = ctx.store_and_primary();
+            commands::prune::run(
+                store.subgraph_store(),
+                primary_pool,
+                deployment,
+                history,
+                once,
+                clear,
+            )
+        }
         Run {
             network_name,
             subgraph,
@@ -849,7 +921,8 @@ async fn main() {
             target,
             query,
             vars,
-        } => commands::query::run(ctx.graphql_runner(), target, query, vars).await,
+            explain,
+        } => commands::query::run(ctx.graphql_runner(), target, query, vars, explain).await,
         Chain(cmd) => {
             use ChainCommand::*;
             match cmd {
@@ -869,6 +942,10 @@ async fn main() {
                     let (block_store, primary) = ctx.block_store_and_primary_pool();
                     commands::chain::remove(primary, block_store, name)
                 }
+                IndexTimestamps { name } => {
+                    let (block_store, _) = ctx.block_store_and_primary_pool();
+                    commands::chain::index_timestamps(block_store, name)
+                }
             }
         }
         Stats(cmd) => {
@@ -1,6 +1,7 @@
 use graph::{
     anyhow::Error,
     blockchain::BlockchainKind,
+    data::query::{ApiKey, ApiKeys, RateLimit},
     prelude::{
         anyhow::{anyhow, bail, Context, Result},
         info,
@@ -69,6 +70,8 @@ pub struct Config {
     pub stores: BTreeMap<String, Shard>,
     pub chains: ChainSection,
     pub deployment: Deployment,
+    #[serde(default)]
+    pub api_keys: ApiKeySection,
 }
 
 fn validate_name(s: &str) -> Result<()> {
@@ -140,6 +143,7 @@ impl Config {
         }
 
         self.chains.validate()?;
+        self.api_keys.validate()?;
 
         Ok(())
     }
@@ -172,6 +176,7 @@ impl Config {
             stores,
             chains,
             deployment,
+            api_keys: ApiKeySection::default(),
         })
     }
 
@@ -208,6 +213,124 @@ pub struct GeneralSection {
     query: Regex,
 }
 
+/// The API keys that clients can use to send queries
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ApiKeySection {
+    /// Whether queries without a valid API key are rejected
+    #[serde(default)]
+    required: bool,
+    #[serde(rename = "key", default)]
+    keys: BTreeMap<String, ApiKeyConfig>,
+}
+
+impl ApiKeySection {
+    fn validate(&mut self) -> Result<()> {
+        let mut secrets = BTreeSet::new();
+        for (name, key) in self.keys.iter_mut() {
+            validate_name(name).context("illegal API key name")?;
+            key.validate(name)?;
+            if !secrets.insert(key.key.clone()) {
+                return Err(anyhow!(
+                    "API key `{}` uses the same key as another API key",
+                    name
+                ));
+            }
+        }
+        if self.required && self.keys.is_empty() {
+            return Err(anyhow!(
+                "API keys are required but no API keys are configured"
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn api_keys(&self) -> ApiKeys {
+        let keys = self
+            .keys
+            .iter()
+            .map(|(name, key)| (key.key.clone(), key.api_key(name)))
+            .collect();
+        ApiKeys::new(self.required, keys)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiKeyConfig {
+    /// The secret key that clients send; never printed
+    #[serde(skip_serializing)]
+    key: String,
+    /// Subgraph names and deployment ids that the key can query; if empty,
+    /// the key can query all subgraphs
+    #[serde(default)]
+    subgraphs: Vec<String>,
+    queries_per_second: Option<f64>,
+    /// Defaults to `queries_per_second`
+    burst: Option<f64>,
+    max_complexity: Option<u64>,
+    complexity_per_second: Option<f64>,
+    /// Defaults to `complexity_per_second`
+    complexity_burst: Option<f64>,
+    /// Whether the key can ask for diagnostics like query plans
+    #[serde(default)]
+    admin: bool,
+}
+
+impl ApiKeyConfig {
+    fn validate(&mut self, name: &str) -> Result<()> {
+        self.key = shellexpand::env(&self.key)?.into_owned();
+        if self.key.is_empty() {
+            return Err(anyhow!("the key for API key `{}` is empty", name));
+        }
+        let rates = [
+            self.queries_per_second,
+            self.burst,
+            self.complexity_per_second,
+            self.complexity_burst,
+        ];
+        if rates
+            .iter()
+            .flatten()
+            .any(|rate| rate.is_nan() || *rate <= 0.0)
+        {
+            return Err(anyhow!(
+                "the rates and bursts for API key `{}` must be bigger than 0",
+                name
+            ));
+        }
+        if self.burst.is_some() && self.queries_per_second.is_none() {
+            return Err(anyhow!(
+                "API key `{}` sets `burst` but not `queries_per_second`",
+                name
+            ));
+        }
+        if self.complexity_burst.is_some() && self.complexity_per_second.is_none() {
+            return Err(anyhow!(
+                "API key `{}` sets `complexity_burst` but not `complexity_per_second`",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    fn api_key(&self, name: &str) -> ApiKey {
+        fn limit(rate: Option<f64>, burst: Option<f64>) -> Option<RateLimit> {
+            rate.map(|rate| RateLimit {
+                rate,
+                burst: burst.unwrap_or(rate),
+            })
+        }
+
+        ApiKey::new(
+            name.to_string(),
+            self.subgraphs.clone(),
+            self.max_complexity,
+            limit(self.queries_per_second, self.burst),
+            limit(self.complexity_per_second, self.complexity_burst),
+            self.admin,
+        )
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Shard {
     pub connection: String,
@@ -1023,7 +1146,8 @@ where
 mod tests {
 
     use super::{
-        Chain, Config, FirehoseProvider, Provider, ProviderDetails, Transport, Web3Provider,
+        ApiKeySection, Chain, Config, FirehoseProvider, Provider, ProviderDetails, Transport,
+        Web3Provider,
     };
     use graph::blockchain::BlockchainKind;
     use http::{HeaderMap, HeaderValue};
@@ -1307,6 +1431,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_works_on_api_keys() {
+        let mut actual = toml::from_str::<ApiKeySection>(
+            r#"
+                required = true
+                [key.frontend]
+                key = "secret"
+                subgraphs = [ "org/subgraph" ]
+                queries_per_second = 10
+                max_complexity = 1000
+                admin = true
+            "#,
+        )
+        .unwrap();
+        actual.validate().unwrap();
+
+        let keys = actual.api_keys();
+        let target = graph::data::query::QueryTarget::Name(
+            graph::prelude::SubgraphName::new("org/subgraph").unwrap(),
+        );
+        let key = keys.authenticate(Some("secret"), &target).unwrap().unwrap();
+        assert_eq!("frontend", key.name());
+        assert_eq!(Some(1000), key.max_complexity());
+        assert!(key.is_admin());
+        assert!(keys.authenticate(None, &target).is_err());
+
+        let mut invalid = toml::from_str::<ApiKeySection>(
+            r#"
+                [key.frontend]
+                key = "secret"
+                burst = 10
+            "#,
+        )
+        .unwrap();
+        assert!(invalid.validate().is_err());
+    }
+
     fn read_resource_as_string<P: AsRef<Path>>(path: P) -> String {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         d.push("resources/tests");
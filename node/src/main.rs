@@ -28,7 +28,7 @@ use graph_node::chain::{
 use graph_node::config::Config;
 use graph_node::opt;
 use graph_node::store_builder::StoreBuilder;
-use graph_server_http::GraphQLServer as GraphQLQueryServer;
+use graph_server_http::{GraphQLServer as GraphQLQueryServer, PersistedQueries};
 use graph_server_index_node::IndexNodeServer;
 use graph_server_json_rpc::JsonRpcServer;
 use graph_server_metrics::PrometheusMetricsServer;
@@ -316,20 +316,35 @@ async fn main() {
             load_manager,
             metrics_registry.clone(),
         ));
+        let persisted_queries = Arc::new(PersistedQueries::new(
+            ENV_VARS.graphql.persisted_query_cache_size,
+            ENV_VARS
+                .graphql
+                .persist_queries
+                .then(|| network_store.subgraph_store().persisted_queries()),
+        ));
+        let api_keys = Arc::new(config.api_keys.api_keys());
         let mut graphql_server = GraphQLQueryServer::new(
             &logger_factory,
             graphql_metrics_registry,
             graphql_runner.clone(),
+            persisted_queries,
+            api_keys.clone(),
             node_id.clone(),
         );
-        let subscription_server =
-            GraphQLSubscriptionServer::new(&logger, graphql_runner.clone(), network_store.clone());
+        let subscription_server = GraphQLSubscriptionServer::new(
+            &logger,
+            graphql_runner.clone(),
+            network_store.clone(),
+            api_keys,
+        );
 
         let mut index_node_server = IndexNodeServer::new(
             &logger_factory,
             blockchain_map.clone(),
             graphql_runner.clone(),
             network_store.clone(),
+            subscription_manager.clone(),
             link_resolver.clone(),
         );
 
@@ -26,6 +26,44 @@ pub async fn unassign(
     Ok(())
 }
 
+pub fn pause_or_resume(
+    primary: ConnectionPool,
+    sender: &NotificationSender,
+    search: &DeploymentSearch,
+    pause: bool,
+) -> Result<(), Error> {
+    let locator = search.locate_unique(&primary)?;
+
+    let conn = primary.get()?;
+    let conn = catalog::Connection::new(conn);
+
+    let site = conn
+        .locate_site(locator.clone())?
+        .ok_or_else(|| anyhow!("failed to locate site for {locator}"))?;
+    let changes = match conn.assignment_status(&site)? {
+        Some((_, is_paused)) => {
+            if is_paused == pause {
+                let state = if pause { "paused" } else { "running" };
+                println!("deployment {locator} is already {state}");
+                vec![]
+            } else if pause {
+                println!("pausing {locator}");
+                conn.pause_subgraph(&site)?
+            } else {
+                println!("resuming {locator}");
+                conn.resume_subgraph(&site)?
+            }
+        }
+        None => {
+            println!("deployment {locator} is not assigned to any node");
+            vec![]
+        }
+    };
+    conn.send_store_event(sender, &StoreEvent::new(changes))?;
+
+    Ok(())
+}
+
 pub fn reassign(
     primary: ConnectionPool,
     sender: &NotificationSender,
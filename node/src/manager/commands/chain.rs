@@ -129,3 +129,15 @@ pub fn remove(primary: ConnectionPool, store: Arc<BlockStore>, name: String) ->
 
     Ok(())
 }
+
+pub fn index_timestamps(store: Arc<BlockStore>, name: String) -> Result<(), Error> {
+    let chain_store = store
+        .chain_store(&name)
+        .ok_or_else(|| anyhow!("unknown chain: {}", name))?;
+
+    println!("indexing block timestamps for chain {}", name);
+    chain_store.create_timestamp_index()?;
+    println!("done");
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use graph::prelude::{anyhow, BlockNumber};
+use graph_store_postgres::{connection_pool::ConnectionPool, SubgraphStore};
+
+use crate::manager::deployment::DeploymentSearch;
+
+pub fn run(
+    store: Arc<SubgraphStore>,
+    primary_pool: ConnectionPool,
+    search: DeploymentSearch,
+    history: Option<BlockNumber>,
+    once: bool,
+    clear: bool,
+) -> Result<(), anyhow::Error> {
+    let deployment = search.locate_unique(&primary_pool)?;
+
+    if clear {
+        store.set_history_blocks(&deployment, None)?;
+        println!("{} will keep its entire history", deployment);
+        return Ok(());
+    }
+
+    let history = match history {
+        Some(history) => history,
+        None => store.history_blocks(&deployment)?.ok_or_else(|| {
+            anyhow!(
+                "{} keeps its entire history; use --history to say how many blocks to keep",
+                deployment
+            )
+        })?,
+    };
+
+    if !once {
+        store.set_history_blocks(&deployment, Some(history))?;
+    }
+
+    println!("Pruning {} to the last {} blocks", deployment, history);
+    let (earliest_block, removed) = store.prune(&deployment, history)?;
+    println!(
+        "Removed {} entity versions; the earliest block with data is now {}",
+        removed, earliest_block
+    );
+    if !once {
+        println!("The deployment will be pruned automatically from now on");
+    }
+    Ok(())
+}
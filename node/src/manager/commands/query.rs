@@ -19,6 +19,7 @@ pub async fn run(
     target: String,
     query: String,
     vars: Vec<String>,
+    explain: bool,
 ) -> Result<(), anyhow::Error> {
     let target = if target.starts_with("Qm") {
         let id =
@@ -49,12 +50,27 @@ pub async fn run(
             }
         })
         .collect::<Result<_, _>>()?;
-    let query = Query::new(
+    let mut query = Query::new(
         document,
         Some(QueryVariables::new(HashMap::from_iter(vars))),
     );
+    query.explain = explain;
 
     let res = runner.run_query(query, target).await;
+    for entry in res.explain().unwrap_or_default() {
+        println!(
+            "-- {}: {} entities in {:.3}ms",
+            entry.field,
+            entry.entity_count,
+            entry.time.as_secs_f64() * 1000.0
+        );
+        println!("{}", entry.sql);
+        println!("-- binds: {}", entry.binds);
+        for line in &entry.plan {
+            println!("{}", line);
+        }
+        println!();
+    }
     let json = serde_json::to_string(&res)?;
     println!("{}", json);
 
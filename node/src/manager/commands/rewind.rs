@@ -5,7 +5,7 @@ use std::{collections::HashSet, convert::TryFrom};
 
 use graph::anyhow::bail;
 use graph::components::store::{BlockStore as _, ChainStore as _};
-use graph::prelude::{anyhow, BlockNumber, BlockPtr, NodeId, SubgraphStore};
+use graph::prelude::{anyhow, BlockNumber, BlockPtr, SubgraphStore};
 use graph_store_postgres::BlockStore;
 use graph_store_postgres::{connection_pool::ConnectionPool, Store};
 
@@ -66,8 +66,6 @@ pub fn run(
     force: bool,
     sleep: Duration,
 ) -> Result<(), anyhow::Error> {
-    const PAUSED: &str = "paused_";
-
     let subgraph_store = store.subgraph_store();
     let block_store = store.block_store();
 
@@ -93,21 +91,17 @@ pub fn run(
     )?;
 
     println!("Pausing deployments");
-    let mut paused = false;
+    let mut paused = vec![];
     for deployment in &deployments {
-        if let Some(node) = &deployment.node_id {
-            if !node.starts_with(PAUSED) {
-                let loc = deployment.locator();
-                let node =
-                    NodeId::new(format!("{}{}", PAUSED, node)).expect("paused_ node id is valid");
-                subgraph_store.reassign_subgraph(&loc, &node)?;
-                println!("  ... paused {}", loc);
-                paused = true;
-            }
+        if deployment.node_id.is_some() && !deployment.paused {
+            let loc = deployment.locator();
+            subgraph_store.pause_subgraph(&loc)?;
+            println!("  ... paused {}", loc);
+            paused.push(loc);
         }
     }
 
-    if paused {
+    if !paused.is_empty() {
         // There's no good way to tell that a subgraph has in fact stopped
         // indexing. We sleep and hope for the best.
         println!("\nWaiting 10s to make sure pausing was processed");
@@ -122,12 +116,8 @@ pub fn run(
     }
 
     println!("Resuming deployments");
-    for deployment in &deployments {
-        if let Some(node) = &deployment.node_id {
-            let loc = deployment.locator();
-            let node = NodeId::new(node.clone()).expect("node id is valid");
-            subgraph_store.reassign_subgraph(&loc, &node)?;
-        }
+    for loc in &paused {
+        subgraph_store.resume_subgraph(loc)?;
     }
     Ok(())
 }
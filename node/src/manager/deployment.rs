@@ -99,6 +99,7 @@ impl DeploymentSearch {
                 ds::name,
                 ds::id,
                 a::node_id.nullable(),
+                a::paused_at.nullable().is_not_null(),
                 ds::shard,
                 ds::network,
                 ds::active,
@@ -149,6 +150,7 @@ pub struct Deployment {
     pub namespace: String,
     pub id: i32,
     pub node_id: Option<String>,
+    pub paused: bool,
     pub shard: String,
     pub chain: String,
     pub active: bool,
@@ -172,6 +174,7 @@ impl Deployment {
             "active",
             "chain",
             "node_id",
+            "paused",
         ];
         if !statuses.is_empty() {
             rows.extend(vec!["synced", "health", "latest block", "chain head block"]);
@@ -193,6 +196,7 @@ impl Deployment {
                 deployment.active.to_string(),
                 deployment.chain,
                 deployment.node_id.unwrap_or("---".to_string()),
+                deployment.paused.to_string(),
             ];
             if let Some(status) = status {
                 let chain = &status.chains[0];
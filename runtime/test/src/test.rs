@@ -633,6 +633,50 @@ async fn crypto_keccak256_v0_0_5() {
     test_crypto_keccak256(API_VERSION_0_0_5).await;
 }
 
+#[tokio::test]
+async fn crypto_v0_0_8() {
+    let mut module = test_module(
+        "cryptoV008",
+        mock_data_source(
+            &wasm_file_path("crypto_v0_0_8.wasm", API_VERSION_0_0_5),
+            API_VERSION_0_0_8,
+        ),
+        API_VERSION_0_0_8,
+    )
+    .await;
+
+    let input: &[u8] = "eth".as_ref();
+    let hash: AscPtr<Uint8Array> = module.invoke_export1("sha256", input);
+    let hash: Vec<u8> = asc_get(&module, hash, &module.gas).unwrap();
+    assert_eq!(
+        hex::encode(hash),
+        "c69ea13227bace6e1f8a06364d93f4a6f04632432b64ca9b1fc4036baea4d34c"
+    );
+
+    // Test vector for Ethereum's `ecrecover` precompile
+    let hash =
+        hex::decode("456e9aea5e197a1f1af7a3e85a3212fa4049a3ba34c2289b4c860fc0b0c64ef3").unwrap();
+    let mut signature = hex::decode(
+        "9242685bf161793cc25603c231bc2f568eb630ea16aa137d2664ac8038825608\
+         4f8ae3bd7535248d0bd448298cc2e2071e56992d0774dc340c368ae950852ada",
+    )
+    .unwrap();
+    signature.push(28);
+    let address: AscPtr<Uint8Array> =
+        module.invoke_export2("ecrecover", hash.as_slice(), signature.as_slice());
+    let address: Vec<u8> = asc_get(&module, address, &module.gas).unwrap();
+    assert_eq!(
+        hex::encode(address),
+        "7156526fbd7a3c72969b54f64e42c10fbb768c8a"
+    );
+
+    // An invalid recovery id does not recover an address
+    *signature.last_mut().unwrap() = 29;
+    let address: AscPtr<Uint8Array> =
+        module.invoke_export2("ecrecover", hash.as_slice(), signature.as_slice());
+    assert!(address.is_null());
+}
+
 async fn test_big_int_to_hex(api_version: Version, gas_used: u64) {
     let mut module = test_module(
         "BigIntToHex",
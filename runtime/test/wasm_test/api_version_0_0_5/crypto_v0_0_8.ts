@@ -0,0 +1,19 @@
+export * from './common/global'
+
+declare namespace crypto {
+    function keccak256(input: Uint8Array): Uint8Array
+    function sha256(input: Uint8Array): Uint8Array
+    function ecrecover(hash: Uint8Array, signature: Uint8Array): Uint8Array | null
+}
+
+export function hash(input: Uint8Array): Uint8Array {
+    return crypto.keccak256(input)
+}
+
+export function sha256(input: Uint8Array): Uint8Array {
+    return crypto.sha256(input)
+}
+
+export function ecrecover(hash: Uint8Array, signature: Uint8Array): Uint8Array | null {
+    return crypto.ecrecover(hash, signature)
+}
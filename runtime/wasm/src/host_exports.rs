@@ -14,6 +14,7 @@ use graph::components::store::EntityType;
 use graph::components::store::{EnsLookup, EntityKey};
 use graph::components::subgraph::{CausalityRegion, ProofOfIndexingEvent, SharedProofOfIndexing};
 use graph::data::store;
+use graph::data::store::scalar::RoundingMode;
 use graph::ensure;
 use graph::prelude::ethabi::param_type::Reader;
 use graph::prelude::ethabi::{decode, encode, Token};
@@ -214,6 +215,47 @@ impl<C: Blockchain> HostExports<C> {
         Ok(result)
     }
 
+    pub(crate) fn store_get_in_block(
+        &self,
+        state: &BlockState<C>,
+        entity_type: String,
+        entity_id: String,
+        gas: &GasCounter,
+    ) -> Result<Option<Entity>, anyhow::Error> {
+        let store_key = EntityKey {
+            subgraph_id: self.subgraph_id.clone(),
+            entity_type: EntityType::new(entity_type),
+            entity_id,
+        };
+
+        let result = state.entity_cache.get_in_block(&store_key);
+        gas.consume_host_fn(
+            gas::STORE_GET_IN_BLOCK.with_args(complexity::Linear, (&store_key, &result)),
+        )?;
+
+        Ok(result)
+    }
+
+    pub(crate) fn store_load_related(
+        &self,
+        state: &mut BlockState<C>,
+        entity_type: String,
+        entity_id: String,
+        entity_field: String,
+        gas: &GasCounter,
+    ) -> Result<Vec<Entity>, anyhow::Error> {
+        let store_key = EntityKey {
+            subgraph_id: self.subgraph_id.clone(),
+            entity_type: EntityType::new(entity_type),
+            entity_id,
+        };
+
+        let result = state.entity_cache.load_related(&store_key, &entity_field)?;
+        gas.consume_host_fn(gas::STORE_GET.with_args(complexity::Linear, (&store_key, &result)))?;
+
+        Ok(result)
+    }
+
     /// Prints the module of `n` in hex.
     /// Integers are encoded using the least amount of digits (no leading zero digits).
     /// Their encoding may be of uneven length. The number zero encodes as "0x0".
@@ -376,6 +418,80 @@ impl<C: Blockchain> HostExports<C> {
         Ok(tiny_keccak::keccak256(data))
     }
 
+    pub(crate) fn crypto_sha256(
+        &self,
+        input: Vec<u8>,
+        gas: &GasCounter,
+    ) -> Result<Vec<u8>, DeterministicHostError> {
+        gas.consume_host_fn(gas::HASH_GAS_OP.with_args(complexity::Size, &input))?;
+        Ok(<sha2::Sha256 as sha2::Digest>::digest(&input).to_vec())
+    }
+
+    pub(crate) fn crypto_sha3_256(
+        &self,
+        input: Vec<u8>,
+        gas: &GasCounter,
+    ) -> Result<Vec<u8>, DeterministicHostError> {
+        gas.consume_host_fn(gas::HASH_GAS_OP.with_args(complexity::Size, &input))?;
+        Ok(<sha3::Sha3_256 as sha3::Digest>::digest(&input).to_vec())
+    }
+
+    /// Blake2b with a 256 bit digest
+    pub(crate) fn crypto_blake2b(
+        &self,
+        input: Vec<u8>,
+        gas: &GasCounter,
+    ) -> Result<Vec<u8>, DeterministicHostError> {
+        type Blake2b256 = blake2::Blake2b<blake2::digest::consts::U32>;
+
+        gas.consume_host_fn(gas::HASH_GAS_OP.with_args(complexity::Size, &input))?;
+        Ok(<Blake2b256 as blake2::Digest>::digest(&input).to_vec())
+    }
+
+    pub(crate) fn crypto_ripemd160(
+        &self,
+        input: Vec<u8>,
+        gas: &GasCounter,
+    ) -> Result<Vec<u8>, DeterministicHostError> {
+        gas.consume_host_fn(gas::HASH_GAS_OP.with_args(complexity::Size, &input))?;
+        Ok(<ripemd::Ripemd160 as ripemd::Digest>::digest(&input).to_vec())
+    }
+
+    /// Recover the public key that signed `hash`. See
+    /// `secp256k1_recover` for the format of the inputs and the result
+    pub(crate) fn crypto_secp256k1_recover(
+        &self,
+        hash: Vec<u8>,
+        signature: Vec<u8>,
+        gas: &GasCounter,
+    ) -> Result<Option<Vec<u8>>, DeterministicHostError> {
+        gas.consume_host_fn(gas::SIGNATURE_GAS_OP.with_args(complexity::Size, &hash))?;
+        secp256k1_recover(&hash, &signature)
+    }
+
+    /// Recover the Ethereum address that signed `hash`, like Ethereum's
+    /// `ecrecover` precompile
+    pub(crate) fn crypto_ecrecover(
+        &self,
+        hash: Vec<u8>,
+        signature: Vec<u8>,
+        gas: &GasCounter,
+    ) -> Result<Option<H160>, DeterministicHostError> {
+        let public_key = self.crypto_secp256k1_recover(hash, signature, gas)?;
+        Ok(public_key.map(|public_key| public_key_to_address(&public_key)))
+    }
+
+    pub(crate) fn crypto_ed25519_verify(
+        &self,
+        message: Vec<u8>,
+        signature: Vec<u8>,
+        public_key: Vec<u8>,
+        gas: &GasCounter,
+    ) -> Result<bool, DeterministicHostError> {
+        gas.consume_host_fn(gas::SIGNATURE_GAS_OP.with_args(complexity::Size, &message))?;
+        Ok(ed25519_verify(&message, &signature, &public_key))
+    }
+
     pub(crate) fn big_int_plus(
         &self,
         x: BigInt,
@@ -503,6 +619,40 @@ impl<C: Blockchain> HostExports<C> {
         Ok(x >> bits)
     }
 
+    pub(crate) fn big_int_bit_xor(
+        &self,
+        x: BigInt,
+        y: BigInt,
+        gas: &GasCounter,
+    ) -> Result<BigInt, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_MATH_GAS_OP.with_args(complexity::Max, (&x, &y)))?;
+        Ok(x ^ y)
+    }
+
+    pub(crate) fn big_int_bit_not(
+        &self,
+        x: BigInt,
+        gas: &GasCounter,
+    ) -> Result<BigInt, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_MATH_GAS_OP.with_args(complexity::Size, &x))?;
+        Ok(!x)
+    }
+
+    /// Rounded down to the nearest integer.
+    pub(crate) fn big_int_sqrt(
+        &self,
+        x: BigInt,
+        gas: &GasCounter,
+    ) -> Result<BigInt, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_MATH_GAS_OP.with_args(complexity::Mul, (&x, &x)))?;
+        x.sqrt().ok_or_else(|| {
+            DeterministicHostError::from(anyhow!(
+                "attempted to take the square root of negative BigInt `{}`",
+                x
+            ))
+        })
+    }
+
     /// Useful for IPFS hashes stored as bytes
     pub(crate) fn bytes_to_base58(
         &self,
@@ -570,6 +720,143 @@ impl<C: Blockchain> HostExports<C> {
         Ok(x == y)
     }
 
+    pub(crate) fn big_decimal_compare(
+        &self,
+        x: BigDecimal,
+        y: BigDecimal,
+        gas: &GasCounter,
+    ) -> Result<i32, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_MATH_GAS_OP.with_args(complexity::Max, (&x, &y)))?;
+        Ok(x.cmp(&y) as i32)
+    }
+
+    pub(crate) fn big_decimal_neg(
+        &self,
+        x: BigDecimal,
+        gas: &GasCounter,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_MATH_GAS_OP.with_args(complexity::Size, &x))?;
+        Ok(-x)
+    }
+
+    pub(crate) fn big_decimal_abs(
+        &self,
+        x: BigDecimal,
+        gas: &GasCounter,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_MATH_GAS_OP.with_args(complexity::Size, &x))?;
+        Ok(x.abs())
+    }
+
+    pub(crate) fn big_decimal_truncate(
+        &self,
+        x: BigDecimal,
+        places: i32,
+        gas: &GasCounter,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_MATH_GAS_OP.with_args(complexity::Size, &x))?;
+        x.truncate(places.into()).ok_or_else(|| {
+            DeterministicHostError::from(anyhow!(
+                "truncating BigDecimal `{}` to {} places is out of range",
+                x,
+                places
+            ))
+        })
+    }
+
+    /// The rounding `mode` uses the same numbering as Java's `RoundingMode`: `UP`, `DOWN`,
+    /// `CEILING`, `FLOOR`, `HALF_UP`, `HALF_DOWN` and `HALF_EVEN`.
+    pub(crate) fn big_decimal_round(
+        &self,
+        x: BigDecimal,
+        places: i32,
+        mode: u32,
+        gas: &GasCounter,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_MATH_GAS_OP.with_args(complexity::Size, &x))?;
+        let mode = match mode {
+            0 => RoundingMode::Up,
+            1 => RoundingMode::Down,
+            2 => RoundingMode::Ceiling,
+            3 => RoundingMode::Floor,
+            4 => RoundingMode::HalfUp,
+            5 => RoundingMode::HalfDown,
+            6 => RoundingMode::HalfEven,
+            _ => {
+                return Err(DeterministicHostError::from(anyhow!(
+                    "invalid rounding mode {}",
+                    mode
+                )))
+            }
+        };
+        x.round(places.into(), mode).ok_or_else(|| {
+            DeterministicHostError::from(anyhow!(
+                "rounding BigDecimal `{}` to {} places is out of range",
+                x,
+                places
+            ))
+        })
+    }
+
+    /// Results outside of the range of IEEE-754 decimal128 are errors, see `BigDecimal::MAX_EXP`.
+    pub(crate) fn big_decimal_pow(
+        &self,
+        x: BigDecimal,
+        exp: i32,
+        gas: &GasCounter,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_DECIMAL_ITERATIVE_GAS_OP.with_args(complexity::Size, &x))?;
+        x.pow(exp).ok_or_else(|| {
+            DeterministicHostError::from(anyhow!(
+                "BigDecimal `{}` to the power of {} is undefined or out of range",
+                x,
+                exp
+            ))
+        })
+    }
+
+    pub(crate) fn big_decimal_sqrt(
+        &self,
+        x: BigDecimal,
+        gas: &GasCounter,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_DECIMAL_ITERATIVE_GAS_OP.with_args(complexity::Size, &x))?;
+        x.sqrt().ok_or_else(|| {
+            DeterministicHostError::from(anyhow!(
+                "attempted to take the square root of negative BigDecimal `{}`",
+                x
+            ))
+        })
+    }
+
+    pub(crate) fn big_decimal_ln(
+        &self,
+        x: BigDecimal,
+        gas: &GasCounter,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_DECIMAL_ITERATIVE_GAS_OP.with_args(complexity::Size, &x))?;
+        x.ln().ok_or_else(|| {
+            DeterministicHostError::from(anyhow!(
+                "the natural logarithm of BigDecimal `{}` is undefined or out of range",
+                x
+            ))
+        })
+    }
+
+    pub(crate) fn big_decimal_exp(
+        &self,
+        x: BigDecimal,
+        gas: &GasCounter,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        gas.consume_host_fn(gas::BIG_DECIMAL_ITERATIVE_GAS_OP.with_args(complexity::Size, &x))?;
+        x.exp().ok_or_else(|| {
+            DeterministicHostError::from(anyhow!(
+                "the exponential of BigDecimal `{}` is out of range",
+                x
+            ))
+        })
+    }
+
     pub(crate) fn big_decimal_to_string(
         &self,
         x: BigDecimal,
@@ -768,6 +1055,70 @@ fn string_to_h160(string: &str) -> Result<H160, DeterministicHostError> {
         .map_err(DeterministicHostError::from)
 }
 
+/// Recover the uncompressed public key, without its `0x04` prefix, that
+/// signed the 32 byte `hash`. The `signature` is the 64 bytes of `r` and
+/// `s` followed by the recovery id `v`, which can be `0` or `1`, or `27` or
+/// `28` as in Ethereum. Returns `None` if no public key can be recovered
+fn secp256k1_recover(
+    hash: &[u8],
+    signature: &[u8],
+) -> Result<Option<Vec<u8>>, DeterministicHostError> {
+    use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+    use secp256k1::{Message, Secp256k1, VerifyOnly};
+
+    lazy_static! {
+        static ref SECP256K1: Secp256k1<VerifyOnly> = Secp256k1::verification_only();
+    }
+
+    if hash.len() != 32 {
+        return Err(DeterministicHostError::from(anyhow!(
+            "secp256k1 recovery needs a 32 byte hash, but the hash has {} bytes",
+            hash.len()
+        )));
+    }
+    if signature.len() != 65 {
+        return Err(DeterministicHostError::from(anyhow!(
+            "secp256k1 recovery needs a 65 byte signature, but the signature has {} bytes",
+            signature.len()
+        )));
+    }
+
+    let v = match signature[64] {
+        v @ 0..=1 => v,
+        v @ 27..=28 => v - 27,
+        _ => return Ok(None),
+    };
+    let public_key = RecoveryId::from_i32(v as i32)
+        .and_then(|id| RecoverableSignature::from_compact(&signature[..64], id))
+        .and_then(|signature| {
+            let message = Message::from_slice(hash)?;
+            SECP256K1.recover_ecdsa(&message, &signature)
+        });
+    Ok(public_key
+        .ok()
+        .map(|public_key| public_key.serialize_uncompressed()[1..].to_vec()))
+}
+
+/// The Ethereum address for an uncompressed public key without its `0x04`
+/// prefix
+fn public_key_to_address(public_key: &[u8]) -> H160 {
+    H160::from_slice(&tiny_keccak::keccak256(public_key)[12..])
+}
+
+/// Check that `signature` is a valid ed25519 signature of `message` by
+/// `public_key`. Malformed signatures and public keys are not valid
+fn ed25519_verify(message: &[u8], signature: &[u8], public_key: &[u8]) -> bool {
+    let public_key = match ed25519_dalek::PublicKey::from_bytes(public_key) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    let signature = match ed25519_dalek::Signature::try_from(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    public_key.verify_strict(message, &signature).is_ok()
+}
+
 fn bytes_to_string(logger: &Logger, bytes: Vec<u8>) -> String {
     let s = String::from_utf8_lossy(&bytes);
 
@@ -816,3 +1167,58 @@ fn bytes_to_string_is_lossy() {
         )
     )
 }
+
+#[test]
+fn secp256k1_recover_finds_signer() {
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(&[7u8; 32]).unwrap();
+    let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+    let hash = tiny_keccak::keccak256(b"graph-node");
+    let (id, rs) = secp
+        .sign_ecdsa_recoverable(&Message::from_slice(&hash).unwrap(), &secret_key)
+        .serialize_compact();
+
+    let mut signature = rs.to_vec();
+    signature.push(id.to_i32() as u8 + 27);
+    let recovered = secp256k1_recover(&hash, &signature).unwrap().unwrap();
+    assert_eq!(
+        &public_key.serialize_uncompressed()[1..],
+        recovered.as_slice()
+    );
+    assert_eq!(
+        public_key_to_address(&public_key.serialize_uncompressed()[1..]),
+        public_key_to_address(&recovered)
+    );
+
+    // An invalid recovery id recovers nothing
+    signature[64] = 5;
+    assert_eq!(None, secp256k1_recover(&hash, &signature).unwrap());
+
+    // Hashes must be 32 bytes long
+    assert!(secp256k1_recover(&hash[1..], &signature).is_err());
+}
+
+#[test]
+fn ed25519_verify_checks_signature() {
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+
+    let secret = SecretKey::from_bytes(&[3u8; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    let keypair = Keypair { secret, public };
+    let signature = keypair.sign(b"graph-node").to_bytes();
+
+    assert!(ed25519_verify(b"graph-node", &signature, public.as_bytes()));
+    assert!(!ed25519_verify(
+        b"graph-nodes",
+        &signature,
+        public.as_bytes()
+    ));
+    assert!(!ed25519_verify(
+        b"graph-node",
+        &signature[1..],
+        public.as_bytes()
+    ));
+    assert!(!ed25519_verify(b"graph-node", &signature, &[0u8; 3]));
+}
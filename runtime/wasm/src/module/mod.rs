@@ -19,7 +19,7 @@ use graph::runtime::HostExportError;
 use graph::runtime::{AscHeap, IndexForAscTypeId};
 use graph::{components::subgraph::MappingError, runtime::AscPtr};
 use graph::{
-    data::subgraph::schema::SubgraphError,
+    data::subgraph::{schema::SubgraphError, API_VERSION_0_0_8},
     runtime::{asc_get, asc_new, try_asc_get, DeterministicHostError},
 };
 pub use into_wasm_ret::IntoWasmRet;
@@ -457,6 +457,21 @@ impl<C: Blockchain> WasmInstance<C> {
         link!("abort", abort, message_ptr, file_name_ptr, line, column);
 
         link!("store.get", store_get, "host_export_store_get", entity, id);
+        link!(
+            "store.get_in_block",
+            store_get_in_block,
+            "host_export_store_get_in_block",
+            entity,
+            id
+        );
+        link!(
+            "store.loadRelated",
+            store_load_related,
+            "host_export_store_load_related",
+            entity,
+            id,
+            field
+        );
         link!(
             "store.set",
             store_set,
@@ -500,6 +515,32 @@ impl<C: Blockchain> WasmInstance<C> {
 
         link!("crypto.keccak256", crypto_keccak_256, ptr);
 
+        if api_version >= API_VERSION_0_0_8 {
+            link!("crypto.sha256", crypto_sha256, ptr);
+            link!("crypto.sha3_256", crypto_sha3_256, ptr);
+            link!("crypto.blake2b", crypto_blake2b, ptr);
+            link!("crypto.ripemd160", crypto_ripemd160, ptr);
+            link!(
+                "crypto.secp256k1Recover",
+                crypto_secp256k1_recover,
+                hash_ptr,
+                signature_ptr
+            );
+            link!(
+                "crypto.ecrecover",
+                crypto_ecrecover,
+                hash_ptr,
+                signature_ptr
+            );
+            link!(
+                "crypto.ed25519Verify",
+                crypto_ed25519_verify,
+                message_ptr,
+                signature_ptr,
+                public_key_ptr
+            );
+        }
+
         link!("bigInt.plus", big_int_plus, x_ptr, y_ptr);
         link!("bigInt.minus", big_int_minus, x_ptr, y_ptr);
         link!("bigInt.times", big_int_times, x_ptr, y_ptr);
@@ -521,6 +562,22 @@ impl<C: Blockchain> WasmInstance<C> {
         link!("bigDecimal.dividedBy", big_decimal_divided_by, x, y);
         link!("bigDecimal.equals", big_decimal_equals, x_ptr, y_ptr);
 
+        if api_version >= API_VERSION_0_0_8 {
+            link!("bigInt.sqrt", big_int_sqrt, x_ptr);
+            link!("bigInt.bitXor", big_int_bit_xor, x_ptr, y_ptr);
+            link!("bigInt.bitNot", big_int_bit_not, x_ptr);
+
+            link!("bigDecimal.compare", big_decimal_compare, x_ptr, y_ptr);
+            link!("bigDecimal.neg", big_decimal_neg, x_ptr);
+            link!("bigDecimal.abs", big_decimal_abs, x_ptr);
+            link!("bigDecimal.truncate", big_decimal_truncate, x_ptr, places);
+            link!("bigDecimal.round", big_decimal_round, x_ptr, places, mode);
+            link!("bigDecimal.pow", big_decimal_pow, x_ptr, exp);
+            link!("bigDecimal.sqrt", big_decimal_sqrt, x_ptr);
+            link!("bigDecimal.ln", big_decimal_ln, x_ptr);
+            link!("bigDecimal.exp", big_decimal_exp, x_ptr);
+        }
+
         link!("dataSource.create", data_source_create, name, params);
         link!(
             "dataSource.createWithContext",
@@ -938,6 +995,59 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         Ok(ret)
     }
 
+    /// function store.get_in_block(entity: string, id: string): Entity | null
+    pub fn store_get_in_block(
+        &mut self,
+        gas: &GasCounter,
+        entity_ptr: AscPtr<AscString>,
+        id_ptr: AscPtr<AscString>,
+    ) -> Result<AscPtr<AscEntity>, HostExportError> {
+        let _timer = self
+            .host_metrics
+            .cheap_clone()
+            .time_host_fn_execution_region("store_get_in_block");
+
+        let entity_type: String = asc_get(self, entity_ptr, gas)?;
+        let id: String = asc_get(self, id_ptr, gas)?;
+        let entity_option =
+            self.ctx
+                .host_exports
+                .store_get_in_block(&self.ctx.state, entity_type, id, gas)?;
+
+        match entity_option {
+            Some(entity) => Ok(asc_new(self, &entity.sorted(), gas)?),
+            None => Ok(AscPtr::null()),
+        }
+    }
+
+    /// function store.loadRelated(entity: string, id: string, field: string): Array<Entity>
+    pub fn store_load_related(
+        &mut self,
+        gas: &GasCounter,
+        entity_ptr: AscPtr<AscString>,
+        id_ptr: AscPtr<AscString>,
+        field_ptr: AscPtr<AscString>,
+    ) -> Result<AscPtr<Array<AscPtr<AscEntity>>>, HostExportError> {
+        let _timer = self
+            .host_metrics
+            .cheap_clone()
+            .time_host_fn_execution_region("store_load_related");
+
+        let entity_type: String = asc_get(self, entity_ptr, gas)?;
+        let id: String = asc_get(self, id_ptr, gas)?;
+        let field: String = asc_get(self, field_ptr, gas)?;
+        let entities = self.ctx.host_exports.store_load_related(
+            &mut self.ctx.state,
+            entity_type,
+            id,
+            field,
+            gas,
+        )?;
+
+        let entities: Vec<_> = entities.into_iter().map(Entity::sorted).collect();
+        Ok(asc_new(self, entities.as_slice(), gas)?)
+    }
+
     /// function typeConversion.bytesToString(bytes: Bytes): string
     pub fn bytes_to_string(
         &mut self,
@@ -1213,6 +1323,110 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         asc_new(self, input.as_ref(), gas)
     }
 
+    /// function crypto.sha256(input: Bytes): Bytes
+    pub fn crypto_sha256(
+        &mut self,
+        gas: &GasCounter,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, DeterministicHostError> {
+        let digest = self
+            .ctx
+            .host_exports
+            .crypto_sha256(asc_get(self, input_ptr, gas)?, gas)?;
+        asc_new(self, digest.as_slice(), gas)
+    }
+
+    /// function crypto.sha3_256(input: Bytes): Bytes
+    pub fn crypto_sha3_256(
+        &mut self,
+        gas: &GasCounter,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, DeterministicHostError> {
+        let digest = self
+            .ctx
+            .host_exports
+            .crypto_sha3_256(asc_get(self, input_ptr, gas)?, gas)?;
+        asc_new(self, digest.as_slice(), gas)
+    }
+
+    /// function crypto.blake2b(input: Bytes): Bytes
+    pub fn crypto_blake2b(
+        &mut self,
+        gas: &GasCounter,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, DeterministicHostError> {
+        let digest = self
+            .ctx
+            .host_exports
+            .crypto_blake2b(asc_get(self, input_ptr, gas)?, gas)?;
+        asc_new(self, digest.as_slice(), gas)
+    }
+
+    /// function crypto.ripemd160(input: Bytes): Bytes
+    pub fn crypto_ripemd160(
+        &mut self,
+        gas: &GasCounter,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, DeterministicHostError> {
+        let digest = self
+            .ctx
+            .host_exports
+            .crypto_ripemd160(asc_get(self, input_ptr, gas)?, gas)?;
+        asc_new(self, digest.as_slice(), gas)
+    }
+
+    /// function crypto.secp256k1Recover(hash: Bytes, signature: Bytes): Bytes | null
+    pub fn crypto_secp256k1_recover(
+        &mut self,
+        gas: &GasCounter,
+        hash_ptr: AscPtr<Uint8Array>,
+        signature_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, DeterministicHostError> {
+        let public_key = self.ctx.host_exports.crypto_secp256k1_recover(
+            asc_get(self, hash_ptr, gas)?,
+            asc_get(self, signature_ptr, gas)?,
+            gas,
+        )?;
+        match public_key {
+            Some(public_key) => asc_new(self, public_key.as_slice(), gas),
+            None => Ok(AscPtr::null()),
+        }
+    }
+
+    /// function crypto.ecrecover(hash: Bytes, signature: Bytes): Address | null
+    pub fn crypto_ecrecover(
+        &mut self,
+        gas: &GasCounter,
+        hash_ptr: AscPtr<Uint8Array>,
+        signature_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<AscH160>, DeterministicHostError> {
+        let address = self.ctx.host_exports.crypto_ecrecover(
+            asc_get(self, hash_ptr, gas)?,
+            asc_get(self, signature_ptr, gas)?,
+            gas,
+        )?;
+        match address {
+            Some(address) => asc_new(self, &address, gas),
+            None => Ok(AscPtr::null()),
+        }
+    }
+
+    /// function crypto.ed25519Verify(message: Bytes, signature: Bytes, publicKey: Bytes): bool
+    pub fn crypto_ed25519_verify(
+        &mut self,
+        gas: &GasCounter,
+        message_ptr: AscPtr<Uint8Array>,
+        signature_ptr: AscPtr<Uint8Array>,
+        public_key_ptr: AscPtr<Uint8Array>,
+    ) -> Result<bool, DeterministicHostError> {
+        self.ctx.host_exports.crypto_ed25519_verify(
+            asc_get(self, message_ptr, gas)?,
+            asc_get(self, signature_ptr, gas)?,
+            asc_get(self, public_key_ptr, gas)?,
+            gas,
+        )
+    }
+
     /// function bigInt.plus(x: BigInt, y: BigInt): BigInt
     pub fn big_int_plus(
         &mut self,
@@ -1378,6 +1592,47 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         asc_new(self, &result, gas)
     }
 
+    /// function bigInt.bitXor(x: BigInt, y: BigInt): BigInt
+    pub fn big_int_bit_xor(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigInt>,
+        y_ptr: AscPtr<AscBigInt>,
+    ) -> Result<AscPtr<AscBigInt>, DeterministicHostError> {
+        let result = self.ctx.host_exports.big_int_bit_xor(
+            asc_get(self, x_ptr, gas)?,
+            asc_get(self, y_ptr, gas)?,
+            gas,
+        )?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigInt.bitNot(x: BigInt): BigInt
+    pub fn big_int_bit_not(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigInt>,
+    ) -> Result<AscPtr<AscBigInt>, DeterministicHostError> {
+        let result = self
+            .ctx
+            .host_exports
+            .big_int_bit_not(asc_get(self, x_ptr, gas)?, gas)?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigInt.sqrt(x: BigInt): BigInt
+    pub fn big_int_sqrt(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigInt>,
+    ) -> Result<AscPtr<AscBigInt>, DeterministicHostError> {
+        let result = self
+            .ctx
+            .host_exports
+            .big_int_sqrt(asc_get(self, x_ptr, gas)?, gas)?;
+        asc_new(self, &result, gas)
+    }
+
     /// function typeConversion.bytesToBase58(bytes: Bytes): string
     pub fn bytes_to_base58(
         &mut self,
@@ -1491,6 +1746,132 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         )
     }
 
+    /// function bigDecimal.compare(x: BigDecimal, y: BigDecimal): i32
+    pub fn big_decimal_compare(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+        y_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<i32, DeterministicHostError> {
+        self.ctx.host_exports.big_decimal_compare(
+            try_asc_get(self, x_ptr, gas)?,
+            try_asc_get(self, y_ptr, gas)?,
+            gas,
+        )
+    }
+
+    /// function bigDecimal.neg(x: BigDecimal): BigDecimal
+    pub fn big_decimal_neg(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self
+            .ctx
+            .host_exports
+            .big_decimal_neg(try_asc_get(self, x_ptr, gas)?, gas)?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.abs(x: BigDecimal): BigDecimal
+    pub fn big_decimal_abs(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self
+            .ctx
+            .host_exports
+            .big_decimal_abs(try_asc_get(self, x_ptr, gas)?, gas)?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.truncate(x: BigDecimal, places: i32): BigDecimal
+    pub fn big_decimal_truncate(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+        places: u32,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self.ctx.host_exports.big_decimal_truncate(
+            try_asc_get(self, x_ptr, gas)?,
+            places as i32,
+            gas,
+        )?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.round(x: BigDecimal, places: i32, mode: i32): BigDecimal
+    pub fn big_decimal_round(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+        places: u32,
+        mode: u32,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self.ctx.host_exports.big_decimal_round(
+            try_asc_get(self, x_ptr, gas)?,
+            places as i32,
+            mode,
+            gas,
+        )?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.pow(x: BigDecimal, exp: i32): BigDecimal
+    pub fn big_decimal_pow(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+        exp: u32,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self.ctx.host_exports.big_decimal_pow(
+            try_asc_get(self, x_ptr, gas)?,
+            exp as i32,
+            gas,
+        )?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.sqrt(x: BigDecimal): BigDecimal
+    pub fn big_decimal_sqrt(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self
+            .ctx
+            .host_exports
+            .big_decimal_sqrt(try_asc_get(self, x_ptr, gas)?, gas)?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.ln(x: BigDecimal): BigDecimal
+    pub fn big_decimal_ln(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self
+            .ctx
+            .host_exports
+            .big_decimal_ln(try_asc_get(self, x_ptr, gas)?, gas)?;
+        asc_new(self, &result, gas)
+    }
+
+    /// function bigDecimal.exp(x: BigDecimal): BigDecimal
+    pub fn big_decimal_exp(
+        &mut self,
+        gas: &GasCounter,
+        x_ptr: AscPtr<AscBigDecimal>,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self
+            .ctx
+            .host_exports
+            .big_decimal_exp(try_asc_get(self, x_ptr, gas)?, gas)?;
+        asc_new(self, &result, gas)
+    }
+
     /// function dataSource.create(name: string, params: Array<string>): void
     pub fn data_source_create(
         &mut self,
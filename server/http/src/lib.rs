@@ -1,17 +1,25 @@
+extern crate brotli;
+extern crate flate2;
 extern crate futures;
 extern crate graph;
 extern crate graph_graphql;
 #[cfg(test)]
 extern crate graph_mock;
 extern crate graphql_parser;
+extern crate hex;
 extern crate http;
 extern crate hyper;
 extern crate serde;
+extern crate sha2;
+extern crate zstd;
 
+mod persisted;
 mod request;
+mod response;
 mod server;
 mod service;
 
+pub use self::persisted::PersistedQueries;
 pub use self::request::GraphQLRequest;
 pub use self::server::GraphQLServer;
 pub use self::service::{GraphQLService, GraphQLServiceResponse};
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::sync::Mutex;
+
+use graph::components::server::query::GraphQLServerError;
+use graph::components::store::PersistedQueryStore;
+use graph::prelude::*;
+use graph::util::lru_cache::LruCache;
+use sha2::{Digest, Sha256};
+
+/// Automatic persisted queries: clients send the hex-encoded sha256 hash
+/// of a query instead of its full text, and only send the text when the
+/// server does not know the hash yet. The text of the most recently used
+/// queries is kept in memory; if a `store` is given, queries that requests
+/// with an API key register are also persisted so that they survive
+/// restarts and are shared between query nodes. Looking queries up in the
+/// store, and storing them, happens on a blocking thread so that it does
+/// not hold up the async runtime.
+///
+/// Since the query text is resolved before the query is parsed, a query
+/// sent by hash produces exactly the same cache key for the query result
+/// cache as the same query sent in full.
+pub struct PersistedQueries {
+    cache: Mutex<LruCache<String, Arc<String>>>,
+    store: Option<Arc<dyn PersistedQueryStore>>,
+}
+
+impl fmt::Debug for PersistedQueries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PersistedQueries {{ }}")
+    }
+}
+
+impl PersistedQueries {
+    pub fn new(capacity: usize, store: Option<Arc<dyn PersistedQueryStore>>) -> Self {
+        PersistedQueries {
+            cache: Mutex::new(LruCache::new(capacity)),
+            store,
+        }
+    }
+
+    /// Look up the query text for `hash`, first in memory and then in the
+    /// store
+    pub async fn get(&self, hash: &str) -> Result<Option<Arc<String>>, GraphQLServerError> {
+        let hash = hash.to_lowercase();
+        if let Some(query) = self.cache.lock().unwrap().get(&hash) {
+            return Ok(Some(query));
+        }
+        let store = match &self.store {
+            Some(store) => store.clone(),
+            None => return Ok(None),
+        };
+        let query = {
+            let hash = hash.clone();
+            graph::spawn_blocking_allow_panic(move || store.find_query(&hash))
+                .await
+                .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?
+                .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?
+                .map(Arc::new)
+        };
+        if let Some(query) = &query {
+            self.cache.lock().unwrap().insert(hash, query.clone());
+        }
+        Ok(query)
+    }
+
+    /// Remember `query` under `hash` after checking that `hash` really is
+    /// the hash of `query`. The query is only written to the store if
+    /// `persist` is `true`
+    pub async fn insert(
+        &self,
+        hash: &str,
+        query: &str,
+        persist: bool,
+    ) -> Result<(), GraphQLServerError> {
+        let hash = hash.to_lowercase();
+        if query.len() > ENV_VARS.graphql.max_persisted_query_size {
+            return Err(GraphQLServerError::ClientError(format!(
+                "persisted queries can be at most {} bytes long",
+                ENV_VARS.graphql.max_persisted_query_size
+            )));
+        }
+        if hex::encode(Sha256::digest(query.as_bytes())) != hash {
+            return Err(GraphQLServerError::ClientError(
+                "provided sha does not match query".to_string(),
+            ));
+        }
+        if self.cache.lock().unwrap().get(&hash).is_some() {
+            return Ok(());
+        }
+        let query = Arc::new(query.to_string());
+        if let Some(store) = self.store.as_ref().filter(|_| persist) {
+            let store = store.clone();
+            let hash = hash.clone();
+            let query = query.clone();
+            graph::spawn_blocking_allow_panic(move || store.insert_query(&hash, &query))
+                .await
+                .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?
+                .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?;
+        }
+        self.cache.lock().unwrap().insert(hash, query);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY: &str = "{ user { name } }";
+
+    fn hash(query: &str) -> String {
+        hex::encode(Sha256::digest(query.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_hash() {
+        let pq = PersistedQueries::new(10, None);
+        pq.insert(&hash("{ other }"), QUERY, true)
+            .await
+            .expect_err("Should reject a hash that does not match the query");
+        assert_eq!(None, pq.get(&hash("{ other }")).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rejects_large_queries() {
+        let pq = PersistedQueries::new(10, None);
+        let query = format!(
+            "{{ {} }}",
+            "a ".repeat(ENV_VARS.graphql.max_persisted_query_size)
+        );
+        pq.insert(&hash(&query), &query, true)
+            .await
+            .expect_err("Should reject a query that is too large");
+        assert_eq!(None, pq.get(&hash(&query)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn remembers_queries() {
+        let pq = PersistedQueries::new(10, None);
+        assert_eq!(None, pq.get(&hash(QUERY)).await.unwrap());
+        pq.insert(&hash(QUERY), QUERY, true).await.unwrap();
+        assert_eq!(
+            Some(QUERY),
+            pq.get(&hash(QUERY))
+                .await
+                .unwrap()
+                .as_ref()
+                .map(|q| q.as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        let pq = PersistedQueries::new(2, None);
+        let queries = ["{ a }", "{ b }", "{ c }"];
+        pq.insert(&hash(queries[0]), queries[0], true)
+            .await
+            .unwrap();
+        pq.insert(&hash(queries[1]), queries[1], true)
+            .await
+            .unwrap();
+        // Touch the first query so that the second one is evicted
+        pq.get(&hash(queries[0])).await.unwrap();
+        pq.insert(&hash(queries[2]), queries[2], true)
+            .await
+            .unwrap();
+
+        assert!(pq.get(&hash(queries[0])).await.unwrap().is_some());
+        assert!(pq.get(&hash(queries[1])).await.unwrap().is_none());
+        assert!(pq.get(&hash(queries[2])).await.unwrap().is_some());
+    }
+}
@@ -1,46 +1,217 @@
 use graph::prelude::serde_json;
+use graph::url::form_urlencoded;
 use hyper::body::Bytes;
 
 use graph::components::server::query::GraphQLServerError;
 use graph::prelude::*;
 
+use crate::persisted::PersistedQueries;
+
+/// Where the data for a request comes from
+enum Source {
+    /// The JSON body of a `POST` request
+    Body(Bytes),
+    /// The URL query string of a `GET` request
+    Params(String),
+    /// The request data after automatic persisted queries have been
+    /// resolved
+    Json(serde_json::Value),
+}
+
 /// Future for a query parsed from an HTTP request.
 pub struct GraphQLRequest {
-    body: Bytes,
+    source: Source,
+    /// Whether the text of the query was looked up from its hash
+    persisted: bool,
 }
 
 impl GraphQLRequest {
     /// Creates a new GraphQLRequest future based on an HTTP request and a result sender.
     pub fn new(body: Bytes) -> Self {
-        GraphQLRequest { body }
+        GraphQLRequest {
+            source: Source::Body(body),
+            persisted: false,
+        }
     }
-}
 
-impl Future for GraphQLRequest {
-    type Item = Query;
-    type Error = GraphQLServerError;
+    /// Creates a new GraphQLRequest future from the URL query string of a
+    /// `GET` request. The `query` parameter holds the query text, and the
+    /// `variables` and `extensions` parameters hold JSON objects
+    pub fn from_params(params: &str) -> Self {
+        GraphQLRequest {
+            source: Source::Params(params.to_string()),
+            persisted: false,
+        }
+    }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        // Parse request body as JSON
-        let json: serde_json::Value = serde_json::from_slice(&self.body)
-            .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
+    /// Resolve and remember automatic persisted queries with
+    /// `persisted_queries`. This has to happen before the request is
+    /// parsed since it might need to access the store. Entries whose hash
+    /// is not known are left without a query and result in a
+    /// `PersistedQueryNotFound` error when they are parsed. Queries that
+    /// the request registers are only written to the store if `persist`
+    /// is `true`
+    pub async fn resolve_persisted_queries(
+        self,
+        persisted_queries: &PersistedQueries,
+        persist: bool,
+    ) -> Result<Self, GraphQLServerError> {
+        let mut json = self.json()?;
+        let mut persisted = false;
+        match &mut json {
+            serde_json::Value::Array(entries) => {
+                for entry in entries {
+                    persisted |= Self::resolve_persisted(persisted_queries, persist, entry).await?;
+                }
+            }
+            entry => persisted = Self::resolve_persisted(persisted_queries, persist, entry).await?,
+        }
+        Ok(GraphQLRequest {
+            source: Source::Json(json),
+            persisted,
+        })
+    }
 
+    /// Resolve the persisted query in one entry of a request. Return
+    /// whether its query text was looked up from the hash
+    async fn resolve_persisted(
+        persisted_queries: &PersistedQueries,
+        persist: bool,
+        entry: &mut serde_json::Value,
+    ) -> Result<bool, GraphQLServerError> {
+        let obj = match entry.as_object_mut() {
+            Some(obj) => obj,
+            None => return Ok(false),
+        };
+        let hash = match persisted_hash(obj) {
+            Some(hash) => hash.to_string(),
+            None => return Ok(false),
+        };
+        match obj.get("query") {
+            Some(serde_json::Value::String(query)) => {
+                persisted_queries.insert(&hash, query, persist).await?;
+                Ok(false)
+            }
+            // `parse` complains about a query that is not a string
+            Some(_) => Ok(false),
+            None => match persisted_queries.get(&hash).await? {
+                Some(query) => {
+                    obj.insert(
+                        "query".to_string(),
+                        serde_json::Value::String(query.as_ref().clone()),
+                    );
+                    Ok(true)
+                }
+                None => Ok(false),
+            },
+        }
+    }
+
+    /// Whether the text of the query was looked up from the hash of an
+    /// automatic persisted query
+    pub fn is_persisted(&self) -> bool {
+        self.persisted
+    }
+
+    fn json(&self) -> Result<serde_json::Value, GraphQLServerError> {
+        match &self.source {
+            // Parse request body as JSON
+            Source::Body(body) => serde_json::from_slice(body)
+                .map_err(|e| GraphQLServerError::ClientError(format!("{}", e))),
+            Source::Params(params) => {
+                let mut obj = serde_json::Map::new();
+                for (key, value) in form_urlencoded::parse(params.as_bytes()) {
+                    let value = match key.as_ref() {
+                        "query" | "operationName" => serde_json::Value::String(value.into_owned()),
+                        "variables" | "extensions" => {
+                            serde_json::from_str(&value).map_err(|e| {
+                                GraphQLServerError::ClientError(format!(
+                                    "Invalid \"{}\" parameter: {}",
+                                    key, e
+                                ))
+                            })?
+                        }
+                        _ => continue,
+                    };
+                    obj.insert(key.into_owned(), value);
+                }
+                Ok(serde_json::Value::Object(obj))
+            }
+            Source::Json(json) => Ok(json.clone()),
+        }
+    }
+
+    /// Whether the request is a batch of queries, i.e., whether its body is
+    /// a JSON array
+    pub fn is_batch(&self) -> bool {
+        match &self.source {
+            Source::Body(body) => body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'['),
+            Source::Params(_) => false,
+            Source::Json(json) => json.is_array(),
+        }
+    }
+
+    /// Parse a batch of queries. Errors that only affect one query in the
+    /// batch, like a syntax error, are returned in place of that query so
+    /// that they can be reported in its result; any other error fails the
+    /// whole batch
+    pub fn batch(&self) -> Result<Vec<Result<Query, QueryError>>, GraphQLServerError> {
+        let json = self.json()?;
+        let entries = json.as_array().ok_or_else(|| {
+            GraphQLServerError::ClientError(String::from("Request data is not an array"))
+        })?;
+        if entries.is_empty() {
+            return Err(GraphQLServerError::ClientError(String::from(
+                "The batch of queries is empty",
+            )));
+        }
+        let max_batch_size = ENV_VARS.graphql.max_batch_size;
+        if entries.len() > max_batch_size {
+            return Err(GraphQLServerError::ClientError(format!(
+                "A batch can contain at most {} queries but this one contains {}",
+                max_batch_size,
+                entries.len()
+            )));
+        }
+        entries
+            .iter()
+            .map(|entry| match self.parse(entry) {
+                Ok(query) => Ok(Ok(query)),
+                Err(GraphQLServerError::QueryError(e)) => Ok(Err(e)),
+                Err(e) => Err(e),
+            })
+            .collect()
+    }
+
+    fn parse(&self, json: &serde_json::Value) -> Result<Query, GraphQLServerError> {
         // Ensure the JSON data is an object
         let obj = json.as_object().ok_or_else(|| {
             GraphQLServerError::ClientError(String::from("Request data is not an object"))
         })?;
 
-        // Ensure the JSON data has a "query" field
-        let query_value = obj.get("query").ok_or_else(|| {
-            GraphQLServerError::ClientError(String::from(
-                "The \"query\" field is missing in request data",
-            ))
-        })?;
-
-        // Ensure the "query" field is a string
-        let query_string = query_value.as_str().ok_or_else(|| {
-            GraphQLServerError::ClientError(String::from("The \"query\" field is not a string"))
-        })?;
+        let query_string = match obj.get("query") {
+            Some(query_value) => {
+                // Ensure the "query" field is a string
+                query_value.as_str().ok_or_else(|| {
+                    GraphQLServerError::ClientError(String::from(
+                        "The \"query\" field is not a string",
+                    ))
+                })?
+            }
+            // Persisted queries that were found have been given a "query"
+            // field by `resolve_persisted_queries`
+            None if persisted_hash(obj).is_some() => {
+                return Err(
+                    QueryError::ExecutionError(QueryExecutionError::PersistedQueryNotFound).into(),
+                )
+            }
+            // Ensure the JSON data has a "query" field
+            None => {
+                return Err(GraphQLServerError::ClientError(String::from(
+                    "The \"query\" field is missing in request data",
+                )))
+            }
+        };
 
         // Parse the "query" field of the JSON body
         let document = graphql_parser::parse_query(query_string)
@@ -60,7 +231,38 @@ impl Future for GraphQLRequest {
             )),
         }?;
 
-        Ok(Async::Ready(Query::new(document, variables)))
+        // Parse the "operationName" field of the JSON body, if present
+        let operation_name = match obj.get("operationName") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(serde_json::Value::String(name)) => Some(name.clone()),
+            _ => {
+                return Err(GraphQLServerError::ClientError(String::from(
+                    "The \"operationName\" field is not a string",
+                )))
+            }
+        };
+
+        let mut query = Query::new(document, variables);
+        query.operation_name = operation_name;
+        Ok(query)
+    }
+}
+
+/// The hash of an automatic persisted query, if the client sent one
+fn persisted_hash(obj: &serde_json::Map<String, serde_json::Value>) -> Option<&str> {
+    obj.get("extensions")
+        .and_then(|ext| ext.get("persistedQuery"))
+        .and_then(|pq| pq.get("sha256Hash"))
+        .and_then(|hash| hash.as_str())
+}
+
+impl Future for GraphQLRequest {
+    type Item = Query;
+    type Error = GraphQLServerError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let json = self.json()?;
+        self.parse(&json).map(Async::Ready)
     }
 }
 
@@ -71,11 +273,13 @@ mod tests {
     use std::collections::HashMap;
 
     use graph::{
+        components::server::query::GraphQLServerError,
         data::{query::QueryTarget, value::Object},
         prelude::*,
     };
 
     use super::GraphQLRequest;
+    use crate::persisted::PersistedQueries;
 
     lazy_static! {
         static ref TARGET: QueryTarget =
@@ -187,4 +391,115 @@ mod tests {
         assert_eq!(query.document, expected_query);
         assert_eq!(query.variables, Some(expected_variables));
     }
+
+    #[test]
+    fn parses_url_params() {
+        let request = GraphQLRequest::from_params(
+            "query=%7B%20user%20%7B%20name%20%7D%20%7D&variables=%7B%22int%22%3A5%7D",
+        );
+        let query = request.wait().expect("Should accept valid URL params");
+
+        let expected_query = graphql_parser::parse_query("{ user { name } }")
+            .unwrap()
+            .into_static();
+        let expected_variables = QueryVariables::new(HashMap::from_iter(
+            vec![(String::from("int"), r::Value::Int(5))].into_iter(),
+        ));
+        assert_eq!(query.document, expected_query);
+        assert_eq!(query.variables, Some(expected_variables));
+    }
+
+    #[test]
+    fn rejects_invalid_variables_param() {
+        let request = GraphQLRequest::from_params("query=%7B%20name%20%7D&variables=5x");
+        request
+            .wait()
+            .expect_err("Should reject variables that are not JSON");
+    }
+
+    #[test]
+    fn parses_batches() {
+        let request = GraphQLRequest::new(hyper::body::Bytes::from(
+            " [{\"query\": \"{ user { name } }\"}, {\"query\": \"foo\"}]",
+        ));
+        assert!(request.is_batch());
+        let batch = request.batch().expect("Should accept a batch");
+        assert_eq!(2, batch.len());
+        assert_eq!(
+            batch[0]
+                .as_ref()
+                .expect("Should accept valid queries")
+                .document,
+            graphql_parser::parse_query("{ user { name } }")
+                .unwrap()
+                .into_static()
+        );
+        match &batch[1] {
+            Err(QueryError::ParseError(_)) => (),
+            other => panic!("Expected a parse error but got {:?}", other),
+        }
+
+        let request = GraphQLRequest::new(hyper::body::Bytes::from("[]"));
+        request.batch().expect_err("Should reject empty batches");
+
+        let request = GraphQLRequest::new(hyper::body::Bytes::from("[5]"));
+        request
+            .batch()
+            .expect_err("Should reject batches with entries that are not objects");
+
+        let request = GraphQLRequest::new(hyper::body::Bytes::from("{\"query\": \"{ name }\"}"));
+        assert!(!request.is_batch());
+    }
+
+    #[tokio::test]
+    async fn resolves_persisted_queries() {
+        use sha2::{Digest, Sha256};
+
+        let pq = Arc::new(PersistedQueries::new(10, None));
+        let hash = hex::encode(Sha256::digest(b"{ user { name } }"));
+        let extensions = format!(
+            "\"extensions\": {{ \"persistedQuery\": {{ \"version\": 1, \"sha256Hash\": \"{}\" }} }}",
+            hash
+        );
+
+        // The hash is not known yet
+        let request =
+            GraphQLRequest::new(hyper::body::Bytes::from(format!("{{ {} }}", extensions)))
+                .resolve_persisted_queries(&pq, false)
+                .await
+                .unwrap();
+        assert!(!request.is_persisted());
+        match request.wait() {
+            Err(GraphQLServerError::QueryError(QueryError::ExecutionError(
+                QueryExecutionError::PersistedQueryNotFound,
+            ))) => (),
+            other => panic!("Expected PersistedQueryNotFound but got {:?}", other.err()),
+        }
+
+        // Register the query with its hash
+        let request = GraphQLRequest::new(hyper::body::Bytes::from(format!(
+            "{{ \"query\": \"{{ user {{ name }} }}\", {} }}",
+            extensions
+        )))
+        .resolve_persisted_queries(&pq, false)
+        .await
+        .unwrap();
+        assert!(!request.is_persisted());
+        request.wait().expect("Should accept query with its hash");
+
+        // Now the hash is enough
+        let request =
+            GraphQLRequest::new(hyper::body::Bytes::from(format!("{{ {} }}", extensions)))
+                .resolve_persisted_queries(&pq, false)
+                .await
+                .unwrap();
+        assert!(request.is_persisted());
+        let query = request.wait().expect("Should resolve persisted query");
+        assert_eq!(
+            query.document,
+            graphql_parser::parse_query("{ user { name } }")
+                .unwrap()
+                .into_static()
+        );
+    }
 }
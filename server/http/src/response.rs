@@ -0,0 +1,273 @@
+use std::io::{self, Write};
+
+use graph::prelude::*;
+use http::header::{HeaderMap, ACCEPT_ENCODING, CONTENT_ENCODING, VARY};
+use hyper::body::Bytes;
+use hyper::{Body, Response};
+use serde::Serialize;
+
+/// The size of the chunks in which we send a response body
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The quality level for brotli; higher levels compress better but are
+/// much slower, and level 11, the maximum, is too slow for responses that
+/// are produced on the fly
+const BROTLI_QUALITY: u32 = 5;
+
+/// The compression level for zstd
+const ZSTD_LEVEL: i32 = 3;
+
+/// How many chunks the serializer can get ahead of the client before it
+/// has to wait for the client to receive them
+const CHUNK_BUFFER: usize = 4;
+
+/// The content encodings we can use for responses
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl Encoding {
+    /// Pick the encoding for a response from the `Accept-Encoding` header
+    /// of the request. We use the encoding with the highest quality value
+    /// that the client accepts; if there are several, we prefer `br` over
+    /// `zstd` over `gzip`
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        let accept = match headers
+            .get(ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(accept) => accept,
+            None => return Encoding::Identity,
+        };
+
+        let mut best = (Encoding::Identity, 0.0);
+        for item in accept.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .next()
+                .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            let encoding = match name.as_str() {
+                "br" => Encoding::Brotli,
+                "zstd" => Encoding::Zstd,
+                "gzip" | "x-gzip" => Encoding::Gzip,
+                // We only use compression the client names explicitly,
+                // except for a wildcard which surely includes gzip
+                "*" => Encoding::Gzip,
+                _ => continue,
+            };
+            if quality <= 0.0 {
+                continue;
+            }
+            if quality > best.1 || (quality == best.1 && encoding.rank() > best.0.rank()) {
+                best = (encoding, quality);
+            }
+        }
+        best.0
+    }
+
+    /// The value of the `Content-Encoding` header for this encoding
+    fn header_value(&self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity => None,
+            Encoding::Gzip => Some("gzip"),
+            Encoding::Brotli => Some("br"),
+            Encoding::Zstd => Some("zstd"),
+        }
+    }
+
+    /// How much we prefer this encoding over others with the same quality
+    fn rank(&self) -> u8 {
+        match self {
+            Encoding::Identity => 0,
+            Encoding::Gzip => 1,
+            Encoding::Zstd => 2,
+            Encoding::Brotli => 3,
+        }
+    }
+}
+
+/// A writer that compresses what is written to it with one of our
+/// encodings
+enum Encoder<W: Write> {
+    Identity(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Brotli(Box<brotli::CompressorWriter<W>>),
+    Zstd(zstd::Encoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(writer: W, encoding: Encoding) -> io::Result<Self> {
+        Ok(match encoding {
+            Encoding::Identity => Encoder::Identity(writer),
+            Encoding::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                writer,
+                CHUNK_SIZE,
+                BROTLI_QUALITY,
+                22,
+            ))),
+            Encoding::Zstd => Encoder::Zstd(zstd::Encoder::new(writer, ZSTD_LEVEL)?),
+        })
+    }
+
+    /// Write whatever the compressor still holds and return the
+    /// underlying writer
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Identity(writer) => Ok(writer),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Brotli(encoder) => Ok(encoder.into_inner()),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Identity(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Brotli(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Identity(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Brotli(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// A writer that collects what is written to it into chunks of
+/// `CHUNK_SIZE` bytes and passes them on to the task that sends the
+/// response body. Since the channel to that task is bounded, writing
+/// blocks while the client is slow to receive the response
+struct ChunkWriter {
+    sender: tokio::sync::mpsc::Sender<io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new(sender: tokio::sync::mpsc::Sender<io::Result<Bytes>>) -> Self {
+        ChunkWriter {
+            sender,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    fn send_chunk(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.sender
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the client went away"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_chunk()
+    }
+}
+
+/// Respond with `value` serialized as JSON and compressed with
+/// `encoding`. The value is serialized on a blocking thread, and its JSON
+/// is sent to the client as it is produced, so that we never need to hold
+/// the entire response in memory; serialization waits when it gets more
+/// than `CHUNK_BUFFER` chunks ahead of the client
+pub fn json_response<T>(
+    builder: http::response::Builder,
+    value: T,
+    encoding: Encoding,
+) -> Response<Body>
+where
+    T: Serialize + Send + 'static,
+{
+    let (sender, receiver) = tokio::sync::mpsc::channel(CHUNK_BUFFER);
+    let (mut body_sender, body) = Body::channel();
+
+    graph::spawn_blocking_allow_panic(move || {
+        let result = Encoder::new(ChunkWriter::new(sender.clone()), encoding)
+            .and_then(|mut writer| {
+                serde_json::to_writer(&mut writer, &value)?;
+                writer.finish()
+            })
+            .and_then(|mut writer| writer.flush());
+        if let Err(e) = result {
+            let _ = sender.blocking_send(Err(e));
+        }
+    });
+
+    graph::spawn(async move {
+        let mut receiver = receiver;
+        while let Some(chunk) = receiver.recv().await {
+            match chunk {
+                Ok(chunk) => {
+                    if body_sender.send_data(chunk).await.is_err() {
+                        return;
+                    }
+                }
+                Err(_) => {
+                    // Make sure the client does not mistake what we sent
+                    // so far for the entire response
+                    body_sender.abort();
+                    return;
+                }
+            }
+        }
+    });
+
+    let builder = match encoding.header_value() {
+        Some(value) => builder.header(CONTENT_ENCODING, value),
+        None => builder,
+    };
+    builder.header(VARY, "Accept-Encoding").body(body).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn negotiate(accept: &str) -> Encoding {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_str(accept).unwrap());
+        Encoding::negotiate(&headers)
+    }
+
+    #[test]
+    fn negotiates_encoding() {
+        assert_eq!(Encoding::Identity, Encoding::negotiate(&HeaderMap::new()));
+        assert_eq!(Encoding::Identity, negotiate("identity"));
+        assert_eq!(Encoding::Gzip, negotiate("gzip, deflate"));
+        assert_eq!(Encoding::Brotli, negotiate("gzip, deflate, br"));
+        assert_eq!(Encoding::Brotli, negotiate("zstd, br, gzip"));
+        assert_eq!(Encoding::Zstd, negotiate("br;q=0.5, zstd"));
+        assert_eq!(Encoding::Gzip, negotiate("br;q=0, gzip"));
+        assert_eq!(Encoding::Gzip, negotiate("*"));
+    }
+}
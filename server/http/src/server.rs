@@ -3,7 +3,9 @@ use std::net::{Ipv4Addr, SocketAddrV4};
 use hyper::service::make_service_fn;
 use hyper::Server;
 
+use crate::persisted::PersistedQueries;
 use crate::service::{GraphQLService, GraphQLServiceMetrics};
+use graph::data::query::ApiKeys;
 use graph::prelude::{GraphQLServer as GraphQLServerTrait, *};
 use thiserror::Error;
 
@@ -25,6 +27,8 @@ pub struct GraphQLServer<Q> {
     logger: Logger,
     metrics: Arc<GraphQLServiceMetrics>,
     graphql_runner: Arc<Q>,
+    persisted_queries: Arc<PersistedQueries>,
+    api_keys: Arc<ApiKeys>,
     node_id: NodeId,
 }
 
@@ -34,6 +38,8 @@ impl<Q> GraphQLServer<Q> {
         logger_factory: &LoggerFactory,
         metrics_registry: Arc<dyn MetricsRegistry>,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
+        api_keys: Arc<ApiKeys>,
         node_id: NodeId,
     ) -> Self {
         let logger = logger_factory.component_logger(
@@ -49,6 +55,8 @@ impl<Q> GraphQLServer<Q> {
             logger,
             metrics,
             graphql_runner,
+            persisted_queries,
+            api_keys,
             node_id,
         }
     }
@@ -79,12 +87,16 @@ where
         let logger_for_service = self.logger.clone();
         let graphql_runner = self.graphql_runner.clone();
         let metrics = self.metrics.clone();
+        let persisted_queries = self.persisted_queries.clone();
+        let api_keys = self.api_keys.clone();
         let node_id = self.node_id.clone();
         let new_service = make_service_fn(move |_| {
             futures03::future::ok::<_, Error>(GraphQLService::new(
                 logger_for_service.clone(),
                 metrics.clone(),
                 graphql_runner.clone(),
+                persisted_queries.clone(),
+                api_keys.clone(),
                 ws_port,
                 node_id.clone(),
             ))
@@ -5,17 +5,130 @@ use std::task::Context;
 use std::task::Poll;
 use std::time::Instant;
 
+use graph::components::server::query::GraphQLServerError;
+use graph::data::query::{ApiKey, ApiKeys, QueryResults, QueryTarget};
 use graph::prelude::*;
-use graph::{components::server::query::GraphQLServerError, data::query::QueryTarget};
+use graph::url::form_urlencoded;
+use graph_graphql::query::ast::get_operation;
 use http::header;
 use http::header::{
-    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
-    CONTENT_TYPE, LOCATION,
+    ACCEPT, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, LOCATION, VARY,
 };
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
 
+use crate::persisted::PersistedQueries;
 use crate::request::GraphQLRequest;
+use crate::response::{json_response, Encoding};
+
+/// URL parameters that belong to a query sent with `GET` and therefore
+/// can not be used as namespaces of a composite query
+const QUERY_PARAMS: &[&str] = &["query", "variables", "operationName", "extensions"];
+
+/// The header with which clients ask for the SQL queries that a query
+/// runs, together with their query plans
+const EXPLAIN_HEADER: &str = "X-GraphQL-Explain";
+
+/// The header with which clients ask for what it cost to run a query to
+/// be reported in `extensions.cost`
+const COST_HEADER: &str = "X-GraphQL-Cost";
+
+/// The request headers that change the response to a persisted query
+/// sent with `GET`, for the `Vary` header of such responses
+const PERSISTED_VARY: &str = "Authorization, X-GraphQL-Explain, X-GraphQL-Cost";
+
+/// The `Cache-Control` header for successful responses to persisted
+/// queries sent with `GET`. Only responses to anonymous requests can be
+/// stored by shared caches since what an API key may see, and what it
+/// cost to run its queries, is nobody else's business. Explained queries
+/// contain the SQL we ran and are never stored at all
+fn persisted_cache_control(api_key: Option<&ApiKey>, explain: bool, cost: bool) -> String {
+    if explain {
+        return "no-store".to_string();
+    }
+    let scope = if api_key.is_some() || cost {
+        "private"
+    } else {
+        "public"
+    };
+    format!(
+        "{}, max-age={}",
+        scope, ENV_VARS.graphql.persisted_query_max_age
+    )
+}
+
+/// Whether the operation that `query` runs is a subscription. Operations
+/// that can not be determined are not, and running them reports the error
+fn is_subscription(query: &Query) -> bool {
+    matches!(
+        get_operation(&query.document, query.operation_name.as_deref()),
+        Ok(q::OperationDefinition::Subscription(_))
+    )
+}
+
+/// Whether automatic persisted queries that a request registers should be
+/// written to the store. Anonymous requests can only do that if it is
+/// explicitly allowed so that anybody can't fill up the primary database
+fn persist_queries(api_key: Option<&ApiKey>) -> bool {
+    api_key.is_some() || ENV_VARS.graphql.persist_anonymous_queries
+}
+
+/// Whether the request asks for the cost of its query. Responses that
+/// report the cost are not attestable
+fn wants_cost(headers: &http::HeaderMap) -> bool {
+    headers
+        .get(COST_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Whether the request asks for its query to be explained. Since that is
+/// expensive and exposes details of the database, only requests with an
+/// admin API key can do that
+fn wants_explain(
+    headers: &http::HeaderMap,
+    api_key: Option<&ApiKey>,
+) -> Result<bool, GraphQLServerError> {
+    let explain = headers
+        .get(EXPLAIN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if explain && !api_key.map(ApiKey::is_admin).unwrap_or(false) {
+        return Err(GraphQLServerError::Unauthorized(
+            "explaining queries requires an admin API key".to_string(),
+        ));
+    }
+    Ok(explain)
+}
+
+/// Parse the namespaces of a composite query from URL parameters of the
+/// form `<namespace>=<subgraph>`. The subgraph is either a deployment id
+/// or a subgraph name
+fn composite_namespaces(params: &str) -> Result<Vec<(String, String)>, GraphQLServerError> {
+    let mut namespaces: Vec<(String, String)> = Vec::new();
+    for (namespace, subgraph) in form_urlencoded::parse(params.as_bytes()) {
+        if QUERY_PARAMS.contains(&namespace.as_ref()) {
+            continue;
+        }
+        if namespaces.iter().any(|(ns, _)| ns == namespace.as_ref()) {
+            return Err(GraphQLServerError::ClientError(format!(
+                "namespace `{}` is used more than once",
+                namespace
+            )));
+        }
+        namespaces.push((namespace.into_owned(), subgraph.into_owned()));
+    }
+    if namespaces.is_empty() {
+        return Err(GraphQLServerError::ClientError(
+            "a composite query needs at least one `<namespace>=<subgraph>` URL parameter"
+                .to_string(),
+        ));
+    }
+    Ok(namespaces)
+}
 
 pub struct GraphQLServiceMetrics {
     query_execution_time: Box<HistogramVec>,
@@ -78,6 +191,8 @@ pub struct GraphQLService<Q> {
     logger: Logger,
     metrics: Arc<GraphQLServiceMetrics>,
     graphql_runner: Arc<Q>,
+    persisted_queries: Arc<PersistedQueries>,
+    api_keys: Arc<ApiKeys>,
     ws_port: u16,
     node_id: NodeId,
 }
@@ -88,6 +203,8 @@ impl<Q> Clone for GraphQLService<Q> {
             logger: self.logger.clone(),
             metrics: self.metrics.clone(),
             graphql_runner: self.graphql_runner.clone(),
+            persisted_queries: self.persisted_queries.clone(),
+            api_keys: self.api_keys.clone(),
             ws_port: self.ws_port,
             node_id: self.node_id.clone(),
         }
@@ -103,6 +220,8 @@ where
         logger: Logger,
         metrics: Arc<GraphQLServiceMetrics>,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
+        api_keys: Arc<ApiKeys>,
         ws_port: u16,
         node_id: NodeId,
     ) -> Self {
@@ -110,6 +229,8 @@ where
             logger,
             metrics,
             graphql_runner,
+            persisted_queries,
+            api_keys,
             ws_port,
             node_id,
         }
@@ -158,7 +279,7 @@ where
             GraphQLServerError::ClientError(format!("Invalid subgraph name {:?}", subgraph_name))
         })?;
 
-        self.handle_graphql_query(subgraph_name.into(), request.into_body())
+        self.handle_graphql_query(subgraph_name.into(), request)
             .await
     }
 
@@ -171,27 +292,74 @@ where
             .map_err(|id| GraphQLServerError::ClientError(format!("Invalid subgraph id `{}`", id)));
         match res {
             Err(_) => self.handle_not_found(),
-            Ok(id) => self
-                .handle_graphql_query(id.into(), request.into_body())
-                .boxed(),
+            Ok(id) => self.handle_graphql_query(id.into(), request).boxed(),
         }
     }
 
     async fn handle_graphql_query(
         self,
         target: QueryTarget,
-        request_body: Body,
+        request: Request<Body>,
     ) -> GraphQLServiceResult {
         let service = self.clone();
         let service_metrics = self.metrics.clone();
 
+        // Clients that accept a stream of server-sent events get the
+        // results of a subscription; other operations get a normal response
+        let stream_events = request
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .map(|accept| accept.contains("text/event-stream"))
+            .unwrap_or(false);
+
+        // Check the API key before doing anything else with the request
+        let token = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(ApiKeys::bearer_token);
+        let api_key = self.api_keys.authenticate(token, &target)?;
+        let explain = wants_explain(request.headers(), api_key.as_deref())?;
+        let cost = wants_cost(request.headers());
+        let encoding = Encoding::negotiate(request.headers());
+
         let start = Instant::now();
-        let body = hyper::body::to_bytes(request_body)
-            .map_err(|_| GraphQLServerError::InternalError("Failed to read request body".into()))
+        let is_get = request.method() == Method::GET;
+        let request = if is_get {
+            GraphQLRequest::from_params(request.uri().query().unwrap_or(""))
+        } else {
+            let body = hyper::body::to_bytes(request.into_body())
+                .map_err(|_| {
+                    GraphQLServerError::InternalError("Failed to read request body".into())
+                })
+                .await?;
+            GraphQLRequest::new(body)
+        };
+        if request.is_batch() {
+            return service
+                .handle_graphql_batch(request, target, api_key, cost, start, encoding)
+                .await;
+        }
+        if let Some(api_key) = &api_key {
+            api_key.check_rate(1)?;
+        }
+        let request = request
+            .resolve_persisted_queries(&self.persisted_queries, persist_queries(api_key.as_deref()))
             .await?;
-        let query = GraphQLRequest::new(body).compat().await;
+        let persisted = request.is_persisted();
+        let cache_control = persisted_cache_control(api_key.as_deref(), explain, cost);
+        let query = request.compat().await.map(|mut query| {
+            query.api_key = api_key;
+            query.explain = explain;
+            query.cost = cost;
+            query
+        });
 
         let result = match query {
+            Ok(query) if stream_events && is_subscription(&query) => {
+                return service.handle_graphql_subscription(query, target).await
+            }
             Ok(query) => service.graphql_runner.run_query(query, target).await,
             Err(GraphQLServerError::QueryError(e)) => QueryResult::from(e).into(),
             Err(e) => return Err(e),
@@ -202,7 +370,226 @@ where
                 .observe_query_execution_time(start.elapsed().as_secs_f64(), id.to_string());
         }
 
-        Ok(result.as_http_response())
+        let mut builder = result.http_response_builder();
+        // Responses to persisted queries sent with `GET` have short URLs
+        // and can therefore be cached by CDNs and browsers
+        if is_get && persisted && !result.has_errors() {
+            builder = builder
+                .header(CACHE_CONTROL, cache_control)
+                .header(VARY, PERSISTED_VARY);
+        }
+        Ok(json_response(builder, result, encoding))
+    }
+
+    /// Runs a composite query against the deployments given in the URL
+    /// parameters of the request
+    async fn handle_graphql_composite(self, request: Request<Body>) -> GraphQLServiceResult {
+        let params = request.uri().query().unwrap_or("").to_string();
+        let mut namespaces = Vec::new();
+        for (namespace, subgraph) in composite_namespaces(&params)? {
+            let target = self
+                .graphql_runner
+                .cheap_clone()
+                .query_target(subgraph.clone())
+                .await
+                .map_err(|e| GraphQLServerError::from(QueryError::from(e)))?
+                .ok_or_else(|| {
+                    GraphQLServerError::ClientError(format!(
+                        "Invalid subgraph name {:?} for namespace `{}`",
+                        subgraph, namespace
+                    ))
+                })?;
+            namespaces.push((namespace, target));
+        }
+
+        // The API key must be allowed to query all deployments
+        let token = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(ApiKeys::bearer_token);
+        let mut api_key = None;
+        for (_, target) in &namespaces {
+            api_key = self.api_keys.authenticate(token, target)?;
+        }
+        let explain = wants_explain(request.headers(), api_key.as_deref())?;
+        let cost = wants_cost(request.headers());
+        let encoding = Encoding::negotiate(request.headers());
+
+        let is_get = request.method() == Method::GET;
+        let request = if is_get {
+            GraphQLRequest::from_params(&params)
+        } else {
+            let body = hyper::body::to_bytes(request.into_body())
+                .map_err(|_| {
+                    GraphQLServerError::InternalError("Failed to read request body".into())
+                })
+                .await?;
+            GraphQLRequest::new(body)
+        };
+        if request.is_batch() {
+            return Err(GraphQLServerError::ClientError(
+                "composite queries can not be sent in batches".to_string(),
+            ));
+        }
+        if let Some(api_key) = &api_key {
+            api_key.check_rate(1)?;
+        }
+        let request = request
+            .resolve_persisted_queries(&self.persisted_queries, persist_queries(api_key.as_deref()))
+            .await?;
+        let persisted = request.is_persisted();
+        let cache_control = persisted_cache_control(api_key.as_deref(), explain, cost);
+        let query = request.compat().await.map(|mut query| {
+            query.api_key = api_key;
+            query.explain = explain;
+            query.cost = cost;
+            query
+        });
+
+        let result = match query {
+            Ok(query) => {
+                self.graphql_runner
+                    .cheap_clone()
+                    .run_composite_query(query, namespaces)
+                    .await
+            }
+            Err(GraphQLServerError::QueryError(e)) => QueryResult::from(e).into(),
+            Err(e) => return Err(e),
+        };
+
+        let mut builder = result.http_response_builder();
+        if is_get && persisted && !result.has_errors() {
+            builder = builder
+                .header(CACHE_CONTROL, cache_control)
+                .header(VARY, PERSISTED_VARY);
+        }
+        Ok(json_response(builder, result, encoding))
+    }
+
+    /// Runs a batch of queries against the same block and responds with the
+    /// list of their results
+    async fn handle_graphql_batch(
+        self,
+        request: GraphQLRequest,
+        target: QueryTarget,
+        api_key: Option<Arc<ApiKey>>,
+        cost: bool,
+        start: Instant,
+        encoding: Encoding,
+    ) -> GraphQLServiceResult {
+        let batch = request
+            .resolve_persisted_queries(&self.persisted_queries, persist_queries(api_key.as_deref()))
+            .await?
+            .batch()?;
+        if let Some(api_key) = &api_key {
+            api_key.check_rate(batch.len())?;
+        }
+
+        // Only run the queries that could be parsed, and remember where
+        // the errors for the others go
+        let mut queries = Vec::new();
+        let mut errors = Vec::new();
+        for entry in batch {
+            match entry {
+                Ok(mut query) => {
+                    query.api_key = api_key.clone();
+                    query.cost = cost;
+                    queries.push(query);
+                    errors.push(None);
+                }
+                Err(e) => errors.push(Some(e)),
+            }
+        }
+        let mut results = self
+            .graphql_runner
+            .cheap_clone()
+            .run_query_batch(queries, target)
+            .await
+            .into_iter();
+        let results: Vec<QueryResults> = errors
+            .into_iter()
+            .map(|error| match error {
+                Some(e) => QueryResult::from(e).into(),
+                None => results
+                    .next()
+                    .expect("the runner returns one result per query"),
+            })
+            .collect();
+
+        if let Some(id) = results
+            .iter()
+            .find_map(|result| result.first().and_then(|res| res.deployment.clone()))
+        {
+            self.metrics
+                .observe_query_execution_time(start.elapsed().as_secs_f64(), id.to_string());
+        }
+
+        Ok(json_response(
+            QueryResults::batch_http_response_builder(&results),
+            results,
+            encoding,
+        ))
+    }
+
+    /// Runs a subscription and streams its results as server-sent events,
+    /// following the 'distinct connections' mode of the GraphQL over SSE
+    /// protocol. Each result is sent as a `next` event; errors that prevent
+    /// the subscription from starting are sent as a single `next` event
+    /// followed by a `complete` event
+    async fn handle_graphql_subscription(
+        self,
+        query: Query,
+        target: QueryTarget,
+    ) -> GraphQLServiceResult {
+        fn event(name: &str, data: &str) -> hyper::body::Bytes {
+            hyper::body::Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+        }
+
+        fn next_event(result: &QueryResult) -> hyper::body::Bytes {
+            event(
+                "next",
+                &serde_json::to_string(result).expect("invalid GraphQL result"),
+            )
+        }
+
+        let (mut sender, body) = Body::channel();
+        let subscription = Subscription { query };
+        match self
+            .graphql_runner
+            .cheap_clone()
+            .run_subscription(subscription, target)
+            .await
+        {
+            Ok(mut results) => {
+                graph::spawn(async move {
+                    while let Some(result) = results.next().await {
+                        // An error means the client went away; dropping the
+                        // result stream ends the subscription
+                        if sender.send_data(next_event(&result)).await.is_err() {
+                            break;
+                        }
+                    }
+                    let _ = sender.send_data(event("complete", "")).await;
+                });
+            }
+            Err(SubscriptionError::GraphQLError(errors)) => {
+                let result = QueryResult::from(errors);
+                graph::spawn(async move {
+                    if sender.send_data(next_event(&result)).await.is_ok() {
+                        let _ = sender.send_data(event("complete", "")).await;
+                    }
+                });
+            }
+        }
+
+        Ok(Response::builder()
+            .status(200)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap())
     }
 
     // Handles OPTIONS requests
@@ -211,7 +598,10 @@ where
             Ok(Response::builder()
                 .status(200)
                 .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                .header(ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, User-Agent")
+                .header(
+                    ACCESS_CONTROL_ALLOW_HEADERS,
+                    "Content-Type, User-Agent, Authorization, X-GraphQL-Explain, X-GraphQL-Cost",
+                )
                 .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, OPTIONS, POST")
                 .header(CONTENT_TYPE, "text/html")
                 .body(Body::from(""))
@@ -253,6 +643,17 @@ where
     fn handle_call(self, req: Request<Body>) -> GraphQLServiceResponse {
         let method = req.method().clone();
 
+        // A `GET` request for a subgraph is a query if it has the query or
+        // the hash of a persisted query in its URL parameters
+        let is_query = req
+            .uri()
+            .query()
+            .map(|params| {
+                form_urlencoded::parse(params.as_bytes())
+                    .any(|(key, _)| key == "query" || key == "extensions")
+            })
+            .unwrap_or(false);
+
         let path = req.uri().path().to_owned();
         let path_segments = {
             let mut segments = path.split('/');
@@ -271,6 +672,31 @@ where
             | (Method::GET, &["subgraphs", "network", _, _, "graphql"])
             | (Method::GET, &["subgraphs", "graphql"]) => self.handle_graphiql(),
 
+            (Method::GET, &["subgraphs", "id", subgraph_id]) if is_query => {
+                self.handle_graphql_query_by_id(subgraph_id.to_owned(), req)
+            }
+            (Method::GET, &["subgraphs", "name", subgraph_name]) if is_query => self
+                .handle_graphql_query_by_name(subgraph_name.to_owned(), req)
+                .boxed(),
+            (Method::GET, ["subgraphs", "name", subgraph_name_part1, subgraph_name_part2])
+                if is_query =>
+            {
+                let subgraph_name = format!("{}/{}", subgraph_name_part1, subgraph_name_part2);
+                self.handle_graphql_query_by_name(subgraph_name, req)
+                    .boxed()
+            }
+            (Method::GET, ["subgraphs", "network", subgraph_name_part1, subgraph_name_part2])
+                if is_query =>
+            {
+                let subgraph_name =
+                    format!("network/{}/{}", subgraph_name_part1, subgraph_name_part2);
+                self.handle_graphql_query_by_name(subgraph_name, req)
+                    .boxed()
+            }
+
+            (Method::GET, &["subgraphs", "composite"]) if is_query => {
+                self.handle_graphql_composite(req).boxed()
+            }
             (Method::GET, path @ ["subgraphs", "id", _])
             | (Method::GET, path @ ["subgraphs", "name", _])
             | (Method::GET, path @ ["subgraphs", "name", _, _])
@@ -280,6 +706,9 @@ where
                 self.handle_temp_redirect(dest).boxed()
             }
 
+            (Method::POST, &["subgraphs", "composite"]) => {
+                self.handle_graphql_composite(req).boxed()
+            }
             (Method::POST, &["subgraphs", "id", subgraph_id]) => {
                 self.handle_graphql_query_by_id(subgraph_id.to_owned(), req)
             }
@@ -299,7 +728,8 @@ where
                     .boxed()
             }
 
-            (Method::OPTIONS, ["subgraphs", "name", _])
+            (Method::OPTIONS, ["subgraphs", "composite"])
+            | (Method::OPTIONS, ["subgraphs", "name", _])
             | (Method::OPTIONS, ["subgraphs", "name", _, _])
             | (Method::OPTIONS, ["subgraphs", "network", _, _]) => self.handle_graphql_options(req),
 
@@ -356,6 +786,19 @@ where
                         .body(Body::from(format!("Internal server error: {}", err)))
                         .unwrap())
                 }
+                Err(err @ GraphQLServerError::Unauthorized(_)) => Ok(Response::builder()
+                    .status(401)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                    .header(http::header::WWW_AUTHENTICATE, "Bearer")
+                    .body(Body::from(err.to_string()))
+                    .unwrap()),
+                Err(err @ GraphQLServerError::TooManyRequests(_)) => Ok(Response::builder()
+                    .status(429)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                    .body(Body::from(err.to_string()))
+                    .unwrap()),
             }
         })
     }
@@ -364,21 +807,24 @@ where
 #[cfg(test)]
 mod tests {
     use graph::data::value::Object;
+    use http::header::CACHE_CONTROL;
     use http::status::StatusCode;
     use hyper::service::Service;
     use hyper::{Body, Method, Request};
 
     use graph::data::{
         graphql::effort::LoadManager,
-        query::{QueryResults, QueryTarget},
+        query::{ApiKey, ApiKeys, QueryResults, QueryTarget},
     };
     use graph::prelude::*;
     use graph_mock::MockMetricsRegistry;
 
+    use crate::persisted::PersistedQueries;
     use crate::test_utils;
 
     use super::GraphQLService;
     use super::GraphQLServiceMetrics;
+    use super::{composite_namespaces, persisted_cache_control, wants_cost, wants_explain};
 
     /// A simple stupid query runner for testing.
     pub struct TestGraphQlRunner;
@@ -416,7 +862,55 @@ mod tests {
             _subscription: Subscription,
             _target: QueryTarget,
         ) -> Result<SubscriptionResult, SubscriptionError> {
-            unreachable!();
+            let result = QueryResult::from(Object::from_iter(
+                vec![(
+                    String::from("name"),
+                    r::Value::String(String::from("Jordi")),
+                )]
+                .into_iter(),
+            ));
+            Ok(Box::pin(futures03::stream::once(async move {
+                Arc::new(result)
+            })))
+        }
+
+        async fn run_query_batch(
+            self: Arc<Self>,
+            queries: Vec<Query>,
+            target: QueryTarget,
+        ) -> Vec<QueryResults> {
+            let mut results = Vec::new();
+            for query in queries {
+                results.push(self.cheap_clone().run_query(query, target.clone()).await);
+            }
+            results
+        }
+
+        async fn run_composite_query(
+            self: Arc<Self>,
+            _query: Query,
+            namespaces: Vec<(String, QueryTarget)>,
+        ) -> QueryResults {
+            QueryResults::from(Object::from_iter(namespaces.into_iter().map(
+                |(namespace, target)| {
+                    let target = match target {
+                        QueryTarget::Name(name) => name.to_string(),
+                        QueryTarget::Deployment(id) => id.to_string(),
+                    };
+                    (namespace, r::Value::String(target))
+                },
+            )))
+        }
+
+        async fn query_target(
+            self: Arc<Self>,
+            subgraph: String,
+        ) -> Result<Option<QueryTarget>, QueryExecutionError> {
+            // The only deployment that exists is `users`
+            if subgraph == USERS.as_str() {
+                return Ok(Some(QueryTarget::Deployment(USERS.clone())));
+            }
+            Ok(SubgraphName::new(subgraph).ok().map(QueryTarget::Name))
         }
 
         fn load_manager(&self) -> Arc<LoadManager> {
@@ -433,7 +927,16 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id);
+        let persisted_queries = Arc::new(PersistedQueries::new(10, None));
+        let mut service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            persisted_queries,
+            Arc::new(ApiKeys::default()),
+            8001,
+            node_id,
+        );
 
         let request = Request::builder()
             .method(Method::POST)
@@ -465,7 +968,16 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id);
+        let persisted_queries = Arc::new(PersistedQueries::new(10, None));
+        let mut service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            persisted_queries,
+            Arc::new(ApiKeys::default()),
+            8001,
+            node_id,
+        );
 
         let request = Request::builder()
             .method(Method::POST)
@@ -491,4 +1003,403 @@ mod tests {
             .expect("Query result field \"name\" is not a string");
         assert_eq!(name, "Jordi".to_string());
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn getting_valid_queries_yields_result_response() {
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let subgraph_id = USERS.clone();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let persisted_queries = Arc::new(PersistedQueries::new(10, None));
+        let node_id = NodeId::new("test").unwrap();
+        let mut service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            persisted_queries,
+            Arc::new(ApiKeys::default()),
+            8001,
+            node_id,
+        );
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!(
+                "http://localhost:8000/subgraphs/id/{}?query=%7B%20name%20%7D",
+                subgraph_id
+            ))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = tokio::spawn(service.call(request))
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        let data = test_utils::assert_successful_response(response);
+
+        let name = data
+            .get("name")
+            .expect("Query result data has no \"name\" field")
+            .as_str()
+            .expect("Query result field \"name\" is not a string");
+        assert_eq!(name, "Jordi".to_string());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn getting_persisted_queries_is_cacheable() {
+        use graph::url::form_urlencoded::byte_serialize;
+        use sha2::{Digest, Sha256};
+
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let subgraph_id = USERS.clone();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let persisted_queries = Arc::new(PersistedQueries::new(10, None));
+        let node_id = NodeId::new("test").unwrap();
+        let admin = ApiKey::new("admin".to_string(), vec![], None, None, None, true);
+        let api_keys = ApiKeys::new(false, vec![("secret".to_string(), admin)]);
+        let service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            persisted_queries,
+            Arc::new(api_keys),
+            8001,
+            node_id,
+        );
+
+        let query = "{ name }";
+        let extensions = format!(
+            "{{\"persistedQuery\":{{\"version\":1,\"sha256Hash\":\"{}\"}}}}",
+            hex::encode(Sha256::digest(query.as_bytes()))
+        );
+        let extensions: String = byte_serialize(extensions.as_bytes()).collect();
+        let query: String = byte_serialize(query.as_bytes()).collect();
+        let get = |params: String, headers: &[(&str, &str)]| {
+            let mut service = service.clone();
+            let mut request = Request::builder().method(Method::GET).uri(format!(
+                "http://localhost:8000/subgraphs/id/{}?{}",
+                subgraph_id, params
+            ));
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            tokio::spawn(service.call(request.body(Body::empty()).unwrap()))
+        };
+
+        // Registering the query sends its text, which is not cacheable
+        let response = get(format!("query={}&extensions={}", query, extensions), &[])
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        assert!(response.headers().get(CACHE_CONTROL).is_none());
+        test_utils::assert_successful_response(response);
+
+        let response = get(format!("extensions={}", extensions), &[])
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        assert_eq!(
+            "public, max-age=1",
+            response.headers().get(CACHE_CONTROL).unwrap()
+        );
+        assert!(response
+            .headers()
+            .get_all(http::header::VARY)
+            .iter()
+            .any(|vary| vary == super::PERSISTED_VARY));
+        let data = test_utils::assert_successful_response(response);
+        assert_eq!(
+            Some("Jordi"),
+            data.get("name").and_then(|name| name.as_str())
+        );
+
+        // Shared caches must not store responses that report their cost
+        let response = get(
+            format!("extensions={}", extensions),
+            &[(super::COST_HEADER, "true")],
+        )
+        .await
+        .unwrap()
+        .expect("Should return a response");
+        assert_eq!(
+            "private, max-age=1",
+            response.headers().get(CACHE_CONTROL).unwrap()
+        );
+        test_utils::assert_successful_response(response);
+
+        // Explained queries contain the SQL we ran and must never be
+        // stored, even by the client
+        let response = get(
+            format!("extensions={}", extensions),
+            &[
+                ("Authorization", "Bearer secret"),
+                (super::EXPLAIN_HEADER, "true"),
+            ],
+        )
+        .await
+        .unwrap()
+        .expect("Should return a response");
+        assert_eq!("no-store", response.headers().get(CACHE_CONTROL).unwrap());
+        test_utils::assert_successful_response(response);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn accepting_event_streams_yields_subscription_events() {
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let subgraph_id = USERS.clone();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let persisted_queries = Arc::new(PersistedQueries::new(10, None));
+        let node_id = NodeId::new("test").unwrap();
+        let mut service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            persisted_queries,
+            Arc::new(ApiKeys::default()),
+            8001,
+            node_id,
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "http://localhost:8000/subgraphs/id/{}",
+                subgraph_id
+            ))
+            .header(http::header::ACCEPT, "text/event-stream")
+            .body(Body::from("{\"query\": \"subscription { name }\"}"))
+            .unwrap();
+
+        let response = tokio::spawn(service.call(request))
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(
+            body,
+            "event: next\ndata: {\"data\":{\"name\":\"Jordi\"}}\n\nevent: complete\ndata: \n\n"
+        );
+
+        // Queries get a normal JSON response even if the client accepts
+        // event streams
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "http://localhost:8000/subgraphs/id/{}",
+                subgraph_id
+            ))
+            .header(http::header::ACCEPT, "text/event-stream, application/json")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+
+        let response = tokio::spawn(service.call(request))
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+        let data = test_utils::assert_successful_response(response);
+        assert_eq!(
+            data.get("name").and_then(|name| name.as_str()),
+            Some("Jordi")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn posting_batches_yields_list_of_results() {
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let subgraph_id = USERS.clone();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let persisted_queries = Arc::new(PersistedQueries::new(10, None));
+        let node_id = NodeId::new("test").unwrap();
+        let mut service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            persisted_queries,
+            Arc::new(ApiKeys::default()),
+            8001,
+            node_id,
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "http://localhost:8000/subgraphs/id/{}",
+                subgraph_id
+            ))
+            .body(Body::from(
+                "[{\"query\": \"{ name }\"}, {\"query\": \"foo\"}]",
+            ))
+            .unwrap();
+
+        let response = tokio::spawn(service.call(request))
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let results: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let results = results.as_array().expect("Response is not a list");
+        assert_eq!(2, results.len());
+        assert_eq!(results[0], serde_json::json!({"data": {"name": "Jordi"}}));
+        assert!(results[1].get("data").is_none());
+        assert!(results[1].get("errors").is_some());
+    }
+
+    #[test]
+    fn parses_composite_namespaces() {
+        let namespaces = composite_namespaces(
+            "a=org/subgraph&b=QmVwMhCcmqqGMNWDnqK9bCa7jSELqwKKJeUZtjjwBGbgdV&query={a{id}}",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                ("a".to_string(), "org/subgraph".to_string()),
+                (
+                    "b".to_string(),
+                    "QmVwMhCcmqqGMNWDnqK9bCa7jSELqwKKJeUZtjjwBGbgdV".to_string()
+                )
+            ],
+            namespaces
+        );
+
+        assert!(composite_namespaces("").is_err());
+        assert!(composite_namespaces("query={a{id}}").is_err());
+        assert!(composite_namespaces("a=one&a=two").is_err());
+    }
+
+    #[test]
+    fn only_admins_can_explain() {
+        let admin = ApiKey::new("admin".to_string(), vec![], None, None, None, true);
+        let client = ApiKey::new("client".to_string(), vec![], None, None, None, false);
+
+        let mut headers = http::HeaderMap::new();
+        assert!(!wants_explain(&headers, None).unwrap());
+        assert!(!wants_explain(&headers, Some(&client)).unwrap());
+
+        headers.insert(super::EXPLAIN_HEADER, "true".parse().unwrap());
+        assert!(wants_explain(&headers, Some(&admin)).unwrap());
+        assert!(wants_explain(&headers, Some(&client)).is_err());
+        assert!(wants_explain(&headers, None).is_err());
+    }
+
+    #[test]
+    fn only_anonymous_persisted_queries_are_public() {
+        let client = ApiKey::new("client".to_string(), vec![], None, None, None, false);
+
+        assert_eq!(
+            "public, max-age=1",
+            persisted_cache_control(None, false, false)
+        );
+        assert_eq!(
+            "private, max-age=1",
+            persisted_cache_control(Some(&client), false, false)
+        );
+        assert_eq!(
+            "private, max-age=1",
+            persisted_cache_control(None, false, true)
+        );
+        assert_eq!(
+            "private, max-age=1",
+            persisted_cache_control(Some(&client), false, true)
+        );
+    }
+
+    #[test]
+    fn explained_persisted_queries_are_not_stored() {
+        let admin = ApiKey::new("admin".to_string(), vec![], None, None, None, true);
+
+        assert_eq!(
+            "no-store",
+            persisted_cache_control(Some(&admin), true, false)
+        );
+        assert_eq!(
+            "no-store",
+            persisted_cache_control(Some(&admin), true, true)
+        );
+    }
+
+    #[test]
+    fn cost_is_opt_in() {
+        let mut headers = http::HeaderMap::new();
+        assert!(!wants_cost(&headers));
+
+        headers.insert(super::COST_HEADER, "true".parse().unwrap());
+        assert!(wants_cost(&headers));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn posting_composite_queries_yields_result_response() {
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let persisted_queries = Arc::new(PersistedQueries::new(10, None));
+        let node_id = NodeId::new("test").unwrap();
+        let mut service = GraphQLService::new(
+            logger,
+            metrics,
+            graphql_runner,
+            persisted_queries,
+            Arc::new(ApiKeys::default()),
+            8001,
+            node_id,
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/subgraphs/composite?users=org/users&posts=users")
+            .body(Body::from("{\"query\": \"{ users { name } }\"}"))
+            .unwrap();
+
+        let response = tokio::spawn(service.call(request))
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            result,
+            serde_json::json!({"data": {"users": "org/users", "posts": "users"}})
+        );
+
+        // Subgraphs that are neither a deployment nor a valid name are
+        // rejected
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/subgraphs/composite?users=not%20a%20name")
+            .body(Body::from("{\"query\": \"{ users { name } }\"}"))
+            .unwrap();
+        let response = tokio::spawn(service.call(request))
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
 }
@@ -1,16 +1,18 @@
 use http::StatusCode;
 use hyper::{Body, Client, Request};
+use std::io::Read;
 use std::time::Duration;
 
 use graph::data::{
     graphql::effort::LoadManager,
-    query::{QueryResults, QueryTarget},
+    query::{ApiKeys, QueryResults, QueryTarget},
     value::Object,
 };
 use graph::prelude::*;
 
 use graph_server_http::test_utils;
 use graph_server_http::GraphQLServer as HyperGraphQLServer;
+use graph_server_http::PersistedQueries;
 
 use tokio::time::sleep;
 
@@ -61,6 +63,26 @@ impl GraphQlRunner for TestGraphQlRunner {
         .into()
     }
 
+    async fn run_query_batch(
+        self: Arc<Self>,
+        queries: Vec<Query>,
+        target: QueryTarget,
+    ) -> Vec<QueryResults> {
+        let mut results = Vec::new();
+        for query in queries {
+            results.push(self.cheap_clone().run_query(query, target.clone()).await);
+        }
+        results
+    }
+
+    async fn run_composite_query(
+        self: Arc<Self>,
+        _query: Query,
+        _namespaces: Vec<(String, QueryTarget)>,
+    ) -> QueryResults {
+        unimplemented!();
+    }
+
     async fn run_subscription(
         self: Arc<Self>,
         _subscription: Subscription,
@@ -69,6 +91,13 @@ impl GraphQlRunner for TestGraphQlRunner {
         unreachable!();
     }
 
+    async fn query_target(
+        self: Arc<Self>,
+        _subgraph: String,
+    ) -> Result<Option<QueryTarget>, QueryExecutionError> {
+        unimplemented!();
+    }
+
     fn load_manager(&self) -> Arc<LoadManager> {
         unimplemented!()
     }
@@ -93,8 +122,9 @@ mod test {
                 let metrics_registry = Arc::new(MockMetricsRegistry::new());
                 let id = USERS.clone();
                 let query_runner = Arc::new(TestGraphQlRunner);
+                let persisted_queries = Arc::new(PersistedQueries::new(10, None));
                 let node_id = NodeId::new("test").unwrap();
-                let mut server = HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+                let mut server = HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, persisted_queries, Arc::new(ApiKeys::default()), node_id);
                 let http_server = server
                     .serve(8007, 8008)
                     .expect("Failed to start GraphQL server");
@@ -135,9 +165,16 @@ mod test {
             let metrics_registry = Arc::new(MockMetricsRegistry::new());
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
+            let persisted_queries = Arc::new(PersistedQueries::new(10, None));
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                persisted_queries,
+                Arc::new(ApiKeys::default()),
+                node_id,
+            );
             let http_server = server
                 .serve(8002, 8003)
                 .expect("Failed to start GraphQL server");
@@ -217,9 +254,16 @@ mod test {
             let metrics_registry = Arc::new(MockMetricsRegistry::new());
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
+            let persisted_queries = Arc::new(PersistedQueries::new(10, None));
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                persisted_queries,
+                Arc::new(ApiKeys::default()),
+                node_id,
+            );
             let http_server = server
                 .serve(8003, 8004)
                 .expect("Failed to start GraphQL server");
@@ -264,9 +308,16 @@ mod test {
             let metrics_registry = Arc::new(MockMetricsRegistry::new());
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
+            let persisted_queries = Arc::new(PersistedQueries::new(10, None));
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                persisted_queries,
+                Arc::new(ApiKeys::default()),
+                node_id,
+            );
             let http_server = server
                 .serve(8005, 8006)
                 .expect("Failed to start GraphQL server");
@@ -314,4 +365,67 @@ mod test {
                 .unwrap()
         });
     }
+
+    #[test]
+    fn compresses_responses() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let logger = Logger::root(slog::Discard, o!());
+            let logger_factory = LoggerFactory::new(logger, None);
+            let metrics_registry = Arc::new(MockMetricsRegistry::new());
+            let id = USERS.clone();
+            let query_runner = Arc::new(TestGraphQlRunner);
+            let persisted_queries = Arc::new(PersistedQueries::new(10, None));
+            let node_id = NodeId::new("test").unwrap();
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                persisted_queries,
+                Arc::new(ApiKeys::default()),
+                node_id,
+            );
+            let http_server = server
+                .serve(8009, 8010)
+                .expect("Failed to start GraphQL server");
+
+            // Launch the server to handle a single request
+            tokio::spawn(http_server.fuse().compat());
+            // Give some time for the server to start.
+            sleep(Duration::from_secs(2))
+                .then(move |()| {
+                    // Send a valid example query that accepts a gzipped response
+                    let client = Client::new();
+                    let request =
+                        Request::post(format!("http://localhost:8009/subgraphs/id/{}", id))
+                            .header("Accept-Encoding", "gzip")
+                            .body(Body::from("{\"query\": \"{ name }\"}"))
+                            .unwrap();
+
+                    client.request(request)
+                })
+                .and_then(|response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                    assert_eq!(
+                        response
+                            .headers()
+                            .get("Content-Encoding")
+                            .expect("Response is not compressed"),
+                        "gzip"
+                    );
+                    hyper::body::to_bytes(response.into_body())
+                })
+                .map_ok(|body| {
+                    let mut json = String::new();
+                    flate2::read::GzDecoder::new(body.as_ref())
+                        .read_to_string(&mut json)
+                        .expect("Response is not valid gzip");
+                    let json: serde_json::Value =
+                        serde_json::from_str(&json).expect("GraphQL response is not valid JSON");
+                    assert_eq!(json["data"]["name"], "Jordi");
+                })
+                .await
+                .unwrap()
+        });
+    }
 }
@@ -0,0 +1,431 @@
+//! A change-data-capture stream for a deployment: every insert, update and
+//! removal of an entity, block by block, sent as server-sent events that
+//! can be resumed from an opaque cursor.
+//!
+//! Reverts are detected through the deployment's `reorg_count`, which grows
+//! by one for every revert, no matter how many blocks the revert removed.
+//! When it changes, the stream looks up the blocks to which the store
+//! reverted the deployment since then, and sends a `revert` event telling
+//! the client to discard everything it received for blocks after the
+//! lowest of them before it sends the changes for the new blocks. Reverts
+//! that the store did not record, because they happened before it
+//! started recording them, make the stream go back to the earliest block
+//! of the deployment since that is the only block that is known to be
+//! unaffected.
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use graph::components::server::query::GraphQLServerError;
+use graph::components::store::{EntityType, Store, SubscriptionManager, UnitStream};
+use graph::data::graphql::DocumentExt;
+use graph::prelude::*;
+use hyper::body::{Bytes, Sender};
+use hyper::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Response};
+
+/// The most blocks we look at in one go while a stream is catching up
+const MAX_BLOCKS_PER_STEP: BlockNumber = 1000;
+
+/// How long we wait for a store event before checking the deployment
+/// anyway. Store events are not sent for blocks that do not change any
+/// entities
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The position of a client in the change stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ChangeCursor {
+    /// The client has received all changes up to and including this block
+    block: BlockNumber,
+    /// The `reorg_count` of the deployment when `block` was sent
+    reorg_count: u32,
+}
+
+impl ChangeCursor {
+    fn encode(&self) -> String {
+        hex::encode(serde_json::json!([self.block, self.reorg_count]).to_string())
+    }
+
+    fn decode(cursor: &str) -> Result<Self, GraphQLServerError> {
+        let invalid = || GraphQLServerError::ClientError(format!("invalid cursor `{}`", cursor));
+
+        let bytes = hex::decode(cursor).map_err(|_| invalid())?;
+        let (block, reorg_count): (BlockNumber, u32) =
+            serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        Ok(ChangeCursor { block, reorg_count })
+    }
+
+    /// If the deployment reverted blocks since this cursor was handed out,
+    /// return the cursor for the last block whose changes the client can
+    /// keep. `reverts` are the blocks to which the deployment was reverted
+    /// since `self.reorg_count` as far as the store knows
+    fn reverted(&self, state: &DeploymentState, reverts: &[BlockNumber]) -> Option<ChangeCursor> {
+        let head = state.latest_ethereum_block_number;
+        if self.reorg_count == state.reorg_count && self.block <= head {
+            return None;
+        }
+        let missing = state.reorg_count.saturating_sub(self.reorg_count) as usize > reverts.len();
+        let block = if missing {
+            state.earliest_block_number - 1
+        } else {
+            reverts.iter().cloned().min().unwrap_or(self.block)
+        };
+        Some(ChangeCursor {
+            block: block.min(self.block).min(head),
+            reorg_count: state.reorg_count,
+        })
+    }
+}
+
+/// A message in the change stream
+enum ChangeMessage {
+    /// The stream starts after `cursor.block`
+    Start { cursor: ChangeCursor },
+    /// The changes the deployment made in block `cursor.block`
+    Changes {
+        cursor: ChangeCursor,
+        changes: Vec<EntityOperation>,
+    },
+    /// The changes for blocks after `cursor.block` have been reverted
+    Revert { cursor: ChangeCursor },
+}
+
+impl ChangeMessage {
+    fn change_as_json(change: EntityOperation) -> serde_json::Value {
+        match change {
+            EntityOperation::Set { key, data } => {
+                let data = r::Value::object(
+                    data.sorted()
+                        .into_iter()
+                        .map(|(name, value)| (name, value.into()))
+                        .collect(),
+                );
+                serde_json::json!({
+                    "operation": "set",
+                    "type": key.entity_type.as_str(),
+                    "id": key.entity_id,
+                    "data": data,
+                })
+            }
+            EntityOperation::Remove { key } => serde_json::json!({
+                "operation": "remove",
+                "type": key.entity_type.as_str(),
+                "id": key.entity_id,
+            }),
+        }
+    }
+
+    /// Format the message as a server-sent event whose id is the cursor
+    /// from which the stream can be resumed
+    fn into_event(self) -> Bytes {
+        let (name, cursor, data) = match self {
+            ChangeMessage::Start { cursor } => (
+                "start",
+                cursor,
+                serde_json::json!({ "block": cursor.block }),
+            ),
+            ChangeMessage::Changes { cursor, changes } => {
+                let changes: Vec<_> = changes.into_iter().map(Self::change_as_json).collect();
+                (
+                    "changes",
+                    cursor,
+                    serde_json::json!({ "block": cursor.block, "changes": changes }),
+                )
+            }
+            ChangeMessage::Revert { cursor } => (
+                "revert",
+                cursor,
+                serde_json::json!({ "block": cursor.block }),
+            ),
+        };
+        Bytes::from(format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            cursor.encode(),
+            name,
+            data
+        ))
+    }
+}
+
+fn error_event(error: impl std::fmt::Display) -> Bytes {
+    let data = serde_json::json!({ "message": error.to_string() });
+    Bytes::from(format!("event: error\ndata: {}\n\n", data))
+}
+
+/// Serves change streams for deployments
+pub(crate) struct ChangeStreams<S> {
+    store: Arc<S>,
+    subscription_manager: Arc<dyn SubscriptionManager>,
+}
+
+impl<S> std::fmt::Debug for ChangeStreams<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ChangeStreams {{ }}")
+    }
+}
+
+impl<S: Store> ChangeStreams<S> {
+    pub fn new(store: Arc<S>, subscription_manager: Arc<dyn SubscriptionManager>) -> Self {
+        ChangeStreams {
+            store,
+            subscription_manager,
+        }
+    }
+
+    /// Start streaming the changes of `deployment`. The stream resumes
+    /// after `cursor` if one is given, otherwise it starts after `block`,
+    /// or at the current head of the deployment if neither is given
+    pub async fn handle(
+        &self,
+        logger: &Logger,
+        deployment: &str,
+        cursor: Option<&str>,
+        block: Option<&str>,
+    ) -> Result<Response<Body>, GraphQLServerError> {
+        let deployment = DeploymentHash::new(deployment).map_err(|id| {
+            GraphQLServerError::ClientError(format!("invalid deployment id `{}`", id))
+        })?;
+        let subgraph_store = self.store.subgraph_store();
+        let state = subgraph_store.deployment_state(&deployment).await?;
+
+        let cursor = match (cursor, block) {
+            (Some(cursor), _) => ChangeCursor::decode(cursor)?,
+            (None, Some(block)) => ChangeCursor {
+                block: block.parse().map_err(|_| {
+                    GraphQLServerError::ClientError(format!("invalid block number `{}`", block))
+                })?,
+                reorg_count: state.reorg_count,
+            },
+            (None, None) => ChangeCursor {
+                block: state.latest_ethereum_block_number,
+                reorg_count: state.reorg_count,
+            },
+        };
+        if cursor.reorg_count > state.reorg_count {
+            return Err(GraphQLServerError::ClientError(format!(
+                "cursor `{}` is not for deployment {}",
+                cursor.encode(),
+                deployment
+            )));
+        }
+
+        // Get woken up whenever the deployment changes any of its entities
+        let filter: BTreeSet<_> = subgraph_store
+            .input_schema(&deployment)?
+            .document
+            .get_object_type_definitions()
+            .into_iter()
+            .map(|obj_type| {
+                SubscriptionFilter::Entities(
+                    deployment.clone(),
+                    EntityType::new(obj_type.name.clone()),
+                )
+            })
+            .collect();
+        let store_events = self.subscription_manager.subscribe_no_payload(filter);
+
+        let (sender, body) = Body::channel();
+        let logger = logger.new(o!("component" => "ChangeStream",
+                                   "deployment" => deployment.to_string()));
+        graph::spawn(stream_changes(
+            logger,
+            subgraph_store,
+            deployment,
+            cursor,
+            store_events,
+            sender,
+        ));
+
+        Ok(Response::builder()
+            .status(200)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap())
+    }
+}
+
+/// Advance the stream at `cursor` by at most `MAX_BLOCKS_PER_STEP` blocks.
+/// Returns the messages to send, the new cursor and whether the stream
+/// has caught up with the head of the deployment
+async fn step<S: SubgraphStore>(
+    store: Arc<S>,
+    deployment: &DeploymentHash,
+    mut cursor: ChangeCursor,
+) -> Result<(Vec<ChangeMessage>, ChangeCursor, bool), StoreError> {
+    let state = store.deployment_state(deployment).await?;
+    let head = state.latest_ethereum_block_number;
+
+    let mut messages = Vec::new();
+    let reverts = if state.reorg_count != cursor.reorg_count {
+        let store = store.cheap_clone();
+        let deployment = deployment.clone();
+        let reorg_count = cursor.reorg_count;
+        graph::spawn_blocking_allow_panic(move || store.reverts_since(&deployment, reorg_count))
+            .await
+            .map_err(|e| StoreError::Unknown(anyhow!("failed to read reverts: {}", e)))??
+    } else {
+        vec![]
+    };
+    if let Some(reverted) = cursor.reverted(&state, &reverts) {
+        cursor = reverted;
+        messages.push(ChangeMessage::Revert { cursor });
+    }
+
+    if cursor.block + 1 < state.earliest_block_number {
+        return Err(StoreError::QueryExecutionError(format!(
+            "the changes for blocks before {} have been pruned",
+            state.earliest_block_number
+        )));
+    }
+
+    let start = cursor.block + 1;
+    let end = head.min(cursor.block + MAX_BLOCKS_PER_STEP);
+    let reorg_count = cursor.reorg_count;
+    let changes = if start <= end {
+        let store = store.cheap_clone();
+        let deployment = deployment.clone();
+        graph::spawn_blocking_allow_panic(move || {
+            store.entity_changes_in_block_range(&deployment, start, end)
+        })
+        .await
+        .map_err(|e| StoreError::Unknown(anyhow!("failed to read changes: {}", e)))??
+    } else {
+        BTreeMap::new()
+    };
+    let changes = changes
+        .into_iter()
+        .map(|(block, changes)| ChangeMessage::Changes {
+            cursor: ChangeCursor { block, reorg_count },
+            changes,
+        });
+
+    // If the deployment reverted blocks while we were reading, some of the
+    // changes might be gone already. Drop them; the next step will send a
+    // revert and read them again
+    if store.deployment_state(deployment).await?.reorg_count != reorg_count {
+        return Ok((messages, cursor, false));
+    }
+
+    messages.extend(changes);
+    cursor.block = cursor.block.max(end);
+    Ok((messages, cursor, end >= head))
+}
+
+async fn stream_changes<S: SubgraphStore>(
+    logger: Logger,
+    store: Arc<S>,
+    deployment: DeploymentHash,
+    mut cursor: ChangeCursor,
+    mut store_events: UnitStream,
+    mut sender: Sender,
+) {
+    debug!(logger, "Change stream opened"; "block" => cursor.block);
+
+    // An error from `send_data` means the client went away
+    if sender
+        .send_data(ChangeMessage::Start { cursor }.into_event())
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let (messages, next, caught_up) = match step(store.cheap_clone(), &deployment, cursor).await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                warn!(logger, "Change stream failed"; "error" => e.to_string());
+                let _ = sender.send_data(error_event(e)).await;
+                return;
+            }
+        };
+        for msg in messages {
+            if sender.send_data(msg.into_event()).await.is_err() {
+                debug!(logger, "Change stream closed"; "block" => cursor.block);
+                return;
+            }
+        }
+        cursor = next;
+
+        if caught_up {
+            let woken = tokio::time::timeout(POLL_INTERVAL, store_events.next()).await;
+            if woken.is_err() {
+                // Send a comment so that we notice when the client is gone
+                if sender.send_data(Bytes::from(":\n\n")).await.is_err() {
+                    debug!(logger, "Change stream closed"; "block" => cursor.block);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::{DeploymentHash, DeploymentState};
+
+    use super::ChangeCursor;
+
+    fn state(reorg_count: u32, head: i32) -> DeploymentState {
+        DeploymentState {
+            id: DeploymentHash::new("changes").unwrap(),
+            reorg_count,
+            max_reorg_depth: 0,
+            latest_ethereum_block_number: head,
+            earliest_block_number: 3,
+        }
+    }
+
+    #[test]
+    fn cursor_roundtrip() {
+        let cursor = ChangeCursor {
+            block: 17,
+            reorg_count: 3,
+        };
+        assert_eq!(cursor, ChangeCursor::decode(&cursor.encode()).unwrap());
+        assert!(ChangeCursor::decode("not a cursor").is_err());
+    }
+
+    #[test]
+    fn detects_reverts() {
+        let cursor = ChangeCursor {
+            block: 10,
+            reorg_count: 2,
+        };
+
+        // No reverts, whether the deployment moved on or not
+        assert_eq!(None, cursor.reverted(&state(2, 10), &[]));
+        assert_eq!(None, cursor.reverted(&state(2, 20), &[]));
+
+        // Two reverts; the deployment might have moved on since then
+        let expected = ChangeCursor {
+            block: 6,
+            reorg_count: 4,
+        };
+        assert_eq!(Some(expected), cursor.reverted(&state(4, 9), &[8, 6]));
+        assert_eq!(Some(expected), cursor.reverted(&state(4, 12), &[6, 9]));
+
+        // One revert that went back many blocks
+        let expected = ChangeCursor {
+            block: 4,
+            reorg_count: 3,
+        };
+        assert_eq!(Some(expected), cursor.reverted(&state(3, 5), &[4]));
+
+        // Reverts to blocks after the cursor do not affect the client
+        let expected = ChangeCursor {
+            block: 10,
+            reorg_count: 3,
+        };
+        assert_eq!(Some(expected), cursor.reverted(&state(3, 11), &[11]));
+
+        // Some reverts are not known; start over from the earliest block
+        let expected = ChangeCursor {
+            block: 2,
+            reorg_count: 4,
+        };
+        assert_eq!(Some(expected), cursor.reverted(&state(4, 12), &[9]));
+    }
+}
@@ -1,4 +1,5 @@
 mod auth;
+mod changes;
 mod explorer;
 mod resolver;
 mod schema;
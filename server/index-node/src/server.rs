@@ -5,7 +5,7 @@ use std::net::{Ipv4Addr, SocketAddrV4};
 
 use graph::{
     blockchain::BlockchainMap,
-    components::store::Store,
+    components::store::{Store, SubscriptionManager},
     prelude::{IndexNodeServer as IndexNodeServerTrait, *},
 };
 
@@ -31,6 +31,7 @@ pub struct IndexNodeServer<Q, S> {
     blockchain_map: Arc<BlockchainMap>,
     graphql_runner: Arc<Q>,
     store: Arc<S>,
+    subscription_manager: Arc<dyn SubscriptionManager>,
     link_resolver: Arc<dyn LinkResolver>,
 }
 
@@ -41,6 +42,7 @@ impl<Q, S> IndexNodeServer<Q, S> {
         blockchain_map: Arc<BlockchainMap>,
         graphql_runner: Arc<Q>,
         store: Arc<S>,
+        subscription_manager: Arc<dyn SubscriptionManager>,
         link_resolver: Arc<dyn LinkResolver>,
     ) -> Self {
         let logger = logger_factory.component_logger(
@@ -57,6 +59,7 @@ impl<Q, S> IndexNodeServer<Q, S> {
             blockchain_map,
             graphql_runner,
             store,
+            subscription_manager,
             link_resolver,
         }
     }
@@ -92,6 +95,7 @@ where
             self.blockchain_map.clone(),
             graphql_runner.clone(),
             store.clone(),
+            self.subscription_manager.clone(),
             self.link_resolver.clone(),
         );
         let new_service =
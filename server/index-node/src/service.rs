@@ -7,17 +7,23 @@ use hyper::body::Bytes;
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
 use serde_json;
+use std::collections::HashMap;
 use std::task::Context;
 use std::task::Poll;
 
-use graph::components::{server::query::GraphQLServerError, store::Store};
+use graph::components::{
+    server::query::GraphQLServerError,
+    store::{Store, SubscriptionManager},
+};
 use graph::data::query::QueryResults;
 use graph::prelude::*;
+use graph::url::form_urlencoded;
 use graph_graphql::prelude::{execute_query, Query as PreparedQuery, QueryExecutionOptions};
 use graphql_parser;
 
 use crate::auth::bearer_token;
 
+use crate::changes::ChangeStreams;
 use crate::explorer::Explorer;
 use crate::resolver::IndexNodeResolver;
 use crate::schema::SCHEMA;
@@ -33,6 +39,7 @@ pub struct IndexNodeService<Q, S> {
     graphql_runner: Arc<Q>,
     store: Arc<S>,
     explorer: Arc<Explorer<S>>,
+    changes: Arc<ChangeStreams<S>>,
     link_resolver: Arc<dyn LinkResolver>,
 }
 
@@ -44,6 +51,7 @@ impl<Q, S> Clone for IndexNodeService<Q, S> {
             graphql_runner: self.graphql_runner.clone(),
             store: self.store.clone(),
             explorer: self.explorer.clone(),
+            changes: self.changes.clone(),
             link_resolver: self.link_resolver.clone(),
         }
     }
@@ -62,9 +70,11 @@ where
         blockchain_map: Arc<BlockchainMap>,
         graphql_runner: Arc<Q>,
         store: Arc<S>,
+        subscription_manager: Arc<dyn SubscriptionManager>,
         link_resolver: Arc<dyn LinkResolver>,
     ) -> Self {
         let explorer = Arc::new(Explorer::new(store.clone()));
+        let changes = Arc::new(ChangeStreams::new(store.clone(), subscription_manager));
 
         IndexNodeService {
             logger,
@@ -72,6 +82,7 @@ where
             graphql_runner,
             store,
             explorer,
+            changes,
             link_resolver,
         }
     }
@@ -145,7 +156,7 @@ where
                 max_skip: std::u32::MAX,
                 load_manager,
             };
-            let result = execute_query(query_clone.cheap_clone(), None, None, options).await;
+            let (result, _) = execute_query(query_clone.cheap_clone(), None, None, options).await;
             query_clone.log_execution(0);
             QueryResult::from(
                 // Index status queries are not cacheable, so we may unwrap this.
@@ -230,6 +241,28 @@ where
 
             (Method::GET, ["explorer", rest @ ..]) => self.explorer.handle(&self.logger, rest),
 
+            (Method::GET, ["changes", deployment]) => {
+                let params: HashMap<_, _> = req
+                    .uri()
+                    .query()
+                    .map(|query| form_urlencoded::parse(query.as_bytes()).collect())
+                    .unwrap_or_default();
+                // Clients that reconnect on their own send the id of the
+                // last event they received, which is its cursor
+                let cursor = params
+                    .get("cursor")
+                    .map(|cursor| cursor.as_ref())
+                    .or_else(|| {
+                        req.headers()
+                            .get("Last-Event-ID")
+                            .and_then(|id| id.to_str().ok())
+                    });
+                let block = params.get("block").map(|block| block.as_ref());
+                self.changes
+                    .handle(&self.logger, deployment, cursor, block)
+                    .await
+            }
+
             _ => Ok(Self::handle_not_found()),
         }
     }
@@ -281,6 +314,20 @@ where
                     Err(err @ GraphQLServerError::InternalError(_)) => {
                         error!(logger, "IndexNodeService call failed: {}", err);
 
+                        Ok(Response::builder()
+                            .status(500)
+                            .header(CONTENT_TYPE, "text/plain")
+                            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                            .body(Body::from(format!("Internal server error: {}", err)))
+                            .unwrap())
+                    }
+                    // The index node does not use API keys
+                    Err(
+                        err @ GraphQLServerError::Unauthorized(_)
+                        | err @ GraphQLServerError::TooManyRequests(_),
+                    ) => {
+                        error!(logger, "IndexNodeService call failed: {}", err);
+
                         Ok(Response::builder()
                             .status(500)
                             .header(CONTENT_TYPE, "text/plain")
@@ -18,6 +18,8 @@ const JSON_RPC_DEPLOY_ERROR: i64 = 0;
 const JSON_RPC_REMOVE_ERROR: i64 = 1;
 const JSON_RPC_CREATE_ERROR: i64 = 2;
 const JSON_RPC_REASSIGN_ERROR: i64 = 3;
+const JSON_RPC_PAUSE_ERROR: i64 = 4;
+const JSON_RPC_RESUME_ERROR: i64 = 5;
 
 #[derive(Debug, Deserialize)]
 struct SubgraphCreateParams {
@@ -43,6 +45,11 @@ struct SubgraphReassignParams {
     node_id: NodeId,
 }
 
+#[derive(Debug, Deserialize)]
+struct SubgraphPauseParams {
+    deployment: DeploymentHash,
+}
+
 pub struct JsonRpcServer<R> {
     registrar: Arc<R>,
     http_port: u16,
@@ -149,6 +156,44 @@ impl<R: SubgraphRegistrar> JsonRpcServer<R> {
             )),
         }
     }
+
+    /// Handler for the `subgraph_pause` endpoint.
+    async fn pause_handler(
+        &self,
+        params: SubgraphPauseParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        info!(&self.logger, "Received subgraph_pause request"; "params" => format!("{:?}", params));
+
+        match self.registrar.pause_subgraph(&params.deployment).await {
+            Ok(_) => Ok(Value::Null),
+            Err(e) => Err(json_rpc_error(
+                &self.logger,
+                "subgraph_pause",
+                e,
+                JSON_RPC_PAUSE_ERROR,
+                params,
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_resume` endpoint.
+    async fn resume_handler(
+        &self,
+        params: SubgraphPauseParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        info!(&self.logger, "Received subgraph_resume request"; "params" => format!("{:?}", params));
+
+        match self.registrar.resume_subgraph(&params.deployment).await {
+            Ok(_) => Ok(Value::Null),
+            Err(e) => Err(json_rpc_error(
+                &self.logger,
+                "subgraph_resume",
+                e,
+                JSON_RPC_RESUME_ERROR,
+                params,
+            )),
+        }
+    }
 }
 
 impl<R> JsonRpcServerTrait<R> for JsonRpcServer<R>
@@ -211,7 +256,7 @@ where
             }
         });
 
-        let me = arc_self;
+        let me = arc_self.clone();
         handler.add_method("subgraph_reassign", move |params: Params| {
             let me = me.clone();
             async move {
@@ -220,6 +265,24 @@ where
             }
         });
 
+        let me = arc_self.clone();
+        handler.add_method("subgraph_pause", move |params: Params| {
+            let me = me.clone();
+            async move {
+                let params = params.parse()?;
+                me.pause_handler(params).await
+            }
+        });
+
+        let me = arc_self;
+        handler.add_method("subgraph_resume", move |params: Params| {
+            let me = me.clone();
+            async move {
+                let params = params.parse()?;
+                me.resume_handler(params).await
+            }
+        });
+
         ServerBuilder::new(handler)
             // Enable REST API:
             // POST /<method>/<param1>/<param2>
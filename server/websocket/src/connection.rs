@@ -1,15 +1,53 @@
 use futures::future::IntoFuture;
 use futures::sync::mpsc;
-use futures03::stream::SplitStream;
 use graphql_parser::parse_query;
 use http::StatusCode;
 use std::collections::HashMap;
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::protocol::frame::{coding::CloseCode, CloseFrame};
 use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
 use tokio_tungstenite::WebSocketStream;
 use uuid::Uuid;
 
-use graph::{data::query::QueryTarget, prelude::*};
+use graph::{
+    data::query::{ApiKey, QueryTarget},
+    prelude::*,
+};
+
+/// The GraphQL over WebSocket subprotocols we support
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    /// The legacy protocol from `subscriptions-transport-ws`
+    GraphQlWs,
+    /// The protocol from `graphql-ws`, see
+    /// https://github.com/enisdenjo/graphql-ws/blob/master/PROTOCOL.md
+    GraphQlTransportWs,
+}
+
+impl Protocol {
+    /// Pick the protocol to use from the comma-separated list of
+    /// subprotocols in the client's `Sec-WebSocket-Protocol` header. We
+    /// prefer `graphql-transport-ws` and fall back to the legacy protocol
+    /// for clients that do not ask for it
+    pub fn negotiate(requested: Option<&str>) -> Self {
+        let requested = requested.unwrap_or("");
+        if requested
+            .split(',')
+            .any(|proto| proto.trim() == "graphql-transport-ws")
+        {
+            Protocol::GraphQlTransportWs
+        } else {
+            Protocol::GraphQlWs
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::GraphQlWs => "graphql-ws",
+            Protocol::GraphQlTransportWs => "graphql-transport-ws",
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -19,7 +57,9 @@ struct StartPayload {
     operation_name: Option<String>,
 }
 
-/// GraphQL/WebSocket message received from a client.
+/// GraphQL/WebSocket message received from a client. The variants and
+/// their serialization are those of the legacy protocol; messages in the
+/// `graphql-transport-ws` protocol are translated into them
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum IncomingMessage {
@@ -35,12 +75,64 @@ enum IncomingMessage {
     Stop {
         id: String,
     },
+    #[serde(skip_deserializing)]
+    Ping,
+    #[serde(skip_deserializing)]
+    Pong,
+}
+
+/// GraphQL/WebSocket message received from a client that uses the
+/// `graphql-transport-ws` protocol
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TransportIncomingMessage {
+    ConnectionInit {
+        payload: Option<serde_json::Value>,
+    },
+    Ping {
+        #[allow(dead_code)]
+        payload: Option<serde_json::Value>,
+    },
+    Pong {
+        #[allow(dead_code)]
+        payload: Option<serde_json::Value>,
+    },
+    Subscribe {
+        id: String,
+        payload: StartPayload,
+    },
+    Complete {
+        id: String,
+    },
+}
+
+impl From<TransportIncomingMessage> for IncomingMessage {
+    fn from(msg: TransportIncomingMessage) -> Self {
+        match msg {
+            TransportIncomingMessage::ConnectionInit { payload } => {
+                IncomingMessage::ConnectionInit { payload }
+            }
+            TransportIncomingMessage::Ping { .. } => IncomingMessage::Ping,
+            TransportIncomingMessage::Pong { .. } => IncomingMessage::Pong,
+            TransportIncomingMessage::Subscribe { id, payload } => {
+                IncomingMessage::Start { id, payload }
+            }
+            TransportIncomingMessage::Complete { id } => IncomingMessage::Stop { id },
+        }
+    }
 }
 
 impl IncomingMessage {
-    pub fn from_ws_message(msg: WsMessage) -> Result<Self, WsError> {
+    pub fn from_ws_message(msg: WsMessage, protocol: Protocol) -> Result<Self, WsError> {
         let text = msg.into_text()?;
-        serde_json::from_str(text.as_str()).map_err(|e| {
+        let msg = match protocol {
+            Protocol::GraphQlWs => serde_json::from_str(text.as_str()),
+            Protocol::GraphQlTransportWs => {
+                serde_json::from_str::<TransportIncomingMessage>(text.as_str())
+                    .map(IncomingMessage::from)
+            }
+        };
+        msg.map_err(|e| {
             WsError::Http(http::Response::new(Some(format!(
                 "Invalid GraphQL over WebSocket message: {}: {}",
                 text, e
@@ -65,6 +157,42 @@ enum OutgoingMessage {
     Complete {
         id: String,
     },
+    Pong,
+}
+
+/// GraphQL/WebSocket message to be sent to a client that uses the
+/// `graphql-transport-ws` protocol
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TransportOutgoingMessage {
+    ConnectionAck,
+    Next {
+        id: String,
+        payload: Arc<QueryResult>,
+    },
+    Error {
+        id: String,
+        payload: serde_json::Value,
+    },
+    Complete {
+        id: String,
+    },
+    Pong,
+}
+
+impl From<OutgoingMessage> for TransportOutgoingMessage {
+    fn from(msg: OutgoingMessage) -> Self {
+        match msg {
+            OutgoingMessage::ConnectionAck => TransportOutgoingMessage::ConnectionAck,
+            OutgoingMessage::Error { id, payload } => TransportOutgoingMessage::Error {
+                id,
+                payload: serde_json::json!([{ "message": payload }]),
+            },
+            OutgoingMessage::Data { id, payload } => TransportOutgoingMessage::Next { id, payload },
+            OutgoingMessage::Complete { id } => TransportOutgoingMessage::Complete { id },
+            OutgoingMessage::Pong => TransportOutgoingMessage::Pong,
+        }
+    }
 }
 
 impl OutgoingMessage {
@@ -78,38 +206,61 @@ impl OutgoingMessage {
     pub fn from_error_string(id: String, s: String) -> Self {
         OutgoingMessage::Error { id, payload: s }
     }
-}
 
-impl From<OutgoingMessage> for WsMessage {
-    fn from(msg: OutgoingMessage) -> Self {
-        WsMessage::text(serde_json::to_string(&msg).expect("invalid GraphQL/WebSocket message"))
+    pub fn into_ws_message(self, protocol: Protocol) -> WsMessage {
+        let text = match protocol {
+            Protocol::GraphQlWs => serde_json::to_string(&self),
+            Protocol::GraphQlTransportWs => {
+                serde_json::to_string(&TransportOutgoingMessage::from(self))
+            }
+        };
+        WsMessage::text(text.expect("invalid GraphQL/WebSocket message"))
     }
 }
 
 /// Helper function to send outgoing messages.
 fn send_message(
     sink: &mpsc::UnboundedSender<WsMessage>,
+    protocol: Protocol,
     msg: OutgoingMessage,
 ) -> Result<(), WsError> {
-    sink.unbounded_send(msg.into()).map_err(|_| {
-        let mut response = http::Response::new(None);
-        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-        WsError::Http(response)
-    })
+    sink.unbounded_send(msg.into_ws_message(protocol))
+        .map_err(|_| {
+            let mut response = http::Response::new(None);
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            WsError::Http(response)
+        })
 }
 
 /// Helper function to send error messages.
 fn send_error_string(
     sink: &mpsc::UnboundedSender<WsMessage>,
+    protocol: Protocol,
     operation_id: String,
     error: String,
 ) -> Result<(), WsError> {
-    sink.unbounded_send(OutgoingMessage::from_error_string(operation_id, error).into())
-        .map_err(|_| {
-            let mut response = http::Response::new(None);
-            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-            WsError::Http(response)
-        })
+    send_message(
+        sink,
+        protocol,
+        OutgoingMessage::from_error_string(operation_id, error),
+    )
+}
+
+/// Helper function to close the connection with one of the close codes
+/// from the `graphql-transport-ws` protocol. Always returns an error so
+/// that the caller stops processing incoming messages
+fn close_connection(
+    sink: &mpsc::UnboundedSender<WsMessage>,
+    code: u16,
+    reason: String,
+) -> Result<(), WsError> {
+    let frame = CloseFrame {
+        code: CloseCode::from(code),
+        reason: reason.into(),
+    };
+    // An error means the client is gone already
+    let _ = sink.unbounded_send(WsMessage::Close(Some(frame)));
+    Err(WsError::ConnectionClosed)
 }
 
 /// Responsible for recording operation ids and stopping them.
@@ -117,13 +268,15 @@ fn send_error_string(
 struct Operations {
     operations: HashMap<String, CancelGuard>,
     msg_sink: mpsc::UnboundedSender<WsMessage>,
+    protocol: Protocol,
 }
 
 impl Operations {
-    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>) -> Self {
+    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>, protocol: Protocol) -> Self {
         Self {
             operations: HashMap::new(),
             msg_sink,
+            protocol,
         }
     }
 
@@ -137,7 +290,18 @@ impl Operations {
 
     fn stop(&mut self, operation_id: String) -> Result<(), WsError> {
         // Remove the operation with this ID from the known operations.
-        match self.operations.remove(&operation_id) {
+        let stopper = self.operations.remove(&operation_id);
+
+        // With `graphql-transport-ws`, the client's `complete` message
+        // ends the operation, and the server must not respond to it
+        if self.protocol == Protocol::GraphQlTransportWs {
+            if let Some(stopper) = stopper {
+                stopper.cancel();
+            }
+            return Ok(());
+        }
+
+        match stopper {
             Some(stopper) => {
                 // Cancel the subscription result stream.
                 stopper.cancel();
@@ -145,6 +309,7 @@ impl Operations {
                 // Send a GQL_COMPLETE to indicate the operation is been completed.
                 send_message(
                     &self.msg_sink,
+                    self.protocol,
                     OutgoingMessage::Complete {
                         id: operation_id.clone(),
                     },
@@ -152,6 +317,7 @@ impl Operations {
             }
             None => send_error_string(
                 &self.msg_sink,
+                self.protocol,
                 operation_id.clone(),
                 format!("Unknown operation ID: {}", operation_id),
             ),
@@ -176,6 +342,8 @@ pub struct GraphQlConnection<Q, S> {
     graphql_runner: Arc<Q>,
     stream: WebSocketStream<S>,
     deployment: DeploymentHash,
+    protocol: Protocol,
+    api_key: Option<Arc<ApiKey>>,
 }
 
 impl<Q, S> GraphQlConnection<Q, S>
@@ -189,6 +357,8 @@ where
         deployment: DeploymentHash,
         stream: WebSocketStream<S>,
         graphql_runner: Arc<Q>,
+        protocol: Protocol,
+        api_key: Option<Arc<ApiKey>>,
     ) -> Self {
         GraphQlConnection {
             id: Uuid::new_v4().to_string(),
@@ -196,21 +366,56 @@ where
             graphql_runner,
             stream,
             deployment,
+            protocol,
+            api_key,
         }
     }
 
-    async fn handle_incoming_messages(
-        mut ws_stream: SplitStream<WebSocketStream<S>>,
+    /// Handle the messages that the client sends over `ws_stream` and send
+    /// responses to `msg_sink` until the client closes the connection
+    async fn handle_incoming_messages<W>(
+        mut ws_stream: W,
         mut msg_sink: mpsc::UnboundedSender<WsMessage>,
         logger: Logger,
         connection_id: String,
         deployment: DeploymentHash,
         graphql_runner: Arc<Q>,
-    ) -> Result<(), WsError> {
-        let mut operations = Operations::new(msg_sink.clone());
+        protocol: Protocol,
+        api_key: Option<Arc<ApiKey>>,
+    ) -> Result<(), WsError>
+    where
+        W: futures03::Stream<Item = Result<WsMessage, WsError>> + Unpin,
+    {
+        let mut operations = Operations::new(msg_sink.clone(), protocol);
+        let transport_ws = protocol == Protocol::GraphQlTransportWs;
+        let mut acknowledged = false;
+        // `graphql-transport-ws` clients have to initialise the connection
+        // within a fixed time after opening it
+        let init_deadline =
+            tokio::time::Instant::now() + ENV_VARS.graphql.ws_connection_init_timeout;
 
         // Process incoming messages as long as the WebSocket is open
-        while let Some(ws_msg) = ws_stream.try_next().await? {
+        loop {
+            let next = ws_stream.try_next();
+            let ws_msg = if transport_ws && !acknowledged {
+                match tokio::time::timeout_at(init_deadline, next).await {
+                    Ok(ws_msg) => ws_msg?,
+                    Err(_) => {
+                        return close_connection(
+                            &msg_sink,
+                            4408,
+                            "Connection initialisation timeout".to_string(),
+                        )
+                    }
+                }
+            } else {
+                next.await?
+            };
+            let ws_msg = match ws_msg {
+                Some(ws_msg) => ws_msg,
+                None => break,
+            };
+
             use self::IncomingMessage::*;
             use self::OutgoingMessage::*;
 
@@ -218,15 +423,35 @@ where
                    "connection" => &connection_id,
                    "msg" => format!("{}", ws_msg).as_str());
 
-            let msg = IncomingMessage::from_ws_message(ws_msg.clone())?;
+            let msg = match IncomingMessage::from_ws_message(ws_msg.clone(), protocol) {
+                Ok(msg) => msg,
+                Err(_) if transport_ws => {
+                    return close_connection(&msg_sink, 4400, "Invalid message received".into())
+                }
+                Err(e) => return Err(e),
+            };
 
             debug!(logger, "GraphQL/WebSocket message";
                    "connection" => &connection_id,
                    "msg" => format!("{:?}", msg).as_str());
 
             match msg {
-                // Always accept connection init requests
-                ConnectionInit { payload: _ } => send_message(&msg_sink, ConnectionAck),
+                // `graphql-transport-ws` allows only one connection init request
+                ConnectionInit { payload: _ } if transport_ws && acknowledged => close_connection(
+                    &msg_sink,
+                    4429,
+                    "Too many initialisation requests".to_string(),
+                ),
+
+                // Otherwise, always accept connection init requests
+                ConnectionInit { payload: _ } => {
+                    acknowledged = true;
+                    send_message(&msg_sink, protocol, ConnectionAck)
+                }
+
+                IncomingMessage::Ping => send_message(&msg_sink, protocol, OutgoingMessage::Pong),
+
+                IncomingMessage::Pong => Ok(()),
 
                 // When receiving a connection termination request
                 ConnectionTerminate => {
@@ -242,10 +467,24 @@ where
 
                 // When receiving a start request
                 Start { id, payload } => {
+                    // `graphql-transport-ws` requires that the connection was
+                    // initialised before operations are started
+                    if transport_ws && !acknowledged {
+                        return close_connection(&msg_sink, 4401, "Unauthorized".to_string());
+                    }
+
                     // Respond with a GQL_ERROR if we already have an operation with this ID
                     if operations.contains(&id) {
+                        if transport_ws {
+                            return close_connection(
+                                &msg_sink,
+                                4409,
+                                format!("Subscriber for {} already exists", id),
+                            );
+                        }
                         return send_error_string(
                             &msg_sink,
+                            protocol,
                             id.clone(),
                             format!("Operation with ID already started: {}", id),
                         );
@@ -255,6 +494,7 @@ where
                         if operations.operations.len() >= max_ops {
                             return send_error_string(
                                 &msg_sink,
+                                protocol,
                                 id,
                                 format!(
                                     "Reached the limit of {} operations per connection",
@@ -264,6 +504,14 @@ where
                         }
                     }
 
+                    // Every operation counts against the rate limit of the
+                    // connection's API key
+                    if let Some(api_key) = &api_key {
+                        if let Err(e) = api_key.check_rate(1) {
+                            return send_error_string(&msg_sink, protocol, id, e.to_string());
+                        }
+                    }
+
                     // Parse the GraphQL query document; respond with a GQL_ERROR if
                     // the query is invalid
                     let query = match parse_query(&payload.query) {
@@ -271,6 +519,7 @@ where
                         Err(e) => {
                             return send_error_string(
                                 &msg_sink,
+                                protocol,
                                 id,
                                 format!("Invalid query: {}: {}", payload.query, e),
                             );
@@ -286,6 +535,7 @@ where
                                 Err(e) => {
                                     return send_error_string(
                                         &msg_sink,
+                                        protocol,
                                         id,
                                         format!("Invalid variables provided: {}", e),
                                     );
@@ -295,6 +545,7 @@ where
                         _ => {
                             return send_error_string(
                                 &msg_sink,
+                                protocol,
                                 id,
                                 format!("Invalid variables provided (must be an object)"),
                             );
@@ -303,10 +554,12 @@ where
 
                     // Construct a subscription
                     let target = QueryTarget::Deployment(deployment.clone());
+                    let mut query = Query::new(query, variables);
+                    query.api_key = api_key.clone();
                     let subscription = Subscription {
                         // Subscriptions currently do not benefit from the generational cache
                         // anyways, so don't bother passing a network.
-                        query: Query::new(query, variables),
+                        query,
                     };
 
                     debug!(logger, "Start operation";
@@ -347,7 +600,8 @@ where
 
                                         // An error means the client closed the websocket, ignore
                                         // and let it be handled in the websocket loop above.
-                                        let _ = error_sink.unbounded_send(msg.into());
+                                        let _ = error_sink
+                                            .unbounded_send(msg.into_ws_message(protocol));
                                     }
                                 }
                             };
@@ -358,7 +612,7 @@ where
                                 .map(move |result| {
                                     OutgoingMessage::from_query_result(result_id.clone(), result)
                                 })
-                                .map(WsMessage::from)
+                                .map(move |msg| msg.into_ws_message(protocol))
                                 .map(Ok)
                                 .compat()
                                 .forward(result_sink.sink_map_err(|_| ()))
@@ -414,6 +668,8 @@ where
             self.id.clone(),
             self.deployment.clone(),
             self.graphql_runner.clone(),
+            self.protocol,
+            self.api_key.clone(),
         );
 
         // Send outgoing messages asynchronously
@@ -436,3 +692,345 @@ where
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc;
+    use graph::data::graphql::effort::LoadManager;
+    use graph::data::query::{QueryResults, QueryTarget};
+    use graph::prelude::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+    use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
+
+    use super::{GraphQlConnection, IncomingMessage, OutgoingMessage, Protocol};
+
+    /// A query runner for connections that never get to run an operation
+    struct TestGraphQlRunner;
+
+    #[async_trait]
+    impl GraphQlRunner for TestGraphQlRunner {
+        async fn run_query(self: Arc<Self>, _query: Query, _target: QueryTarget) -> QueryResults {
+            unimplemented!()
+        }
+
+        async fn run_query_with_complexity(
+            self: Arc<Self>,
+            _query: Query,
+            _target: QueryTarget,
+            _max_complexity: Option<u64>,
+            _max_depth: Option<u8>,
+            _max_first: Option<u32>,
+            _max_skip: Option<u32>,
+        ) -> QueryResults {
+            unimplemented!()
+        }
+
+        async fn run_query_batch(
+            self: Arc<Self>,
+            _queries: Vec<Query>,
+            _target: QueryTarget,
+        ) -> Vec<QueryResults> {
+            unimplemented!()
+        }
+
+        async fn run_composite_query(
+            self: Arc<Self>,
+            _query: Query,
+            _namespaces: Vec<(String, QueryTarget)>,
+        ) -> QueryResults {
+            unimplemented!()
+        }
+
+        async fn run_subscription(
+            self: Arc<Self>,
+            _subscription: Subscription,
+            _target: QueryTarget,
+        ) -> Result<SubscriptionResult, SubscriptionError> {
+            unimplemented!()
+        }
+
+        async fn query_target(
+            self: Arc<Self>,
+            _subgraph: String,
+        ) -> Result<Option<QueryTarget>, QueryExecutionError> {
+            unimplemented!()
+        }
+
+        fn load_manager(&self) -> Arc<LoadManager> {
+            unimplemented!()
+        }
+    }
+
+    /// A socket that is never used; the tests feed messages to the
+    /// connection directly, but its type needs a socket type
+    struct NoSocket;
+
+    impl AsyncRead for NoSocket {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Pending
+        }
+    }
+
+    impl AsyncWrite for NoSocket {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Pending
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Pending
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Pending
+        }
+    }
+
+    /// Run a connection that uses `protocol` and on which the client sends
+    /// `incoming`, and return the messages the server sends to the client
+    async fn run_connection<W>(protocol: Protocol, incoming: W) -> Vec<WsMessage>
+    where
+        W: futures03::Stream<Item = Result<WsMessage, WsError>> + Unpin,
+    {
+        let (msg_sink, msg_stream) = mpsc::unbounded();
+        let _ = GraphQlConnection::<TestGraphQlRunner, NoSocket>::handle_incoming_messages(
+            incoming,
+            msg_sink,
+            Logger::root(slog::Discard, o!()),
+            "connection".to_string(),
+            DeploymentHash::new("test").unwrap(),
+            Arc::new(TestGraphQlRunner),
+            protocol,
+            None,
+        )
+        .await;
+        msg_stream.collect().wait().unwrap()
+    }
+
+    fn messages(
+        texts: &[&str],
+    ) -> impl futures03::Stream<Item = Result<WsMessage, WsError>> + Unpin {
+        let messages: Vec<_> = texts
+            .iter()
+            .map(|text| Ok(WsMessage::text(*text)))
+            .collect();
+        futures03::stream::iter(messages)
+    }
+
+    fn close_code(msg: &WsMessage) -> Option<u16> {
+        match msg {
+            WsMessage::Close(Some(frame)) => Some(frame.code.into()),
+            _ => None,
+        }
+    }
+
+    fn json(msg: &WsMessage) -> serde_json::Value {
+        serde_json::from_str(msg.to_text().unwrap()).unwrap()
+    }
+
+    const INIT: &str = r#"{"type": "connection_init"}"#;
+
+    #[test]
+    fn negotiate_protocol() {
+        use Protocol::*;
+
+        assert_eq!(GraphQlWs, Protocol::negotiate(None));
+        assert_eq!(GraphQlWs, Protocol::negotiate(Some("")));
+        assert_eq!(GraphQlWs, Protocol::negotiate(Some("graphql-ws")));
+        assert_eq!(
+            GraphQlWs,
+            Protocol::negotiate(Some("graphql-transport-ws2"))
+        );
+        assert_eq!(
+            GraphQlTransportWs,
+            Protocol::negotiate(Some("graphql-transport-ws"))
+        );
+        assert_eq!(
+            GraphQlTransportWs,
+            Protocol::negotiate(Some("graphql-ws, graphql-transport-ws"))
+        );
+        assert_eq!(
+            GraphQlTransportWs,
+            Protocol::negotiate(Some("graphql-transport-ws ,graphql-ws"))
+        );
+    }
+
+    #[test]
+    fn translate_incoming_messages() {
+        use IncomingMessage::*;
+        use Protocol::*;
+
+        let parse = |protocol, text: &str| {
+            IncomingMessage::from_ws_message(WsMessage::text(text), protocol)
+        };
+        let subscribe = r#"{"type": "subscribe", "id": "1", "payload": { "query": "subscription { users { id } }" }}"#;
+        let start = r#"{"type": "start", "id": "1", "payload": { "query": "subscription { users { id } }" }}"#;
+
+        assert!(matches!(
+            parse(GraphQlTransportWs, subscribe),
+            Ok(Start { id, payload }) if id == "1" && payload.query.starts_with("subscription")
+        ));
+        assert!(matches!(
+            parse(GraphQlTransportWs, r#"{"type": "complete", "id": "1"}"#),
+            Ok(Stop { id }) if id == "1"
+        ));
+        assert!(matches!(
+            parse(GraphQlTransportWs, r#"{"type": "ping"}"#),
+            Ok(Ping)
+        ));
+        assert!(matches!(
+            parse(GraphQlTransportWs, r#"{"type": "pong", "payload": {}}"#),
+            Ok(Pong)
+        ));
+        assert!(matches!(
+            parse(GraphQlTransportWs, INIT),
+            Ok(ConnectionInit { .. })
+        ));
+        assert!(parse(GraphQlTransportWs, start).is_err());
+
+        assert!(matches!(
+            parse(GraphQlWs, start),
+            Ok(Start { id, .. }) if id == "1"
+        ));
+        assert!(matches!(
+            parse(GraphQlWs, r#"{"type": "stop", "id": "1"}"#),
+            Ok(Stop { id }) if id == "1"
+        ));
+        assert!(matches!(
+            parse(GraphQlWs, r#"{"type": "connection_terminate"}"#),
+            Ok(ConnectionTerminate)
+        ));
+        assert!(parse(GraphQlWs, subscribe).is_err());
+        assert!(parse(GraphQlWs, r#"{"type": "ping"}"#).is_err());
+    }
+
+    #[test]
+    fn translate_outgoing_messages() {
+        use Protocol::*;
+
+        let error = || OutgoingMessage::from_error_string("1".to_string(), "boom".to_string());
+        let data = || {
+            let result = QueryResult::from(Object::from_iter(vec![(
+                "name".to_string(),
+                r::Value::String("Jordi".to_string()),
+            )]));
+            OutgoingMessage::from_query_result("1".to_string(), Arc::new(result))
+        };
+
+        assert_eq!(
+            serde_json::json!({ "type": "error", "id": "1", "payload": "boom" }),
+            json(&error().into_ws_message(GraphQlWs))
+        );
+        assert_eq!(
+            serde_json::json!({ "type": "error", "id": "1", "payload": [{ "message": "boom" }] }),
+            json(&error().into_ws_message(GraphQlTransportWs))
+        );
+        assert_eq!(
+            serde_json::json!({ "type": "data", "id": "1", "payload": { "data": { "name": "Jordi" } } }),
+            json(&data().into_ws_message(GraphQlWs))
+        );
+        assert_eq!(
+            serde_json::json!({ "type": "next", "id": "1", "payload": { "data": { "name": "Jordi" } } }),
+            json(&data().into_ws_message(GraphQlTransportWs))
+        );
+        for protocol in [GraphQlWs, GraphQlTransportWs] {
+            assert_eq!(
+                serde_json::json!({ "type": "connection_ack" }),
+                json(&OutgoingMessage::ConnectionAck.into_ws_message(protocol))
+            );
+            assert_eq!(
+                serde_json::json!({ "type": "complete", "id": "1" }),
+                json(
+                    &OutgoingMessage::Complete {
+                        id: "1".to_string()
+                    }
+                    .into_ws_message(protocol)
+                )
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn close_on_invalid_message() {
+        let sent = run_connection(
+            Protocol::GraphQlTransportWs,
+            messages(&[r#"{"type": "start"}"#]),
+        )
+        .await;
+        assert_eq!(
+            vec![Some(4400)],
+            sent.iter().map(close_code).collect::<Vec<_>>()
+        );
+
+        // The legacy protocol has no close codes and drops the connection
+        let sent =
+            run_connection(Protocol::GraphQlWs, messages(&[r#"{"type": "subscribe"}"#])).await;
+        assert!(sent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn close_on_subscribe_before_init() {
+        let subscribe = r#"{"type": "subscribe", "id": "1", "payload": { "query": "subscription { users { id } }" }}"#;
+        let sent = run_connection(Protocol::GraphQlTransportWs, messages(&[subscribe])).await;
+        assert_eq!(
+            vec![Some(4401)],
+            sent.iter().map(close_code).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn close_on_repeated_init() {
+        let sent = run_connection(Protocol::GraphQlTransportWs, messages(&[INIT, INIT])).await;
+        assert_eq!(2, sent.len());
+        assert_eq!(
+            serde_json::json!({ "type": "connection_ack" }),
+            json(&sent[0])
+        );
+        assert_eq!(Some(4429), close_code(&sent[1]));
+
+        // The legacy protocol acknowledges every init request
+        let sent = run_connection(Protocol::GraphQlWs, messages(&[INIT, INIT])).await;
+        assert_eq!(2, sent.len());
+        assert!(sent.iter().all(|msg| close_code(msg).is_none()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_on_init_timeout() {
+        // The client opens the connection but never initialises it
+        let sent = run_connection(
+            Protocol::GraphQlTransportWs,
+            futures03::stream::pending::<Result<WsMessage, WsError>>(),
+        )
+        .await;
+        assert_eq!(
+            vec![Some(4408)],
+            sent.iter().map(close_code).collect::<Vec<_>>()
+        );
+
+        // Pings do not count as initialising the connection
+        let incoming = messages(&[r#"{"type": "ping"}"#]).chain(futures03::stream::pending());
+        let sent = run_connection(Protocol::GraphQlTransportWs, incoming).await;
+        assert_eq!(2, sent.len());
+        assert_eq!(serde_json::json!({ "type": "pong" }), json(&sent[0]));
+        assert_eq!(Some(4408), close_code(&sent[1]));
+
+        // Connections that were initialised in time stay open until the
+        // client closes them
+        let incoming = messages(&[INIT]);
+        let sent = run_connection(Protocol::GraphQlTransportWs, incoming).await;
+        assert_eq!(1, sent.len());
+        assert_eq!(
+            serde_json::json!({ "type": "connection_ack" }),
+            json(&sent[0])
+        );
+    }
+}
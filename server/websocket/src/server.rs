@@ -1,5 +1,7 @@
+use graph::url::form_urlencoded;
 use graph::{
-    data::query::QueryTarget,
+    components::server::query::GraphQLServerError,
+    data::query::{ApiKeys, QueryTarget},
     prelude::{SubscriptionServer as SubscriptionServerTrait, *},
 };
 use http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE};
@@ -10,13 +12,14 @@ use tokio::net::TcpListener;
 use tokio_tungstenite::accept_hdr_async;
 use tokio_tungstenite::tungstenite::handshake::server::Request;
 
-use crate::connection::GraphQlConnection;
+use crate::connection::{GraphQlConnection, Protocol};
 
 /// A GraphQL subscription server based on Hyper / Websockets.
 pub struct SubscriptionServer<Q, S> {
     logger: Logger,
     graphql_runner: Arc<Q>,
     store: Arc<S>,
+    api_keys: Arc<ApiKeys>,
 }
 
 impl<Q, S> SubscriptionServer<Q, S>
@@ -24,18 +27,43 @@ where
     Q: GraphQlRunner,
     S: QueryStoreManager,
 {
-    pub fn new(logger: &Logger, graphql_runner: Arc<Q>, store: Arc<S>) -> Self {
+    pub fn new(
+        logger: &Logger,
+        graphql_runner: Arc<Q>,
+        store: Arc<S>,
+        api_keys: Arc<ApiKeys>,
+    ) -> Self {
         SubscriptionServer {
             logger: logger.new(o!("component" => "SubscriptionServer")),
             graphql_runner,
             store,
+            api_keys,
         }
     }
 
+    /// The API key token of a request, either from an `Authorization:
+    /// Bearer` header or, since browsers can not set headers for
+    /// WebSockets, from an `api_key` URL parameter
+    fn api_key_token(request: &Request) -> Option<String> {
+        let header = request
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(ApiKeys::bearer_token)
+            .map(str::to_string);
+        header.or_else(|| {
+            request.uri().query().and_then(|params| {
+                form_urlencoded::parse(params.as_bytes())
+                    .find(|(key, _)| key == "api_key")
+                    .map(|(_, value)| value.into_owned())
+            })
+        })
+    }
+
     async fn subgraph_id_from_url_path(
         store: Arc<S>,
         path: &str,
-    ) -> Result<Option<DeploymentState>, Error> {
+    ) -> Result<Option<(QueryTarget, DeploymentState)>, Error> {
         fn target_from_name(name: String) -> Option<QueryTarget> {
             SubgraphName::new(name).ok().map(QueryTarget::Name)
         }
@@ -47,13 +75,17 @@ where
         async fn state<S: QueryStoreManager>(
             store: Arc<S>,
             target: Option<QueryTarget>,
-        ) -> Option<DeploymentState> {
+        ) -> Option<(QueryTarget, DeploymentState)> {
             let target = match target {
                 Some(target) => target,
                 None => return None,
             };
-            match store.query_store(target, false).await.ok() {
-                Some(query_store) => query_store.deployment_state().await.ok(),
+            match store.query_store(target.clone(), false).await.ok() {
+                Some(query_store) => query_store
+                    .deployment_state()
+                    .await
+                    .ok()
+                    .map(|state| (target, state)),
                 None => None,
             }
         }
@@ -114,8 +146,10 @@ where
             let logger2 = self.logger.clone();
             let graphql_runner = self.graphql_runner.clone();
             let store = self.store.clone();
+            let api_keys = self.api_keys.clone();
 
-            // Subgraph that the request is resolved to (if any)
+            // Subgraph that the request is resolved to (if any) and the
+            // GraphQL over WebSocket protocol the client asked for
             let subgraph_id = Arc::new(Mutex::new(None));
             let accept_subgraph_id = subgraph_id.clone();
 
@@ -127,7 +161,7 @@ where
                 // `block_in_place` is not recommended but in this case we have no alternative since
                 // we're in an async context but `tokio_tungstenite` doesn't allow this callback
                 // to be a future.
-                let state = tokio::task::block_in_place(|| {
+                let (target, state) = tokio::task::block_in_place(|| {
                     graph::block_on(Self::subgraph_id_from_url_path(
                         store.clone(),
                         path,
@@ -171,10 +205,35 @@ where
                             .unwrap());
                     }
 
-                *accept_subgraph_id.lock().unwrap() = Some(state.id);
+                // Check the API key; rate limits are enforced for each
+                // operation on the connection
+                let token = Self::api_key_token(request);
+                let api_key = api_keys
+                    .authenticate(token.as_deref(), &target)
+                    .map_err(|e| {
+                        let status = match e {
+                            GraphQLServerError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+                            _ => StatusCode::INTERNAL_SERVER_ERROR,
+                        };
+                        Response::builder()
+                            .status(status)
+                            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                            .header(CONTENT_TYPE, "text/plain")
+                            .body(Some(e.to_string()))
+                            .unwrap()
+                    })?;
+
+                let protocol = Protocol::negotiate(
+                    request
+                        .headers()
+                        .get("Sec-WebSocket-Protocol")
+                        .and_then(|value| value.to_str().ok()),
+                );
+
+                *accept_subgraph_id.lock().unwrap() = Some((state.id, protocol, api_key));
                 response.headers_mut().insert(
                     "Sec-WebSocket-Protocol",
-                    HeaderValue::from_static("graphql-ws"),
+                    HeaderValue::from_static(protocol.as_str()),
                 );
                 Ok(response)
             })
@@ -182,7 +241,8 @@ where
                 match result {
                     Ok(ws_stream) => {
                         // Obtain the subgraph ID or name that we resolved the request to
-                        let subgraph_id = subgraph_id.lock().unwrap().clone().unwrap();
+                        let (subgraph_id, protocol, api_key) =
+                            subgraph_id.lock().unwrap().clone().unwrap();
 
                         // Spawn a GraphQL over WebSocket connection
                         let service = GraphQlConnection::new(
@@ -190,6 +250,8 @@ where
                             subgraph_id,
                             ws_stream,
                             graphql_runner.clone(),
+                            protocol,
+                            api_key,
                         );
 
                         graph::spawn_allow_panic(service.into_future().compat());
@@ -0,0 +1,3 @@
+alter table subgraphs.subgraph_deployment
+      add column history_blocks int4,
+      add column earliest_block_number int4 not null default 0;
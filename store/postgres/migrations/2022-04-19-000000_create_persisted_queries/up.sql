@@ -0,0 +1,8 @@
+create table if not exists public.persisted_queries(
+       hash       text primary key,
+       query      text not null,
+       created_at timestamptz not null default now()
+);
+
+create index if not exists persisted_queries_created_at
+    on public.persisted_queries(created_at);
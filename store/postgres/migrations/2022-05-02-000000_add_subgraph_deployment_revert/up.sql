@@ -0,0 +1,7 @@
+create table if not exists subgraphs.subgraph_deployment_revert(
+       deployment   int4 not null
+                    references subgraphs.subgraph_deployment(id) on delete cascade,
+       reorg_count  int4 not null,
+       block_number int4 not null,
+       primary key(deployment, reorg_count)
+);
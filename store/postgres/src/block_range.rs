@@ -92,29 +92,55 @@ impl ToSql<Range<Integer>, Pg> for BlockRange {
     }
 }
 
+/// Matches rows whose block range starts at a block between `start` and
+/// `end`, both inclusive. For immutable entities, the block range starts at
+/// the block in their `block$` column
 #[derive(Constructor)]
 pub struct BlockRangeLowerBoundClause<'a> {
-    _table_prefix: &'a str,
-    block: BlockNumber,
+    table: &'a Table,
+    table_prefix: &'a str,
+    start: BlockNumber,
+    end: BlockNumber,
+}
+
+impl<'a> BlockRangeLowerBoundClause<'a> {
+    /// Output the block at which the block range of a row starts
+    pub fn lower_bound(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        if self.table.immutable {
+            out.push_sql(self.table_prefix);
+            out.push_identifier(BLOCK_COLUMN)
+        } else {
+            out.push_sql("lower(");
+            out.push_sql(self.table_prefix);
+            out.push_identifier(BLOCK_RANGE_COLUMN)?;
+            out.push_sql(")");
+            Ok(())
+        }
+    }
 }
 
 impl<'a> QueryFragment<Pg> for BlockRangeLowerBoundClause<'a> {
     fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
         out.unsafe_to_cache_prepared();
 
-        out.push_sql("lower(");
-        out.push_identifier(BLOCK_RANGE_COLUMN)?;
-        out.push_sql(") = ");
-        out.push_bind_param::<Integer, _>(&self.block)?;
+        self.lower_bound(&mut out)?;
+        out.push_sql(" between ");
+        out.push_bind_param::<Integer, _>(&self.start)?;
+        out.push_sql(" and ");
+        out.push_bind_param::<Integer, _>(&self.end)?;
 
         Ok(())
     }
 }
 
+/// Matches rows whose block range ends at a block between `start` and
+/// `end`, both inclusive. Immutable entities have no such column since
+/// their block range never ends
 #[derive(Constructor)]
 pub struct BlockRangeUpperBoundClause<'a> {
     _table_prefix: &'a str,
-    block: BlockNumber,
+    start: BlockNumber,
+    end: BlockNumber,
 }
 
 impl<'a> QueryFragment<Pg> for BlockRangeUpperBoundClause<'a> {
@@ -123,8 +149,10 @@ impl<'a> QueryFragment<Pg> for BlockRangeUpperBoundClause<'a> {
 
         out.push_sql("coalesce(upper(");
         out.push_identifier(BLOCK_RANGE_COLUMN)?;
-        out.push_sql("), 2147483647) = ");
-        out.push_bind_param::<Integer, _>(&self.block)?;
+        out.push_sql("), 2147483647) between ");
+        out.push_bind_param::<Integer, _>(&self.start)?;
+        out.push_sql(" and ");
+        out.push_bind_param::<Integer, _>(&self.end)?;
 
         Ok(())
     }
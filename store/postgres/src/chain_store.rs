@@ -51,7 +51,7 @@ mod data {
     use diesel::dsl::sql;
     use diesel::pg::{Pg, PgConnection};
     use diesel::serialize::Output;
-    use diesel::sql_types::{BigInt, Binary, Bytea, Integer, Jsonb, Text};
+    use diesel::sql_types::{BigInt, Binary, Bool, Bytea, Integer, Jsonb, Text};
     use diesel::types::{FromSql, ToSql};
     use diesel::{delete, insert_into, prelude::*, sql_query, update};
     use diesel_dynamic_schema as dds;
@@ -122,6 +122,76 @@ mod data {
         hash: Vec<u8>,
     }
 
+    // Helpers for literal SQL queries that look up a block pointer
+    #[derive(QueryableByName)]
+    struct BlockPtrText {
+        #[sql_type = "Text"]
+        hash: String,
+        #[sql_type = "BigInt"]
+        number: i64,
+    }
+
+    #[derive(QueryableByName)]
+    struct BlockPtrBytea {
+        #[sql_type = "Bytea"]
+        hash: Vec<u8>,
+        #[sql_type = "BigInt"]
+        number: i64,
+    }
+
+    // Helpers for the literal SQL query in `walk_to_timestamp`
+    #[derive(QueryableByName)]
+    struct WalkEndText {
+        #[sql_type = "Text"]
+        hash: String,
+        #[sql_type = "BigInt"]
+        number: i64,
+        #[sql_type = "BigInt"]
+        depth: i64,
+        #[sql_type = "Bool"]
+        found: bool,
+    }
+
+    #[derive(QueryableByName)]
+    struct WalkEndBytea {
+        #[sql_type = "Bytea"]
+        hash: Vec<u8>,
+        #[sql_type = "BigInt"]
+        number: i64,
+        #[sql_type = "BigInt"]
+        depth: i64,
+        #[sql_type = "Bool"]
+        found: bool,
+    }
+
+    /// Where walking back from a block in search of a timestamp ended
+    enum WalkEnd {
+        /// The block we were looking for
+        Found(BlockPtr),
+        /// A block whose parent is not in the cache, or the block we
+        /// started from is not in the cache
+        Gap,
+        /// The walk went as far back as it was allowed to without finding
+        /// the block; this is the last block it reached
+        Final(BlockPtr),
+    }
+
+    /// How many blocks `block_ptr_by_timestamp` walks back from the chain
+    /// head to make sure it only returns canonical blocks. Blocks further
+    /// back are considered final; this is the default reorg threshold
+    const TIMESTAMP_WALK_DEPTH: i64 = 250;
+
+    /// The timestamp of a block in seconds since the Unix epoch. Block
+    /// timestamps are stored in the block JSON as hex strings, either in a
+    /// toplevel 'block' field or directly in the data. There is an index on
+    /// this expression for the block tables, and queries must use it
+    /// exactly as written here to benefit from that index
+    ///
+    /// see also 7736e440-4c6b-11ec-8c4d-b42e99f52061
+    pub(crate) const BLOCK_TIMESTAMP_EXPR: &str =
+        "('x' || lpad(ltrim(coalesce(data->'block'->>'timestamp', \
+                                  data->>'timestamp'), '0x'), 16, '0'))::bit(64)::bigint";
+
     // Like H256::from_slice, but returns an error instead of panicking
     // when `bytes` does not have the right length
     fn h256_from_bytes(bytes: &[u8]) -> Result<H256, StoreError> {
@@ -333,6 +403,7 @@ mod data {
                   data         jsonb not null
                 );
                 create index blocks_number ON {nsp}.blocks using btree(number);
+                create index blocks_timestamp ON {nsp}.blocks using btree(({ts}));
 
                 create table {nsp}.call_cache (
 	              id               bytea not null primary key,
@@ -347,7 +418,8 @@ mod data {
                     accessed_at      date  not null
                 );
             ",
-                    nsp = nsp
+                    nsp = nsp,
+                    ts = BLOCK_TIMESTAMP_EXPR
                 )
             }
 
@@ -360,6 +432,48 @@ mod data {
             }
         }
 
+        /// Create the index on block timestamps for blocks tables that were
+        /// created before we indexed timestamps. The index is built
+        /// concurrently so that writes to the table can continue, which is
+        /// why `conn` must not be in a transaction
+        pub(super) fn create_timestamp_index(&self, conn: &PgConnection) -> Result<(), Error> {
+            let (nsp, index, ddl) = match self {
+                Storage::Shared => (
+                    Self::PUBLIC,
+                    "ethereum_blocks_timestamp",
+                    format!(
+                        "create index concurrently if not exists ethereum_blocks_timestamp \
+                             on ethereum_blocks(network_name, ({}))",
+                        BLOCK_TIMESTAMP_EXPR
+                    ),
+                ),
+                Storage::Private(Schema { name, .. }) => (
+                    name.as_str(),
+                    "blocks_timestamp",
+                    format!(
+                        "create index concurrently if not exists blocks_timestamp \
+                             on {}.blocks using btree(({}))",
+                        name, BLOCK_TIMESTAMP_EXPR
+                    ),
+                ),
+            };
+            conn.batch_execute(&ddl)?;
+            // A failed concurrent build leaves an invalid index behind that
+            // we need to remove so that the build can be retried
+            if !crate::catalog::check_index_is_valid(conn, nsp, index)? {
+                conn.batch_execute(&format!(
+                    "drop index concurrently if exists {}.{}",
+                    nsp, index
+                ))?;
+                return Err(anyhow::anyhow!(
+                    "building the index {}.{} failed",
+                    nsp,
+                    index
+                ));
+            }
+            Ok(())
+        }
+
         /// Returns a fully qualified table name to the blocks table
         #[inline]
         fn blocks_table(&self) -> &str {
@@ -612,6 +726,194 @@ mod data {
                 .transpose()
         }
 
+        /// Find the block with the highest number on the chain that ends in
+        /// `head` whose timestamp is at or before `timestamp`, given in
+        /// seconds since the Unix epoch. Only blocks that are in the block
+        /// cache are considered.
+        ///
+        /// We walk back at most `TIMESTAMP_WALK_DEPTH` blocks from `head` so
+        /// that recent blocks that are not on the canonical chain are never
+        /// returned; if the walk hits a block whose parent is not in the
+        /// cache before it reaches the block we are looking for, we can not
+        /// tell which block that is and return `None`. Blocks below that
+        /// are final, and we look them up with the timestamp index instead
+        /// of walking the entire cache
+        pub(super) fn block_ptr_by_timestamp(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            head: &BlockPtr,
+            timestamp: u64,
+        ) -> Result<Option<BlockPtr>, StoreError> {
+            let timestamp = i64::try_from(timestamp)
+                .map_err(|e| StoreError::QueryExecutionError(e.to_string()))?;
+
+            let ptr = match self.walk_to_timestamp(conn, head, timestamp, TIMESTAMP_WALK_DEPTH)? {
+                WalkEnd::Found(ptr) => Some(ptr),
+                WalkEnd::Gap => None,
+                WalkEnd::Final(floor) => {
+                    let candidates =
+                        self.final_blocks_by_timestamp(conn, chain, &floor, timestamp)?;
+                    match candidates.as_slice() {
+                        [] => None,
+                        [ptr] => Some(ptr.clone()),
+                        // Several blocks at the same number are still in the
+                        // cache; only walking the chain can tell which one
+                        // of them is canonical
+                        _ => match self.walk_to_timestamp(conn, &floor, timestamp, i64::MAX)? {
+                            WalkEnd::Found(ptr) => Some(ptr),
+                            WalkEnd::Gap | WalkEnd::Final(_) => None,
+                        },
+                    }
+                }
+            };
+            Ok(ptr)
+        }
+
+        /// Walk back at most `depth` blocks from `start` until we find a block
+        /// whose timestamp is at or before `timestamp`
+        fn walk_to_timestamp(
+            &self,
+            conn: &PgConnection,
+            start: &BlockPtr,
+            timestamp: i64,
+            depth: i64,
+        ) -> Result<WalkEnd, StoreError> {
+            // Returns the last block that the walk reached, or nothing if
+            // `start` is not in the cache
+            const WALK_SQL: &str = "
+        with recursive ancestors(hash, number, parent_hash, timestamp, depth) as (
+            select hash, number, parent_hash, {ts}, 0::int8
+              from {blocks}
+             where hash = $1
+            union all
+            select b.hash, b.number, b.parent_hash, {ts}, a.depth + 1
+              from ancestors a, {blocks} b
+             where b.hash = a.parent_hash
+               and a.timestamp > $2
+               and a.depth < $3
+        )
+        select a.hash, a.number, a.depth, a.timestamp <= $2 as found
+          from ancestors a
+         order by a.depth desc
+         limit 1";
+
+            let end = match self {
+                Storage::Shared => {
+                    let query = WALK_SQL
+                        .replace("{ts}", BLOCK_TIMESTAMP_EXPR)
+                        .replace("{blocks}", "ethereum_blocks");
+                    sql_query(query)
+                        .bind::<Text, _>(start.hash_hex())
+                        .bind::<BigInt, _>(timestamp)
+                        .bind::<BigInt, _>(depth)
+                        .get_result::<WalkEndText>(conn)
+                        .optional()?
+                        .map(|end| {
+                            let hash = end.hash.parse::<H256>().map_err(|e| {
+                                constraint_violation!("invalid block hash `{}`: {}", end.hash, e)
+                            })?;
+                            Ok::<_, StoreError>((hash, end.number, end.depth, end.found))
+                        })
+                        .transpose()?
+                }
+                Storage::Private(Schema { blocks, .. }) => {
+                    let query = WALK_SQL
+                        .replace("{ts}", BLOCK_TIMESTAMP_EXPR)
+                        .replace("{blocks}", blocks.qname.as_str());
+                    sql_query(query)
+                        .bind::<Bytea, _>(start.hash_slice())
+                        .bind::<BigInt, _>(timestamp)
+                        .bind::<BigInt, _>(depth)
+                        .get_result::<WalkEndBytea>(conn)
+                        .optional()?
+                        .map(|end| {
+                            h256_from_bytes(&end.hash)
+                                .map(|hash| (hash, end.number, end.depth, end.found))
+                        })
+                        .transpose()?
+                }
+            };
+            Ok(match end {
+                None => WalkEnd::Gap,
+                Some((hash, number, _, true)) => WalkEnd::Found(BlockPtr::from((hash, number))),
+                Some((hash, number, end_depth, false)) if end_depth == depth => {
+                    WalkEnd::Final(BlockPtr::from((hash, number)))
+                }
+                Some(_) => WalkEnd::Gap,
+            })
+        }
+
+        /// Find all blocks below `floor` that have the number of the block
+        /// with the latest timestamp at or before `timestamp`. This uses the
+        /// timestamp index and therefore never scans the entire cache
+        fn final_blocks_by_timestamp(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            floor: &BlockPtr,
+            timestamp: i64,
+        ) -> Result<Vec<BlockPtr>, StoreError> {
+            match self {
+                Storage::Shared => {
+                    let query = format!(
+                        "
+        select hash, number
+          from ethereum_blocks
+         where network_name = $1
+           and number = (select number
+                           from ethereum_blocks
+                          where network_name = $1
+                            and {ts} <= $2
+                            and number < $3
+                          order by {ts} desc, number desc
+                          limit 1)",
+                        ts = BLOCK_TIMESTAMP_EXPR
+                    );
+                    sql_query(query)
+                        .bind::<Text, _>(chain)
+                        .bind::<BigInt, _>(timestamp)
+                        .bind::<BigInt, _>(floor.number as i64)
+                        .load::<BlockPtrText>(conn)?
+                        .into_iter()
+                        .map(|ptr| {
+                            let hash = ptr.hash.parse::<H256>().map_err(|e| {
+                                constraint_violation!("invalid block hash `{}`: {}", ptr.hash, e)
+                            })?;
+                            Ok(BlockPtr::from((hash, ptr.number)))
+                        })
+                        .collect()
+                }
+                Storage::Private(Schema { blocks, .. }) => {
+                    // Same as for `Storage::Shared` except for the table
+                    // name and the type of hashes
+                    let query = format!(
+                        "
+        select hash, number
+          from {blocks}
+         where number = (select number
+                           from {blocks}
+                          where {ts} <= $1
+                            and number < $2
+                          order by {ts} desc, number desc
+                          limit 1)",
+                        ts = BLOCK_TIMESTAMP_EXPR,
+                        blocks = blocks.qname
+                    );
+                    sql_query(query)
+                        .bind::<BigInt, _>(timestamp)
+                        .bind::<BigInt, _>(floor.number as i64)
+                        .load::<BlockPtrBytea>(conn)?
+                        .into_iter()
+                        .map(|ptr| {
+                            h256_from_bytes(&ptr.hash)
+                                .map(|hash| BlockPtr::from((hash, ptr.number)))
+                        })
+                        .collect()
+                }
+            }
+        }
+
         /// Find the first block that is missing from the database needed to
         /// complete the chain from block `hash` to the block with number
         /// `first_block`.
@@ -1266,6 +1568,13 @@ impl ChainStore {
         Ok(())
     }
 
+    /// Index the timestamps of the blocks of this chain if that has not
+    /// been done yet. This can take a long time for large block caches
+    pub fn create_timestamp_index(&self) -> Result<(), Error> {
+        let conn = self.get_conn()?;
+        self.storage.create_timestamp_index(&conn)
+    }
+
     pub(crate) fn drop_chain(&self) -> Result<(), Error> {
         use diesel::dsl::delete;
         use public::ethereum_networks as n;
@@ -1635,6 +1944,23 @@ impl ChainStoreTrait for ChainStore {
             .map(|number| (self.chain.clone(), number)))
     }
 
+    fn block_ptr_by_timestamp(&self, timestamp: u64) -> Result<Option<BlockPtr>, StoreError> {
+        use public::ethereum_networks as n;
+
+        let conn = self.get_conn()?;
+        let head = n::table
+            .select((n::head_block_hash, n::head_block_number))
+            .filter(n::name.eq(&self.chain))
+            .first::<(Option<String>, Option<i64>)>(&conn)
+            .optional()?;
+        let head = match head {
+            Some((Some(hash), Some(number))) => BlockPtr::try_from((hash.as_str(), number))?,
+            _ => return Ok(None),
+        };
+        self.storage
+            .block_ptr_by_timestamp(&conn, &self.chain, &head, timestamp)
+    }
+
     async fn transaction_receipts_in_block(
         &self,
         block_hash: &H256,
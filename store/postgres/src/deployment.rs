@@ -30,7 +30,10 @@ use std::{collections::BTreeSet, convert::TryFrom, ops::Bound};
 use std::{str::FromStr, sync::Arc};
 
 use crate::connection_pool::ForeignServer;
-use crate::{block_range::BLOCK_RANGE_COLUMN, primary::Site};
+use crate::{
+    block_range::BLOCK_RANGE_COLUMN,
+    primary::{DeploymentId, Site},
+};
 use graph::constraint_violation;
 
 #[derive(DbEnum, Debug, Clone, Copy)]
@@ -85,6 +88,18 @@ table! {
         current_reorg_depth -> Integer,
         max_reorg_depth -> Integer,
         firehose_cursor -> Nullable<Text>,
+        history_blocks -> Nullable<Integer>,
+        earliest_block_number -> Integer,
+    }
+}
+
+table! {
+    /// The block to which each revert of a deployment reverted it. The
+    /// `reorg_count` is the one the deployment had after the revert
+    subgraphs.subgraph_deployment_revert (deployment, reorg_count) {
+        deployment -> Integer,
+        reorg_count -> Integer,
+        block_number -> Integer,
     }
 }
 
@@ -220,6 +235,81 @@ pub fn debug_fork(
     }
 }
 
+/// Look up how many blocks of history the deployment should keep. Returns
+/// `None` if the deployment keeps its entire history
+pub fn history_blocks(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+) -> Result<Option<BlockNumber>, StoreError> {
+    use subgraph_deployment as d;
+
+    d::table
+        .select(d::history_blocks)
+        .filter(d::deployment.eq(id.as_str()))
+        .first::<Option<i32>>(conn)
+        .map_err(|e| e.into())
+}
+
+pub fn set_history_blocks(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+    history_blocks: Option<BlockNumber>,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(d::table.filter(d::deployment.eq(id.as_str())))
+        .set(d::history_blocks.eq(history_blocks))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|e| e.into())
+}
+
+/// Return the deployments that should only keep a limited number of
+/// blocks of history, together with that number
+pub fn deployments_with_history_blocks(
+    conn: &PgConnection,
+) -> Result<Vec<(DeploymentId, BlockNumber)>, StoreError> {
+    use subgraph_deployment as d;
+
+    d::table
+        .select((d::id, d::history_blocks))
+        .filter(d::history_blocks.is_not_null())
+        .load::<(DeploymentId, Option<i32>)>(conn)
+        .map(|rows| {
+            rows.into_iter()
+                .filter_map(|(id, history_blocks)| history_blocks.map(|blocks| (id, blocks)))
+                .collect()
+        })
+        .map_err(|e| e.into())
+}
+
+/// Return the number of the earliest block for which the deployment still
+/// has data. Versions of entities that were only visible at blocks before
+/// that have been pruned
+pub fn earliest_block(conn: &PgConnection, id: &DeploymentHash) -> Result<BlockNumber, StoreError> {
+    use subgraph_deployment as d;
+
+    d::table
+        .select(d::earliest_block_number)
+        .filter(d::deployment.eq(id.as_str()))
+        .first::<i32>(conn)
+        .map_err(|e| e.into())
+}
+
+pub fn set_earliest_block(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+    earliest_block: BlockNumber,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(d::table.filter(d::deployment.eq(id.as_str())))
+        .set(d::earliest_block_number.eq(earliest_block))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|e| e.into())
+}
+
 pub fn schema(conn: &PgConnection, site: &Site) -> Result<(Schema, bool), StoreError> {
     use subgraph_manifest as sm;
     let (s, use_bytea_prefix) = sm::table
@@ -430,7 +520,7 @@ pub fn revert_block_ptr(
     // Work around a Diesel issue with serializing BigDecimals to numeric
     let number = format!("{}::numeric", ptr.number);
 
-    update(d::table.filter(d::deployment.eq(id.as_str())))
+    let (deployment, reorg_count) = update(d::table.filter(d::deployment.eq(id.as_str())))
         .set((
             d::latest_ethereum_block_number.eq(sql(&number)),
             d::latest_ethereum_block_hash.eq(ptr.hash_slice()),
@@ -438,11 +528,50 @@ pub fn revert_block_ptr(
             d::current_reorg_depth.eq(d::current_reorg_depth + 1),
             d::max_reorg_depth.eq(sql("greatest(current_reorg_depth + 1, max_reorg_depth)")),
         ))
+        .returning((d::id, d::reorg_count))
+        .get_result::<(i32, i32)>(conn)?;
+
+    // Remember where this revert went so that readers that only know the
+    // `reorg_count` can tell which blocks were reverted
+    use subgraph_deployment_revert as r;
+    insert_into(r::table)
+        .values((
+            r::deployment.eq(deployment),
+            r::reorg_count.eq(reorg_count),
+            r::block_number.eq(ptr.number),
+        ))
+        .on_conflict((r::deployment, r::reorg_count))
+        .do_update()
+        .set(r::block_number.eq(ptr.number))
         .execute(conn)
         .map(|_| ())
         .map_err(|e| e.into())
 }
 
+/// Return the blocks to which the deployment `id` was reverted by the
+/// reverts that happened after its `reorg_count` was `reorg_count`, one
+/// entry for each revert that is known. Reverts that happened before
+/// reverts were recorded are missing from the result
+pub fn reverts_since(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+    reorg_count: u32,
+) -> Result<Vec<BlockNumber>, StoreError> {
+    use subgraph_deployment as d;
+    use subgraph_deployment_revert as r;
+
+    let deployment = d::table
+        .filter(d::deployment.eq(id.as_str()))
+        .select(d::id)
+        .first::<i32>(conn)?;
+    r::table
+        .filter(r::deployment.eq(deployment))
+        .filter(r::reorg_count.gt(reorg_count as i32))
+        .select(r::block_number)
+        .load::<BlockNumber>(conn)
+        .map_err(|e| e.into())
+}
+
 pub fn block_ptr(conn: &PgConnection, id: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError> {
     use subgraph_deployment as d;
 
@@ -541,15 +670,22 @@ pub fn state(conn: &PgConnection, id: DeploymentHash) -> Result<DeploymentState,
             d::reorg_count,
             d::max_reorg_depth,
             d::latest_ethereum_block_number,
+            d::earliest_block_number,
         ))
-        .first::<(String, i32, i32, Option<BigDecimal>)>(conn)
+        .first::<(String, i32, i32, Option<BigDecimal>, i32)>(conn)
         .optional()?
     {
         None => Err(StoreError::QueryExecutionError(format!(
             "No data found for subgraph {}",
             id
         ))),
-        Some((_, reorg_count, max_reorg_depth, latest_ethereum_block_number)) => {
+        Some((
+            _,
+            reorg_count,
+            max_reorg_depth,
+            latest_ethereum_block_number,
+            earliest_block_number,
+        )) => {
             let reorg_count = convert_to_u32(Some(reorg_count), "reorg_count", id.as_str())?;
             let max_reorg_depth =
                 convert_to_u32(Some(max_reorg_depth), "max_reorg_depth", id.as_str())?;
@@ -561,6 +697,7 @@ pub fn state(conn: &PgConnection, id: DeploymentHash) -> Result<DeploymentState,
                 reorg_count,
                 max_reorg_depth,
                 latest_ethereum_block_number,
+                earliest_block_number,
             })
         }
     }
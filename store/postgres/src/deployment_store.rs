@@ -4,7 +4,9 @@ use diesel::connection::SimpleConnection;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
-use graph::components::store::{EntityType, StoredDynamicDataSource};
+use graph::components::store::{
+    DerivedEntityQuery, EntityAggregate, EntityType, StoredDynamicDataSource,
+};
 use graph::data::subgraph::status;
 use graph::prelude::{
     tokio, CancelHandle, CancelToken, CancelableError, EntityOperation, PoolWaitStats,
@@ -38,10 +40,13 @@ use crate::block_range::block_number;
 use crate::catalog;
 use crate::deployment;
 use crate::detail::ErrorDetail;
-use crate::relational::{Layout, LayoutCache, SqlName, Table};
+use crate::relational::{Column, Layout, LayoutCache, SqlName, Table};
 use crate::relational_queries::FromEntityData;
 use crate::{connection_pool::ConnectionPool, detail};
-use crate::{dynds, primary::Site};
+use crate::{
+    dynds,
+    primary::{DeploymentId, Site},
+};
 
 /// When connected to read replicas, this allows choosing which DB server to use for an operation.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -238,6 +243,36 @@ impl DeploymentStore {
             query.range,
             query.block,
             query.query_id,
+            query.explain.as_ref(),
+        )
+    }
+
+    pub(crate) fn execute_aggregate_query(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        query: EntityQuery,
+        aggregates: &[EntityAggregate],
+    ) -> Result<Vec<Value>, QueryExecutionError> {
+        let layout = self.layout(conn, site)?;
+
+        let entity_type = match &query.collection {
+            EntityCollection::All(entity_types) if entity_types.len() == 1 => &entity_types[0].0,
+            _ => {
+                return Err(constraint_violation!(
+                    "aggregates can only be computed over a single entity type"
+                )
+                .into())
+            }
+        };
+        layout.aggregate(
+            conn,
+            entity_type,
+            query.filter,
+            aggregates,
+            query.block,
+            query.query_id,
+            query.explain.as_ref(),
         )
     }
 
@@ -691,7 +726,22 @@ impl DeploymentStore {
             let schema_name = site.namespace.clone();
             let layout = store.layout(conn, site)?;
             let table = resolve_table_name(&layout, &entity_name)?;
-            let column_names = resolve_column_names(table, &field_names)?;
+            let columns = resolve_columns(table, &field_names)?;
+            // Without the `btree_gin` extension, Postgres can only build
+            // GIN indexes on arrays and `tsvector`s. Those indexes speed up
+            // the list filters `contains`, `contains_any` and
+            // `contains_all`, and fulltext search
+            if index_method == "gin" {
+                if let Some(column) = columns.iter().find(|c| !c.is_list() && !c.is_fulltext()) {
+                    return Err(StoreError::Unknown(anyhow!(
+                        "a gin index can only be created on list or fulltext fields, \
+                         but `{}` is neither",
+                        column.field
+                    ))
+                    .into());
+                }
+            }
+            let column_names: Vec<_> = columns.iter().map(|c| c.name.as_str()).collect();
             let column_names_sep_by_underscores = column_names.join("_");
             let column_names_sep_by_commas = column_names.join(", ");
             let table_name = &table.name;
@@ -924,6 +974,19 @@ impl DeploymentStore {
         layout.find_many(&conn, ids_for_type, block)
     }
 
+    /// Find all the entities matching `query` in the deployment `site`.
+    /// Only consider entities as of the given `block`
+    pub(crate) fn get_derived(
+        &self,
+        site: Arc<Site>,
+        query: &DerivedEntityQuery,
+        block: BlockNumber,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        let conn = self.get_conn()?;
+        let layout = self.layout(&conn, site)?;
+        layout.find_derived(&conn, query, block)
+    }
+
     pub(crate) fn get_changes(
         &self,
         site: Arc<Site>,
@@ -936,6 +999,26 @@ impl DeploymentStore {
         Ok(changes)
     }
 
+    pub(crate) fn get_changes_in_range(
+        &self,
+        site: Arc<Site>,
+        start: BlockNumber,
+        end: BlockNumber,
+    ) -> Result<BTreeMap<BlockNumber, Vec<EntityOperation>>, StoreError> {
+        let conn = self.get_conn()?;
+        let layout = self.layout(&conn, site)?;
+        layout.find_changes_in_range(&conn, start, end)
+    }
+
+    pub(crate) fn reverts_since(
+        &self,
+        site: &Site,
+        reorg_count: u32,
+    ) -> Result<Vec<BlockNumber>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::reverts_since(&conn, &site.deployment, reorg_count)
+    }
+
     // Only used by tests
     #[cfg(debug_assertions)]
     pub(crate) fn find(
@@ -975,22 +1058,37 @@ impl DeploymentStore {
         };
 
         let event = conn.transaction(|| -> Result<_, StoreError> {
+            let layout = self.layout(&conn, site.clone())?;
+
+            // Compute how the rollups for `@aggregation` types change
+            let section = stopwatch.start_section("rollup_aggregations");
+            let rollups = layout.rollup(&conn, mods)?;
+            section.end();
+
             // Emit a store event for the changes we are about to make. We
             // wait with sending it until we have done all our other work
             // so that we do not hold a lock on the notification queue
             // for longer than we have to
-            let event: StoreEvent = mods.iter().collect();
+            let event: StoreEvent = mods.iter().chain(rollups.iter()).collect();
 
             // Make the changes
-            let layout = self.layout(&conn, site.clone())?;
             let section = stopwatch.start_section("apply_entity_modifications");
-            let count = self.apply_entity_modifications(
+            let mut count = self.apply_entity_modifications(
                 &conn,
                 layout.as_ref(),
                 mods,
                 block_ptr_to,
                 stopwatch,
             )?;
+            if !rollups.is_empty() {
+                count += self.apply_entity_modifications(
+                    &conn,
+                    layout.as_ref(),
+                    &rollups,
+                    block_ptr_to,
+                    stopwatch,
+                )?;
+            }
             section.end();
 
             dynds::insert(&conn, &site.deployment, data_sources, block_ptr_to)?;
@@ -1027,6 +1125,19 @@ impl DeploymentStore {
         firehose_cursor: Option<&str>,
     ) -> Result<StoreEvent, StoreError> {
         let event = conn.transaction(|| -> Result<_, StoreError> {
+            // Don't revert into history that has been pruned
+            let earliest_block = deployment::earliest_block(conn, &site.deployment)?;
+            if block_ptr_to.number < earliest_block {
+                return Err(anyhow!(
+                    "Can not revert subgraph `{}` to block {} as its history \
+                    before block {} has been pruned",
+                    site.deployment.clone(),
+                    block_ptr_to.number,
+                    earliest_block
+                )
+                .into());
+            }
+
             // Don't revert past a graft point
             let info = self.subgraph_info_with_conn(conn, site.as_ref())?;
             if let Some(graft_block) = info.graft_block {
@@ -1123,6 +1234,82 @@ impl DeploymentStore {
         self.rewind_with_conn(&conn, site, block_ptr_to, firehose_cursor)
     }
 
+    /// Set how many blocks of history the deployment should keep when it
+    /// is pruned automatically; `None` keeps the entire history
+    pub(crate) fn set_history_blocks(
+        &self,
+        site: &Site,
+        history_blocks: Option<BlockNumber>,
+    ) -> Result<(), StoreError> {
+        if let Some(history_blocks) = history_blocks {
+            Self::check_history_blocks(history_blocks)?;
+        }
+        let conn = self.get_conn()?;
+        deployment::set_history_blocks(&conn, &site.deployment, history_blocks)
+    }
+
+    pub(crate) fn history_blocks(&self, site: &Site) -> Result<Option<BlockNumber>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::history_blocks(&conn, &site.deployment)
+    }
+
+    pub(crate) fn deployments_with_history_blocks(
+        &self,
+    ) -> Result<Vec<(DeploymentId, BlockNumber)>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::deployments_with_history_blocks(&conn)
+    }
+
+    fn check_history_blocks(history_blocks: BlockNumber) -> Result<(), StoreError> {
+        if history_blocks < ENV_VARS.store.history_min_blocks {
+            return Err(anyhow!(
+                "deployments must keep at least {} blocks of history but {} were requested",
+                ENV_VARS.store.history_min_blocks,
+                history_blocks
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Remove all entity versions that are only visible at blocks that are
+    /// more than `history_blocks` blocks behind the deployment head. The
+    /// earliest block that the deployment has data for is recorded before
+    /// any data is removed so that queries for older blocks fail instead
+    /// of returning incomplete data. Returns the new earliest block and the
+    /// number of entity versions that were removed
+    pub(crate) fn prune(
+        &self,
+        site: Arc<Site>,
+        history_blocks: BlockNumber,
+    ) -> Result<(BlockNumber, usize), StoreError> {
+        Self::check_history_blocks(history_blocks)?;
+
+        let conn = self.get_conn()?;
+        let current = deployment::earliest_block(&conn, &site.deployment)?;
+        let head = match Self::block_ptr_with_conn(&conn, site.cheap_clone())? {
+            Some(head) => head,
+            None => return Ok((current, 0)),
+        };
+        let earliest_block = head.number - history_blocks;
+        if earliest_block <= current {
+            return Ok((current, 0));
+        }
+
+        let layout = self.layout(&conn, site.cheap_clone())?;
+        let removed = conn.transaction(|| {
+            deployment::set_earliest_block(&conn, &site.deployment, earliest_block)?;
+            layout.prune(&conn, earliest_block)
+        })?;
+        Ok((earliest_block, removed))
+    }
+
+    /// The earliest block for which the deployment still has data
+    pub(crate) fn earliest_block(&self, site: &Site) -> Result<BlockNumber, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::earliest_block(&conn, &site.deployment)
+    }
+
     pub(crate) async fn deployment_state_from_id(
         &self,
         id: DeploymentHash,
@@ -1197,7 +1384,10 @@ impl DeploymentStore {
     /// Bring the subgraph into a state where we can start or resume
     /// indexing.
     ///
-    /// If `graft_src` is `Some(..)`, copy data from that subgraph. It
+    /// If `graft_src` is `Some(..)`, copy data from that subgraph up to the
+    /// given block; the last element is the earliest block of the source,
+    /// which becomes the earliest block of the copy since the copy has no
+    /// more history than the source. It
     /// should only be `Some(..)` if we know we still need to copy data. The
     /// code is idempotent so that a copy process that has been interrupted
     /// can be resumed seamlessly, but the code sets the block pointer back
@@ -1208,12 +1398,22 @@ impl DeploymentStore {
         &self,
         logger: &Logger,
         site: Arc<Site>,
-        graft_src: Option<(Arc<Layout>, BlockPtr)>,
+        graft_src: Option<(Arc<Layout>, BlockPtr, BlockNumber)>,
     ) -> Result<(), StoreError> {
         let dst = self.find_layout(site)?;
 
         // Do any cleanup to bring the subgraph into a known good state
-        if let Some((src, block)) = graft_src {
+        if let Some((src, block, earliest_block)) = graft_src {
+            if block.number < earliest_block {
+                return Err(StoreError::Unknown(anyhow!(
+                    "can not graft onto block {} of `{}` since its history \
+                     before block {} has been pruned",
+                    block.number,
+                    src.site.deployment,
+                    earliest_block
+                )));
+            }
+
             info!(
                 logger,
                 "Initializing graft by copying data from {} to {}",
@@ -1268,6 +1468,8 @@ impl DeploymentStore {
                 info!(logger, "Rewound subgraph to block {}", block.number;
                       "time_ms" => start.elapsed().as_millis());
 
+                deployment::set_earliest_block(&conn, &dst.site.deployment, earliest_block)?;
+
                 let start = Instant::now();
                 deployment::set_entity_count(&conn, &dst.site, &dst.count_query)?;
                 info!(logger, "Counted the entities";
@@ -1501,26 +1703,23 @@ fn resolve_table_name<'a>(layout: &'a Layout, name: &'_ str) -> Result<&'a Table
         })
 }
 
-// Resolves column names.
+// Resolves the columns for the given field names.
 //
 // Since we allow our input to be either camel-case or snake-case, we must retry the
 // search using the latter if the search for the former fails.
-fn resolve_column_names<'a, T: AsRef<str>>(
+fn resolve_columns<'a, T: AsRef<str>>(
     table: &'a Table,
     field_names: &[T],
-) -> Result<Vec<&'a str>, StoreError> {
+) -> Result<Vec<&'a Column>, StoreError> {
     field_names
         .iter()
         .map(|f| {
-            table
-                .column_for_field(f.as_ref())
-                .or_else(|_error| {
-                    let sql_name = SqlName::from(f.as_ref());
-                    table
-                        .column(&sql_name)
-                        .ok_or_else(|| StoreError::UnknownField(f.as_ref().to_string()))
-                })
-                .map(|column| column.name.as_str())
+            table.column_for_field(f.as_ref()).or_else(|_error| {
+                let sql_name = SqlName::from(f.as_ref());
+                table
+                    .column(&sql_name)
+                    .ok_or_else(|| StoreError::UnknownField(f.as_ref().to_string()))
+            })
         })
         .collect()
 }
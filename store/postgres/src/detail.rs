@@ -63,6 +63,8 @@ pub struct DeploymentDetail {
     current_reorg_depth: i32,
     max_reorg_depth: i32,
     firehose_cursor: Option<String>,
+    history_blocks: Option<i32>,
+    earliest_block_number: i32,
 }
 
 #[derive(Queryable, QueryableByName)]
@@ -6,7 +6,7 @@ use std::time::{Duration, Instant};
 use async_trait::async_trait;
 use diesel::{prelude::RunQueryDsl, sql_query, sql_types::Double};
 
-use graph::prelude::{error, Logger, MetricsRegistry, StoreError, ENV_VARS};
+use graph::prelude::{error, info, Logger, MetricsRegistry, StoreError, ENV_VARS};
 use graph::prometheus::Gauge;
 use graph::util::jobs::{Job, Runner};
 
@@ -38,7 +38,19 @@ pub fn register(
     runner.register(
         Arc::new(UnusedJob::new(store.subgraph_store())),
         Duration::from_secs(2 * 60 * 60),
-    )
+    );
+
+    runner.register(
+        Arc::new(PruneJob::new(store.subgraph_store())),
+        Duration::from_secs(60 * 60),
+    );
+
+    if ENV_VARS.graphql.persist_queries {
+        runner.register(
+            Arc::new(PersistedQueriesJob::new(store.subgraph_store())),
+            Duration::from_secs(10 * 60),
+        );
+    }
 }
 
 /// A job that vacuums `subgraphs.subgraph_deployment`. With a large number
@@ -207,3 +219,81 @@ impl Job for UnusedJob {
         }
     }
 }
+
+/// A job that prunes the history of all deployments that have a setting
+/// for how many blocks of history they should keep
+struct PruneJob {
+    store: Arc<SubgraphStore>,
+}
+
+impl PruneJob {
+    fn new(store: Arc<SubgraphStore>) -> PruneJob {
+        PruneJob { store }
+    }
+}
+
+#[async_trait]
+impl Job for PruneJob {
+    fn name(&self) -> &str {
+        "Prune the history of deployments"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        let deployments = match self.store.deployments_with_history_blocks() {
+            Ok(deployments) => deployments,
+            Err(e) => {
+                error!(logger, "failed to list deployments to prune"; "error" => e.to_string());
+                return;
+            }
+        };
+
+        for (site, history_blocks) in deployments {
+            let deployment = site.deployment.to_string();
+            match self.store.prune_site(site, history_blocks) {
+                Ok((_, 0)) => { /* nothing to prune */ }
+                Ok((earliest_block, removed)) => {
+                    info!(logger, "pruned deployment";
+                                  "deployment" => deployment,
+                                  "earliest_block" => earliest_block,
+                                  "removed" => removed);
+                }
+                Err(e) => {
+                    error!(logger, "failed to prune deployment";
+                                   "deployment" => deployment,
+                                   "error" => e.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// A job that removes the oldest automatic persisted queries from the
+/// primary when there are more than `GRAPH_GRAPHQL_MAX_PERSISTED_QUERIES`
+struct PersistedQueriesJob {
+    store: Arc<SubgraphStore>,
+}
+
+impl PersistedQueriesJob {
+    fn new(store: Arc<SubgraphStore>) -> PersistedQueriesJob {
+        PersistedQueriesJob { store }
+    }
+}
+
+#[async_trait]
+impl Job for PersistedQueriesJob {
+    fn name(&self) -> &str {
+        "Remove old persisted queries"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        match self.store.remove_old_persisted_queries() {
+            Ok(0) => { /* nothing to remove */ }
+            Ok(removed) => {
+                info!(logger, "removed old persisted queries"; "removed" => removed);
+            }
+            Err(e) => {
+                error!(logger, "failed to remove old persisted queries"; "error" => e.to_string());
+            }
+        }
+    }
+}
@@ -92,6 +92,7 @@ table! {
     subgraphs.subgraph_deployment_assignment {
         id -> Integer,
         node_id -> Text,
+        paused_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -114,6 +115,14 @@ table! {
     }
 }
 
+table! {
+    public.persisted_queries(hash) {
+        hash -> Text,
+        query -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 /// We used to support different layout schemes. The old 'Split' scheme
 /// which used JSONB layout has been removed, and we will only deal
 /// with relational layout. Trying to do anything with a 'Split' subgraph
@@ -575,10 +584,13 @@ mod queries {
         schema.map(|schema| schema.try_into()).transpose()
     }
 
+    /// Return the sites of all deployments that are assigned to `node` and
+    /// that are not paused
     pub(super) fn assignments(conn: &PgConnection, node: &NodeId) -> Result<Vec<Site>, StoreError> {
         ds::table
             .inner_join(a::table.on(a::id.eq(ds::id)))
             .filter(a::node_id.eq(node.as_str()))
+            .filter(a::paused_at.is_null())
             .select(ds::all_columns)
             .load::<Schema>(conn)?
             .into_iter()
@@ -625,6 +637,30 @@ mod queries {
             .transpose()
     }
 
+    /// Return the node that `site` is assigned to together with a flag
+    /// that indicates whether the deployment is paused, or `None` if the
+    /// deployment is not assigned at all
+    pub(super) fn assignment_status(
+        conn: &PgConnection,
+        site: &Site,
+    ) -> Result<Option<(NodeId, bool)>, StoreError> {
+        a::table
+            .filter(a::id.eq(site.id))
+            .select((a::node_id, a::paused_at.is_not_null()))
+            .first::<(String, bool)>(conn)
+            .optional()?
+            .map(|(node, paused)| {
+                NodeId::new(&node).map(|node| (node, paused)).map_err(|()| {
+                    constraint_violation!(
+                        "invalid node id `{}` in assignment for `{}`",
+                        node,
+                        site.deployment
+                    )
+                })
+            })
+            .transpose()
+    }
+
     pub(super) fn version_info(
         conn: &PgConnection,
         version: &str,
@@ -1021,6 +1057,62 @@ impl<'a> Connection<'a> {
         Ok(vec![change])
     }
 
+    /// Pause the deployment `site`. It stays assigned to its node, but
+    /// the node stops indexing it until it is resumed. Pausing a
+    /// deployment that is already paused does nothing
+    pub fn pause_subgraph(&self, site: &Site) -> Result<Vec<EntityChange>, StoreError> {
+        use subgraph_deployment_assignment as a;
+
+        let conn = self.conn.as_ref();
+        let updates = update(
+            a::table
+                .filter(a::id.eq(site.id))
+                .filter(a::paused_at.is_null()),
+        )
+        .set(a::paused_at.eq(sql("now()")))
+        .execute(conn)?;
+        match updates {
+            0 => Ok(vec![]),
+            1 => {
+                let change =
+                    EntityChange::for_assignment(site.into(), EntityChangeOperation::Removed);
+                Ok(vec![change])
+            }
+            _ => {
+                // `id` is the primary key of the subgraph_deployment_assignment table,
+                // and we can therefore only update no or one entry
+                unreachable!()
+            }
+        }
+    }
+
+    /// Resume indexing the deployment `site` on the node it is assigned
+    /// to. Resuming a deployment that is not paused does nothing
+    pub fn resume_subgraph(&self, site: &Site) -> Result<Vec<EntityChange>, StoreError> {
+        use subgraph_deployment_assignment as a;
+
+        let conn = self.conn.as_ref();
+        let updates = update(
+            a::table
+                .filter(a::id.eq(site.id))
+                .filter(a::paused_at.is_not_null()),
+        )
+        .set(a::paused_at.eq(None::<PgTimestamp>))
+        .execute(conn)?;
+        match updates {
+            0 => Ok(vec![]),
+            1 => {
+                let change = EntityChange::for_assignment(site.into(), EntityChangeOperation::Set);
+                Ok(vec![change])
+            }
+            _ => {
+                // `id` is the primary key of the subgraph_deployment_assignment table,
+                // and we can therefore only update no or one entry
+                unreachable!()
+            }
+        }
+    }
+
     pub fn unassign_subgraph(&self, site: &Site) -> Result<Vec<EntityChange>, StoreError> {
         use subgraph_deployment_assignment as a;
 
@@ -1106,6 +1198,10 @@ impl<'a> Connection<'a> {
         queries::assigned_node(self.conn.as_ref(), site)
     }
 
+    pub fn assignment_status(&self, site: &Site) -> Result<Option<(NodeId, bool)>, StoreError> {
+        queries::assignment_status(self.conn.as_ref(), site)
+    }
+
     /// Create a copy of the site `src` in the shard `shard`, but mark it as
     /// not active. If there already is a site in `shard`, return that
     /// instead.
@@ -1491,6 +1587,39 @@ impl<'a> Connection<'a> {
             .map_err(|e| anyhow!("error looking up ens_name for hash {}: {}", hash, e).into())
     }
 
+    pub fn find_persisted_query(&self, hash: &str) -> Result<Option<String>, StoreError> {
+        use persisted_queries as pq;
+
+        pq::table
+            .select(pq::query)
+            .find(hash)
+            .get_result::<String>(self.conn.as_ref())
+            .optional()
+            .map_err(|e| anyhow!("error looking up persisted query {}: {}", hash, e).into())
+    }
+
+    pub fn insert_persisted_query(&self, hash: &str, query: &str) -> Result<(), StoreError> {
+        use persisted_queries as pq;
+
+        insert_into(pq::table)
+            .values((pq::hash.eq(hash), pq::query.eq(query)))
+            .on_conflict_do_nothing()
+            .execute(self.conn.as_ref())?;
+        Ok(())
+    }
+
+    /// Remove the oldest persisted queries so that at most `max_queries`
+    /// remain, and return how many were removed
+    pub fn remove_old_persisted_queries(&self, max_queries: usize) -> Result<usize, StoreError> {
+        use persisted_queries as pq;
+
+        let oldest = pq::table
+            .select(pq::hash)
+            .order((pq::created_at.desc(), pq::hash))
+            .offset(max_queries as i64);
+        Ok(delete(pq::table.filter(pq::hash.eq_any(oldest))).execute(self.conn.as_ref())?)
+    }
+
     pub fn record_active_copy(&self, src: &Site, dst: &Site) -> Result<(), StoreError> {
         use active_copies as cp;
 
@@ -1659,6 +1788,10 @@ impl Mirror {
         self.read(|conn| queries::assigned_node(conn, site))
     }
 
+    pub fn assignment_status(&self, site: &Site) -> Result<Option<(NodeId, bool)>, StoreError> {
+        self.read(|conn| queries::assignment_status(conn, site))
+    }
+
     pub fn find_active_site(&self, subgraph: &DeploymentHash) -> Result<Option<Site>, StoreError> {
         self.read(|conn| queries::find_active_site(conn, subgraph))
     }
@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 use web3::types::H256;
 
 use crate::deployment_store::{DeploymentStore, ReplicaId};
-use graph::components::store::QueryStore as QueryStoreTrait;
+use graph::components::store::{EntityAggregate, QueryStore as QueryStoreTrait};
 use graph::prelude::*;
 
 use crate::primary::Site;
@@ -45,6 +45,20 @@ impl QueryStoreTrait for QueryStore {
         self.store.execute_query(&conn, self.site.clone(), query)
     }
 
+    fn find_query_aggregates(
+        &self,
+        query: EntityQuery,
+        aggregates: &[EntityAggregate],
+    ) -> Result<Vec<Value>, QueryExecutionError> {
+        assert_eq!(&self.site.deployment, &query.subgraph_id);
+        let conn = self
+            .store
+            .get_replica_conn(self.replica_id)
+            .map_err(|e| QueryExecutionError::StoreError(e.into()))?;
+        self.store
+            .execute_aggregate_query(&conn, self.site.clone(), query, aggregates)
+    }
+
     /// Return true if the deployment with the given id is fully synced,
     /// and return false otherwise. Errors from the store are passed back up
     async fn is_deployment_synced(&self) -> Result<bool, Error> {
@@ -81,6 +95,10 @@ impl QueryStoreTrait for QueryStore {
             .transpose()
     }
 
+    fn block_ptr_by_timestamp(&self, timestamp: u64) -> Result<Option<BlockPtr>, StoreError> {
+        self.chain_store.block_ptr_by_timestamp(timestamp)
+    }
+
     fn wait_stats(&self) -> PoolWaitStats {
         self.store.wait_stats(self.replica_id)
     }
@@ -8,10 +8,13 @@
 //! information about mapping a GraphQL schema to database tables
 
 mod ddl;
+mod rollup;
 
 #[cfg(test)]
 mod tests;
 
+use diesel::pg::Pg;
+use diesel::query_builder::QueryFragment;
 use diesel::{connection::SimpleConnection, Connection};
 use diesel::{debug_query, OptionalExtension, PgConnection, RunQueryDsl};
 use graph::cheap_clone::CheapClone;
@@ -33,19 +36,24 @@ use crate::relational_queries::{FindChangesQuery, FindPossibleDeletionsQuery};
 use crate::{
     primary::{Namespace, Site},
     relational_queries::{
-        ClampRangeQuery, ConflictingEntityQuery, EntityData, EntityDeletion, FilterCollection,
-        FilterQuery, FindManyQuery, FindQuery, InsertQuery, RevertClampQuery, RevertRemoveQuery,
+        cursor_filter, reversed_order, AggregateData, AggregateQuery, ClampRangeQuery,
+        ConflictingEntityQuery, EntityChange, EntityData, EntityDeletion, ExplainLine,
+        ExplainQuery, FilterCollection, FilterQuery, FindDerivedQuery, FindManyQuery, FindQuery,
+        InsertQuery, PruneQuery, RevertClampQuery, RevertRemoveQuery,
     },
 };
-use graph::components::store::EntityType;
+use graph::components::store::{DerivedEntityQuery, EntityAggregate, EntityType};
 use graph::data::graphql::ext::{DirectiveFinder, DocumentExt, ObjectTypeExt};
-use graph::data::schema::{FulltextConfig, FulltextDefinition, Schema, SCHEMA_TYPE_NAME};
+use graph::data::query::QueryExplain;
+use graph::data::schema::{
+    Aggregation, FulltextConfig, FulltextDefinition, FulltextWeight, Schema, SCHEMA_TYPE_NAME,
+};
 use graph::data::store::BYTES_SCALAR;
 use graph::data::subgraph::schema::{POI_OBJECT, POI_TABLE};
 use graph::prelude::{
     anyhow, info, BlockNumber, DeploymentHash, Entity, EntityChange, EntityCollection,
     EntityFilter, EntityKey, EntityOperation, EntityOrder, EntityRange, Logger,
-    QueryExecutionError, StoreError, StoreEvent, ValueType, BLOCK_NUMBER_MAX,
+    QueryExecutionError, StoreError, StoreEvent, Value, ValueType, BLOCK_NUMBER_MAX,
 };
 
 use crate::block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN};
@@ -56,6 +64,9 @@ use crate::{catalog, deployment};
 const POSTGRES_MAX_PARAMETERS: usize = u16::MAX as usize; // 65535
 const DELETE_OPERATION_CHUNK_SIZE: usize = 1_000;
 
+/// How many entity versions `Layout::prune` looks at in one statement
+const PRUNE_BATCH_SIZE: i64 = 10_000;
+
 /// The size of string prefixes that we index. This is chosen so that we
 /// will index strings that people will do string comparisons like
 /// `=` or `!=` on; if text longer than this is stored in a String attribute
@@ -210,6 +221,9 @@ pub struct Layout {
     pub enums: EnumMap,
     /// The query to count all entities
     pub count_query: String,
+    /// The rollups for `@aggregation` types that need to be maintained
+    /// when entities are written
+    pub aggregations: Vec<Aggregation>,
 }
 
 impl Layout {
@@ -334,6 +348,7 @@ impl Layout {
             tables,
             enums,
             count_query,
+            aggregations: schema.aggregations().to_vec(),
         })
     }
 
@@ -506,62 +521,127 @@ impl Layout {
         Ok(entities_for_type)
     }
 
+    /// Find the entities matching `query` as of `block`
+    pub fn find_derived(
+        &self,
+        conn: &PgConnection,
+        query: &DerivedEntityQuery,
+        block: BlockNumber,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        let table = self.table_for_entity(&query.entity_type)?;
+        let column = table.column_for_field(&query.entity_field)?;
+        let value = Value::String(query.value.clone());
+
+        let mut entities = BTreeMap::new();
+        for data in FindDerivedQuery::new(table, column, &value, block).load::<EntityData>(conn)? {
+            let entity = data.deserialize_with_layout::<Entity>(self, None)?;
+            let key = EntityKey {
+                subgraph_id: self.site.deployment.clone(),
+                entity_type: query.entity_type.clone(),
+                entity_id: entity.id()?,
+            };
+            entities.insert(key, entity);
+        }
+        Ok(entities)
+    }
+
     pub fn find_changes(
         &self,
         conn: &PgConnection,
         block: BlockNumber,
     ) -> Result<Vec<EntityOperation>, StoreError> {
+        Ok(self
+            .find_changes_in_range(conn, block, block)?
+            .remove(&block)
+            .unwrap_or_default())
+    }
+
+    /// Find the changes for all blocks from `start` to `end`, both
+    /// inclusive, with one query. Blocks without changes are not in the
+    /// result
+    pub fn find_changes_in_range(
+        &self,
+        conn: &PgConnection,
+        start: BlockNumber,
+        end: BlockNumber,
+    ) -> Result<BTreeMap<BlockNumber, Vec<EntityOperation>>, StoreError> {
         let mut tables = Vec::new();
         for table in self.tables.values() {
             if table.name.as_str() != POI_TABLE {
                 tables.push(&**table);
             }
         }
+        // Immutable entities can not be deleted
+        let mutable_tables: Vec<_> = tables
+            .iter()
+            .copied()
+            .filter(|table| !table.immutable)
+            .collect();
 
-        let inserts_or_updates =
-            FindChangesQuery::new(&self.catalog.site.namespace, &tables[..], block)
-                .load::<EntityData>(conn)?;
-        let deletions =
-            FindPossibleDeletionsQuery::new(&self.catalog.site.namespace, &tables[..], block)
-                .load::<EntityDeletion>(conn)?;
+        let inserts_or_updates = if tables.is_empty() {
+            vec![]
+        } else {
+            FindChangesQuery::new(&self.catalog.site.namespace, &tables[..], start, end)
+                .load::<EntityChange>(conn)?
+        };
+        let deletions = if mutable_tables.is_empty() {
+            vec![]
+        } else {
+            FindPossibleDeletionsQuery::new(
+                &self.catalog.site.namespace,
+                &mutable_tables[..],
+                start,
+                end,
+            )
+            .load::<EntityDeletion>(conn)?
+        };
 
         let mut processed_entities = HashSet::new();
-        let mut changes = Vec::new();
+        let mut changes: BTreeMap<BlockNumber, Vec<EntityOperation>> = BTreeMap::new();
 
-        for entity_data in inserts_or_updates.into_iter() {
+        for change in inserts_or_updates.into_iter() {
+            let block = change.block();
+            let entity_data = change.into_data();
             let entity_type = entity_data.entity_type();
             let mut data: Entity = entity_data.deserialize_with_layout(self, None)?;
             let entity_id = data.id().expect("Invalid ID for entity.");
-            processed_entities.insert((entity_type.clone(), entity_id.clone()));
+            processed_entities.insert((block, entity_type.clone(), entity_id.clone()));
 
             // `__typename` is not a real field.
             data.remove("__typename")
                 .expect("__typename expected; this is a bug");
 
-            changes.push(EntityOperation::Set {
-                key: EntityKey {
-                    subgraph_id: self.site.deployment.cheap_clone(),
-                    entity_type,
-                    entity_id,
-                },
-                data,
-            });
+            changes
+                .entry(block)
+                .or_default()
+                .push(EntityOperation::Set {
+                    key: EntityKey {
+                        subgraph_id: self.site.deployment.cheap_clone(),
+                        entity_type,
+                        entity_id,
+                    },
+                    data,
+                });
         }
 
         for del in &deletions {
+            let block = del.block();
             let entity_type = del.entity_type();
             let entity_id = del.id().to_string();
 
             // See the doc comment of `FindPossibleDeletionsQuery` for details
             // about why this check is necessary.
-            if !processed_entities.contains(&(entity_type.clone(), entity_id.clone())) {
-                changes.push(EntityOperation::Remove {
-                    key: EntityKey {
-                        subgraph_id: self.site.deployment.cheap_clone(),
-                        entity_type,
-                        entity_id,
-                    },
-                });
+            if !processed_entities.contains(&(block, entity_type.clone(), entity_id.clone())) {
+                changes
+                    .entry(block)
+                    .or_default()
+                    .push(EntityOperation::Remove {
+                        key: EntityKey {
+                            subgraph_id: self.site.deployment.cheap_clone(),
+                            entity_type,
+                            entity_id,
+                        },
+                    });
             }
         }
 
@@ -615,6 +695,7 @@ impl Layout {
         range: EntityRange,
         block: BlockNumber,
         query_id: Option<String>,
+        explain: Option<&QueryExplain>,
     ) -> Result<Vec<T>, QueryExecutionError> {
         fn log_query_timing(
             logger: &Logger,
@@ -646,9 +727,21 @@ impl Layout {
             );
         }
 
-        let filter_collection = FilterCollection::new(self, collection, filter.as_ref())?;
+        // Restrict the query to the entities between the cursors in `range`
+        let filter = match cursor_filter(&order, &range)? {
+            Some(cursor_filter) => Some(cursor_filter.and_maybe(filter)),
+            None => filter,
+        };
+        // Count `first` and `skip` backwards from a `before` cursor
+        let (order, reversed) = match reversed_order(&order, &range)? {
+            Some(reversed) => (reversed, true),
+            None => (order, false),
+        };
+
+        let filter_collection = FilterCollection::new(self, collection, filter.as_ref(), block)?;
         let query = FilterQuery::new(
             &filter_collection,
+            self,
             filter.as_ref(),
             order,
             range,
@@ -681,17 +774,85 @@ impl Layout {
                     debug_query(&query_clone).to_string()
                 )),
             })?;
-        log_query_timing(logger, &query_clone, start.elapsed(), values.len());
+        let elapsed = start.elapsed();
+        log_query_timing(logger, &query_clone, elapsed, values.len());
+        if let Some(explain) = explain {
+            explain_query(conn, explain, query_clone, elapsed, values.len())?;
+        }
 
         let parent_type = filter_collection.parent_type()?.map(ColumnType::from);
-        values
+        let mut entities = values
             .into_iter()
             .map(|entity_data| {
                 entity_data
                     .deserialize_with_layout(self, parent_type.as_ref())
                     .map_err(|e| e.into())
             })
-            .collect()
+            .collect::<Result<Vec<T>, _>>()?;
+        if reversed {
+            entities.reverse();
+        }
+        Ok(entities)
+    }
+
+    /// Compute `aggregates` over the entities of type `entity_type` that
+    /// match `filter` as of `block`
+    pub fn aggregate(
+        &self,
+        conn: &PgConnection,
+        entity_type: &EntityType,
+        filter: Option<EntityFilter>,
+        aggregates: &[EntityAggregate],
+        block: BlockNumber,
+        query_id: Option<String>,
+        explain: Option<&QueryExplain>,
+    ) -> Result<Vec<Value>, QueryExecutionError> {
+        let query = AggregateQuery::new(
+            self,
+            entity_type,
+            filter.as_ref(),
+            aggregates,
+            block,
+            query_id,
+        )?;
+        let query_clone = query.clone();
+
+        let start = Instant::now();
+        let data = conn
+            .transaction(|| {
+                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                    conn.batch_execute(timeout_sql)?;
+                }
+                query.load::<AggregateData>(conn)
+            })
+            .map_err(|e| match e {
+                diesel::result::Error::QueryBuilderError(e) => {
+                    QueryExecutionError::ResolveEntitiesError(e.to_string())
+                }
+                _ => QueryExecutionError::ResolveEntitiesError(format!(
+                    "{}, query = {}",
+                    e,
+                    debug_query(&query_clone).to_string()
+                )),
+            })?;
+
+        if let Some(explain) = explain {
+            explain_query(
+                conn,
+                explain,
+                query_clone.clone(),
+                start.elapsed(),
+                data.len(),
+            )?;
+        }
+
+        // An aggregate query without a `group by` always returns exactly
+        // one row
+        let data = data
+            .into_iter()
+            .next()
+            .ok_or_else(|| constraint_violation!("aggregate query returned no rows"))?;
+        query_clone.values(data).map_err(|e| e.into())
     }
 
     pub fn update<'a>(
@@ -822,6 +983,55 @@ impl Layout {
         Ok((StoreEvent::new(changes), count))
     }
 
+    /// Remove all entity versions that are only visible at blocks before
+    /// `earliest_block`. After this operation, queries at `earliest_block`
+    /// or later return the same results as before, but queries at earlier
+    /// blocks do not. Immutable tables have no history and the table for
+    /// the proof of indexing is left alone so that the proof of indexing
+    /// remains available for all blocks. Returns the number of removed
+    /// versions.
+    ///
+    /// Tables are pruned in batches of `PRUNE_BATCH_SIZE` versions so that
+    /// no single statement has to look at all versions of a large table.
+    /// The caller must run this in the same transaction in which it
+    /// records the new earliest block so that queries either see the old
+    /// earliest block and the full history, or the new earliest block and
+    /// the pruned history
+    pub fn prune(
+        &self,
+        conn: &PgConnection,
+        earliest_block: BlockNumber,
+    ) -> Result<usize, StoreError> {
+        #[derive(QueryableByName)]
+        struct VidRange {
+            #[sql_type = "diesel::sql_types::BigInt"]
+            min_vid: i64,
+            #[sql_type = "diesel::sql_types::BigInt"]
+            max_vid: i64,
+        }
+
+        let mut count = 0;
+        for table in self.tables.values() {
+            if table.immutable || table.name.as_str() == POI_TABLE {
+                continue;
+            }
+            let VidRange { min_vid, max_vid } = diesel::sql_query(format!(
+                "select coalesce(min(vid), 0) as min_vid, coalesce(max(vid), -1) as max_vid \
+                   from {}",
+                table.qualified_name
+            ))
+            .get_result::<VidRange>(conn)?;
+            let mut first_vid = min_vid;
+            while first_vid <= max_vid {
+                let last_vid = first_vid + PRUNE_BATCH_SIZE - 1;
+                count +=
+                    PruneQuery::new(table, earliest_block, first_vid, last_vid)?.execute(conn)?;
+                first_vid = last_vid + 1;
+            }
+        }
+        Ok(count)
+    }
+
     /// Revert the metadata (dynamic data sources and related entities) for
     /// the given `subgraph`.
     ///
@@ -883,6 +1093,39 @@ impl Layout {
     }
 }
 
+/// Run `query` with `explain (analyze, buffers)` and record the query, its
+/// bind parameters and its query plan in `explain`. The `elapsed` time and
+/// `entity_count` are those of running the query normally
+fn explain_query<Q: QueryFragment<Pg>>(
+    conn: &PgConnection,
+    explain: &QueryExplain,
+    query: Q,
+    elapsed: Duration,
+    entity_count: usize,
+) -> Result<(), QueryExecutionError> {
+    // `debug_query` prints the query as `<sql> -- binds: <binds>`
+    let text = debug_query(&query).to_string();
+    let (sql, binds) = text.split_once(" -- binds: ").unwrap_or((&text, ""));
+    let (sql, binds) = (sql.to_string(), binds.to_string());
+
+    let plan = conn
+        .transaction(|| {
+            if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                conn.batch_execute(timeout_sql)?;
+            }
+            ExplainQuery::new(query).load::<ExplainLine>(conn)
+        })
+        .map_err(|e| {
+            QueryExecutionError::ResolveEntitiesError(format!(
+                "failed to explain query: {}, query = {}",
+                e, sql
+            ))
+        })?;
+    let plan = plan.into_iter().map(|line| line.0).collect();
+    explain.record(sql, binds, plan, elapsed, entity_count);
+    Ok(())
+}
+
 /// A user-defined enum
 #[derive(Clone, Debug, PartialEq)]
 pub struct EnumType {
@@ -915,7 +1158,9 @@ pub enum ColumnType {
     BigInt,
     Bytes,
     Int,
+    Int8,
     String,
+    Timestamp,
     TSVector(FulltextConfig),
     Enum(EnumType),
 }
@@ -972,7 +1217,9 @@ impl ColumnType {
             ValueType::BigInt => Ok(ColumnType::BigInt),
             ValueType::Bytes => Ok(ColumnType::Bytes),
             ValueType::Int => Ok(ColumnType::Int),
+            ValueType::Int8 => Ok(ColumnType::Int8),
             ValueType::String => Ok(ColumnType::String),
+            ValueType::Timestamp => Ok(ColumnType::Timestamp),
         }
     }
 
@@ -983,7 +1230,9 @@ impl ColumnType {
             ColumnType::BigInt => "numeric",
             ColumnType::Bytes => "bytea",
             ColumnType::Int => "integer",
+            ColumnType::Int8 => "int8",
             ColumnType::String => "text",
+            ColumnType::Timestamp => "timestamptz",
             ColumnType::TSVector(_) => "tsvector",
             ColumnType::Enum(enum_type) => enum_type.name.as_str(),
         }
@@ -1009,7 +1258,9 @@ pub struct Column {
     pub field: String,
     pub field_type: q::Type,
     pub column_type: ColumnType,
-    pub fulltext_fields: Option<HashSet<String>>,
+    /// For fulltext columns, the fields that go into the tsvector and
+    /// their weights
+    pub fulltext_fields: Option<BTreeMap<String, FulltextWeight>>,
     is_reference: bool,
     /// Whether to use a prefix of the column for comparisons and index
     /// creation, or column values in their entirety
@@ -1027,8 +1278,8 @@ impl Column {
         SqlName::check_valid_identifier(&*field.name, "attribute")?;
 
         let sql_name = SqlName::from(&*field.name);
-        let is_reference =
-            sql_name.as_str() != PRIMARY_KEY_COLUMN && is_object_type(&field.field_type, enums);
+        let is_reference = sql_name.as_str() != PRIMARY_KEY_COLUMN
+            && is_object_type(&field.field_type, enums, id_types);
 
         let column_type = if sql_name.as_str() == PRIMARY_KEY_COLUMN {
             IdType::try_from(&field.field_type)?.into()
@@ -1283,10 +1534,13 @@ fn named_type(field_type: &q::Type) -> &str {
     }
 }
 
-fn is_object_type(field_type: &q::Type, enums: &EnumMap) -> bool {
+fn is_object_type(field_type: &q::Type, enums: &EnumMap, id_types: &IdTypeMap) -> bool {
     let name = named_type(field_type);
 
-    !enums.contains_key(&*name) && !ValueType::is_scalar(name)
+    // Object types defined in the schema take precedence over builtin
+    // scalars with the same name, like `Timestamp`
+    id_types.contains_key(&EntityType::new(name.to_string()))
+        || (!enums.contains_key(&*name) && !ValueType::is_scalar(name))
 }
 
 #[derive(Clone)]
@@ -0,0 +1,109 @@
+//! Maintain the rollups for `@aggregation` types. Rollups are stored as
+//! ordinary mutable entities, one per interval and combination of
+//! dimensions, so that reverting and querying them needs no special
+//! handling. Whenever a block inserts source entities of an aggregation,
+//! we compute the new values of the affected rollups here and write them
+//! together with the other changes of the block.
+use std::collections::{BTreeMap, HashMap};
+
+use diesel::PgConnection;
+use graph::components::store::EntityType;
+use graph::data::schema::Aggregation;
+use graph::prelude::{Entity, EntityKey, EntityModification, StoreError, BLOCK_NUMBER_MAX};
+
+use super::Layout;
+
+impl Layout {
+    /// Return the modifications to rollups that are caused by the
+    /// insertion of source entities in `mods`
+    pub fn rollup(
+        &self,
+        conn: &PgConnection,
+        mods: &[EntityModification],
+    ) -> Result<Vec<EntityModification>, StoreError> {
+        if self.aggregations.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // The source entities in this block together with the aggregation
+        // and the key of the rollup they contribute to
+        let mut sources: Vec<(&Aggregation, EntityKey, &Entity)> = vec![];
+        for aggregation in &self.aggregations {
+            for modification in mods {
+                let (key, source) = match modification {
+                    EntityModification::Insert { key, data } => (key, data),
+                    // Sources are immutable, and can therefore only be
+                    // inserted
+                    _ => continue,
+                };
+                if key.entity_type != aggregation.source {
+                    continue;
+                }
+
+                let (id, _) = aggregation.bucket(source)?;
+                let rollup_key = EntityKey {
+                    subgraph_id: self.site.deployment.clone(),
+                    entity_type: aggregation.entity_type.clone(),
+                    entity_id: id,
+                };
+                sources.push((aggregation, rollup_key, source));
+            }
+        }
+        if sources.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Load the current versions of all affected rollups with one query
+        let mut ids_for_type: BTreeMap<&EntityType, Vec<&str>> = BTreeMap::new();
+        for (_, key, _) in &sources {
+            ids_for_type
+                .entry(&key.entity_type)
+                .or_default()
+                .push(&key.entity_id);
+        }
+        for ids in ids_for_type.values_mut() {
+            ids.sort_unstable();
+            ids.dedup();
+        }
+        let mut current: HashMap<(EntityType, String), Entity> = HashMap::new();
+        for (entity_type, entities) in self.find_many(conn, &ids_for_type, BLOCK_NUMBER_MAX)? {
+            for entity in entities {
+                current.insert((entity_type.clone(), entity.id()?), entity);
+            }
+        }
+
+        // The rollups that this block changes, in the order in which we
+        // first encountered them, together with a flag indicating whether
+        // the rollup already exists in the store
+        let mut rollups: Vec<(EntityKey, Entity, bool)> = vec![];
+        let mut index: HashMap<EntityKey, usize> = HashMap::new();
+
+        for (aggregation, rollup_key, source) in &sources {
+            match index.get(rollup_key) {
+                Some(pos) => {
+                    let (_, rollup, _) = &mut rollups[*pos];
+                    *rollup = aggregation.fold(Some(rollup.clone()), source)?;
+                }
+                None => {
+                    let current = current
+                        .remove(&(rollup_key.entity_type.clone(), rollup_key.entity_id.clone()));
+                    let exists = current.is_some();
+                    let rollup = aggregation.fold(current, source)?;
+                    index.insert(rollup_key.clone(), rollups.len());
+                    rollups.push((rollup_key.clone(), rollup, exists));
+                }
+            }
+        }
+
+        Ok(rollups
+            .into_iter()
+            .map(|(key, data, exists)| {
+                if exists {
+                    EntityModification::Overwrite { key, data }
+                } else {
+                    EntityModification::Insert { key, data }
+                }
+            })
+            .collect())
+    }
+}
@@ -1,3 +1,4 @@
+use diesel::deserialize::{self, QueryableByName};
 ///! This module contains the gory details of using Diesel to query
 ///! a database schema that is not known at compile time. The code in this
 ///! module is mostly concerned with constructing SQL queries and some
@@ -9,17 +10,23 @@ use diesel::pg::{Pg, PgConnection};
 use diesel::query_builder::{AstPass, QueryFragment, QueryId};
 use diesel::query_dsl::{LoadQuery, RunQueryDsl};
 use diesel::result::{Error as DieselError, QueryResult};
-use diesel::sql_types::{Array, BigInt, Binary, Bool, Integer, Jsonb, Text};
+use diesel::row::NamedRow;
+use diesel::sql_types::{Array, BigInt, Binary, Bool, Integer, Jsonb, Nullable, Text};
 use diesel::Connection;
 
 use graph::prelude::{
     anyhow, r, serde_json, Attribute, BlockNumber, ChildMultiplicity, Entity, EntityCollection,
     EntityFilter, EntityKey, EntityLink, EntityOrder, EntityRange, EntityWindow, ParentLink,
-    QueryExecutionError, StoreError, Value, ENV_VARS,
+    QueryExecutionError, StoreError, Value, ValueType, ENV_VARS,
 };
 use graph::{
-    components::store::{AttributeNames, EntityType},
-    data::{schema::FulltextAlgorithm, store::scalar},
+    components::store::{
+        AttributeNames, Child, EntityAggregate, EntityCursor, EntityOrderByChild, EntityType,
+    },
+    data::{
+        schema::{FulltextAlgorithm, FulltextConfig, FulltextWeight},
+        store::scalar,
+    },
 };
 use itertools::Itertools;
 use std::borrow::Cow;
@@ -45,6 +52,10 @@ use crate::{
 /// Those are columns that we always want to fetch from the database.
 const BASE_SQL_COLUMNS: [&'static str; 2] = ["id", "vid"];
 
+/// The column in which queries that sort by a fulltext column return the
+/// rank of each entity
+const RANK_COLUMN: &str = "g$rank";
+
 #[derive(Debug)]
 pub(crate) struct UnsupportedFilter {
     pub filter: String,
@@ -269,10 +280,16 @@ pub trait FromColumnValue: Sized {
 
     fn from_i32(i: i32) -> Self;
 
+    fn from_i64(i: i64) -> Self;
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self;
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self;
 
     fn from_big_int(i: serde_json::Number) -> Result<Self, StoreError>;
 
+    fn from_f64(f: f64) -> Self;
+
     // The string returned by the DB, without the leading '\x'
     fn from_bytes(i: &str) -> Result<Self, StoreError>;
 
@@ -311,6 +328,13 @@ pub trait FromColumnValue: Sized {
                     })
             }
             (j::Number(number), ColumnType::BigInt) => Self::from_big_int(number),
+            (j::Number(number), ColumnType::Int8) => match number.as_i64() {
+                Some(i) => Ok(Self::from_i64(i)),
+                None => Err(StoreError::Unknown(anyhow!(
+                    "failed to convert {} to Int8",
+                    number
+                ))),
+            },
             (j::Number(number), column_type) => Err(StoreError::Unknown(anyhow!(
                 "can not convert number {} to {:?}",
                 number,
@@ -320,6 +344,11 @@ pub trait FromColumnValue: Sized {
                 Ok(Self::from_string(s))
             }
             (j::String(s), ColumnType::Bytes) => Self::from_bytes(s.trim_start_matches("\\x")),
+            (j::String(s), ColumnType::Timestamp) => scalar::Timestamp::parse_rfc3339(&s)
+                .map(Self::from_timestamp)
+                .map_err(|e| {
+                    StoreError::Unknown(anyhow!("failed to convert {} to Timestamp: {}", s, e))
+                }),
             (j::String(s), column_type) => Err(StoreError::Unknown(anyhow!(
                 "can not convert string {} to {:?}",
                 s,
@@ -359,6 +388,14 @@ impl FromColumnValue for r::Value {
         r::Value::Int(i.into())
     }
 
+    fn from_i64(i: i64) -> Self {
+        r::Value::String(i.to_string())
+    }
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self {
+        r::Value::String(ts.to_string())
+    }
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self {
         r::Value::String(d.to_string())
     }
@@ -367,6 +404,10 @@ impl FromColumnValue for r::Value {
         Ok(r::Value::String(i.to_string()))
     }
 
+    fn from_f64(f: f64) -> Self {
+        r::Value::Float(f)
+    }
+
     fn from_bytes(b: &str) -> Result<Self, StoreError> {
         Ok(r::Value::String(format!("0x{}", b)))
     }
@@ -397,6 +438,14 @@ impl FromColumnValue for graph::prelude::Value {
         graph::prelude::Value::Int(i)
     }
 
+    fn from_i64(i: i64) -> Self {
+        graph::prelude::Value::Int8(i)
+    }
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self {
+        graph::prelude::Value::Timestamp(ts)
+    }
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self {
         graph::prelude::Value::BigDecimal(d)
     }
@@ -407,6 +456,10 @@ impl FromColumnValue for graph::prelude::Value {
             .map_err(|e| StoreError::Unknown(anyhow!("failed to convert {} to BigInt: {}", i, e)))
     }
 
+    fn from_f64(f: f64) -> Self {
+        graph::prelude::Value::BigDecimal(scalar::BigDecimal::from(f))
+    }
+
     fn from_bytes(b: &str) -> Result<Self, StoreError> {
         scalar::Bytes::from_str(b)
             .map(graph::prelude::Value::Bytes)
@@ -426,6 +479,8 @@ pub struct EntityDeletion {
     entity: String,
     #[sql_type = "Text"]
     id: String,
+    #[sql_type = "Integer"]
+    block: BlockNumber,
 }
 
 impl EntityDeletion {
@@ -436,6 +491,31 @@ impl EntityDeletion {
     pub fn id(&self) -> &str {
         &self.id
     }
+
+    /// The block at which the entity was deleted
+    pub fn block(&self) -> BlockNumber {
+        self.block
+    }
+}
+
+/// The data of an entity version together with the block at which the
+/// version was created
+#[derive(QueryableByName, Debug)]
+pub struct EntityChange {
+    #[sql_type = "Integer"]
+    block: BlockNumber,
+    #[diesel(embed)]
+    data: EntityData,
+}
+
+impl EntityChange {
+    pub fn block(&self) -> BlockNumber {
+        self.block
+    }
+
+    pub fn into_data(self) -> EntityData {
+        self.data
+    }
 }
 
 /// Helper struct for retrieving entities from the database. With diesel, we
@@ -498,6 +578,15 @@ impl EntityData {
                                 out.insert_entity_data("g$parent_id".to_owned(), value);
                             }
                         }
+                    } else if key == RANK_COLUMN {
+                        let rank = match &json {
+                            j::Number(number) => number.as_f64(),
+                            _ => None,
+                        }
+                        .ok_or_else(|| {
+                            StoreError::Unknown(anyhow!("invalid fulltext rank {}", json))
+                        })?;
+                        out.insert_entity_data(RANK_COLUMN.to_owned(), T::Value::from_f64(rank));
                     } else if let Some(column) = table.column(&SqlName::verbatim(key)) {
                         let value = T::Value::from_column_value(&column.column_type, json)?;
                         if !value.is_null() {
@@ -548,6 +637,12 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                 ),
             },
             Value::Int(i) => out.push_bind_param::<Integer, _>(i),
+            Value::Int8(i) => out.push_bind_param::<BigInt, _>(i),
+            Value::Timestamp(ts) => {
+                out.push_bind_param::<Text, _>(&ts.to_rfc3339())?;
+                out.push_sql("::timestamptz");
+                Ok(())
+            }
             Value::BigDecimal(d) => {
                 out.push_bind_param::<Text, _>(&d.to_string())?;
                 out.push_sql("::numeric");
@@ -566,7 +661,13 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                     ColumnType::Boolean => out.push_bind_param::<Array<Bool>, _>(&sql_values),
                     ColumnType::Bytes => out.push_bind_param::<Array<Binary>, _>(&sql_values),
                     ColumnType::Int => out.push_bind_param::<Array<Integer>, _>(&sql_values),
+                    ColumnType::Int8 => out.push_bind_param::<Array<BigInt>, _>(&sql_values),
                     ColumnType::String => out.push_bind_param::<Array<Text>, _>(&sql_values),
+                    ColumnType::Timestamp => {
+                        out.push_bind_param::<Array<Text>, _>(&sql_values)?;
+                        out.push_sql("::timestamptz[]");
+                        Ok(())
+                    }
                     ColumnType::Enum(enum_type) => {
                         out.push_bind_param::<Array<Text>, _>(&sql_values)?;
                         out.push_sql("::");
@@ -574,28 +675,11 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                         out.push_sql("[]");
                         Ok(())
                     }
-                    // TSVector will only be in a Value::List() for inserts so "to_tsvector" can always be used here
-                    ColumnType::TSVector(config) => {
-                        if sql_values.is_empty() {
-                            out.push_sql("''::tsvector");
-                        } else {
-                            out.push_sql("(");
-                            for (i, value) in sql_values.iter().enumerate() {
-                                if i > 0 {
-                                    out.push_sql(") || ");
-                                }
-                                out.push_sql("to_tsvector(");
-                                out.push_bind_param::<Text, _>(
-                                    &config.language.as_str().to_string(),
-                                )?;
-                                out.push_sql("::regconfig, ");
-                                out.push_bind_param::<Text, _>(&value)?;
-                            }
-                            out.push_sql("))");
-                        }
-
-                        Ok(())
-                    }
+                    // Values for TSVector columns are inserted with a
+                    // `FulltextValue`
+                    ColumnType::TSVector(_) => Err(constraint_violation!(
+                        "fulltext values can not be bound as a list of values"
+                    )),
                 }
             }
             Value::Null => {
@@ -851,26 +935,57 @@ impl<'a> QueryFragment<Pg> for PrefixComparison<'a> {
 /// the `where` clause of a SQL query. The attributes mentioned in
 /// the `filter` must all come from the given `table`, which is used to
 /// map GraphQL names to column names, and to determine the type of the
-/// column an attribute refers to
+/// column an attribute refers to. Filters on child entities are checked
+/// against the child's table in `layout`
 #[derive(Debug, Clone)]
 pub struct QueryFilter<'a> {
     filter: &'a EntityFilter,
     table: &'a Table,
+    layout: &'a Layout,
+    block: BlockNumber,
+    /// How deeply this filter is nested inside child filters. Each level
+    /// of nesting is an `exists` subquery with its own table alias
+    depth: usize,
 }
 
 impl<'a> QueryFilter<'a> {
-    pub fn new(filter: &'a EntityFilter, table: &'a Table) -> Result<Self, StoreError> {
-        Self::valid_attributes(filter, table)?;
-        Ok(QueryFilter { filter, table })
+    pub fn new(
+        filter: &'a EntityFilter,
+        table: &'a Table,
+        layout: &'a Layout,
+        block: BlockNumber,
+    ) -> Result<Self, StoreError> {
+        Self::valid_attributes(filter, table, layout)?;
+        Ok(QueryFilter {
+            filter,
+            table,
+            layout,
+            block,
+            depth: 0,
+        })
     }
 
-    fn valid_attributes(filter: &'a EntityFilter, table: &'a Table) -> Result<(), StoreError> {
+    fn valid_attributes(
+        filter: &EntityFilter,
+        table: &Table,
+        layout: &Layout,
+    ) -> Result<(), StoreError> {
         use EntityFilter::*;
         match filter {
             And(filters) | Or(filters) => {
                 for filter in filters {
-                    Self::valid_attributes(filter, table)?;
+                    Self::valid_attributes(filter, table, layout)?;
+                }
+            }
+
+            Child(child) => {
+                let child_table = layout.table_for_entity(&child.entity_type)?;
+                if child.derived {
+                    child_table.column_for_field(&child.attr)?;
+                } else {
+                    table.column_for_field(&child.attr)?;
                 }
+                Self::valid_attributes(&child.filter, child_table, layout)?;
             }
 
             // This is a special case since we want to allow passing "block" column filter, but we dont
@@ -881,6 +996,8 @@ impl<'a> QueryFilter<'a> {
             | ContainsNoCase(attr, _)
             | NotContains(attr, _)
             | NotContainsNoCase(attr, _)
+            | ContainsAny(attr, _)
+            | ContainsAll(attr, _)
             | Equal(attr, _)
             | Not(attr, _)
             | GreaterThan(attr, _)
@@ -889,6 +1006,8 @@ impl<'a> QueryFilter<'a> {
             | LessOrEqual(attr, _)
             | In(attr, _)
             | NotIn(attr, _)
+            | InNoCase(attr, _)
+            | NotInNoCase(attr, _)
             | StartsWith(attr, _)
             | StartsWithNoCase(attr, _)
             | NotStartsWith(attr, _)
@@ -896,7 +1015,9 @@ impl<'a> QueryFilter<'a> {
             | EndsWith(attr, _)
             | EndsWithNoCase(attr, _)
             | NotEndsWith(attr, _)
-            | NotEndsWithNoCase(attr, _) => {
+            | NotEndsWithNoCase(attr, _)
+            | IsNull(attr)
+            | IsNotNull(attr) => {
                 table.column_for_field(attr)?;
             }
         }
@@ -907,6 +1028,17 @@ impl<'a> QueryFilter<'a> {
         QueryFilter {
             filter,
             table: self.table,
+            layout: self.layout,
+            block: self.block,
+            depth: self.depth,
+        }
+    }
+
+    /// The alias under which `self.table` appears in the query
+    fn alias(&self) -> String {
+        match self.depth {
+            0 => "c".to_string(),
+            depth => format!("c{}", depth),
         }
     }
 
@@ -989,6 +1121,8 @@ impl<'a> QueryFilter<'a> {
             Value::Null
             | Value::BigDecimal(_)
             | Value::Int(_)
+            | Value::Int8(_)
+            | Value::Timestamp(_)
             | Value::Bool(_)
             | Value::BigInt(_) => {
                 let filter = match negated {
@@ -1005,6 +1139,51 @@ impl<'a> QueryFilter<'a> {
         Ok(())
     }
 
+    /// Compare a list attribute with a list of values using Postgres'
+    /// array operators: `&&` if the attribute must contain any of the
+    /// values, and `@>` if it must contain all of them
+    fn contains_list(
+        &self,
+        attribute: &Attribute,
+        value: &Value,
+        all: bool,
+        mut out: AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let column = self.column(attribute);
+        let (filter, op) = match all {
+            false => ("contains_any", " && "),
+            true => ("contains_all", " @> "),
+        };
+
+        if !column.is_list() || !matches!(value, Value::List(_)) {
+            return Err(UnsupportedFilter {
+                filter: filter.to_owned(),
+                value: value.clone(),
+            }
+            .into());
+        }
+        out.push_identifier(column.name.as_str())?;
+        out.push_sql(op);
+        QueryValue(value, &column.column_type).walk_ast(out)
+    }
+
+    fn is_null(
+        &self,
+        attribute: &Attribute,
+        negated: bool,
+        mut out: AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let column = self.column(attribute);
+
+        out.push_identifier(column.name.as_str())?;
+        if negated {
+            out.push_sql(" is not null");
+        } else {
+            out.push_sql(" is null");
+        }
+        Ok(())
+    }
+
     fn equals(
         &self,
         attribute: &Attribute,
@@ -1056,6 +1235,8 @@ impl<'a> QueryFilter<'a> {
                 | Value::Bytes(_)
                 | Value::BigDecimal(_)
                 | Value::Int(_)
+                | Value::Int8(_)
+                | Value::Timestamp(_)
                 | Value::String(_) => QueryValue(value, &column.column_type).walk_ast(out)?,
                 Value::Bool(_) | Value::List(_) | Value::Null => {
                     return Err(UnsupportedFilter {
@@ -1157,12 +1338,146 @@ impl<'a> QueryFilter<'a> {
         Ok(())
     }
 
+    /// Generate `lower(attribute) {in|not in} (lower(value1), ...)`. Only
+    /// string attributes and values can be compared without regard to case
+    fn in_array_nocase(
+        &self,
+        attribute: &Attribute,
+        values: &[Value],
+        negated: bool,
+        mut out: AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let column = self.column(attribute);
+        let filter = match negated {
+            false => "in_nocase",
+            true => "not_in_nocase",
+        };
+
+        if column.column_type != ColumnType::String || column.is_list() {
+            return Err(UnsupportedFilter {
+                filter: filter.to_owned(),
+                value: Value::List(values.to_vec()),
+            }
+            .into());
+        }
+
+        // Nothing is in an empty list, and everything is not in it
+        if values.is_empty() {
+            out.push_sql(if negated { "true" } else { "false" });
+            return Ok(());
+        }
+
+        out.push_sql("lower(");
+        out.push_identifier(column.name.as_str())?;
+        if negated {
+            out.push_sql(") not in (");
+        } else {
+            out.push_sql(") in (");
+        }
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            match value {
+                Value::String(s) => {
+                    out.push_sql("lower(");
+                    out.push_bind_param::<Text, _>(s)?;
+                    out.push_sql(")");
+                }
+                _ => {
+                    return Err(UnsupportedFilter {
+                        filter: filter.to_owned(),
+                        value: value.clone(),
+                    }
+                    .into());
+                }
+            }
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+
     fn filter_block_gte(
         &self,
         block_number_gte: &BlockNumber,
         mut out: AstPass<Pg>,
     ) -> QueryResult<()> {
-        BlockRangeColumn::new(self.table, "c.", *block_number_gte).changed_since(&mut out)
+        let prefix = format!("{}.", self.alias());
+        BlockRangeColumn::new(self.table, &prefix, *block_number_gte).changed_since(&mut out)
+    }
+
+    /// Generate an `exists` subquery that checks whether the current row
+    /// has a child that matches `child.filter`
+    ///
+    ///   exists (select 1
+    ///             from {child_table} c1
+    ///            where c1.block_range @> $block
+    ///              and {join condition}
+    ///              and {child.filter})
+    ///
+    /// When the parent stores the reference, the join condition is
+    /// `c1.id = c.{attr}` or `c1.id = any(c.{attr})`; for a derived field
+    /// it is `c1.{attr} = c.id` or `c.id = any(c1.{attr})`. Since we only
+    /// ask whether such a child exists, a filter on a list of children
+    /// matches if any of the children match
+    fn child(&self, child: &Child, mut out: AstPass<Pg>) -> QueryResult<()> {
+        let child_table = self
+            .layout
+            .table_for_entity(&child.entity_type)
+            .expect("the constructor already checked that all entity types are valid");
+        let child_filter = QueryFilter {
+            filter: &child.filter,
+            table: child_table,
+            layout: self.layout,
+            block: self.block,
+            depth: self.depth + 1,
+        };
+        let parent = self.alias();
+        let alias = child_filter.alias();
+        let prefix = format!("{}.", alias);
+
+        out.push_sql("exists (select 1 from ");
+        out.push_sql(child_table.qualified_name.as_str());
+        out.push_sql(" ");
+        out.push_sql(&alias);
+        out.push_sql(" where ");
+        BlockRangeColumn::new(child_table, &prefix, self.block).contains(&mut out)?;
+        out.push_sql(" and ");
+        if child.derived {
+            let column = child_filter.column(&child.attr);
+            if column.is_list() {
+                out.push_sql(&parent);
+                out.push_sql(".id = any(");
+                out.push_sql(&prefix);
+                out.push_identifier(column.name.as_str())?;
+                out.push_sql(")");
+            } else {
+                out.push_sql(&prefix);
+                out.push_identifier(column.name.as_str())?;
+                out.push_sql(" = ");
+                out.push_sql(&parent);
+                out.push_sql(".id");
+            }
+        } else {
+            let column = self.column(&child.attr);
+            out.push_sql(&prefix);
+            if column.is_list() {
+                out.push_sql("id = any(");
+                out.push_sql(&parent);
+                out.push_sql(".");
+                out.push_identifier(column.name.as_str())?;
+                out.push_sql(")");
+            } else {
+                out.push_sql("id = ");
+                out.push_sql(&parent);
+                out.push_sql(".");
+                out.push_identifier(column.name.as_str())?;
+            }
+        }
+        out.push_sql(" and ");
+        child_filter.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
     }
 
     fn starts_or_ends_with(
@@ -1191,6 +1506,8 @@ impl<'a> QueryFilter<'a> {
             | Value::Bytes(_)
             | Value::BigDecimal(_)
             | Value::Int(_)
+            | Value::Int8(_)
+            | Value::Timestamp(_)
             | Value::List(_)
             | Value::Null => {
                 return Err(UnsupportedFilter {
@@ -1218,6 +1535,8 @@ impl<'a> QueryFragment<Pg> for QueryFilter<'a> {
             ContainsNoCase(attr, value) => self.contains(attr, value, false, false, out)?,
             NotContains(attr, value) => self.contains(attr, value, true, true, out)?,
             NotContainsNoCase(attr, value) => self.contains(attr, value, true, false, out)?,
+            ContainsAny(attr, value) => self.contains_list(attr, value, false, out)?,
+            ContainsAll(attr, value) => self.contains_list(attr, value, true, out)?,
 
             Equal(attr, value) => self.equals(attr, value, c::Equal, out)?,
             Not(attr, value) => self.equals(attr, value, c::NotEqual, out)?,
@@ -1229,6 +1548,8 @@ impl<'a> QueryFragment<Pg> for QueryFilter<'a> {
 
             In(attr, values) => self.in_array(attr, values, false, out)?,
             NotIn(attr, values) => self.in_array(attr, values, true, out)?,
+            InNoCase(attr, values) => self.in_array_nocase(attr, values, false, out)?,
+            NotInNoCase(attr, values) => self.in_array_nocase(attr, values, true, out)?,
 
             StartsWith(attr, value) => {
                 self.starts_or_ends_with(attr, value, " like ", true, out)?
@@ -1252,7 +1573,10 @@ impl<'a> QueryFragment<Pg> for QueryFilter<'a> {
             NotEndsWithNoCase(attr, value) => {
                 self.starts_or_ends_with(attr, value, " not ilike ", false, out)?
             }
+            IsNull(attr) => self.is_null(attr, false, out)?,
+            IsNotNull(attr) => self.is_null(attr, true, out)?,
             ChangeBlockGte(block_number) => self.filter_block_gte(block_number, out)?,
+            Child(child) => self.child(child, out)?,
         }
         Ok(())
     }
@@ -1299,13 +1623,14 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FindQuery<'a> {
 impl<'a, Conn> RunQueryDsl<Conn> for FindQuery<'a> {}
 
 /// Builds a query over a given set of [`Table`]s in an attempt to find updated
-/// and/or newly inserted entities at a given block number; i.e. such that the
-/// block range's lower bound is equal to said block number.
+/// and/or newly inserted entities in a range of blocks; i.e. such that the
+/// block range's lower bound is between `start` and `end`, both inclusive.
 #[derive(Debug, Clone, Constructor)]
 pub struct FindChangesQuery<'a> {
     pub(crate) _namespace: &'a Namespace,
     pub(crate) tables: &'a [&'a Table],
-    pub(crate) block: BlockNumber,
+    pub(crate) start: BlockNumber,
+    pub(crate) end: BlockNumber,
 }
 
 impl<'a> QueryFragment<Pg> for FindChangesQuery<'a> {
@@ -1316,13 +1641,16 @@ impl<'a> QueryFragment<Pg> for FindChangesQuery<'a> {
             if i > 0 {
                 out.push_sql("\nunion all\n");
             }
+            let lower_bound = BlockRangeLowerBoundClause::new(table, "e.", self.start, self.end);
             out.push_sql("select ");
             out.push_bind_param::<Text, _>(&table.object.as_str())?;
-            out.push_sql(" as entity, to_jsonb(e.*) as data\n");
+            out.push_sql(" as entity, to_jsonb(e.*) as data, ");
+            lower_bound.lower_bound(&mut out)?;
+            out.push_sql(" as block\n");
             out.push_sql("  from ");
             out.push_sql(table.qualified_name.as_str());
             out.push_sql(" e\n where ");
-            BlockRangeLowerBoundClause::new("e.", self.block).walk_ast(out.reborrow())?;
+            lower_bound.walk_ast(out.reborrow())?;
         }
 
         Ok(())
@@ -1335,8 +1663,8 @@ impl<'a> QueryId for FindChangesQuery<'a> {
     const HAS_STATIC_QUERY_ID: bool = false;
 }
 
-impl<'a> LoadQuery<PgConnection, EntityData> for FindChangesQuery<'a> {
-    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<EntityData>> {
+impl<'a> LoadQuery<PgConnection, EntityChange> for FindChangesQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<EntityChange>> {
         conn.query_by_name(&self)
     }
 }
@@ -1344,8 +1672,11 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FindChangesQuery<'a> {
 impl<'a, Conn> RunQueryDsl<Conn> for FindChangesQuery<'a> {}
 
 /// Builds a query over a given set of [`Table`]s in an attempt to find deleted
-/// entities; i.e. such that the block range's lower bound is equal to said
-/// block number.
+/// entities in a range of blocks; i.e. such that the block range's upper
+/// bound is between `start` and `end`, both inclusive.
+///
+/// Immutable entities are never deleted, and `tables` must therefore not
+/// contain any immutable tables.
 ///
 /// Please note that the result set from this query is *not* definitive. This
 /// query is intented to be used together with [`FindChangesQuery`]; by
@@ -1355,7 +1686,8 @@ impl<'a, Conn> RunQueryDsl<Conn> for FindChangesQuery<'a> {}
 pub struct FindPossibleDeletionsQuery<'a> {
     pub(crate) _namespace: &'a Namespace,
     pub(crate) tables: &'a [&'a Table],
-    pub(crate) block: BlockNumber,
+    pub(crate) start: BlockNumber,
+    pub(crate) end: BlockNumber,
 }
 
 impl<'a> QueryFragment<Pg> for FindPossibleDeletionsQuery<'a> {
@@ -1368,11 +1700,13 @@ impl<'a> QueryFragment<Pg> for FindPossibleDeletionsQuery<'a> {
             }
             out.push_sql("select ");
             out.push_bind_param::<Text, _>(&table.object.as_str())?;
-            out.push_sql(" as entity, e.id\n");
+            out.push_sql(" as entity, e.id, coalesce(upper(");
+            out.push_identifier(BLOCK_RANGE_COLUMN)?;
+            out.push_sql("), 2147483647) as block\n");
             out.push_sql("  from ");
             out.push_sql(table.qualified_name.as_str());
             out.push_sql(" e\n where ");
-            BlockRangeUpperBoundClause::new("e.", self.block).walk_ast(out.reborrow())?;
+            BlockRangeUpperBoundClause::new("e.", self.start, self.end).walk_ast(out.reborrow())?;
         }
 
         Ok(())
@@ -1447,6 +1781,115 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FindManyQuery<'a> {
     }
 }
 
+/// A query that finds the entities in `table` whose `column` refers to the
+/// entity with id `value`, which is how the entities for `@derivedFrom`
+/// fields are found. If `column` is a list, it must contain `value`
+#[derive(Debug, Clone, Constructor)]
+pub struct FindDerivedQuery<'a> {
+    table: &'a Table,
+    column: &'a Column,
+    value: &'a Value,
+    block: BlockNumber,
+}
+
+impl<'a> QueryFragment<Pg> for FindDerivedQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Generate
+        //    select $object as entity, to_jsonb(e.*) as data
+        //      from schema.<table> e
+        //     where e.<column> = $value          -- or $value = any(e.<column>)
+        //       and e.block_range @> $block
+        out.push_sql("select ");
+        out.push_bind_param::<Text, _>(&self.table.object.as_str())?;
+        out.push_sql(" as entity, to_jsonb(e.*) as data\n");
+        out.push_sql("  from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" e\n where ");
+        if self.column.is_list() {
+            QueryValue(self.value, &self.column.column_type).walk_ast(out.reborrow())?;
+            out.push_sql(" = any(e.");
+            out.push_identifier(self.column.name.as_str())?;
+            out.push_sql(")");
+        } else {
+            out.push_sql("e.");
+            out.push_identifier(self.column.name.as_str())?;
+            out.push_sql(" = ");
+            QueryValue(self.value, &self.column.column_type).walk_ast(out.reborrow())?;
+        }
+        out.push_sql(" and ");
+        BlockRangeColumn::new(self.table, "e.", self.block).contains(&mut out)
+    }
+}
+
+impl<'a> QueryId for FindDerivedQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, EntityData> for FindDerivedQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<EntityData>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for FindDerivedQuery<'a> {}
+
+/// The value for a fulltext column of an entity. Generates
+///   (setweight(to_tsvector($language::regconfig, $value), 'A') || ...)
+/// with one `to_tsvector` for each of the `fields` of the column that
+/// the entity has a string value for. Fields with the default weight `D`
+/// are not wrapped in `setweight` since that is what `to_tsvector`
+/// produces anyway
+struct FulltextValue<'a> {
+    config: &'a FulltextConfig,
+    fields: &'a BTreeMap<String, FulltextWeight>,
+    entity: &'a Entity,
+}
+
+impl<'a> QueryFragment<Pg> for FulltextValue<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        let values: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|(field, weight)| match self.entity.get(field) {
+                Some(Value::String(value)) => Some((value, weight)),
+                _ => None,
+            })
+            .collect();
+        if values.is_empty() {
+            out.push_sql("''::tsvector");
+            return Ok(());
+        }
+
+        out.push_sql("(");
+        for (i, (value, weight)) in values.into_iter().enumerate() {
+            if i > 0 {
+                out.push_sql(" || ");
+            }
+            if *weight != FulltextWeight::D {
+                out.push_sql("setweight(");
+            }
+            out.push_sql("to_tsvector(");
+            out.push_bind_param::<Text, _>(&self.config.language.as_str().to_string())?;
+            out.push_sql("::regconfig, ");
+            out.push_bind_param::<Text, _>(value)?;
+            out.push_sql(")");
+            if *weight != FulltextWeight::D {
+                out.push_sql(", '");
+                out.push_sql(weight.as_str());
+                out.push_sql("')");
+            }
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
 impl<'a, Conn> RunQueryDsl<Conn> for FindManyQuery<'a> {}
 
 #[derive(Debug)]
@@ -1467,7 +1910,7 @@ impl<'a> InsertQuery<'a> {
             for column in table.columns.iter() {
                 if let Some(fields) = column.fulltext_fields.as_ref() {
                     let fulltext_field_values = fields
-                        .iter()
+                        .keys()
                         .filter_map(|field| entity.get(field))
                         .cloned()
                         .collect::<Vec<Value>>();
@@ -1548,7 +1991,16 @@ impl<'a> QueryFragment<Pg> for InsertQuery<'a> {
             for column in &self.unique_columns {
                 // If the column name is not within this entity's fields, we will issue the
                 // null value in its place
-                if let Some(value) = entity.get(&column.field) {
+                if let (ColumnType::TSVector(config), Some(fields)) =
+                    (&column.column_type, &column.fulltext_fields)
+                {
+                    FulltextValue {
+                        config,
+                        fields,
+                        entity,
+                    }
+                    .walk_ast(out.reborrow())?;
+                } else if let Some(value) = entity.get(&column.field) {
                     QueryValue(value, &column.column_type).walk_ast(out.reborrow())?;
                 } else {
                     out.push_sql("null");
@@ -1797,6 +2249,7 @@ impl<'a> FilterWindow<'a> {
         layout: &'a Layout,
         window: EntityWindow,
         query_filter: Option<&'a EntityFilter>,
+        block: BlockNumber,
     ) -> Result<Self, QueryExecutionError> {
         let EntityWindow {
             child_type,
@@ -1814,7 +2267,7 @@ impl<'a> FilterWindow<'a> {
         }
 
         let query_filter = query_filter
-            .map(|filter| QueryFilter::new(filter, table))
+            .map(|filter| QueryFilter::new(filter, table, layout, block))
             .transpose()?;
         let link = TableLink::new(table, link)?;
         Ok(FilterWindow {
@@ -2144,6 +2597,7 @@ impl<'a> FilterCollection<'a> {
         layout: &'a Layout,
         collection: EntityCollection,
         filter: Option<&'a EntityFilter>,
+        block: BlockNumber,
     ) -> Result<Self, QueryExecutionError> {
         match collection {
             EntityCollection::All(entities) => {
@@ -2159,7 +2613,7 @@ impl<'a> FilterCollection<'a> {
                             .map(|rc| rc.as_ref())
                             .and_then(|table| {
                                 filter
-                                    .map(|filter| QueryFilter::new(filter, table))
+                                    .map(|filter| QueryFilter::new(filter, table, layout, block))
                                     .transpose()
                                     .map(|filter| (table, filter, column_names.clone()))
                             })
@@ -2170,7 +2624,7 @@ impl<'a> FilterCollection<'a> {
             EntityCollection::Window(windows) => {
                 let windows = windows
                     .into_iter()
-                    .map(|window| FilterWindow::new(layout, window, filter))
+                    .map(|window| FilterWindow::new(layout, window, filter, block))
                     .collect::<Result<Vec<_>, _>>()?;
                 let collection = if windows.len() == 1 {
                     let mut windows = windows;
@@ -2237,11 +2691,21 @@ pub enum SortKey<'a> {
         value: Option<&'a str>,
         direction: &'static str,
     },
+    /// Order by the column `child_column` of the entity in `child_table`
+    /// that `parent_column` refers to, as of `block`
+    ChildKey {
+        parent_column: &'a Column,
+        child_table: &'a Table,
+        child_column: &'a Column,
+        direction: &'static str,
+        block: BlockNumber,
+    },
 }
 
 impl<'a> SortKey<'a> {
     fn new(
         order: EntityOrder,
+        layout: &'a Layout,
         table: &'a Table,
         filter: Option<&'a EntityFilter>,
         block: BlockNumber,
@@ -2249,6 +2713,31 @@ impl<'a> SortKey<'a> {
         const ASC: &str = "asc";
         const DESC: &str = "desc";
 
+        fn with_child_key<'a>(
+            layout: &'a Layout,
+            table: &'a Table,
+            child: EntityOrderByChild,
+            direction: &'static str,
+            block: BlockNumber,
+        ) -> Result<SortKey<'a>, QueryExecutionError> {
+            let parent_column = table.column_for_field(&child.attr)?;
+            if parent_column.is_list() {
+                return Err(QueryExecutionError::OrderByNotSupportedError(
+                    table.object.to_string(),
+                    format!("{}__{}", child.attr, child.child_attr),
+                ));
+            }
+            let child_table = layout.table_for_entity(&child.child_type)?;
+            let child_column = child_table.column_for_field(&child.child_attr)?;
+            Ok(SortKey::ChildKey {
+                parent_column,
+                child_table,
+                child_column,
+                direction,
+                block,
+            })
+        }
+
         fn with_key<'a>(
             table: &'a Table,
             attribute: String,
@@ -2294,6 +2783,10 @@ impl<'a> SortKey<'a> {
         match order {
             EntityOrder::Ascending(attr, _) => with_key(table, attr, filter, ASC, br_column),
             EntityOrder::Descending(attr, _) => with_key(table, attr, filter, DESC, br_column),
+            EntityOrder::ChildAscending(child) => with_child_key(layout, table, child, ASC, block),
+            EntityOrder::ChildDescending(child) => {
+                with_child_key(layout, table, child, DESC, block)
+            }
             EntityOrder::Default => Ok(SortKey::IdAsc(br_column)),
             EntityOrder::Unordered => Ok(SortKey::None),
         }
@@ -2318,10 +2811,62 @@ impl<'a> SortKey<'a> {
                 if column.is_primary_key() {
                     return Err(constraint_violation!("SortKey::Key never uses 'id'"));
                 }
+                if column.is_fulltext() {
+                    return self.select_rank(out);
+                }
                 out.push_sql(", c.");
                 out.push_identifier(column.name.as_str())?;
                 Ok(())
             }
+            SortKey::ChildKey { .. } => Err(constraint_violation!(
+                "SortKey::ChildKey is only used for queries against a single table"
+            )),
+        }
+    }
+
+    /// Generate
+    ///   , ts_rank(c.{column}, to_tsquery($value)) as g$rank
+    /// if this sorts by the rank of a fulltext search. Sorting refers to
+    /// the rank by its name so that it also works for queries that combine
+    /// several tables with `union all`
+    fn select_rank(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        match self {
+            SortKey::Key {
+                column,
+                value: Some(value),
+                direction: _,
+            } => {
+                if let ColumnType::TSVector(config) = &column.column_type {
+                    let algorithm = match config.algorithm {
+                        FulltextAlgorithm::Rank => ", ts_rank(c.",
+                        FulltextAlgorithm::ProximityRank => ", ts_rank_cd(c.",
+                    };
+                    out.push_sql(algorithm);
+                    out.push_identifier(column.name.as_str())?;
+                    out.push_sql(", to_tsquery(");
+                    out.push_bind_param::<Text, _>(value)?;
+                    out.push_sql(")) as ");
+                    out.push_sql(RANK_COLUMN);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Generate
+    ///   || jsonb_build_object('g$rank', m.g$rank)
+    /// to add the rank that the `matches` CTE of a query against several
+    /// tables selected to the data of each entity
+    fn rank_data(&self, out: &mut AstPass<Pg>) {
+        if let SortKey::Key { column, .. } = self {
+            if column.is_fulltext() {
+                out.push_sql(" || jsonb_build_object('");
+                out.push_sql(RANK_COLUMN);
+                out.push_sql("', m.");
+                out.push_sql(RANK_COLUMN);
+                out.push_sql(")");
+            }
         }
     }
 
@@ -2352,11 +2897,28 @@ impl<'a> SortKey<'a> {
             }
             SortKey::Key {
                 column,
-                value,
+                value: _,
                 direction,
             } => {
                 out.push_sql("order by ");
-                SortKey::sort_expr(column, value, direction, out)
+                SortKey::sort_expr(column, direction, out)
+            }
+            SortKey::ChildKey {
+                parent_column,
+                child_table,
+                child_column,
+                direction,
+                block,
+            } => {
+                out.push_sql("order by ");
+                SortKey::child_sort_expr(
+                    parent_column,
+                    child_table,
+                    child_column,
+                    direction,
+                    *block,
+                    out,
+                )
             }
         }
     }
@@ -2378,23 +2940,35 @@ impl<'a> SortKey<'a> {
             }
             SortKey::Key {
                 column,
-                value,
+                value: _,
+                direction,
+            } => {
+                out.push_sql("order by g$parent_id, ");
+                SortKey::sort_expr(column, direction, out)
+            }
+            SortKey::ChildKey {
+                parent_column,
+                child_table,
+                child_column,
                 direction,
+                block,
             } => {
                 out.push_sql("order by g$parent_id, ");
-                SortKey::sort_expr(column, value, direction, out)
+                SortKey::child_sort_expr(
+                    parent_column,
+                    child_table,
+                    child_column,
+                    direction,
+                    *block,
+                    out,
+                )
             }
         }
     }
 
     /// Generate
     ///   [name direction,] id
-    fn sort_expr(
-        column: &Column,
-        value: &Option<&str>,
-        direction: &str,
-        out: &mut AstPass<Pg>,
-    ) -> QueryResult<()> {
+    fn sort_expr(column: &Column, direction: &str, out: &mut AstPass<Pg>) -> QueryResult<()> {
         if column.is_primary_key() {
             // This shouldn't happen since we'd use SortKey::IdAsc/Desc
             return Err(constraint_violation!(
@@ -2403,24 +2977,42 @@ impl<'a> SortKey<'a> {
         }
 
         match &column.column_type {
-            ColumnType::TSVector(config) => {
-                let algorithm = match config.algorithm {
-                    FulltextAlgorithm::Rank => "ts_rank(",
-                    FulltextAlgorithm::ProximityRank => "ts_rank_cd(",
-                };
-                out.push_sql(algorithm);
-                let name = column.name.as_str();
-                out.push_identifier(name)?;
-                out.push_sql(", to_tsquery(");
-
-                out.push_bind_param::<Text, _>(&value.unwrap())?;
-                out.push_sql("))");
-            }
+            // The rank is selected by `select_rank`
+            ColumnType::TSVector(_) => out.push_sql(RANK_COLUMN),
             _ => {
                 let name = column.name.as_str();
                 out.push_identifier(name)?;
             }
         }
+        SortKey::direction_and_id(direction, out)
+    }
+
+    /// Generate
+    ///   (select c1.{child_column} from {child_table} c1
+    ///     where c1.id = c.{parent_column} and c1.block_range @> $block) direction, id
+    fn child_sort_expr(
+        parent_column: &Column,
+        child_table: &Table,
+        child_column: &Column,
+        direction: &str,
+        block: BlockNumber,
+        out: &mut AstPass<Pg>,
+    ) -> QueryResult<()> {
+        out.push_sql("(select c1.");
+        out.push_identifier(child_column.name.as_str())?;
+        out.push_sql(" from ");
+        out.push_sql(child_table.qualified_name.as_str());
+        out.push_sql(" c1 where c1.id = c.");
+        out.push_identifier(parent_column.name.as_str())?;
+        out.push_sql(" and ");
+        BlockRangeColumn::new(child_table, "c1.", block).contains(out)?;
+        out.push_sql(")");
+        SortKey::direction_and_id(direction, out)
+    }
+
+    /// Generate
+    ///   direction, id [direction]
+    fn direction_and_id(direction: &str, out: &mut AstPass<Pg>) -> QueryResult<()> {
         if ENV_VARS.store.reversible_order_by_off {
             // Old behavior
             out.push_sql(" ");
@@ -2440,6 +3032,151 @@ impl<'a> SortKey<'a> {
     }
 }
 
+/// Turn the `after` and `before` cursors of `range` into a keyset filter
+/// that only lets entities through that come strictly after `after` and
+/// strictly before `before` when sorting by `order`. The filter has to
+/// agree with the `order by` clause that `SortKey` generates, including
+/// where it puts rows with a `null` sort value and how it breaks ties
+pub fn cursor_filter(
+    order: &EntityOrder,
+    range: &EntityRange,
+) -> Result<Option<EntityFilter>, QueryExecutionError> {
+    fn keyset(attr: &str, ascending: bool, cursor: &EntityCursor, after: bool) -> EntityFilter {
+        use EntityFilter::*;
+
+        // Entities that come later in the sort order have larger values
+        // when we are looking for the ones after an ascending cursor, or
+        // for the ones before a descending cursor
+        let larger = ascending == after;
+        let cmp = |attr: &str, value: Value| {
+            if larger {
+                GreaterThan(attr.to_string(), value)
+            } else {
+                LessThan(attr.to_string(), value)
+            }
+        };
+
+        let id = Value::String(cursor.id.clone());
+        if attr == PRIMARY_KEY_COLUMN {
+            return cmp(attr, id);
+        }
+        let (id_cmp, nulls_last) = if ENV_VARS.store.reversible_order_by_off {
+            // Old behavior: `order by attr {direction} nulls last, id`
+            let id_cmp = if after {
+                GreaterThan(PRIMARY_KEY_COLUMN.to_string(), id)
+            } else {
+                LessThan(PRIMARY_KEY_COLUMN.to_string(), id)
+            };
+            (id_cmp, true)
+        } else {
+            // `order by attr {direction}, id {direction}`, and Postgres
+            // puts nulls last for `asc` and first for `desc`
+            (cmp(PRIMARY_KEY_COLUMN, id), ascending)
+        };
+        // Whether all entities where `attr` is null are on the side of
+        // the cursor that we are looking for
+        let nulls_wanted = nulls_last == after;
+        let is_null = Equal(attr.to_string(), Value::Null);
+
+        match &cursor.value {
+            Value::Null => {
+                let ties = And(vec![is_null, id_cmp]);
+                if nulls_wanted {
+                    ties
+                } else {
+                    Or(vec![Not(attr.to_string(), Value::Null), ties])
+                }
+            }
+            value => {
+                let mut filters = vec![
+                    cmp(attr, value.clone()),
+                    And(vec![Equal(attr.to_string(), value.clone()), id_cmp]),
+                ];
+                if nulls_wanted {
+                    filters.push(is_null);
+                }
+                Or(filters)
+            }
+        }
+    }
+
+    if range.after.is_none() && range.before.is_none() {
+        return Ok(None);
+    }
+
+    let (attr, ascending) = match order {
+        EntityOrder::Ascending(attr, _) => (attr.as_str(), true),
+        EntityOrder::Descending(attr, _) => (attr.as_str(), false),
+        EntityOrder::Default => (PRIMARY_KEY_COLUMN, true),
+        EntityOrder::Unordered => return Ok(None),
+        EntityOrder::ChildAscending(_) | EntityOrder::ChildDescending(_) => {
+            return Err(QueryExecutionError::NotSupported(
+                "cursors can not be used when ordering by fields of referenced entities"
+                    .to_string(),
+            ))
+        }
+    };
+
+    let mut filters: Vec<_> = range
+        .after
+        .iter()
+        .map(|cursor| keyset(attr, ascending, cursor, true))
+        .chain(
+            range
+                .before
+                .iter()
+                .map(|cursor| keyset(attr, ascending, cursor, false)),
+        )
+        .collect();
+    if filters.len() == 1 {
+        Ok(filters.pop())
+    } else {
+        Ok(Some(EntityFilter::And(filters)))
+    }
+}
+
+/// The order in which to run a query with a `before` cursor that also
+/// limits how many entities it returns. Since `first` and `skip` have to
+/// count backwards from `before`, such a query has to run with the reverse
+/// of `order`, and the caller has to reverse its results to restore the
+/// order the user asked for. Returns `None` if `order` can be used as is
+pub fn reversed_order(
+    order: &EntityOrder,
+    range: &EntityRange,
+) -> Result<Option<EntityOrder>, QueryExecutionError> {
+    if range.before.is_none() || (range.first.is_none() && range.skip == 0) {
+        return Ok(None);
+    }
+    let reversed = match order {
+        EntityOrder::Ascending(attr, value_type) => {
+            EntityOrder::Descending(attr.clone(), value_type.clone())
+        }
+        EntityOrder::Descending(attr, value_type) => {
+            EntityOrder::Ascending(attr.clone(), value_type.clone())
+        }
+        EntityOrder::Default => {
+            EntityOrder::Descending(PRIMARY_KEY_COLUMN.to_string(), ValueType::String)
+        }
+        EntityOrder::Unordered => return Ok(None),
+        EntityOrder::ChildAscending(_) | EntityOrder::ChildDescending(_) => {
+            return Err(QueryExecutionError::NotSupported(
+                "cursors can not be used when ordering by fields of referenced entities"
+                    .to_string(),
+            ))
+        }
+    };
+    if ENV_VARS.store.reversible_order_by_off {
+        // The old `order by attr {direction} nulls last, id` is not the
+        // reverse of itself with the direction flipped
+        return Err(QueryExecutionError::NotSupported(
+            "`before` can not be used with `first` or `skip` when \
+             REVERSIBLE_ORDER_BY_OFF is set"
+                .to_string(),
+        ));
+    }
+    Ok(Some(reversed))
+}
+
 /// Generate `[limit {first}] [offset {skip}]
 #[derive(Debug, Clone)]
 pub struct FilterRange(EntityRange);
@@ -2475,6 +3212,7 @@ pub struct FilterQuery<'a> {
 impl<'a> FilterQuery<'a> {
     pub fn new(
         collection: &'a FilterCollection,
+        layout: &'a Layout,
         filter: Option<&'a EntityFilter>,
         order: EntityOrder,
         range: EntityRange,
@@ -2488,7 +3226,25 @@ impl<'a> FilterQuery<'a> {
         let first_table = collection
             .first_table()
             .expect("an entity query always contains at least one entity type/table");
-        let sort_key = SortKey::new(order, first_table, filter, block)?;
+        let sort_key = SortKey::new(order, layout, first_table, filter, block)?;
+
+        // Queries against several tables or windows combine their results
+        // with `union all`, and the order of such a query can only refer to
+        // output columns, not to other tables
+        if let SortKey::ChildKey { .. } = sort_key {
+            let multiple = match collection {
+                FilterCollection::All(entities) => entities.len() > 1,
+                FilterCollection::SingleWindow(_) => false,
+                FilterCollection::MultiWindow(_, _) => true,
+            };
+            if multiple {
+                return Err(QueryExecutionError::NotSupported(
+                    "sorting by fields of referenced entities is not supported \
+                     for queries of interfaces"
+                        .to_string(),
+                ));
+            }
+        }
 
         Ok(FilterQuery {
             collection,
@@ -2552,6 +3308,7 @@ impl<'a> FilterQuery<'a> {
         Self::select_entity_and_data(table, &mut out);
         out.push_sql(" from (select ");
         write_column_names(column_names, table, &mut out)?;
+        self.sort_key.select_rank(&mut out)?;
         self.filtered_rows(table, filter, out.reborrow())?;
         out.push_sql("\n ");
         self.sort_key.order_by(&mut out)?;
@@ -2645,6 +3402,7 @@ impl<'a> FilterQuery<'a> {
             }
             out.push_sql("select m.entity, ");
             jsonb_build_object(column_names, "c", table, &mut out)?;
+            self.sort_key.rank_data(&mut out);
             out.push_sql(" as data, c.id");
             self.sort_key.select(&mut out)?;
             out.push_sql("\n  from ");
@@ -2800,6 +3558,231 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FilterQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for FilterQuery<'a> {}
 
+/// Helper struct for loading the result of an `AggregateQuery`. Since
+/// aggregates have different types, we load them all as text and convert
+/// them with `AggregateQuery::values`
+#[derive(QueryableByName, Debug)]
+pub struct AggregateData {
+    #[sql_type = "Array<Nullable<Text>>"]
+    aggregates: Vec<Option<String>>,
+}
+
+/// Compute aggregates like `count` and `sum` over the entities in `table`
+/// that match `filter` as of `block`
+#[derive(Debug, Clone)]
+pub struct AggregateQuery<'a> {
+    table: &'a Table,
+    filter: Option<QueryFilter<'a>>,
+    /// The name of the SQL aggregate function, and the column to apply it
+    /// to. The column is `None` for `count`
+    aggregates: Vec<(&'static str, Option<&'a Column>)>,
+    block: BlockNumber,
+    query_id: Option<String>,
+}
+
+impl<'a> AggregateQuery<'a> {
+    pub fn new(
+        layout: &'a Layout,
+        entity_type: &EntityType,
+        filter: Option<&'a EntityFilter>,
+        aggregates: &'a [EntityAggregate],
+        block: BlockNumber,
+        query_id: Option<String>,
+    ) -> Result<Self, QueryExecutionError> {
+        let table = layout.table_for_entity(entity_type)?.as_ref();
+        let filter = filter
+            .map(|filter| QueryFilter::new(filter, table, layout, block))
+            .transpose()?;
+        let aggregates = aggregates
+            .iter()
+            .map(|aggregate| {
+                let (function, attr) = match aggregate {
+                    EntityAggregate::Count => return Ok(("count", None)),
+                    EntityAggregate::Sum(attr) => ("sum", attr),
+                    EntityAggregate::Avg(attr) => ("avg", attr),
+                    EntityAggregate::Min(attr) => ("min", attr),
+                    EntityAggregate::Max(attr) => ("max", attr),
+                };
+                let column = table.column_for_field(attr)?;
+                let numeric = matches!(
+                    column.column_type,
+                    ColumnType::Int
+                        | ColumnType::Int8
+                        | ColumnType::BigInt
+                        | ColumnType::BigDecimal
+                );
+                if !numeric || column.is_list() {
+                    return Err(QueryExecutionError::NotSupported(format!(
+                        "computing `{}` of `{}.{}` is not supported",
+                        function, table.object, attr
+                    )));
+                }
+                Ok((function, Some(column)))
+            })
+            .collect::<Result<_, QueryExecutionError>>()?;
+        Ok(AggregateQuery {
+            table,
+            filter,
+            aggregates,
+            block,
+            query_id,
+        })
+    }
+
+    /// Convert the text representation of the aggregates in `data` into
+    /// values of the appropriate type. `count` is an `Int8`, `sum` a
+    /// `BigInt` or `BigDecimal`, `avg` a `BigDecimal`, and `min` and `max`
+    /// have the type of their column
+    pub fn values(&self, data: AggregateData) -> Result<Vec<Value>, StoreError> {
+        fn parse<T: FromStr>(text: &str) -> Result<T, StoreError> {
+            text.parse()
+                .map_err(|_| constraint_violation!("invalid aggregate value `{}`", text))
+        }
+
+        if data.aggregates.len() != self.aggregates.len() {
+            return Err(constraint_violation!(
+                "expected {} aggregates but got {}",
+                self.aggregates.len(),
+                data.aggregates.len()
+            ));
+        }
+
+        self.aggregates
+            .iter()
+            .zip(data.aggregates)
+            .map(|((function, column), text)| {
+                let text = match text {
+                    Some(text) => text,
+                    None => return Ok(Value::Null),
+                };
+                let column_type = column.map(|column| &column.column_type);
+                match (*function, column_type) {
+                    ("count", _) => parse(&text).map(Value::Int8),
+                    ("sum", Some(ColumnType::BigDecimal)) | ("avg", _) => {
+                        parse(&text).map(Value::BigDecimal)
+                    }
+                    ("sum", _) => parse(&text).map(Value::BigInt),
+                    (_, Some(ColumnType::Int)) => parse(&text).map(Value::Int),
+                    (_, Some(ColumnType::Int8)) => parse(&text).map(Value::Int8),
+                    (_, Some(ColumnType::BigInt)) => parse(&text).map(Value::BigInt),
+                    (_, Some(ColumnType::BigDecimal)) => parse(&text).map(Value::BigDecimal),
+                    (function, column_type) => Err(constraint_violation!(
+                        "can not convert `{}` of a {:?} column",
+                        function,
+                        column_type
+                    )),
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a> QueryFragment<Pg> for AggregateQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        if let Some(qid) = &self.query_id {
+            out.push_sql("/* qid: ");
+            out.push_sql(qid);
+            out.push_sql(" */\n");
+        }
+
+        // Generate
+        //   select array[count(*)::text, sum(c.column)::text, ..] as aggregates
+        //     from table c
+        //    where block_range @> $block
+        //      and query_filter
+        out.push_sql("select array[");
+        for (i, (function, column)) in self.aggregates.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            out.push_sql(function);
+            match column {
+                Some(column) => {
+                    out.push_sql("(c.");
+                    out.push_identifier(column.name.as_str())?;
+                    out.push_sql(")");
+                }
+                None => out.push_sql("(*)"),
+            }
+            out.push_sql("::text");
+        }
+        out.push_sql("]::text[] as aggregates");
+        out.push_sql("\n  from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" c");
+        out.push_sql("\n where ");
+        BlockRangeColumn::new(self.table, "c.", self.block).contains(&mut out)?;
+        if let Some(filter) = &self.filter {
+            out.push_sql(" and ");
+            filter.walk_ast(out.reborrow())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for AggregateQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, AggregateData> for AggregateQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<AggregateData>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for AggregateQuery<'a> {}
+
+/// One line of the output of `explain`
+#[derive(Debug)]
+pub struct ExplainLine(pub String);
+
+impl QueryableByName<Pg> for ExplainLine {
+    // The name of the column contains a space and can therefore not be
+    // used with `derive(QueryableByName)`
+    fn build<R: NamedRow<Pg>>(row: &R) -> deserialize::Result<Self> {
+        row.get::<Text, String>("QUERY PLAN").map(ExplainLine)
+    }
+}
+
+/// Run `query` with `explain (analyze, buffers)`. Since that executes
+/// `query`, it takes as long as running `query` itself
+#[derive(Debug, Clone)]
+pub struct ExplainQuery<Q> {
+    query: Q,
+}
+
+impl<Q: QueryFragment<Pg>> ExplainQuery<Q> {
+    pub fn new(query: Q) -> Self {
+        ExplainQuery { query }
+    }
+}
+
+impl<Q: QueryFragment<Pg>> QueryFragment<Pg> for ExplainQuery<Q> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("explain (analyze, buffers) ");
+        self.query.walk_ast(out)
+    }
+}
+
+impl<Q> QueryId for ExplainQuery<Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Q: QueryFragment<Pg>> LoadQuery<PgConnection, ExplainLine> for ExplainQuery<Q> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<ExplainLine>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<Q, Conn> RunQueryDsl<Conn> for ExplainQuery<Q> {}
+
 /// Reduce the upper bound of the current entry's block range to `block` as
 /// long as that does not result in an empty block range
 #[derive(Debug)]
@@ -3023,6 +4006,73 @@ impl<'a> LoadQuery<PgConnection, ReturnedEntityData> for RevertClampQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for RevertClampQuery<'a> {}
 
+/// Remove all versions of entities with a `vid` between `first_vid` and
+/// `last_vid` (inclusive) that are not visible at `earliest_block` or any
+/// later block, i.e., whose block range ends at or before `earliest_block`
+#[derive(Debug, Clone)]
+pub struct PruneQuery<'a> {
+    table: &'a Table,
+    earliest_block: BlockNumber,
+    first_vid: i64,
+    last_vid: i64,
+}
+
+impl<'a> PruneQuery<'a> {
+    pub(crate) fn new(
+        table: &'a Table,
+        earliest_block: BlockNumber,
+        first_vid: i64,
+        last_vid: i64,
+    ) -> Result<Self, StoreError> {
+        if table.immutable {
+            Err(graph::constraint_violation!(
+                "can not prune immutable table `{}` since it has no history",
+                table.qualified_name
+            ))
+        } else {
+            Ok(Self {
+                table,
+                earliest_block,
+                first_vid,
+                last_vid,
+            })
+        }
+    }
+}
+
+impl<'a> QueryFragment<Pg> for PruneQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Construct a query
+        //   delete from table
+        //    where vid >= $first_vid and vid <= $last_vid
+        //      and upper(block_range) <= $earliest_block
+        //
+        // Current versions have an unbounded block range whose upper bound
+        // is null and are therefore never deleted
+        out.push_sql("delete from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql("\n where vid >= ");
+        out.push_bind_param::<BigInt, _>(&self.first_vid)?;
+        out.push_sql(" and vid <= ");
+        out.push_bind_param::<BigInt, _>(&self.last_vid)?;
+        out.push_sql("\n   and upper(");
+        out.push_identifier(BLOCK_RANGE_COLUMN)?;
+        out.push_sql(") <= ");
+        out.push_bind_param::<Integer, _>(&self.earliest_block)?;
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for PruneQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for PruneQuery<'a> {}
+
 #[test]
 fn block_number_max_is_i32_max() {
     // The code in RevertClampQuery::walk_ast embeds i32::MAX
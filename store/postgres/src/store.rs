@@ -6,6 +6,7 @@ use graph::{
         server::index_node::VersionInfo,
         store::{
             BlockStore as BlockStoreTrait, QueryStoreManager, StatusStore, Store as StoreTrait,
+            SubgraphStore as SubgraphStoreTrait,
         },
     },
     constraint_violation,
@@ -94,6 +95,15 @@ impl QueryStoreManager for Store {
 
         Ok(Arc::new(QueryStore::new(store, chain_store, site, replica)))
     }
+
+    async fn deployment_exists(&self, id: &DeploymentHash) -> Result<bool, QueryExecutionError> {
+        let store = self.subgraph_store.cheap_clone();
+        let id = id.clone();
+        graph::spawn_blocking_allow_panic(move || store.is_deployed(&id).map_err(|e| e.into()))
+            .await
+            .map_err(|e| QueryExecutionError::Panic(e.to_string()))
+            .and_then(|x| x)
+    }
 }
 
 #[async_trait]
@@ -5,7 +5,7 @@ use diesel::{
     types::{FromSql, ToSql},
 };
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     sync::{Arc, Mutex},
 };
 use std::{fmt, io::Write};
@@ -15,7 +15,10 @@ use graph::{
     cheap_clone::CheapClone,
     components::{
         server::index_node::VersionInfo,
-        store::{self, BlockStore, DeploymentLocator, EnsLookup as EnsLookupTrait, SubgraphFork},
+        store::{
+            self, BlockStore, DeploymentLocator, EnsLookup as EnsLookupTrait,
+            PersistedQueryStore as PersistedQueryStoreTrait, SubgraphFork,
+        },
     },
     constraint_violation,
     data::query::QueryTarget,
@@ -23,9 +26,9 @@ use graph::{
     prelude::StoreEvent,
     prelude::{
         anyhow, futures03::future::join_all, lazy_static, o, web3::types::Address, ApiSchema,
-        BlockHash, BlockNumber, BlockPtr, ChainStore, DeploymentHash, EntityOperation, Logger,
-        MetricsRegistry, NodeId, PartialBlockPtr, Schema, StoreError, SubgraphName,
-        SubgraphStore as SubgraphStoreTrait, SubgraphVersionSwitchingMode,
+        BlockHash, BlockNumber, BlockPtr, ChainStore, DeploymentHash, DeploymentState,
+        EntityOperation, Logger, MetricsRegistry, NodeId, PartialBlockPtr, Schema, StoreError,
+        SubgraphName, SubgraphStore as SubgraphStoreTrait, SubgraphVersionSwitchingMode, ENV_VARS,
     },
     url::Url,
     util::timed_cache::TimedCache,
@@ -224,6 +227,64 @@ impl SubgraphStore {
         }
     }
 
+    /// Set how many blocks of history the deployment should keep when it
+    /// is pruned automatically. With `None`, the deployment keeps its
+    /// entire history
+    pub fn set_history_blocks(
+        &self,
+        deployment: &DeploymentLocator,
+        history_blocks: Option<BlockNumber>,
+    ) -> Result<(), StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.set_history_blocks(site.as_ref(), history_blocks)
+    }
+
+    /// Look up how many blocks of history the deployment keeps; `None`
+    /// means that it keeps its entire history
+    pub fn history_blocks(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<BlockNumber>, StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.history_blocks(site.as_ref())
+    }
+
+    /// Prune the deployment so that it only keeps history for the last
+    /// `history_blocks` blocks. Returns the earliest block for which the
+    /// deployment has data and the number of entity versions that were
+    /// removed
+    pub fn prune(
+        &self,
+        deployment: &DeploymentLocator,
+        history_blocks: BlockNumber,
+    ) -> Result<(BlockNumber, usize), StoreError> {
+        let (store, site) = self.store(&deployment.hash)?;
+        store.prune(site, history_blocks)
+    }
+
+    /// Return all deployments in all shards that keep only a limited
+    /// number of blocks of history, together with that number
+    pub(crate) fn deployments_with_history_blocks(
+        &self,
+    ) -> Result<Vec<(Arc<Site>, BlockNumber)>, StoreError> {
+        let mut deployments = Vec::new();
+        for store in self.stores.values() {
+            for (id, history_blocks) in store.deployments_with_history_blocks()? {
+                deployments.push((self.find_site(id)?, history_blocks));
+            }
+        }
+        Ok(deployments)
+    }
+
+    pub(crate) fn prune_site(
+        &self,
+        site: Arc<Site>,
+        history_blocks: BlockNumber,
+    ) -> Result<(BlockNumber, usize), StoreError> {
+        let store = self.for_site(site.as_ref())?;
+        store.prune(site, history_blocks)
+    }
+
     pub(crate) async fn get_proof_of_indexing(
         &self,
         id: &DeploymentHash,
@@ -405,6 +466,30 @@ impl SubgraphStoreInner {
         store.find_layout(site)
     }
 
+    /// The earliest block for which the deployment still has data
+    pub(crate) fn earliest_block(&self, id: &DeploymentHash) -> Result<BlockNumber, StoreError> {
+        let (store, site) = self.store(id)?;
+        store.earliest_block(site.as_ref())
+    }
+
+    /// Fail if the history of `base` at `block` has been pruned. Grafting
+    /// onto or copying a deployment at such a block would lose data
+    fn check_not_pruned(
+        base: &DeploymentHash,
+        block: BlockNumber,
+        earliest_block: BlockNumber,
+    ) -> Result<(), StoreError> {
+        if block < earliest_block {
+            return Err(StoreError::Unknown(anyhow!(
+                "can not use block {} of `{}` since its history before block {} has been pruned",
+                block,
+                base,
+                earliest_block
+            )));
+        }
+        Ok(())
+    }
+
     fn place_on_node(
         &self,
         mut nodes: Vec<NodeId>,
@@ -513,6 +598,9 @@ impl SubgraphStoreInner {
         };
         let site = Arc::new(site);
 
+        if let (Some(base), Some(block)) = (&deployment.graft_base, &deployment.graft_block) {
+            Self::check_not_pruned(base, block.number, self.earliest_block(base)?)?;
+        }
         let graft_base = deployment
             .graft_base
             .as_ref()
@@ -594,6 +682,11 @@ impl SubgraphStoreInner {
                 src_loc
             )));
         }
+        Self::check_not_pruned(
+            &src.deployment,
+            block.number,
+            src_store.earliest_block(src.as_ref())?,
+        )?;
 
         // Transmogrify the deployment into a new one
         let deployment = DeploymentCreate {
@@ -735,6 +828,13 @@ impl SubgraphStoreInner {
         Ok(by_shard)
     }
 
+    /// Remove the oldest automatic persisted queries from the primary so
+    /// that at most `GRAPH_GRAPHQL_MAX_PERSISTED_QUERIES` remain
+    pub fn remove_old_persisted_queries(&self) -> Result<usize, StoreError> {
+        self.primary_conn()?
+            .remove_old_persisted_queries(ENV_VARS.graphql.max_persisted_queries)
+    }
+
     /// Look for new unused deployments and add them to the `unused_deployments`
     /// table
     pub fn record_unused_deployments(&self) -> Result<Vec<DeploymentDetail>, StoreError> {
@@ -1049,6 +1149,22 @@ impl EnsLookupTrait for EnsLookup {
     }
 }
 
+struct PersistedQueryStore {
+    primary: ConnectionPool,
+}
+
+impl PersistedQueryStoreTrait for PersistedQueryStore {
+    fn find_query(&self, hash: &str) -> Result<Option<String>, StoreError> {
+        let conn = self.primary.get()?;
+        primary::Connection::new(conn).find_persisted_query(hash)
+    }
+
+    fn insert_query(&self, hash: &str, query: &str) -> Result<(), StoreError> {
+        let conn = self.primary.get()?;
+        primary::Connection::new(conn).insert_persisted_query(hash, query)
+    }
+}
+
 #[async_trait::async_trait]
 impl SubgraphStoreTrait for SubgraphStore {
     fn ens_lookup(&self) -> Arc<dyn EnsLookupTrait> {
@@ -1057,6 +1173,12 @@ impl SubgraphStoreTrait for SubgraphStore {
         })
     }
 
+    fn persisted_queries(&self) -> Arc<dyn PersistedQueryStoreTrait> {
+        Arc::new(PersistedQueryStore {
+            primary: self.mirror.primary().clone(),
+        })
+    }
+
     // FIXME: This method should not get a node_id
     fn create_subgraph_deployment(
         &self,
@@ -1104,11 +1226,37 @@ impl SubgraphStoreTrait for SubgraphStore {
         })
     }
 
+    fn pause_subgraph(&self, deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let pconn = self.primary_conn()?;
+        pconn.transaction(|| -> Result<_, StoreError> {
+            let changes = pconn.pause_subgraph(site.as_ref())?;
+            pconn.send_store_event(&self.sender, &StoreEvent::new(changes))
+        })
+    }
+
+    fn resume_subgraph(&self, deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let pconn = self.primary_conn()?;
+        pconn.transaction(|| -> Result<_, StoreError> {
+            let changes = pconn.resume_subgraph(site.as_ref())?;
+            pconn.send_store_event(&self.sender, &StoreEvent::new(changes))
+        })
+    }
+
     fn assigned_node(&self, deployment: &DeploymentLocator) -> Result<Option<NodeId>, StoreError> {
         let site = self.find_site(deployment.id.into())?;
         self.mirror.assigned_node(site.as_ref())
     }
 
+    fn assignment_status(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<(NodeId, bool)>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.mirror.assignment_status(site.as_ref())
+    }
+
     fn assignments(&self, node: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
         self.mirror
             .assignments(node)
@@ -1129,6 +1277,35 @@ impl SubgraphStoreTrait for SubgraphStore {
         Ok(changes)
     }
 
+    fn entity_changes_in_block_range(
+        &self,
+        subgraph_id: &DeploymentHash,
+        start: BlockNumber,
+        end: BlockNumber,
+    ) -> Result<BTreeMap<BlockNumber, Vec<EntityOperation>>, StoreError> {
+        let (store, site) = self.store(subgraph_id)?;
+        store.get_changes_in_range(site, start, end)
+    }
+
+    fn reverts_since(
+        &self,
+        subgraph_id: &DeploymentHash,
+        reorg_count: u32,
+    ) -> Result<Vec<BlockNumber>, StoreError> {
+        let (store, site) = self.store(subgraph_id)?;
+        store.reverts_since(&site, reorg_count)
+    }
+
+    async fn deployment_state(
+        &self,
+        subgraph_id: &DeploymentHash,
+    ) -> Result<DeploymentState, StoreError> {
+        let (store, site) = self.store(subgraph_id)?;
+        store
+            .deployment_state_from_id(site.deployment.clone())
+            .await
+    }
+
     fn input_schema(&self, id: &DeploymentHash) -> Result<Arc<Schema>, StoreError> {
         let (store, site) = self.store(id)?;
         let info = store.subgraph_info(&site)?;
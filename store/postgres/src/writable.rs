@@ -12,7 +12,9 @@ use graph::slog::info;
 use graph::util::bounded_queue::BoundedQueue;
 use graph::{
     cheap_clone::CheapClone,
-    components::store::{self, EntityType, WritableStore as WritableStoreTrait},
+    components::store::{
+        self, DerivedEntityQuery, EntityType, WritableStore as WritableStoreTrait,
+    },
     data::subgraph::schema::SubgraphError,
     prelude::{
         BlockPtr, DeploymentHash, EntityKey, EntityModification, Error, Logger, StopwatchMetrics,
@@ -55,6 +57,10 @@ impl WritableSubgraphStore {
     fn layout(&self, id: &DeploymentHash) -> Result<Arc<Layout>, StoreError> {
         self.0.layout(id)
     }
+
+    fn earliest_block(&self, id: &DeploymentHash) -> Result<BlockNumber, StoreError> {
+        self.0.earliest_block(id)
+    }
 }
 
 /// Write synchronously to the actual store, i.e., once a method returns,
@@ -173,7 +179,8 @@ impl SyncStore {
             let graft_base = match store.graft_pending(&self.site.deployment)? {
                 Some((base_id, base_ptr)) => {
                     let src = self.store.layout(&base_id)?;
-                    Some((src, base_ptr))
+                    let earliest_block = self.store.earliest_block(&base_id)?;
+                    Some((src, base_ptr, earliest_block))
                 }
                 None => None,
             };
@@ -292,6 +299,17 @@ impl SyncStore {
         })
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+        block: BlockNumber,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        self.retry("get_derived", || {
+            self.writable
+                .get_derived(self.site.cheap_clone(), query, block)
+        })
+    }
+
     async fn is_deployment_synced(&self) -> Result<bool, StoreError> {
         self.retry_async("is_deployment_synced", || async {
             self.writable
@@ -729,6 +747,57 @@ impl Queue {
         Ok(map)
     }
 
+    /// Get the entities matching `query` by looking at both the queue and
+    /// the store
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        // See the implementation of `get` for how we handle reverts
+        let mut tracker = BlockTracker::new();
+
+        // The newest version of each entity of the type we are looking for
+        // that is still in the queue; `None` means the entity was removed
+        let queued = self.queue.fold(
+            BTreeMap::new(),
+            |mut map: BTreeMap<EntityKey, Option<Entity>>, req| {
+                tracker.update(req.as_ref());
+                match req.as_ref() {
+                    Request::Write {
+                        block_ptr, mods, ..
+                    } => {
+                        if tracker.visible(block_ptr) {
+                            for emod in mods {
+                                let key = emod.entity_key();
+                                if key.entity_type == query.entity_type {
+                                    map.entry(key.clone())
+                                        .or_insert_with(|| emod.entity().cloned());
+                                }
+                            }
+                        }
+                    }
+                    Request::RevertTo { .. } => { /* nothing to do */ }
+                }
+                map
+            },
+        );
+
+        // Entities from the store are only current if the queue does not
+        // have a newer version of them
+        let mut entities = self.store.get_derived(query, tracker.query_block())?;
+        for (key, entity) in queued {
+            match entity {
+                Some(entity) if query.matches(&entity) => {
+                    entities.insert(key, entity);
+                }
+                _ => {
+                    entities.remove(&key);
+                }
+            }
+        }
+        Ok(entities)
+    }
+
     /// Load dynamic data sources by looking at both the queue and the store
     async fn load_dynamic_data_sources(&self) -> Result<Vec<StoredDynamicDataSource>, StoreError> {
         // See the implementation of `get` for how we handle reverts
@@ -869,6 +938,16 @@ impl Writer {
         }
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        match self {
+            Writer::Sync(store) => store.get_derived(query, BLOCK_NUMBER_MAX),
+            Writer::Async(queue) => queue.get_derived(query),
+        }
+    }
+
     async fn load_dynamic_data_sources(&self) -> Result<Vec<StoredDynamicDataSource>, StoreError> {
         match self {
             Writer::Sync(store) => store.load_dynamic_data_sources(BLOCK_NUMBER_MAX).await,
@@ -1022,6 +1101,13 @@ impl WritableStoreTrait for WritableStore {
         self.writer.get_many(ids_for_type)
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        self.writer.get_derived(query)
+    }
+
     fn deployment_synced(&self) -> Result<(), StoreError> {
         self.store.deployment_synced()
     }
@@ -244,6 +244,68 @@ fn block_hashes_by_number() {
     })
 }
 
+#[test]
+fn block_ptr_by_timestamp() {
+    // Fake blocks have a timestamp of ten times their block number.
+    // BLOCK_ONE_SIBLING has the same timestamp as BLOCK_ONE but is not on
+    // the chain that ends in the chain head
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_ONE_SIBLING,
+        &*BLOCK_ONE,
+        &*BLOCK_TWO,
+    ];
+    run_test_async(chain, move |store, _| async move {
+        // Without a chain head, we can not tell which blocks are canonical
+        assert_eq!(None, store.block_ptr_by_timestamp(15).unwrap());
+
+        store
+            .clone()
+            .attempt_chain_head_update(ANCESTOR_COUNT)
+            .await
+            .expect("attempt_chain_head_update failed");
+
+        let ptr = store.block_ptr_by_timestamp(0).unwrap();
+        assert_eq!(Some(GENESIS_BLOCK.block_ptr()), ptr);
+
+        let ptr = store.block_ptr_by_timestamp(15).unwrap();
+        assert_eq!(Some(BLOCK_ONE.block_ptr()), ptr);
+
+        let ptr = store.block_ptr_by_timestamp(20).unwrap();
+        assert_eq!(Some(BLOCK_TWO.block_ptr()), ptr);
+
+        let ptr = store.block_ptr_by_timestamp(1_000_000).unwrap();
+        assert_eq!(Some(BLOCK_TWO.block_ptr()), ptr);
+    })
+}
+
+#[test]
+fn block_ptr_by_timestamp_with_gap() {
+    // BLOCK_ONE is missing from the cache
+    let chain = vec![
+        &*GENESIS_BLOCK,
+        &*BLOCK_TWO,
+        &*BLOCK_THREE,
+        &*BLOCK_FOUR,
+        &*BLOCK_FIVE,
+    ];
+    run_test_async(chain, move |store, _| async move {
+        store
+            .clone()
+            .attempt_chain_head_update(ANCESTOR_COUNT)
+            .await
+            .expect("attempt_chain_head_update failed");
+
+        let ptr = store.block_ptr_by_timestamp(25).unwrap();
+        assert_eq!(Some(BLOCK_TWO.block_ptr()), ptr);
+
+        // The genesis block is in the cache, but we can't tell whether it
+        // is the right answer since there is a gap after it
+        assert_eq!(None, store.block_ptr_by_timestamp(15).unwrap());
+        assert_eq!(None, store.block_ptr_by_timestamp(0).unwrap());
+    })
+}
+
 #[track_caller]
 fn check_ancestor(
     store: &Arc<DieselChainStore>,
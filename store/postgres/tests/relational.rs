@@ -6,8 +6,8 @@ use graph::entity;
 use graph::prelude::BlockNumber;
 use graph::prelude::{
     o, slog, tokio, web3::types::H256, DeploymentHash, Entity, EntityCollection, EntityFilter,
-    EntityKey, EntityOrder, EntityQuery, EntityRange, Logger, Schema, StopwatchMetrics, Value,
-    ValueType, BLOCK_NUMBER_MAX,
+    EntityKey, EntityModification, EntityOperation, EntityOrder, EntityQuery, EntityRange, Logger,
+    Schema, StopwatchMetrics, Value, ValueType, BLOCK_NUMBER_MAX,
 };
 use graph_mock::MockMetricsRegistry;
 use graph_store_postgres::layout_for_tests::set_account_like;
@@ -23,8 +23,9 @@ use std::thread::sleep;
 use std::time::Duration;
 
 use graph::{
-    components::store::{AttributeNames, EntityType},
-    data::store::scalar::{BigDecimal, BigInt, Bytes},
+    components::store::{AttributeNames, EntityAggregate, EntityCursor, EntityType},
+    data::query::QueryExplain,
+    data::store::scalar::{BigDecimal, BigInt, Bytes, Timestamp},
 };
 use graph_store_postgres::{
     layout_for_tests::make_dummy_site,
@@ -61,6 +62,16 @@ const THINGS_GQL: &str = r#"
                 ]
             }
         ]
+    ) @fulltext(
+        name: "petSearch"
+        language: en
+        algorithm: rank
+        interface: "Pet"
+        include: [
+            { entity: "Cat", fields: [{ name: "name", weight: A }] },
+            { entity: "Dog", fields: [{ name: "name", weight: B }] },
+            { entity: "Ferret", fields: [{ name: "name" }] }
+        ]
     )
 
     type Thing @entity {
@@ -82,6 +93,9 @@ const THINGS_GQL: &str = r#"
         byteArray: [Bytes!],
         bigInt: BigInt,
         bigIntArray: [BigInt!]!
+        int8: Int8,
+        int8Array: [Int8!],
+        timestamp: Timestamp,
         color: Color,
     }
 
@@ -181,6 +195,9 @@ lazy_static! {
             byteArray: vec![*BYTES_VALUE, *BYTES_VALUE2, *BYTES_VALUE3],
             bigInt: big_int.clone(),
             bigIntArray: vec![big_int.clone(), (big_int + 1.into()).clone()],
+            int8: std::i64::MAX,
+            int8Array: vec![std::i64::MIN, 0i64, std::i64::MAX],
+            timestamp: Timestamp::since_epoch(1_640_995_200_123_456).unwrap(),
             color: "yellow",
             __typename: "Scalar",
         }
@@ -727,9 +744,12 @@ fn count_scalar_entities(conn: &PgConnection, layout: &Layout) -> usize {
             EntityRange {
                 first: None,
                 skip: 0,
+                after: None,
+                before: None,
             },
             BLOCK_NUMBER_MAX,
             None,
+            None,
         )
         .expect("Count query failed")
         .len()
@@ -777,6 +797,75 @@ fn delete() {
     });
 }
 
+#[test]
+fn find_changes_with_immutable_entities() {
+    run_test(|conn, layout| {
+        insert_pet(conn, layout, "Cat", "garfield", "Garfield", 0);
+        insert_pet(conn, layout, "Dog", "pluto", "Pluto", 1);
+        insert_entity_at(
+            conn,
+            layout,
+            "Mink",
+            vec![entity! { id: "m1", order: 1 }],
+            1,
+        );
+        let pluto = entity! { id: "pluto", name: "Pluto the Dog" };
+        update_entity_at(conn, layout, "Dog", vec![pluto], 2);
+        insert_entity_at(
+            conn,
+            layout,
+            "Mink",
+            vec![entity! { id: "m2", order: 2 }],
+            2,
+        );
+        layout
+            .delete(
+                conn,
+                &EntityType::from("Cat"),
+                &["garfield"],
+                2,
+                &MOCK_STOPWATCH,
+            )
+            .expect("Failed to delete");
+
+        let changes = layout
+            .find_changes_in_range(conn, 1, 2)
+            .expect("Failed to find changes");
+        let mut changes: Vec<_> = changes
+            .into_iter()
+            .flat_map(|(block, ops)| {
+                ops.into_iter().map(move |op| {
+                    let (kind, key) = match op {
+                        EntityOperation::Set { key, .. } => ("set", key),
+                        EntityOperation::Remove { key } => ("remove", key),
+                    };
+                    (block, kind, key.entity_type.to_string(), key.entity_id)
+                })
+            })
+            .collect();
+        changes.sort();
+        let expected = vec![
+            (1, "set", "Dog", "pluto"),
+            (1, "set", "Mink", "m1"),
+            (2, "remove", "Cat", "garfield"),
+            (2, "set", "Dog", "pluto"),
+            (2, "set", "Mink", "m2"),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(block, kind, entity_type, id)| {
+                (block, kind, entity_type.to_string(), id.to_string())
+            })
+            .collect();
+        assert_eq!(expected, changes);
+
+        let changes = layout
+            .find_changes(conn, 1)
+            .expect("Failed to find changes");
+        assert_eq!(2, changes.len());
+    });
+}
+
 #[test]
 fn insert_many_and_delete_many() {
     run_test(|conn, layout| {
@@ -964,6 +1053,7 @@ fn revert_block() {
                     EntityRange::first(100),
                     BLOCK_NUMBER_MAX,
                     None,
+                    None,
                 )
                 .expect("loading all marties works");
 
@@ -1004,6 +1094,171 @@ fn revert_block() {
     });
 }
 
+const SWAPS_GQL: &str = r#"
+    type Swap @entity(immutable: true) {
+        id: ID!
+        timestamp: Int8!
+        token: String!
+        amount: BigDecimal!
+    }
+
+    type SwapStats @aggregation(intervals: ["hour"], source: "Swap") {
+        id: ID!
+        timestamp: Int8!
+        token: String!
+        total: BigDecimal! @aggregate(fn: "sum", arg: "amount")
+        count: Int8! @aggregate(fn: "count")
+    }
+"#;
+
+#[test]
+fn rollup_and_revert() {
+    fn swap(id: &str, timestamp: i64, token: &str, amount: i32) -> Entity {
+        entity! {
+            id: id,
+            timestamp: Value::Int8(timestamp),
+            token: token,
+            amount: BigDecimal::from(amount),
+        }
+    }
+
+    /// Write the swaps and the changes to rollups they cause at `block`
+    /// the way the deployment store does it
+    fn write_swaps(conn: &PgConnection, layout: &Layout, swaps: Vec<Entity>, block: BlockNumber) {
+        let mods: Vec<_> = swaps
+            .iter()
+            .map(|swap| EntityModification::Insert {
+                key: EntityKey::data(
+                    THINGS_SUBGRAPH_ID.clone(),
+                    "Swap".to_owned(),
+                    swap.id().unwrap(),
+                ),
+                data: swap.clone(),
+            })
+            .collect();
+        let rollups = layout.rollup(conn, &mods).unwrap();
+        insert_entity_at(conn, layout, "Swap", swaps, block);
+        for rollup in rollups {
+            match rollup {
+                EntityModification::Insert { data, .. } => {
+                    insert_entity_at(conn, layout, "SwapStatsHour", vec![data], block)
+                }
+                EntityModification::Overwrite { data, .. } => {
+                    update_entity_at(conn, layout, "SwapStatsHour", vec![data], block)
+                }
+                EntityModification::Remove { .. } => panic!("rollups are never removed"),
+            }
+        }
+    }
+
+    fn assert_stats(conn: &PgConnection, layout: &Layout, id: &str, total: i32, count: i64) {
+        let stats = layout
+            .find(
+                conn,
+                &EntityType::from("SwapStatsHour"),
+                id,
+                BLOCK_NUMBER_MAX,
+            )
+            .unwrap()
+            .expect("the rollup exists");
+        assert_eq!(
+            Some(&Value::BigDecimal(BigDecimal::from(total))),
+            stats.get("total")
+        );
+        assert_eq!(Some(&Value::Int8(count)), stats.get("count"));
+        assert_eq!(Some(&Value::Int8(7200)), stats.get("timestamp"));
+    }
+
+    run_test_with_conn(|conn| {
+        remove_schema(conn);
+        let schema = Schema::parse(SWAPS_GQL, THINGS_SUBGRAPH_ID.clone()).unwrap();
+        let site = make_dummy_site(
+            THINGS_SUBGRAPH_ID.clone(),
+            NAMESPACE.clone(),
+            NETWORK_NAME.to_string(),
+        );
+        let query = format!("create schema {}", NAMESPACE.as_str());
+        conn.batch_execute(&*query).unwrap();
+        let layout = Layout::create_relational_schema(&conn, Arc::new(site), &schema)
+            .expect("Failed to create relational schema");
+        let hour = &layout.aggregations[0];
+        let bucket = |token: &str| hour.bucket(&swap("x", 7230, token, 0)).unwrap().0;
+
+        write_swaps(
+            conn,
+            &layout,
+            vec![
+                swap("s1", 7230, "a", 1),
+                swap("s2", 7290, "a", 2),
+                swap("s3", 7300, "b", 5),
+            ],
+            1,
+        );
+        assert_stats(conn, &layout, &bucket("a"), 3, 2);
+        assert_stats(conn, &layout, &bucket("b"), 5, 1);
+
+        write_swaps(conn, &layout, vec![swap("s4", 7400, "a", 4)], 2);
+        assert_stats(conn, &layout, &bucket("a"), 7, 3);
+
+        // Reverting the block also reverts its changes to the rollups
+        layout.revert_block(conn, 2).unwrap();
+        assert_stats(conn, &layout, &bucket("a"), 3, 2);
+        assert_stats(conn, &layout, &bucket("b"), 5, 1);
+        assert!(layout
+            .find(conn, &EntityType::from("Swap"), "s4", BLOCK_NUMBER_MAX)
+            .unwrap()
+            .is_none());
+
+        // New sources are folded into the reverted rollups
+        write_swaps(conn, &layout, vec![swap("s5", 7500, "a", 10)], 2);
+        assert_stats(conn, &layout, &bucket("a"), 13, 3);
+    });
+}
+
+#[test]
+fn prune() {
+    run_test(|conn, layout| {
+        let cat = EntityType::from("Cat");
+        let set_fred = |name, block| {
+            let fred = entity! {
+                id: "fred",
+                name: name
+            };
+            if block == 0 {
+                insert_entity_at(conn, layout, "Cat", vec![fred], block);
+            } else {
+                update_entity_at(conn, layout, "Cat", vec![fred], block);
+            }
+        };
+        let fred_at = |block| {
+            layout
+                .find(conn, &cat, "fred", block)
+                .unwrap()
+                .map(|fred| fred.get("name").unwrap().as_str().unwrap().to_owned())
+        };
+
+        set_fred("zero", 0);
+        set_fred("one", 1);
+        set_fred("two", 2);
+        set_fred("three", 3);
+
+        // Pruning to block 2 removes the versions for blocks 0 and 1, but
+        // leaves all the versions that are visible at block 2 and later
+        assert_eq!(2, layout.prune(conn, 2).unwrap());
+        assert_eq!(None, fred_at(0));
+        assert_eq!(None, fred_at(1));
+        assert_eq!(Some("two".to_owned()), fred_at(2));
+        assert_eq!(Some("three".to_owned()), fred_at(BLOCK_NUMBER_MAX));
+
+        // Pruning again is a no-op
+        assert_eq!(0, layout.prune(conn, 2).unwrap());
+
+        // Reverting within the retained history still works
+        layout.revert_block(conn, 3).unwrap();
+        assert_eq!(Some("two".to_owned()), fred_at(BLOCK_NUMBER_MAX));
+    });
+}
+
 struct QueryChecker<'a> {
     conn: &'a PgConnection,
     layout: &'a Layout,
@@ -1045,6 +1300,7 @@ impl<'a> QueryChecker<'a> {
                 query.range,
                 BLOCK_NUMBER_MAX,
                 None,
+                None,
             )
             .expect("layout.query failed to execute query");
 
@@ -1122,6 +1378,47 @@ fn check_fulltext_search_syntax_error() {
     });
 }
 
+#[test]
+fn check_fulltext_search_across_types() {
+    run_test(move |conn, layout| {
+        let search = || {
+            query(vec!["Cat", "Dog", "Ferret"]).filter(EntityFilter::Equal(
+                "petSearch".into(),
+                "garfield | pluto".into(),
+            ))
+        };
+
+        // Names of cats have a higher weight than names of dogs, and
+        // Garfield therefore ranks higher than Pluto
+        QueryChecker::new(conn, layout)
+            .check(vec!["pluto", "garfield"], search().asc("petSearch"))
+            .check(vec!["garfield", "pluto"], search().desc("petSearch"));
+
+        let query = search().desc("petSearch");
+        let ranks: Vec<_> = layout
+            .query::<Entity>(
+                &*LOGGER,
+                conn,
+                query.collection,
+                query.filter,
+                query.order,
+                query.range,
+                BLOCK_NUMBER_MAX,
+                None,
+                None,
+            )
+            .expect("fulltext query succeeds")
+            .into_iter()
+            .map(|pet| match pet.get("g$rank") {
+                Some(Value::BigDecimal(rank)) => rank.clone(),
+                rank => panic!("pets have a rank but got {:?}", rank),
+            })
+            .collect();
+        assert_eq!(2, ranks.len());
+        assert!(ranks[0] > ranks[1]);
+    });
+}
+
 #[test]
 fn check_block_finds() {
     run_test(move |conn, layout| {
@@ -1237,6 +1534,78 @@ fn check_find() {
                 )),
             );
 
+        // list contains any/all
+        let checker = checker
+            .check(
+                vec!["2", "3"],
+                user_query()
+                    .filter(EntityFilter::ContainsAny(
+                        "drinks".into(),
+                        vec!["beer", "tea"].into(),
+                    ))
+                    .asc("id"),
+            )
+            .check(
+                vec![],
+                user_query().filter(EntityFilter::ContainsAny(
+                    "drinks".into(),
+                    vec!["water"].into(),
+                )),
+            )
+            .check(
+                vec!["3"],
+                user_query().filter(EntityFilter::ContainsAll(
+                    "drinks".into(),
+                    vec!["tea", "coffee"].into(),
+                )),
+            )
+            .check(
+                vec![],
+                user_query().filter(EntityFilter::ContainsAll(
+                    "drinks".into(),
+                    vec!["beer", "tea"].into(),
+                )),
+            );
+
+        // null checks and case-insensitive `in`
+        let checker = checker
+            .check(
+                vec!["1"],
+                user_query().filter(EntityFilter::IsNull("drinks".into())),
+            )
+            .check(
+                vec!["2", "3"],
+                user_query()
+                    .filter(EntityFilter::IsNotNull("drinks".into()))
+                    .asc("id"),
+            )
+            .check(
+                vec!["2"],
+                user_query().filter(EntityFilter::InNoCase(
+                    "name".into(),
+                    vec!["cindini".into(), "NOBODY".into()],
+                )),
+            )
+            .check(
+                vec!["1", "3"],
+                user_query()
+                    .filter(EntityFilter::NotInNoCase(
+                        "name".into(),
+                        vec!["CINDINI".into()],
+                    ))
+                    .asc("id"),
+            )
+            .check(
+                vec![],
+                user_query().filter(EntityFilter::InNoCase("name".into(), vec![])),
+            )
+            .check(
+                vec!["1", "2", "3"],
+                user_query()
+                    .filter(EntityFilter::NotInNoCase("name".into(), vec![]))
+                    .asc("id"),
+            );
+
         // string attributes
         let checker = checker
             .check(
@@ -1653,6 +2022,249 @@ fn check_find() {
     })
 }
 
+#[test]
+fn explain_query() {
+    run_test(|conn, layout| {
+        insert_users(conn, layout);
+
+        let explain = QueryExplain::new().for_field("Query.users".to_owned());
+        let users = layout
+            .query::<Entity>(
+                &*LOGGER,
+                conn,
+                EntityCollection::All(vec![(EntityType::from("User"), AttributeNames::All)]),
+                Some(EntityFilter::GreaterThan("age".to_owned(), Value::Int(30))),
+                EntityOrder::Default,
+                EntityRange::first(100),
+                BLOCK_NUMBER_MAX,
+                None,
+                Some(&explain),
+            )
+            .expect("explained query succeeds");
+        assert_eq!(2, users.len());
+
+        let entries = explain.take();
+        assert_eq!(1, entries.len());
+        let entry = &entries[0];
+        assert_eq!("Query.users", entry.field);
+        assert_eq!(2, entry.entity_count);
+        assert!(entry.sql.contains("select"));
+        assert!(entry.binds.contains("30"));
+        assert!(entry.plan.iter().any(|line| line.contains("actual time")));
+    })
+}
+
+#[test]
+fn check_aggregates() {
+    run_test(|conn, layout| {
+        insert_users(conn, layout);
+
+        let user = EntityType::from("User");
+        let aggregates = vec![
+            EntityAggregate::Count,
+            EntityAggregate::Sum("age".to_owned()),
+            EntityAggregate::Avg("age".to_owned()),
+            EntityAggregate::Min("age".to_owned()),
+            EntityAggregate::Max("weight".to_owned()),
+            EntityAggregate::Sum("seconds_age".to_owned()),
+        ];
+        let aggregate = |filter: Option<EntityFilter>| {
+            layout
+                .aggregate(
+                    conn,
+                    &user,
+                    filter,
+                    &aggregates,
+                    BLOCK_NUMBER_MAX,
+                    None,
+                    None,
+                )
+                .expect("aggregate query succeeds")
+        };
+
+        // Users have ages 67, 43, and 28
+        let values = aggregate(None);
+        assert_eq!(
+            vec![
+                Value::Int8(3),
+                Value::BigInt(BigInt::from(138)),
+                Value::BigDecimal(BigDecimal::from(46)),
+                Value::Int(28),
+                Value::BigDecimal(BigDecimal::from(184.4)),
+                Value::BigInt(BigInt::from(138) * BigInt::from(31557600 as u64)),
+            ],
+            values
+        );
+
+        let values = aggregate(Some(EntityFilter::GreaterThan(
+            "age".to_owned(),
+            Value::Int(30),
+        )));
+        assert_eq!(Value::Int8(2), values[0]);
+        assert_eq!(Value::BigInt(BigInt::from(110)), values[1]);
+        assert_eq!(Value::BigDecimal(BigDecimal::from(55)), values[2]);
+
+        // Aggregates over an empty set are null, except for the count
+        let values = aggregate(Some(EntityFilter::GreaterThan(
+            "age".to_owned(),
+            Value::Int(100),
+        )));
+        assert_eq!(Value::Int8(0), values[0]);
+        assert!(values[1..].iter().all(|value| value == &Value::Null));
+
+        // Only numeric attributes can be aggregated
+        let res = layout.aggregate(
+            conn,
+            &user,
+            None,
+            &[EntityAggregate::Sum("name".to_owned())],
+            BLOCK_NUMBER_MAX,
+            None,
+            None,
+        );
+        assert!(res.is_err());
+    });
+}
+
+#[test]
+fn check_cursors() {
+    fn cursor(value: impl Into<Value>, id: &str) -> Option<EntityCursor> {
+        Some(EntityCursor {
+            value: value.into(),
+            id: id.to_string(),
+        })
+    }
+
+    fn between(
+        query: EntityQuery,
+        after: Option<EntityCursor>,
+        before: Option<EntityCursor>,
+    ) -> EntityQuery {
+        query.range(EntityRange {
+            first: None,
+            skip: 0,
+            after,
+            before,
+        })
+    }
+
+    run_test(move |conn, layout| {
+        // Users sorted by name are 2 (Cindini), 1 (Jono), 3 (Shaqueeena)
+        let checker = QueryChecker::new(conn, layout)
+            .check(
+                vec!["1", "3"],
+                between(user_query().asc("name"), cursor("Cindini", "2"), None),
+            )
+            .check(
+                vec!["2", "1"],
+                between(user_query().asc("name"), None, cursor("Shaqueeena", "3")),
+            )
+            .check(
+                vec!["1"],
+                between(
+                    user_query().asc("name"),
+                    cursor("Cindini", "2"),
+                    cursor("Shaqueeena", "3"),
+                ),
+            )
+            .check(
+                vec!["1", "2"],
+                between(user_query().desc("name"), cursor("Shaqueeena", "3"), None),
+            )
+            .check(
+                vec!["2", "3"],
+                between(user_query(), cursor(Value::Null, "1"), None),
+            );
+
+        // The `Color` enum sorts `yellow` before `red`, and user 3 has no
+        // favorite color; nulls sort last when ascending and first when
+        // descending
+        checker
+            .check(
+                vec!["2", "3"],
+                between(
+                    user_query().asc("favorite_color"),
+                    cursor("yellow", "1"),
+                    None,
+                ),
+            )
+            .check(
+                vec!["1", "2"],
+                between(
+                    user_query().asc("favorite_color"),
+                    None,
+                    cursor(Value::Null, "3"),
+                ),
+            )
+            .check(
+                vec!["2", "1"],
+                between(
+                    user_query().desc("favorite_color"),
+                    cursor(Value::Null, "3"),
+                    None,
+                ),
+            );
+    })
+}
+
+#[test]
+fn check_cursors_with_limit() {
+    fn before(query: EntityQuery, id: &str, value: Value, first: u32, skip: u32) -> EntityQuery {
+        query.range(EntityRange {
+            first: Some(first),
+            skip,
+            after: None,
+            before: Some(EntityCursor {
+                value,
+                id: id.to_string(),
+            }),
+        })
+    }
+
+    run_test(move |conn, layout| {
+        // Users sorted by name are 2 (Cindini), 1 (Jono), 3 (Shaqueeena);
+        // `first` and `skip` count backwards from the `before` cursor, but
+        // the entities still come back in the order of the query
+        QueryChecker::new(conn, layout)
+            .check(
+                vec!["1"],
+                before(user_query().asc("name"), "3", "Shaqueeena".into(), 1, 0),
+            )
+            .check(
+                vec!["2", "1"],
+                before(user_query().asc("name"), "3", "Shaqueeena".into(), 2, 0),
+            )
+            .check(
+                vec!["2"],
+                before(user_query().asc("name"), "3", "Shaqueeena".into(), 1, 1),
+            )
+            .check(
+                vec!["1"],
+                before(user_query().desc("name"), "2", "Cindini".into(), 1, 0),
+            )
+            .check(
+                vec!["3", "1"],
+                before(user_query().desc("name"), "2", "Cindini".into(), 5, 0),
+            )
+            .check(vec!["2"], before(user_query(), "3", Value::Null, 1, 0))
+            .check(
+                vec!["1"],
+                user_query().range(EntityRange {
+                    first: Some(1),
+                    skip: 0,
+                    after: Some(EntityCursor {
+                        value: "Cindini".into(),
+                        id: "2".to_string(),
+                    }),
+                    before: Some(EntityCursor {
+                        value: "Shaqueeena".into(),
+                        id: "3".to_string(),
+                    }),
+                }),
+            );
+    })
+}
+
 // We call our test strings aN so that
 //   aN = "a" * (STRING_PREFIX_SIZE - 2 + N)
 // chosen so that they straddle the boundary between strings that fit into
@@ -1702,6 +2314,7 @@ impl<'a> FilterChecker<'a> {
                 query.range,
                 BLOCK_NUMBER_MAX,
                 None,
+                None,
             )
             .expect("layout.query failed to execute query");
 
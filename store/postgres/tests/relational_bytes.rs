@@ -11,11 +11,12 @@ use std::{collections::BTreeMap, sync::Arc};
 
 use graph::prelude::{
     o, slog, web3::types::H256, AttributeNames, ChildMultiplicity, DeploymentHash, Entity,
-    EntityCollection, EntityKey, EntityLink, EntityOrder, EntityRange, EntityWindow, Logger,
-    ParentLink, Schema, StopwatchMetrics, Value, WindowAttribute, BLOCK_NUMBER_MAX,
+    EntityCollection, EntityFilter, EntityKey, EntityLink, EntityOrder, EntityRange, EntityWindow,
+    Logger, ParentLink, Schema, StopwatchMetrics, Value, ValueType, WindowAttribute,
+    BLOCK_NUMBER_MAX,
 };
 use graph::{
-    components::store::EntityType,
+    components::store::{Child, EntityOrderByChild, EntityType},
     data::store::scalar::{BigDecimal, BigInt},
 };
 use graph_store_postgres::{
@@ -411,6 +412,7 @@ fn query() {
                 EntityRange::first(10),
                 BLOCK_NUMBER_MAX,
                 None,
+                None,
             )
             .expect("the query succeeds")
             .into_iter()
@@ -519,3 +521,103 @@ fn query() {
         assert_eq!(vec![ROOT, ROOT], things);
     });
 }
+
+#[test]
+fn query_child_filter() {
+    fn fetch(conn: &PgConnection, layout: &Layout, filter: EntityFilter) -> Vec<String> {
+        layout
+            .query::<Entity>(
+                &*LOGGER,
+                conn,
+                EntityCollection::All(vec![(THING.clone(), AttributeNames::All)]),
+                Some(filter),
+                EntityOrder::Default,
+                EntityRange::first(10),
+                BLOCK_NUMBER_MAX,
+                None,
+                None,
+            )
+            .expect("the query succeeds")
+            .into_iter()
+            .map(|e| e.id().expect("entities have an id"))
+            .collect::<Vec<_>>()
+    }
+
+    fn child(attr: &str, filter: EntityFilter, derived: bool) -> EntityFilter {
+        EntityFilter::Child(Child {
+            attr: attr.to_string(),
+            entity_type: THING.clone(),
+            filter: Box::new(filter),
+            derived,
+        })
+    }
+
+    fn name_is(name: &str) -> EntityFilter {
+        EntityFilter::Equal("name".to_string(), Value::from(name))
+    }
+
+    run_test(|conn, layout| {
+        make_thing_tree(conn, layout);
+
+        //   things(where: { parent_: { name: "root" } }) { id }
+        let things = fetch(conn, layout, child("parent", name_is("root"), false));
+        assert_eq!(vec![CHILD1, CHILD2], things);
+
+        //   things(where: { children_: { name: "child2" } }) { id }
+        let things = fetch(conn, layout, child("children", name_is("child2"), false));
+        assert_eq!(vec![ROOT], things);
+
+        //   things(where: { parent_: { parent_: { name: "root" } } }) { id }
+        let filter = child("parent", child("parent", name_is("root"), false), false);
+        let things = fetch(conn, layout, filter);
+        assert_eq!(vec![GRANDCHILD1, GRANDCHILD2], things);
+
+        // A derived field `offspring: [Thing!] @derivedFrom(field: "parent")`
+        //   things(where: { offspring_: { name: "grandchild2" } }) { id }
+        let things = fetch(conn, layout, child("parent", name_is("grandchild2"), true));
+        assert_eq!(vec![CHILD2], things);
+    });
+}
+
+#[test]
+fn query_child_order_by() {
+    fn fetch(conn: &PgConnection, layout: &Layout, order: EntityOrder) -> Vec<String> {
+        layout
+            .query::<Entity>(
+                &*LOGGER,
+                conn,
+                EntityCollection::All(vec![(THING.clone(), AttributeNames::All)]),
+                None,
+                order,
+                EntityRange::first(10),
+                BLOCK_NUMBER_MAX,
+                None,
+                None,
+            )
+            .expect("the query succeeds")
+            .into_iter()
+            .map(|e| e.id().expect("entities have an id"))
+            .collect::<Vec<_>>()
+    }
+
+    fn parent_name() -> EntityOrderByChild {
+        EntityOrderByChild {
+            attr: "parent".to_string(),
+            child_type: THING.clone(),
+            child_attr: "name".to_string(),
+            value_type: ValueType::String,
+        }
+    }
+
+    run_test(|conn, layout| {
+        make_thing_tree(conn, layout);
+
+        //   things(orderBy: parent__name, orderDirection: asc) { id }
+        let things = fetch(conn, layout, EntityOrder::ChildAscending(parent_name()));
+        assert_eq!(vec![GRANDCHILD1, GRANDCHILD2, CHILD1, CHILD2, ROOT], things);
+
+        //   things(orderBy: parent__name, orderDirection: desc) { id }
+        let things = fetch(conn, layout, EntityOrder::ChildDescending(parent_name()));
+        assert_eq!(vec![ROOT, CHILD2, CHILD1, GRANDCHILD2, GRANDCHILD1], things);
+    });
+}
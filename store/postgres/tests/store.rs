@@ -1099,6 +1099,88 @@ fn revert_block_with_delete() {
     })
 }
 
+#[test]
+fn entity_changes_across_reverts() {
+    run_test(|store, _, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+        let id = &deployment.hash;
+        let reorg_count = subgraph_store
+            .deployment_state(id)
+            .await
+            .unwrap()
+            .reorg_count;
+
+        // Remove user 2 in block 3 and add user 4 in block 4
+        let del_key = EntityKey::data(id.clone(), USER.to_owned(), "2".to_owned());
+        transact_and_wait(
+            &subgraph_store,
+            &deployment,
+            TEST_BLOCK_3_PTR.clone(),
+            vec![EntityOperation::Remove { key: del_key }],
+        )
+        .await
+        .unwrap();
+        let test_entity_4 = create_test_entity(
+            "4",
+            USER,
+            "Shaqueeena",
+            "queensha@email.com",
+            28 as i32,
+            111.7,
+            false,
+            None,
+        );
+        transact_and_wait(
+            &subgraph_store,
+            &deployment,
+            TEST_BLOCK_4_PTR.clone(),
+            vec![test_entity_4],
+        )
+        .await
+        .unwrap();
+
+        // One query for a range of blocks finds the same changes as
+        // looking at each block individually
+        let changes = subgraph_store
+            .entity_changes_in_block_range(id, 1, 4)
+            .unwrap();
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            changes.keys().cloned().collect::<Vec<_>>()
+        );
+        for (block, ops) in &changes {
+            let single = subgraph_store.entity_changes_in_block(id, *block).unwrap();
+            assert_eq!(single.len(), ops.len());
+            assert!(ops.iter().all(|op| single.contains(op)));
+        }
+        assert_eq!(2, changes[&1].len());
+        assert!(matches!(
+            &changes[&3][..],
+            [EntityOperation::Remove { key }] if key.entity_id == "2"
+        ));
+
+        // Reverting two blocks at once only increases the reorg count by
+        // one, but the store remembers which block the revert went to
+        revert_block(&store, &deployment, &*TEST_BLOCK_2_PTR).await;
+        let state = subgraph_store.deployment_state(id).await.unwrap();
+        assert_eq!(reorg_count + 1, state.reorg_count);
+        assert_eq!(
+            vec![2],
+            subgraph_store.reverts_since(id, reorg_count).unwrap()
+        );
+        assert!(subgraph_store
+            .reverts_since(id, state.reorg_count)
+            .unwrap()
+            .is_empty());
+
+        // The changes for the reverted blocks are gone
+        let changes = subgraph_store
+            .entity_changes_in_block_range(id, 1, 4)
+            .unwrap();
+        assert_eq!(vec![1, 2], changes.keys().cloned().collect::<Vec<_>>());
+    })
+}
+
 #[test]
 fn revert_block_with_partial_update() {
     run_test(|store, writable, deployment| async move {
@@ -96,6 +96,46 @@ fn reassign_subgraph() {
     })
 }
 
+#[test]
+fn pause_and_resume_subgraph() {
+    async fn setup() -> DeploymentLocator {
+        let id = DeploymentHash::new("pauseSubgraph").unwrap();
+        remove_subgraphs();
+        create_test_subgraph(&id, SUBGRAPH_GQL).await
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = setup().await;
+        let store = store.subgraph_store();
+        let node = store
+            .assigned_node(&id)
+            .unwrap()
+            .expect("deployment is assigned");
+
+        // Pausing keeps the assignment, but removes the deployment from
+        // the node's assignments. Pausing a second time does nothing
+        let (_, events) = tap_store_events(|| store.pause_subgraph(&id).unwrap());
+        assert_eq!(vec![StoreEvent::new(vec![unassigned(&id)])], events);
+        assert_eq!(
+            Some((node.clone(), true)),
+            store.assignment_status(&id).unwrap()
+        );
+        assert!(!store.assignments(&node).unwrap().contains(&id));
+
+        let (_, events) = tap_store_events(|| store.pause_subgraph(&id).unwrap());
+        assert_eq!(Vec::<StoreEvent>::new(), events);
+
+        // Resuming puts the deployment back on the node
+        let (_, events) = tap_store_events(|| store.resume_subgraph(&id).unwrap());
+        assert_eq!(vec![StoreEvent::new(vec![assigned(&id)])], events);
+        assert_eq!(
+            Some((node.clone(), false)),
+            store.assignment_status(&id).unwrap()
+        );
+        assert!(store.assignments(&node).unwrap().contains(&id));
+    })
+}
+
 #[test]
 fn create_subgraph() {
     const SUBGRAPH_NAME: &str = "create/subgraph";
@@ -4,7 +4,7 @@ use std::marker::PhantomData;
 use test_store::*;
 
 use graph::components::store::{DeploymentLocator, WritableStore};
-use graph::components::store::{EntityKey, EntityType};
+use graph::components::store::{DerivedEntityQuery, EntityKey, EntityType};
 use graph::data::subgraph::*;
 use graph::prelude::*;
 use graph::semver::Version;
@@ -17,9 +17,27 @@ const SCHEMA_GQL: &str = "
         id: ID!,
         count: Int,
     }
+
+    type Owner @entity {
+        id: ID!,
+        pets: [Pet!]! @derivedFrom(field: \"owner\"),
+        teams: [Team!]! @derivedFrom(field: \"members\"),
+    }
+
+    type Pet @entity {
+        id: ID!,
+        owner: Owner!,
+    }
+
+    type Team @entity {
+        id: ID!,
+        members: [Owner!]!,
+    }
 ";
 
 const COUNTER: &str = "Counter";
+const PET: &str = "Pet";
+const TEAM: &str = "Team";
 
 lazy_static! {
     static ref TEST_SUBGRAPH_ID_STRING: String = String::from("writableSubgraph");
@@ -123,6 +141,49 @@ async fn insert_count(store: &Arc<DieselSubgraphStore>, deployment: &DeploymentL
         .unwrap();
 }
 
+fn entity_key(deployment: &DeploymentLocator, entity_type: &str, id: &str) -> EntityKey {
+    EntityKey {
+        subgraph_id: deployment.hash.clone(),
+        entity_type: EntityType::from(entity_type),
+        entity_id: id.to_owned(),
+    }
+}
+
+fn set_pet(deployment: &DeploymentLocator, id: &str, owner: &str) -> EntityOperation {
+    EntityOperation::Set {
+        key: entity_key(deployment, PET, id),
+        data: entity! { id: id, owner: owner },
+    }
+}
+
+fn set_team(deployment: &DeploymentLocator, id: &str, members: Vec<&str>) -> EntityOperation {
+    EntityOperation::Set {
+        key: entity_key(deployment, TEAM, id),
+        data: entity! { id: id, members: members },
+    }
+}
+
+/// Return the sorted ids of the entities of type `entity_type` whose
+/// `field` refers to `owner`
+fn derived_ids(
+    writable: &Arc<dyn WritableStore>,
+    entity_type: &str,
+    field: &str,
+    owner: &str,
+) -> Vec<String> {
+    let query = DerivedEntityQuery {
+        entity_type: EntityType::from(entity_type),
+        entity_field: field.to_owned(),
+        value: owner.to_owned(),
+    };
+    writable
+        .get_derived(&query)
+        .unwrap()
+        .into_keys()
+        .map(|key| key.entity_id)
+        .collect()
+}
+
 async fn pause_writer(deployment: &DeploymentLocator) {
     flush(&deployment).await.unwrap();
     writable::allow_steps(0).await;
@@ -165,3 +226,94 @@ fn tracker() {
         assert_eq!(2, read_count());
     })
 }
+
+#[test]
+fn get_derived() {
+    run_test(|store, writable, deployment| async move {
+        let subgraph_store = store.subgraph_store();
+
+        let pets = |owner| derived_ids(&writable, PET, "owner", owner);
+        let teams = |owner| derived_ids(&writable, TEAM, "members", owner);
+
+        transact_entity_operations(
+            &subgraph_store,
+            &deployment,
+            block_pointer(1),
+            vec![
+                set_pet(&deployment, "p1", "o1"),
+                set_pet(&deployment, "p2", "o1"),
+                set_pet(&deployment, "p3", "o2"),
+                set_team(&deployment, "t1", vec!["o1", "o2"]),
+                set_team(&deployment, "t2", vec!["o2"]),
+            ],
+        )
+        .await
+        .unwrap();
+        flush(&deployment).await.unwrap();
+
+        // Everything is in the store
+        assert_eq!(vec!["p1", "p2"], pets("o1"));
+        assert_eq!(vec!["p3"], pets("o2"));
+        assert_eq!(vec!["t1"], teams("o1"));
+        assert_eq!(vec!["t1", "t2"], teams("o2"));
+        assert!(pets("o3").is_empty());
+
+        pause_writer(&deployment).await;
+
+        // Add, move and remove entities while the changes are still
+        // in the queue
+        transact_entity_operations(
+            &subgraph_store,
+            &deployment,
+            block_pointer(2),
+            vec![
+                set_pet(&deployment, "p4", "o1"),
+                set_pet(&deployment, "p2", "o2"),
+                EntityOperation::Remove {
+                    key: entity_key(&deployment, PET, "p1"),
+                },
+                set_team(&deployment, "t1", vec!["o2"]),
+                set_team(&deployment, "t2", vec!["o2", "o1"]),
+                set_team(&deployment, "t3", vec!["o1"]),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let check = || {
+            assert_eq!(vec!["p4"], pets("o1"));
+            assert_eq!(vec!["p2", "p3"], pets("o2"));
+            assert_eq!(vec!["t2", "t3"], teams("o1"));
+            assert_eq!(vec!["t1", "t2"], teams("o2"));
+        };
+        check();
+
+        // The same entities are found once the changes have been written
+        resume_writer(&deployment, 1).await;
+        check();
+
+        // Removes that are still in the queue hide entities in the store
+        pause_writer(&deployment).await;
+        transact_entity_operations(
+            &subgraph_store,
+            &deployment,
+            block_pointer(3),
+            vec![
+                EntityOperation::Remove {
+                    key: entity_key(&deployment, PET, "p4"),
+                },
+                EntityOperation::Remove {
+                    key: entity_key(&deployment, TEAM, "t2"),
+                },
+            ],
+        )
+        .await
+        .unwrap();
+        assert!(pets("o1").is_empty());
+        assert_eq!(vec!["t3"], teams("o1"));
+        assert_eq!(vec!["t1"], teams("o2"));
+        resume_writer(&deployment, 1).await;
+        assert!(pets("o1").is_empty());
+        assert_eq!(vec!["t3"], teams("o1"));
+    })
+}
@@ -6,7 +6,9 @@ use graph::components::store::BlockStore;
 use graph::{
     blockchain::Block,
     prelude::{
-        serde_json, web3::types::H256, BlockNumber, BlockPtr, EthereumBlock, LightEthereumBlock,
+        serde_json,
+        web3::types::{H256, U256},
+        BlockNumber, BlockPtr, EthereumBlock, LightEthereumBlock,
     },
 };
 
@@ -78,6 +80,8 @@ impl FakeBlock {
         block.number = Some(self.number.into());
         block.parent_hash = parent_hash;
         block.hash = Some(self.block_hash());
+        // Fake blocks are ten seconds apart
+        block.timestamp = U256::from(self.number as u64 * 10);
 
         EthereumBlock {
             block: Arc::new(block),
@@ -480,7 +480,8 @@ async fn execute_subgraph_query_internal(
                     max_skip: std::u32::MAX,
                 },
             )
-            .await,
+            .await
+            .0,
         )
     }
     result